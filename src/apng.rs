@@ -0,0 +1,939 @@
+use std::{ops::Range, str::FromStr};
+
+use rayon::prelude::*;
+
+use crate::{
+    bytes::{be_u16, be_u32, put_be_u16, put_be_u32},
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    limits,
+    png::Png,
+    raster::{self, Decoder, Image},
+    Result,
+};
+
+#[derive(Debug)]
+pub enum ApngError {
+    NotAnimated,
+    InvalidControlChunk(&'static str),
+    SequenceOutOfOrder {
+        expected: u32,
+        found: u32,
+    },
+    FrameOutOfBounds(u32),
+    MissingFrameData(u32),
+    NoFrames,
+    NoSuchFrame(usize),
+    LastFrame,
+    MismatchedFrameSize {
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+    MismatchedFormat,
+    InvalidDelay(String),
+}
+
+impl std::error::Error for ApngError {}
+
+impl std::fmt::Display for ApngError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ApngError::NotAnimated => write!(f, "PNG has no acTL chunk, it is not animated"),
+            ApngError::InvalidControlChunk(typ) => write!(f, "Invalid {} chunk", typ),
+            ApngError::SequenceOutOfOrder { expected, found } => write!(
+                f,
+                "Expected sequence number {} but found {}",
+                expected, found
+            ),
+            ApngError::FrameOutOfBounds(seq) => {
+                write!(f, "Frame {} does not fit inside the canvas", seq)
+            }
+            ApngError::MissingFrameData(seq) => write!(f, "Frame {} has no image data", seq),
+            ApngError::NoFrames => write!(f, "An animation needs at least one frame"),
+            ApngError::NoSuchFrame(idx) => write!(f, "The animation has no frame {}", idx),
+            ApngError::LastFrame => write!(f, "Cannot drop the only frame of an animation"),
+            ApngError::MismatchedFrameSize { expected, found } => write!(
+                f,
+                "Frame is {}x{} but the animation is {}x{}",
+                found.0, found.1, expected.0, expected.1
+            ),
+            ApngError::MismatchedFormat => write!(
+                f,
+                "Frames differ in bit depth, color type, interlacing or palette"
+            ),
+            ApngError::InvalidDelay(delay) => write!(
+                f,
+                "Invalid delay '{}', expected a duration such as 100ms or 1.5s",
+                delay
+            ),
+        }
+    }
+}
+
+/// The `acTL` chunk: how many frames the animation has and how many times it loops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnimationControl {
+    pub num_frames: u32,
+    /// `0` means loop forever.
+    pub num_plays: u32,
+}
+
+impl TryFrom<&Chunk> for AnimationControl {
+    type Error = crate::Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let data = chunk.data();
+        if data.len() != 8 {
+            return Err(ApngError::InvalidControlChunk("acTL").into());
+        }
+
+        Ok(Self {
+            num_frames: be_u32(&data[0..4]),
+            num_plays: be_u32(&data[4..8]),
+        })
+    }
+}
+
+impl AnimationControl {
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(8);
+        put_be_u32(&mut bytes, self.num_frames);
+        put_be_u32(&mut bytes, self.num_plays);
+        bytes
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisposeOp {
+    None,
+    Background,
+    Previous,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendOp {
+    Source,
+    Over,
+}
+
+/// The `fcTL` chunk: placement, timing and compositing rules of a single frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameControl {
+    pub sequence_number: u32,
+    pub width: u32,
+    pub height: u32,
+    pub x_offset: u32,
+    pub y_offset: u32,
+    pub delay_num: u16,
+    pub delay_den: u16,
+    pub dispose_op: DisposeOp,
+    pub blend_op: BlendOp,
+}
+
+impl TryFrom<&Chunk> for FrameControl {
+    type Error = crate::Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let data = chunk.data();
+        if data.len() != 26 {
+            return Err(ApngError::InvalidControlChunk("fcTL").into());
+        }
+
+        let dispose_op = match data[24] {
+            0 => DisposeOp::None,
+            1 => DisposeOp::Background,
+            2 => DisposeOp::Previous,
+            _ => return Err(ApngError::InvalidControlChunk("fcTL").into()),
+        };
+        let blend_op = match data[25] {
+            0 => BlendOp::Source,
+            1 => BlendOp::Over,
+            _ => return Err(ApngError::InvalidControlChunk("fcTL").into()),
+        };
+
+        Ok(Self {
+            sequence_number: be_u32(&data[0..4]),
+            width: be_u32(&data[4..8]),
+            height: be_u32(&data[8..12]),
+            x_offset: be_u32(&data[12..16]),
+            y_offset: be_u32(&data[16..20]),
+            delay_num: be_u16(&data[20..22]),
+            delay_den: be_u16(&data[22..24]),
+            dispose_op,
+            blend_op,
+        })
+    }
+}
+
+impl FrameControl {
+    pub fn delay(&self) -> Delay {
+        Delay {
+            num: self.delay_num,
+            den: self.delay_den,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(26);
+        put_be_u32(&mut bytes, self.sequence_number);
+        put_be_u32(&mut bytes, self.width);
+        put_be_u32(&mut bytes, self.height);
+        put_be_u32(&mut bytes, self.x_offset);
+        put_be_u32(&mut bytes, self.y_offset);
+        put_be_u16(&mut bytes, self.delay_num);
+        put_be_u16(&mut bytes, self.delay_den);
+        bytes.push(self.dispose_op as u8);
+        bytes.push(self.blend_op as u8);
+        bytes
+    }
+}
+
+/// How long a frame stays on screen, stored as the `delay_num / delay_den` seconds of an fcTL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Delay {
+    pub num: u16,
+    pub den: u16,
+}
+
+impl FromStr for Delay {
+    type Err = ApngError;
+
+    /// Parses durations such as `100ms`, `1.5s` or a bare number of milliseconds.
+    fn from_str(s: &str) -> std::result::Result<Self, ApngError> {
+        let invalid = || ApngError::InvalidDelay(s.to_string());
+        let trimmed = s.trim();
+
+        let millis = if let Some(ms) = trimmed.strip_suffix("ms") {
+            ms.trim().parse::<f64>().map_err(|_| invalid())?
+        } else if let Some(secs) = trimmed.strip_suffix('s') {
+            secs.trim().parse::<f64>().map_err(|_| invalid())? * 1000.0
+        } else {
+            trimmed.parse::<f64>().map_err(|_| invalid())?
+        };
+
+        Delay::from_millis(millis).ok_or_else(invalid)
+    }
+}
+
+impl Delay {
+    /// The closest representable delay, or `None` if it is negative or too long for an fcTL.
+    pub fn from_millis(millis: f64) -> Option<Self> {
+        if !(0.0..=u16::MAX as f64 * 1000.0).contains(&millis) {
+            return None;
+        }
+
+        // Millisecond precision when it fits, otherwise whole centiseconds or seconds
+        let millis = millis.round();
+        let (num, den) = [(millis, 1000), (millis / 10.0, 100), (millis / 1000.0, 1)]
+            .into_iter()
+            .find(|(num, _)| *num <= u16::MAX as f64 && num.fract() == 0.0)
+            .unwrap_or(((millis / 1000.0).round(), 1));
+
+        Some(Delay {
+            num: num as u16,
+            den,
+        })
+    }
+
+    pub fn millis(&self) -> u64 {
+        // A denominator of 0 means 1/100th of a second
+        let den = if self.den == 0 { 100 } else { self.den };
+        self.num as u64 * 1000 / den as u64
+    }
+}
+
+impl std::fmt::Display for Delay {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}ms", self.millis())
+    }
+}
+
+/// A single animation frame: its control chunk and its concatenated zlib stream.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub control: FrameControl,
+    pub data: Vec<u8>,
+}
+
+/// The frames of an animated PNG, in playback order.
+#[derive(Debug, Clone)]
+pub struct Animation {
+    pub control: AnimationControl,
+    pub frames: Vec<Frame>,
+    /// Whether the default image (the `IDAT` chunks) is also the first frame.
+    pub default_image_is_first_frame: bool,
+}
+
+impl TryFrom<&Png> for Animation {
+    type Error = crate::Error;
+
+    fn try_from(png: &Png) -> Result<Self> {
+        let control = png.chunk_by_type("acTL").ok_or(ApngError::NotAnimated)?;
+        let control = AnimationControl::try_from(control)?;
+        limits::active().check_frames(control.num_frames)?;
+
+        let mut frames: Vec<Frame> = vec![];
+        let mut expected_sequence = 0;
+        let mut default_image_is_first_frame = false;
+        let mut seen_idat = false;
+
+        for chunk in png.chunks() {
+            match chunk.chunk_type().to_string().as_str() {
+                "fcTL" => {
+                    let frame_control = FrameControl::try_from(chunk)?;
+                    check_sequence(&mut expected_sequence, frame_control.sequence_number)?;
+                    frames.push(Frame {
+                        control: frame_control,
+                        data: vec![],
+                    });
+                }
+                "IDAT" => {
+                    // Only an fcTL seen before the first IDAT makes the default image a frame
+                    if !seen_idat && frames.len() == 1 {
+                        default_image_is_first_frame = true;
+                    }
+                    seen_idat = true;
+                    if default_image_is_first_frame {
+                        frames[0].data.extend_from_slice(chunk.data());
+                    }
+                }
+                "fdAT" => {
+                    let data = chunk.data();
+                    if data.len() < 4 {
+                        return Err(ApngError::InvalidControlChunk("fdAT").into());
+                    }
+                    check_sequence(&mut expected_sequence, be_u32(&data[0..4]))?;
+                    let frame = frames
+                        .last_mut()
+                        .ok_or(ApngError::InvalidControlChunk("fdAT"))?;
+                    frame.data.extend_from_slice(&data[4..]);
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(frame) = frames.iter().find(|frame| frame.data.is_empty()) {
+            return Err(ApngError::MissingFrameData(frame.control.sequence_number).into());
+        }
+
+        Ok(Self {
+            control,
+            frames,
+            default_image_is_first_frame,
+        })
+    }
+}
+
+/// Assemble same-sized RGBA images into an animated PNG.
+///
+/// The first image doubles as the default image, so viewers without APNG support show it.
+pub fn build(frames: &[Image], delay: Delay, num_plays: u32) -> Result<Png> {
+    let first = frames.first().ok_or(ApngError::NoFrames)?;
+    let (width, height) = (first.width, first.height);
+
+    let mut data = Vec::with_capacity(frames.len());
+    for frame in frames {
+        if (frame.width, frame.height) != (width, height) {
+            return Err(ApngError::MismatchedFrameSize {
+                expected: (width, height),
+                found: (frame.width, frame.height),
+            }
+            .into());
+        }
+        data.push(raster::compress(frame)?);
+    }
+
+    let ihdr = Chunk::new(
+        ChunkType::from_str("IHDR")?,
+        raster::ihdr_data(width, height),
+    );
+    assemble(vec![ihdr], (width, height), data, delay, num_plays)
+}
+
+/// Assemble still PNGs into an animated PNG without decoding them: the image data of each file
+/// becomes a frame as is.
+///
+/// Every file must have the same dimensions and pixel format (bit depth, color type,
+/// interlacing, palette and transparency), otherwise decode them and use [`build`]. The chunks
+/// that describe the first file's pixels (palette, color space, ...) are kept.
+pub fn build_from_pngs(pngs: &[Png], delay: Delay, num_plays: u32) -> Result<Png> {
+    let first = pngs.first().ok_or(ApngError::NoFrames)?;
+    let size = raster::dimensions(first)?;
+    // Everything in IHDR after the dimensions, the palette and the transparency
+    let format = |png: &Png| -> Vec<Option<Vec<u8>>> {
+        let data = |code| png.chunk_by_type(code).map(|chunk| chunk.data().to_vec());
+        let pixel_format = data("IHDR").map(|ihdr| ihdr.get(8..).unwrap_or_default().to_vec());
+        vec![pixel_format, data("PLTE"), data("tRNS")]
+    };
+    let first_format = format(first);
+
+    let mut data = Vec::with_capacity(pngs.len());
+    for png in pngs {
+        let found = raster::dimensions(png)?;
+        if found != size {
+            return Err(ApngError::MismatchedFrameSize {
+                expected: size,
+                found,
+            }
+            .into());
+        }
+        if format(png) != first_format {
+            return Err(ApngError::MismatchedFormat.into());
+        }
+
+        data.push(
+            png.chunks()
+                .iter()
+                .filter(|chunk| chunk.chunk_type().to_string() == "IDAT")
+                .flat_map(|chunk| chunk.data().iter().copied())
+                .collect(),
+        );
+    }
+
+    let header: Vec<Chunk> = first
+        .chunks()
+        .iter()
+        .take_while(|chunk| chunk.chunk_type().to_string() != "IDAT")
+        .filter(|chunk| !matches!(chunk.chunk_type().to_string().as_str(), "acTL" | "fcTL"))
+        .cloned()
+        .collect();
+    assemble(header, size, data, delay, num_plays)
+}
+
+/// Put an animated PNG together from the chunks that go before the image data and the zlib
+/// stream of every full-size frame.
+fn assemble(
+    mut chunks: Vec<Chunk>,
+    (width, height): (u32, u32),
+    frames: Vec<Vec<u8>>,
+    delay: Delay,
+    num_plays: u32,
+) -> Result<Png> {
+    let chunk = |code: &str, data: Vec<u8>| -> Result<Chunk> {
+        Ok(Chunk::new(ChunkType::from_str(code)?, data))
+    };
+
+    let control = AnimationControl {
+        num_frames: frames.len() as u32,
+        num_plays,
+    };
+    // acTL goes right after IHDR, ahead of anything else before the image data
+    chunks.insert(1.min(chunks.len()), chunk("acTL", control.as_bytes())?);
+
+    let mut sequence_number = 0;
+    for (idx, compressed) in frames.into_iter().enumerate() {
+        let frame_control = FrameControl {
+            sequence_number,
+            width,
+            height,
+            x_offset: 0,
+            y_offset: 0,
+            delay_num: delay.num,
+            delay_den: delay.den,
+            dispose_op: DisposeOp::None,
+            blend_op: BlendOp::Source,
+        };
+        chunks.push(chunk("fcTL", frame_control.as_bytes())?);
+        sequence_number += 1;
+
+        if idx == 0 {
+            chunks.push(chunk("IDAT", compressed)?);
+        } else {
+            let mut data = Vec::with_capacity(4 + compressed.len());
+            put_be_u32(&mut data, sequence_number);
+            data.extend(compressed);
+            chunks.push(chunk("fdAT", data)?);
+            sequence_number += 1;
+        }
+    }
+
+    chunks.push(chunk("IEND", vec![])?);
+
+    Ok(Png::from_chunks(chunks))
+}
+
+/// The chunk index range that belongs to each frame, in playback order.
+///
+/// A frame starts at its fcTL chunk and extends up to the next fcTL (or IEND), so it covers the
+/// frame's IDAT/fdAT data and anything stored alongside it.
+pub fn frame_ranges(png: &Png) -> Result<Vec<Range<usize>>> {
+    Animation::try_from(png)?;
+
+    let types: Vec<String> = png
+        .chunks()
+        .iter()
+        .map(|chunk| chunk.chunk_type().to_string())
+        .collect();
+    let end = types
+        .iter()
+        .position(|typ| typ == "IEND")
+        .unwrap_or(types.len());
+
+    let starts: Vec<usize> = (0..end).filter(|&idx| types[idx] == "fcTL").collect();
+    Ok(starts
+        .iter()
+        .enumerate()
+        .map(|(n, &start)| start..starts.get(n + 1).copied().unwrap_or(end))
+        .collect())
+}
+
+/// Chunk index range of frame `frame`, see [`frame_ranges`].
+pub fn frame_range(png: &Png, frame: usize) -> Result<Range<usize>> {
+    frame_ranges(png)?
+        .get(frame)
+        .cloned()
+        .ok_or_else(|| ApngError::NoSuchFrame(frame).into())
+}
+
+/// Rewrite the delay of every frame (or only frame `only`) through `retime`.
+///
+/// Only the fcTL chunks are touched, frame data is left exactly as it was. Returns how many
+/// frames were changed.
+pub fn set_delays<F>(png: &mut Png, only: Option<usize>, retime: F) -> Result<usize>
+where
+    F: Fn(Delay) -> Delay,
+{
+    Animation::try_from(&*png)?;
+
+    let mut frame_idx = 0;
+    let mut changed = 0;
+    for chunk in png.chunks.iter_mut() {
+        if chunk.chunk_type().to_string() != "fcTL" {
+            continue;
+        }
+
+        if only.is_none_or(|only| only == frame_idx) {
+            let mut control = FrameControl::try_from(&*chunk)?;
+            let delay = retime(control.delay());
+            control.delay_num = delay.num;
+            control.delay_den = delay.den;
+            *chunk = Chunk::new(chunk.chunk_type().clone(), control.as_bytes());
+            changed += 1;
+        }
+        frame_idx += 1;
+    }
+
+    match only {
+        Some(idx) if changed == 0 => Err(ApngError::NoSuchFrame(idx).into()),
+        _ => Ok(changed),
+    }
+}
+
+/// Rewrite the number of plays stored in the acTL chunk, `0` loops forever.
+pub fn set_plays(png: &mut Png, num_plays: u32) -> Result<()> {
+    let chunk = png
+        .chunks
+        .iter_mut()
+        .find(|chunk| chunk.chunk_type().to_string() == "acTL")
+        .ok_or(ApngError::NotAnimated)?;
+
+    let mut control = AnimationControl::try_from(&*chunk)?;
+    control.num_plays = num_plays;
+    *chunk = Chunk::new(chunk.chunk_type().clone(), control.as_bytes());
+
+    Ok(())
+}
+
+/// Remove frame `frame` from the animation.
+///
+/// The frame's fcTL and fdAT chunks are removed and the remaining ones renumbered. If the frame
+/// is the default image, only its fcTL goes: the IDAT data stays as the image shown by viewers
+/// without APNG support, it just stops being part of the animation.
+pub fn drop_frame(png: &mut Png, frame: usize) -> Result<()> {
+    let animation = Animation::try_from(&*png)?;
+    if animation.frames.len() <= 1 {
+        return Err(ApngError::LastFrame.into());
+    }
+
+    let range = frame_range(png, frame)?;
+    let mut idx = range.end;
+    while idx > range.start {
+        idx -= 1;
+        if matches!(
+            png.chunks[idx].chunk_type().to_string().as_str(),
+            "fcTL" | "fdAT"
+        ) {
+            png.chunks.remove(idx);
+        }
+    }
+
+    renumber(png)?;
+
+    let chunk = png
+        .chunks
+        .iter_mut()
+        .find(|chunk| chunk.chunk_type().to_string() == "acTL")
+        .ok_or(ApngError::NotAnimated)?;
+    let mut control = AnimationControl::try_from(&*chunk)?;
+    control.num_frames -= 1;
+    *chunk = Chunk::new(chunk.chunk_type().clone(), control.as_bytes());
+
+    Ok(())
+}
+
+/// Give the fcTL and fdAT chunks consecutive sequence numbers again, in file order.
+fn renumber(png: &mut Png) -> Result<()> {
+    let mut sequence = 0;
+    for chunk in png.chunks.iter_mut() {
+        let data = match chunk.chunk_type().to_string().as_str() {
+            "fcTL" => {
+                let mut control = FrameControl::try_from(&*chunk)?;
+                control.sequence_number = sequence;
+                control.as_bytes()
+            }
+            "fdAT" if chunk.data().len() >= 4 => {
+                let mut data = Vec::with_capacity(chunk.data().len());
+                put_be_u32(&mut data, sequence);
+                data.extend_from_slice(&chunk.data()[4..]);
+                data
+            }
+            "fdAT" => return Err(ApngError::InvalidControlChunk("fdAT").into()),
+            _ => continue,
+        };
+        *chunk = Chunk::new(chunk.chunk_type().clone(), data);
+        sequence += 1;
+    }
+    Ok(())
+}
+
+fn check_sequence(expected: &mut u32, found: u32) -> Result<()> {
+    if found != *expected {
+        return Err(ApngError::SequenceOutOfOrder {
+            expected: *expected,
+            found,
+        }
+        .into());
+    }
+    *expected += 1;
+    Ok(())
+}
+
+/// Inflate and defilter every frame on its own, without compositing.
+///
+/// Frames are independent until they are composited, so they are decoded in parallel on the
+/// rayon thread pool.
+pub fn decode_frames(png: &Png) -> Result<Vec<Image>> {
+    let animation = Animation::try_from(png)?;
+    let decoder = Decoder::new(png)?;
+
+    animation
+        .frames
+        .par_iter()
+        .map(|frame| decoder.decode(frame.control.width, frame.control.height, &frame.data))
+        .collect()
+}
+
+/// Decode every frame of an animated PNG and composite it onto the full canvas.
+///
+/// Each returned image is what a viewer would show while that frame is on screen, after applying
+/// the frame offsets, blend operation and the dispose operation of the previous frame.
+pub fn render_frames(png: &Png) -> Result<Vec<Image>> {
+    let animation = Animation::try_from(png)?;
+    let images = decode_frames(png)?;
+    let canvas_size = raster::dimensions(png)?;
+    limits::active().check_dimensions(canvas_size.0, canvas_size.1)?;
+
+    let mut canvas = Image::new(canvas_size.0, canvas_size.1);
+    let mut rendered = Vec::with_capacity(animation.frames.len());
+
+    for (idx, (frame, image)) in animation.frames.iter().zip(images).enumerate() {
+        let control = &frame.control;
+        let fits =
+            |offset: u32, len: u32, max: u32| offset.checked_add(len).is_some_and(|end| end <= max);
+        if !fits(control.x_offset, control.width, canvas.width)
+            || !fits(control.y_offset, control.height, canvas.height)
+        {
+            return Err(ApngError::FrameOutOfBounds(control.sequence_number).into());
+        }
+
+        let mut dispose_op = control.dispose_op;
+        if idx == 0 && dispose_op == DisposeOp::Previous {
+            dispose_op = DisposeOp::Background;
+        }
+        let previous = (dispose_op == DisposeOp::Previous).then(|| canvas.clone());
+
+        for y in 0..control.height {
+            for x in 0..control.width {
+                let (cx, cy) = (control.x_offset + x, control.y_offset + y);
+                let source = image.pixel(x, y);
+                let pixel = match control.blend_op {
+                    BlendOp::Source => source,
+                    BlendOp::Over => blend_over(source, canvas.pixel(cx, cy)),
+                };
+                canvas.set_pixel(cx, cy, pixel);
+            }
+        }
+
+        rendered.push(canvas.clone());
+
+        match dispose_op {
+            DisposeOp::None => {}
+            DisposeOp::Background => {
+                for y in 0..control.height {
+                    for x in 0..control.width {
+                        canvas.set_pixel(control.x_offset + x, control.y_offset + y, [0; 4]);
+                    }
+                }
+            }
+            DisposeOp::Previous => canvas = previous.unwrap_or(canvas),
+        }
+    }
+
+    Ok(rendered)
+}
+
+/// Alpha-composite `source` over `destination` (both non-premultiplied RGBA).
+fn blend_over(source: [u8; 4], destination: [u8; 4]) -> [u8; 4] {
+    let sa = source[3] as u32;
+    if sa == 255 {
+        return source;
+    }
+    if sa == 0 {
+        return destination;
+    }
+
+    let da = destination[3] as u32;
+    let out_a = sa * 255 + da * (255 - sa);
+    let mut out = [0u8; 4];
+    for i in 0..3 {
+        let value = source[i] as u32 * sa * 255 + destination[i] as u32 * da * (255 - sa);
+        out[i] = (value / out_a) as u8;
+    }
+    out[3] = (out_a / 255) as u8;
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn chunk(code: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(code).unwrap(), data)
+    }
+
+    fn fctl(seq: u32, width: u32, height: u32, x: u32, y: u32, dispose: u8, blend: u8) -> Chunk {
+        let mut data = vec![];
+        for value in [seq, width, height, x, y] {
+            put_be_u32(&mut data, value);
+        }
+        data.extend_from_slice(&[0, 1, 0, 10, dispose, blend]);
+        chunk("fcTL", data)
+    }
+
+    fn solid(width: u32, height: u32, rgba: [u8; 4]) -> Image {
+        let mut image = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                image.set_pixel(x, y, rgba);
+            }
+        }
+        image
+    }
+
+    fn fdat(seq: u32, image: &Image) -> Chunk {
+        let mut data = vec![];
+        put_be_u32(&mut data, seq);
+        data.extend(raster::compress(image).unwrap());
+        chunk("fdAT", data)
+    }
+
+    fn testing_apng() -> Png {
+        let mut actl = vec![];
+        put_be_u32(&mut actl, 2);
+        put_be_u32(&mut actl, 0);
+
+        Png::from_chunks(vec![
+            chunk("IHDR", raster::ihdr_data(4, 4)),
+            chunk("acTL", actl),
+            fctl(0, 4, 4, 0, 0, 0, 0),
+            chunk(
+                "IDAT",
+                raster::compress(&solid(4, 4, [255, 0, 0, 255])).unwrap(),
+            ),
+            fctl(1, 2, 2, 1, 1, 0, 0),
+            fdat(2, &solid(2, 2, [0, 0, 255, 255])),
+            chunk("IEND", vec![]),
+        ])
+    }
+
+    #[test]
+    fn test_parse_animation() {
+        let animation = Animation::try_from(&testing_apng()).unwrap();
+        assert_eq!(animation.control.num_frames, 2);
+        assert_eq!(animation.frames.len(), 2);
+        assert!(animation.default_image_is_first_frame);
+        assert_eq!(animation.frames[1].control.x_offset, 1);
+    }
+
+    #[test]
+    fn test_render_applies_offsets() {
+        let frames = render_frames(&testing_apng()).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].pixel(1, 1), [255, 0, 0, 255]);
+        assert_eq!(frames[1].pixel(0, 0), [255, 0, 0, 255]);
+        assert_eq!(frames[1].pixel(1, 1), [0, 0, 255, 255]);
+        assert_eq!(frames[1].pixel(3, 3), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_sequence_out_of_order() {
+        let mut png = testing_apng();
+        png.chunks[5] = fdat(7, &solid(2, 2, [0, 0, 255, 255]));
+        assert!(Animation::try_from(&png).is_err());
+    }
+
+    #[test]
+    fn test_not_animated() {
+        let png = raster::encode(&solid(1, 1, [0; 4])).unwrap();
+        assert!(Animation::try_from(&png).is_err());
+    }
+
+    #[test]
+    fn test_build_round_trip() {
+        let frames = vec![
+            solid(3, 2, [255, 0, 0, 255]),
+            solid(3, 2, [0, 255, 0, 255]),
+            solid(3, 2, [0, 0, 255, 255]),
+        ];
+        let delay = Delay::from_str("100ms").unwrap();
+        let png = build(&frames, delay, 0).unwrap();
+
+        let animation = Animation::try_from(&png).unwrap();
+        assert_eq!(animation.control.num_frames, 3);
+        assert_eq!(animation.frames[2].control.sequence_number, 3);
+        assert_eq!(render_frames(&png).unwrap(), frames);
+    }
+
+    #[test]
+    fn test_build_from_pngs_keeps_image_data() {
+        let frames = [
+            raster::encode(&solid(3, 2, [255, 0, 0, 255])).unwrap(),
+            raster::encode(&solid(3, 2, [0, 255, 0, 255])).unwrap(),
+        ];
+        let png = build_from_pngs(&frames, Delay { num: 1, den: 10 }, 0).unwrap();
+
+        let animation = Animation::try_from(&png).unwrap();
+        assert_eq!(animation.frames.len(), 2);
+        assert_eq!(
+            animation.frames[1].data,
+            frames[1].chunk_by_type("IDAT").unwrap().data()
+        );
+        assert_eq!(
+            render_frames(&png).unwrap()[1],
+            solid(3, 2, [0, 255, 0, 255])
+        );
+
+        let mut other = frames[1].clone();
+        other.chunks[0] = chunk("IHDR", {
+            let mut ihdr = raster::ihdr_data(3, 2);
+            ihdr[9] = 2;
+            ihdr
+        });
+        assert!(matches!(
+            build_from_pngs(&[frames[0].clone(), other], Delay { num: 1, den: 10 }, 0)
+                .unwrap_err()
+                .downcast_ref(),
+            Some(ApngError::MismatchedFormat)
+        ));
+    }
+
+    #[test]
+    fn test_build_mismatched_sizes() {
+        let frames = vec![solid(3, 2, [0; 4]), solid(2, 2, [0; 4])];
+        assert!(build(&frames, Delay { num: 1, den: 10 }, 0).is_err());
+        assert!(build(&[], Delay { num: 1, den: 10 }, 0).is_err());
+    }
+
+    #[test]
+    fn test_retime_keeps_frame_data() {
+        let mut png = testing_apng();
+        let before = png.chunk_by_type("fdAT").unwrap().clone();
+
+        let changed = set_delays(&mut png, Some(1), |_| Delay { num: 3, den: 10 }).unwrap();
+        assert_eq!(changed, 1);
+        set_plays(&mut png, 4).unwrap();
+
+        let animation = Animation::try_from(&png).unwrap();
+        assert_eq!(animation.control.num_plays, 4);
+        assert_eq!(
+            animation.frames[0].control.delay(),
+            Delay { num: 1, den: 10 }
+        );
+        assert_eq!(
+            animation.frames[1].control.delay(),
+            Delay { num: 3, den: 10 }
+        );
+        assert_eq!(
+            png.chunk_by_type("fdAT").unwrap().as_bytes(),
+            before.as_bytes()
+        );
+
+        assert!(set_delays(&mut png, Some(9), |delay| delay).is_err());
+    }
+
+    #[test]
+    fn test_drop_frame() {
+        let mut png = testing_apng();
+        drop_frame(&mut png, 1).unwrap();
+        let animation = Animation::try_from(&png).unwrap();
+        assert_eq!(animation.control.num_frames, 1);
+        assert_eq!(animation.frames.len(), 1);
+        assert!(drop_frame(&mut png, 0).is_err());
+
+        // Dropping the default image keeps its IDAT and renumbers the frame after it
+        let mut png = testing_apng();
+        drop_frame(&mut png, 0).unwrap();
+        let animation = Animation::try_from(&png).unwrap();
+        assert!(!animation.default_image_is_first_frame);
+        assert_eq!(animation.frames[0].control.sequence_number, 0);
+        assert!(png.chunk_by_type("IDAT").is_some());
+        assert_eq!(
+            render_frames(&png).unwrap()[0].pixel(1, 1),
+            [0, 0, 255, 255]
+        );
+    }
+
+    #[test]
+    fn test_frame_ranges() {
+        let png = testing_apng();
+        let ranges = frame_ranges(&png).unwrap();
+        assert_eq!(ranges, vec![2..4, 4..6]);
+        assert!(frame_range(&png, 2).is_err());
+    }
+
+    #[test]
+    fn test_parse_delay() {
+        assert_eq!(
+            Delay::from_str("100ms").unwrap(),
+            Delay {
+                num: 100,
+                den: 1000
+            }
+        );
+        assert_eq!(
+            Delay::from_str("1.5s").unwrap(),
+            Delay {
+                num: 1500,
+                den: 1000
+            }
+        );
+        assert_eq!(Delay::from_str("250").unwrap().to_string(), "250ms");
+        assert_eq!(
+            Delay::from_str("120s").unwrap(),
+            Delay {
+                num: 12000,
+                den: 100
+            }
+        );
+        assert!(Delay::from_str("soon").is_err());
+        assert!(Delay::from_str("-5ms").is_err());
+    }
+
+    #[test]
+    fn test_blend_over() {
+        assert_eq!(blend_over([1, 2, 3, 255], [9, 9, 9, 255]), [1, 2, 3, 255]);
+        assert_eq!(blend_over([1, 2, 3, 0], [9, 9, 9, 255]), [9, 9, 9, 255]);
+    }
+}
@@ -1,39 +1,1105 @@
-use clap::{Parser, Subcommand};
+use clap::{ArgGroup, Args, Parser, Subcommand};
 use std::path::PathBuf;
 
+use crate::{
+    apng::Delay,
+    codec::Rendering,
+    compress::Compression,
+    crypto::Cipher,
+    ecc::EccLevel,
+    edit::{Dpi, TextEntry},
+    extension::{Offset, Scale, Stereo},
+    format::Format,
+    phash,
+    position::Position,
+    recipient::Recipient,
+    sink::Sink,
+    span,
+    style::ColorChoice,
+    template::OutputTemplate,
+    text::Charset,
+    transform::Transform,
+};
+
 #[derive(Debug, Parser)]
 #[command(
     name = "pngme",
     version = "0.1.0",
-    about = "A tool for working with PNG files"
+    about = "A tool for working with PNG files",
+    after_help = "A PNG path of '-' reads the image from stdin, and commands that modify it then write the result to stdout. A quoted glob pattern such as 'assets/**/*.png' runs the command on every matching file. An http:// or https:// URL is downloaded and read like a file, commands that modify it need -o"
 )]
 #[command(propagate_version = true)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+
+    /// Trace what happens on stderr: -v for file writes, -vv for parsing and CRC checks, -vvv
+    /// for every chunk
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Don't print warnings, diagnostics or progress bars on stderr
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Print nothing for verify, decode and diff and report the result through the exit code:
+    /// 0 valid/found/identical, 2 invalid PNG, 3 CRC mismatch, 4 not found, 5 I/O error,
+    /// 6 files differ, 1 anything else
+    #[arg(long, global = true)]
+    pub check: bool,
+
+    /// Show the chunk-level changes a command would make without writing anything
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// Write the result to this file and leave the input untouched. For extract and decode this
+    /// is where the raw chunk data or message goes, for apng build where the animation goes. '-'
+    /// means stdout
+    #[arg(
+        short,
+        long,
+        global = true,
+        value_name = "PATH",
+        visible_alias = "out",
+        conflicts_with = "output_template"
+    )]
+    pub output: Option<PathBuf>,
+
+    /// Name written PNGs after this pattern instead of overwriting the input, e.g.
+    /// "{stem}-{hash8}.png". Placeholders: {stem}, {ext}, {name}, {index}, {hash}, {hash8}
+    #[arg(long, global = true, value_name = "TEMPLATE")]
+    pub output_template: Option<OutputTemplate>,
+
+    /// Copy a file to --backup=PATTERN, "{name}.bak" by default, before a command overwrites it.
+    /// Takes the same placeholders as --output-template
+    #[arg(
+        long,
+        global = true,
+        value_name = "PATTERN",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "{name}.bak",
+        env = "PNGME_BACKUP"
+    )]
+    pub backup: Option<OutputTemplate>,
+
+    /// Keep the access and modification times of edited files, so metadata-only edits don't
+    /// look like new content to backup tools and build caches. Permissions are always kept
+    #[arg(long, global = true)]
+    pub preserve_times: bool,
+
+    /// Don't keep backups, even if PNGME_BACKUP asks for them
+    #[arg(long, global = true)]
+    pub no_backup: bool,
+
+    /// Write a JSON summary of every file the command touched to this path
+    #[arg(long, global = true, value_name = "PATH")]
+    pub summary_file: Option<PathBuf>,
+
+    /// Use the suggested chunk type when the one given looks like a typo (e.g. "ihdr")
+    #[arg(long, global = true)]
+    pub fix_case: bool,
+
+    /// Process untrusted images: bound file, chunk, image and decompression sizes and report
+    /// panics as errors
+    #[arg(long, global = true)]
+    pub hardened: bool,
+
+    /// How print, list, info, decode, verify, grep, scan and meta find report results: text,
+    /// json or jsonl (one object per line, per match for searches). list and scan also write csv
+    /// and tsv
+    #[arg(long, global = true, default_value = "text")]
+    pub format: Format,
+
+    /// Color text output: auto (when writing to a terminal and NO_COLOR isn't set), always or
+    /// never
+    #[arg(long, global = true, default_value = "auto", value_name = "WHEN")]
+    pub color: ColorChoice,
+
+    /// Report peak memory use and bytes allocated on stderr when the command finishes
+    #[arg(long, global = true)]
+    pub mem_stats: bool,
+
+    /// Threads for work on many files, such as verifying a directory, and for decoding
+    /// animation frames. All cores by default
+    #[arg(short, long, global = true, value_name = "N")]
+    pub jobs: Option<usize>,
+
+    /// Given a directory, run the command on every PNG beneath it
+    #[arg(short, long, global = true)]
+    pub recursive: bool,
 }
 
-#[derive(Subcommand, Debug)]
+#[derive(Subcommand, Debug, Clone)]
 pub enum Commands {
+    #[command(group(ArgGroup::new("sealing").multiple(true).args(["encrypt", "decoy"])))]
     Encode {
         png_file: PathBuf,
         chunk_type: String,
-        message: String,
+        #[arg(required_unless_present_any = ["file", "add"])]
+        message: Option<String>,
+
+        /// Embed the contents of this file, which may be binary, instead of a message. '-'
+        /// reads stdin
+        #[arg(long, value_name = "PATH", conflicts_with = "message")]
+        file: Option<PathBuf>,
+
+        /// Pack this file into an archive embedded instead of a message, repeat it to add more.
+        /// decode lists them with --list and restores them with --extract or --out-dir
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["message", "file"])]
+        add: Vec<PathBuf>,
+
+        /// Spread the message over `png_file` and these additional images
+        #[arg(long, num_args = 1.., value_name = "PNG_FILE", conflicts_with_all = ["at", "before", "after"])]
+        span: Vec<PathBuf>,
+
+        /// Encrypt the message under a key derived from a password, with the cipher chosen by
+        /// --cipher
+        #[arg(long, conflicts_with = "span")]
+        encrypt: bool,
+
+        /// Passphrase that reveals the real message, with --encrypt or --decoy. Asked for on
+        /// the terminal when left out, which keeps it out of shell history and `ps`
+        #[arg(long, requires = "sealing", conflicts_with = "span")]
+        password: Option<String>,
+
+        /// Read the passphrase from this file instead. Without either, PNGME_PASSWORD is used
+        /// if set
+        #[arg(
+            long,
+            value_name = "FILE",
+            requires = "sealing",
+            conflicts_with_all = ["password", "span"]
+        )]
+        password_file: Option<PathBuf>,
+
+        /// Innocuous message revealed by --decoy-password instead of the real one
+        #[arg(long, conflicts_with = "span")]
+        decoy: Option<String>,
+
+        /// Passphrase that reveals the decoy message, asked for on the terminal when left out
+        #[arg(long, requires = "decoy", conflicts_with = "span")]
+        decoy_password: Option<String>,
+
+        /// Encrypt the message to this age (age1...) or SSH public key instead of a passphrase.
+        /// Repeat to let any of several keys decrypt it
+        #[arg(long, value_name = "PUBLIC_KEY", conflicts_with_all = ["encrypt", "decoy", "span"])]
+        recipient: Vec<Recipient>,
+
+        /// Store an HMAC-SHA256 of the payload under this key, so decode can tell if it was
+        /// altered
+        #[arg(long, value_name = "KEY", conflicts_with = "span")]
+        hmac_key: Option<String>,
+
+        /// Read the HMAC key from this file instead of --hmac-key
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["hmac_key", "span"])]
+        key_file: Option<PathBuf>,
+
+        /// Cipher used with --encrypt or --decoy: aes-gcm (default) or chacha20
+        #[arg(long, requires = "sealing")]
+        cipher: Option<Cipher>,
+
+        /// Argon2id memory cost in KiB when deriving the key, 19456 by default
+        #[arg(long, value_name = "KIB", requires = "sealing")]
+        kdf_memory: Option<u32>,
+
+        /// Argon2id iterations when deriving the key, 2 by default
+        #[arg(long, value_name = "N", requires = "sealing")]
+        kdf_iterations: Option<u32>,
+
+        /// Compress the message before encrypting and embedding it: zlib or zstd. decode
+        /// decompresses it again
+        #[arg(long, value_name = "ALGORITHM", conflicts_with = "span")]
+        compress: Option<Compression>,
+
+        /// Protect the stored message with Reed-Solomon parity so that it survives a few
+        /// damaged bytes: low, medium or high. decode repairs what it can
+        #[arg(long, value_name = "LEVEL", conflicts_with = "span")]
+        ecc: Option<EccLevel>,
+
+        /// Split a message larger than this many bytes over consecutive chunks, which decode
+        /// joins again
+        #[arg(
+            long,
+            value_name = "BYTES",
+            default_value_t = span::CHUNK_SIZE,
+            value_parser = clap::value_parser!(u32).range(1..=i32::MAX as i64),
+            conflicts_with = "span"
+        )]
+        chunk_size: u32,
+
+        /// Store the message in this slot (0-based) among the messages of the chunk type,
+        /// replacing the one there. The slot after the last message adds a new one
+        #[arg(long, conflicts_with_all = ["span", "frame", "at", "before", "after"])]
+        slot: Option<usize>,
+
+        /// Replace the messages of this chunk type already in the image. Without --overwrite,
+        /// --append or --slot, encode refuses to touch an image that has some
+        #[arg(long, conflicts_with_all = ["append", "slot"])]
+        overwrite: bool,
+
+        /// Keep the messages of this chunk type already in the image and add this one in the
+        /// next slot
+        #[arg(long, conflicts_with = "slot")]
+        append: bool,
+
+        /// Store the message next to this frame of an animated PNG (0-based)
+        #[arg(long, conflicts_with_all = ["span", "at", "before", "after"])]
+        frame: Option<usize>,
+
+        /// Encodings applied to the message before embedding, in order: gzip, base64, hex, reverse
+        #[arg(
+            long,
+            value_delimiter = ',',
+            value_name = "TRANSFORMS",
+            conflicts_with = "span"
+        )]
+        transform: Vec<Transform>,
+
+        #[command(flatten)]
+        placement: Placement,
     },
     Decode {
         png_file: PathBuf,
         chunk_type: String,
+
+        /// Reassemble a message spread over `png_file` and these additional images
+        #[arg(long, num_args = 1.., value_name = "PNG_FILE")]
+        span: Vec<PathBuf>,
+
+        /// Passphrase of an encrypted message. Taken from PNGME_PASSWORD when set, and asked
+        /// for on the terminal otherwise
+        #[arg(long)]
+        password: Option<String>,
+
+        /// Read the passphrase from this file instead
+        #[arg(long, value_name = "FILE", conflicts_with = "password")]
+        password_file: Option<PathBuf>,
+
+        /// age identity file or unencrypted SSH private key, for messages encrypted with
+        /// --recipient
+        #[arg(long, value_name = "FILE")]
+        identity: Option<PathBuf>,
+
+        /// Key the message's HMAC was made with, asked for on the terminal when left out.
+        /// Decoding fails if the message was altered
+        #[arg(long, value_name = "KEY", conflicts_with = "span")]
+        hmac_key: Option<String>,
+
+        /// Read the HMAC key from this file instead of --hmac-key
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["hmac_key", "span"])]
+        key_file: Option<PathBuf>,
+
+        /// Character set of the message: auto, utf8 or latin1
+        #[arg(long, default_value = "auto")]
+        charset: Charset,
+
+        /// Read the message stored next to this frame of an animated PNG (0-based)
+        #[arg(long, conflicts_with = "span")]
+        frame: Option<usize>,
+
+        /// Read the message in this slot (0-based) when several share the chunk type
+        #[arg(long, default_value_t = 0, conflicts_with = "span")]
+        slot: usize,
+
+        /// Print every message of the chunk type, with its slot
+        #[arg(
+            long,
+            conflicts_with_all = ["slot", "span", "pipe_to", "send_to", "write_to", "raw", "hex", "base64"]
+        )]
+        all: bool,
+
+        /// Encodings applied when the message was embedded, undone in reverse order
+        #[arg(
+            long,
+            value_delimiter = ',',
+            value_name = "TRANSFORMS",
+            conflicts_with = "span"
+        )]
+        transform: Vec<Transform>,
+
+        #[command(flatten)]
+        sink: SinkArgs,
+
+        #[command(flatten)]
+        rendering: RenderingArgs,
+
+        /// Restore a message embedded with encode --file under its original name and
+        /// modification time in this directory, after checking its hash
+        #[arg(
+            long,
+            value_name = "DIR",
+            conflicts_with_all = ["pipe_to", "send_to", "write_to", "raw", "hex", "base64"]
+        )]
+        out_dir: Option<PathBuf>,
+
+        /// Show the names, sizes and modification times of the files in the message
+        #[arg(
+            long,
+            conflicts_with_all = ["all", "out_dir", "extract", "pipe_to", "send_to", "write_to", "raw", "hex", "base64"]
+        )]
+        list: bool,
+
+        /// Restore only this file of the message, repeat it for more. They go to --out-dir, or
+        /// the current directory
+        #[arg(long, value_name = "NAME", conflicts_with = "all")]
+        extract: Vec<String>,
+    },
+
+    /// List the messages hidden in images: chunk type, slot, size, how each is protected and
+    /// when it was embedded
+    Messages {
+        /// PNG files, directories with -r, or glob patterns such as 'assets/**/*.png'
+        #[arg(value_name = "PNG_FILE", required = true)]
+        png_files: Vec<PathBuf>,
     },
     Remove {
         png_file: PathBuf,
+
+        #[arg(required_unless_present = "index")]
+        chunk_type: Option<String>,
+
+        /// Remove every chunk of this type instead of only the first
+        #[arg(long, requires = "chunk_type", conflicts_with = "index")]
+        all: bool,
+
+        /// Remove the chunk at this index (0-based, as shown by `list`), or the chunk at this
+        /// index among those of `chunk_type`
+        #[arg(long)]
+        index: Option<usize>,
+    },
+
+    /// Swap the data of an existing chunk without moving it
+    Replace {
+        png_file: PathBuf,
         chunk_type: String,
+        message: String,
+
+        /// Which chunk of that type to replace (0-based)
+        #[arg(long, default_value_t = 0)]
+        index: usize,
+    },
+
+    /// Remove all ancillary chunks, leaving only what is needed to display the image and any
+    /// animation
+    Strip {
+        /// PNG files, directories with -r, or glob patterns such as 'assets/**/*.png'
+        #[arg(value_name = "PNG_FILE", required = true)]
+        png_files: Vec<PathBuf>,
+
+        /// Ancillary chunk types to keep, e.g. tRNS,gAMA
+        #[arg(long, value_delimiter = ',', value_name = "CHUNK_TYPES")]
+        keep: Vec<String>,
+    },
+
+    /// Make several edits in one pass, reading and writing each file once. Edits apply in the
+    /// order listed below, whatever the order on the command line
+    #[command(group(
+        ArgGroup::new("edits")
+            .required(true)
+            .multiple(true)
+            .args(["strip", "strip_text", "remove", "set_text", "set_dpi", "encode"])
+    ))]
+    Edit {
+        /// PNG files, directories with -r, or glob patterns such as 'assets/**/*.png'
+        #[arg(value_name = "PNG_FILE", required = true)]
+        png_files: Vec<PathBuf>,
+
+        /// Remove all ancillary chunks, like strip
+        #[arg(long)]
+        strip: bool,
+
+        /// Ancillary chunk types --strip keeps, e.g. tRNS,gAMA
+        #[arg(
+            long,
+            value_delimiter = ',',
+            value_name = "CHUNK_TYPES",
+            requires = "strip"
+        )]
+        keep: Vec<String>,
+
+        /// Remove all tEXt, zTXt and iTXt chunks
+        #[arg(long)]
+        strip_text: bool,
+
+        /// Remove every chunk of this type, can be repeated
+        #[arg(long, value_name = "CHUNK_TYPE")]
+        remove: Vec<String>,
+
+        /// Set a text chunk, replacing any with the same keyword, can be repeated
+        #[arg(long, value_name = "KEYWORD=TEXT")]
+        set_text: Vec<TextEntry>,
+
+        /// Set the pixel density in pHYs, in dots per inch
+        #[arg(long, value_name = "DPI")]
+        set_dpi: Option<Dpi>,
+
+        /// Append a chunk holding a message, like encode, can be repeated
+        #[arg(long, num_args = 2, value_names = ["CHUNK_TYPE", "MESSAGE"])]
+        encode: Vec<String>,
+    },
+
+    /// Apply a TOML metadata template to every file: text such as Author, Copyright and
+    /// Software, the pHYs density and what happens to tIME
+    ///
+    /// Template keys: replace_text (remove other text first), dpi, time (keep, now, modified
+    /// or remove) and a [text] table of keywords and their text
+    Stamp {
+        /// The metadata template
+        #[arg(long, value_name = "FILE")]
+        template: PathBuf,
+
+        /// PNG files, directories with -r, or glob patterns such as 'assets/**/*.png'
+        #[arg(value_name = "PNG_FILE", required = true)]
+        png_files: Vec<PathBuf>,
+    },
+
+    /// Prepare images for the web: strip all but color, transparency and animation chunks,
+    /// recompress the image data and put chunks in spec order
+    WebPrep {
+        /// PNG files or directories to search for them
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// More ancillary chunk types to keep, e.g. pHYs,tEXt
+        #[arg(long, value_delimiter = ',', value_name = "CHUNK_TYPES")]
+        keep: Vec<String>,
+
+        /// Leave the image data as it is
+        #[arg(long)]
+        no_optimize: bool,
+
+        /// Leave chunks where they are
+        #[arg(long)]
+        no_reorder: bool,
+    },
+
+    /// Prepare images for archiving: record their source, pngme version and time, then seal
+    /// them with a SHA-256 hash that `verify` checks
+    ArchivePrep {
+        /// PNG files or directories to search for them
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+
+        #[arg(long)]
+        author: Option<String>,
+
+        #[arg(long)]
+        comment: Option<String>,
+
+        /// Don't set the tIME chunk
+        #[arg(long)]
+        no_timestamp: bool,
+
+        /// Don't add a seal
+        #[arg(long)]
+        no_seal: bool,
+    },
+
+    /// Describe what a chunk type is for, where it may appear and how its data is laid out
+    Explain { chunk_type: String },
+
+    /// Flag PNGs that may hide data: unknown or private chunks, data after IEND, oversized
+    /// ancillary chunks and text that looks encoded rather than written
+    Scan {
+        /// Files, directories or glob patterns to scan
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Also write the files, their chunks and findings to this SQLite database
+        #[arg(long, value_name = "FILE")]
+        db: Option<PathBuf>,
+
+        /// Also verify the files and write a report of the failures, findings and statistics,
+        /// as HTML or Markdown depending on the extension of FILE
+        #[arg(long, value_name = "FILE")]
+        report: Option<PathBuf>,
+    },
+
+    /// Summarize a tree of PNGs: how often each chunk type appears and how many bytes it takes,
+    /// the average IDAT compression ratio, color types and the largest files
+    Stats {
+        /// Files, directories or glob patterns to summarize
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// How many of the largest files to list
+        #[arg(long, value_name = "N", default_value_t = 10)]
+        top: usize,
+
+        /// Also write the files, their chunks and hashes to this SQLite database
+        #[arg(long, value_name = "FILE")]
+        db: Option<PathBuf>,
+    },
+
+    /// Find PNGs that hold the same image under different metadata, grouped with the bytes
+    /// that keeping only one of each would save
+    Dupes {
+        /// Files, directories or glob patterns to compare
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Compare decoded pixels instead of the compressed image data, to also catch the same
+        /// image compressed differently. Slower
+        #[arg(long)]
+        pixels: bool,
+    },
+
+    /// Print a perceptual hash of each image, which stays close for images that look alike
+    /// after resizing or recompression
+    Phash {
+        /// Files, directories or glob patterns to hash
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Group images whose hashes are within --threshold of each other instead
+        #[arg(long)]
+        near_dupes: bool,
+
+        /// Differing bits, out of 64, up to which two images count as near duplicates
+        #[arg(long, value_name = "BITS", default_value_t = phash::DEFAULT_THRESHOLD, requires = "near_dupes")]
+        threshold: u32,
+    },
+
+    /// Search the text of tEXt, zTXt and iTXt chunks for a regular expression
+    Grep {
+        pattern: String,
+
+        /// Files or directories to search
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Match case-insensitively
+        #[arg(short, long)]
+        ignore_case: bool,
+
+        /// Character set of tEXt/zTXt values: auto, utf8 or latin1
+        #[arg(long, default_value = "auto")]
+        charset: Charset,
+    },
+
+    /// Compare the chunk sequences of two PNGs
+    Diff { a_file: PathBuf, b_file: PathBuf },
+
+    /// Replace a broken embedded color profile (iCCP) with an sRGB chunk
+    FixColor {
+        /// PNG files, directories with -r, or glob patterns such as 'assets/**/*.png'
+        #[arg(value_name = "PNG_FILE", required = true)]
+        png_files: Vec<PathBuf>,
+    },
+
+    /// Copy chunks of the given types from one PNG into another
+    CopyChunks {
+        src_file: PathBuf,
+        dst_file: PathBuf,
+
+        /// Chunk types to copy, e.g. iCCP,tEXt
+        #[arg(
+            long,
+            value_delimiter = ',',
+            value_name = "CHUNK_TYPES",
+            required = true
+        )]
+        types: Vec<String>,
+
+        /// Also copy unsafe-to-copy chunks when the images' color setup differs
+        #[arg(long)]
+        allow_unsafe: bool,
     },
 
+    /// Remove or blank GPS coordinates, timestamps, serial numbers and author fields
+    Scrub {
+        /// PNG files, directories with -r, or glob patterns such as 'assets/**/*.png'
+        #[arg(value_name = "PNG_FILE", required = true)]
+        png_files: Vec<PathBuf>,
+    },
+
+    /// Show the chunks as a table, with a preview of their data
     Print {
+        /// PNG files, directories with -r, or glob patterns such as 'assets/**/*.png'
+        #[arg(value_name = "PNG_FILE", required = true)]
+        png_files: Vec<PathBuf>,
+    },
+
+    /// Append a chunk whose data comes from a file or a hex/base64 literal
+    #[command(group(
+        ArgGroup::new("source")
+            .required(true)
+            .args(["data_file", "hex", "base64"])
+    ))]
+    ImportChunk {
+        png_file: PathBuf,
+        chunk_type: String,
+
+        /// File holding the chunk data
+        data_file: Option<PathBuf>,
+
+        /// Chunk data as hex digits, e.g. 89ab01
+        #[arg(long)]
+        hex: Option<String>,
+
+        /// Chunk data as base64
+        #[arg(long)]
+        base64: Option<String>,
+
+        /// Encodings applied to the data before embedding, in order: gzip, base64, hex, reverse
+        #[arg(long, value_delimiter = ',', value_name = "TRANSFORMS")]
+        transform: Vec<Transform>,
+    },
+
+    /// Wrap the contents of a file in a chunk and insert it at a structural position
+    Inject {
         png_file: PathBuf,
+        chunk_type: String,
+        data_file: PathBuf,
+
+        /// after-ihdr, before-plte, before-idat, after-idat, before-iend or end
+        #[arg(long, default_value = "before-iend", conflicts_with_all = ["at", "before", "after"])]
+        position: Position,
+
+        #[command(flatten)]
+        placement: Placement,
+    },
+
+    /// Dump the data of a chunk, without length, type or CRC, to a file
+    Extract {
+        png_file: PathBuf,
+        chunk_type: String,
+
+        /// Which chunk of that type to extract (0-based)
+        #[arg(long, default_value_t = 0)]
+        index: usize,
+
+        /// Encodings applied when the data was embedded, undone in reverse order
+        #[arg(long, value_delimiter = ',', value_name = "TRANSFORMS")]
+        transform: Vec<Transform>,
+    },
+
+    /// Show the data of a chunk as offset, hex and ASCII columns
+    Hexdump {
+        png_file: PathBuf,
+        chunk_type: String,
+
+        /// Which chunk of that type to dump (0-based)
+        #[arg(long, default_value_t = 0)]
+        index: usize,
+    },
+
+    /// Summarize the image: dimensions, pixel format, compressed data and notable chunks
+    Info {
+        /// PNG files, directories with -r, or glob patterns such as 'assets/**/*.png'
+        #[arg(value_name = "PNG_FILE", required = true)]
+        png_files: Vec<PathBuf>,
+    },
+
+    /// List every chunk with its offset, length, CRC and property flags
+    List {
+        /// PNG files, directories with -r, or glob patterns such as 'assets/**/*.png'
+        #[arg(value_name = "PNG_FILE", required = true)]
+        png_files: Vec<PathBuf>,
     },
 
     Verify {
+        /// PNG files, directories with -r, or glob patterns such as 'assets/**/*.png'
+        #[arg(value_name = "PNG_FILE", required = true)]
+        png_files: Vec<PathBuf>,
+
+        /// Write each failing chunk's raw bytes and a JSON note on what is wrong with it into
+        /// this directory, as a minimal reproducer for bug reports
+        #[arg(long, value_name = "DIR")]
+        dump_failing_chunk: Option<PathBuf>,
+    },
+
+    /// Sign the critical chunks with an Ed25519 key, storing the signature in a private siGN
+    /// chunk. Ancillary chunks can change without breaking it
+    Sign {
+        /// PEM encoded PKCS#8 private key, e.g. from `openssl genpkey -algorithm ed25519`
+        #[arg(long, value_name = "FILE")]
+        key: PathBuf,
+
+        /// PNG files, directories with -r, or glob patterns such as 'assets/**/*.png'
+        #[arg(value_name = "PNG_FILE", required = true)]
+        png_files: Vec<PathBuf>,
+    },
+
+    /// Check the siGN signature of images against an Ed25519 public key
+    VerifySignature {
+        /// PEM encoded public key, e.g. from `openssl pkey -pubout`
+        #[arg(long, value_name = "FILE")]
+        pubkey: PathBuf,
+
+        /// PNG files, directories with -r, or glob patterns such as 'assets/**/*.png'
+        #[arg(value_name = "PNG_FILE", required = true)]
+        png_files: Vec<PathBuf>,
+    },
+
+    /// Run the jobs listed in a TOML manifest, writing nothing unless they all succeed
+    ///
+    /// Each [[job]] table names the command in `op`, the PNG in `file` (or `files`), other
+    /// positional arguments in `args` and options by their long name, e.g.
+    /// op = "strip", file = "a.png", keep = ["tRNS"]
+    Run {
+        /// The manifest of jobs to run
+        manifest: PathBuf,
+    },
+
+    /// Serve queries over a Unix socket, keeping recently used files parsed in memory
+    ///
+    /// Each line sent is a JSON request answered by one JSON line, e.g.
+    /// {"op": "info", "path": "image.png"}. Ops: info, chunks, decode (with chunk_type), stats.
+    Daemon {
+        /// Path of the Unix socket to listen on
+        #[arg(long, default_value = "pngme.sock")]
+        socket: PathBuf,
+
+        /// How many parsed files to keep in memory
+        #[arg(long, default_value_t = 16)]
+        cache_size: usize,
+    },
+
+    /// Run round-trips on a generated image to check this build works on this platform
+    Selftest,
+
+    /// Query text metadata (tEXt, zTXt and iTXt chunks)
+    Meta {
+        #[command(subcommand)]
+        command: MetaCommands,
+    },
+
+    /// Inspect hidden payloads
+    Payload {
+        #[command(subcommand)]
+        command: PayloadCommands,
+    },
+
+    /// Work with animated PNGs
+    Apng {
+        #[command(subcommand)]
+        command: ApngCommands,
+    },
+}
+
+/// Where a command keeps the PNGs it works on.
+pub enum Inputs<'a> {
+    One(&'a mut PathBuf),
+    /// Commands that accept several files, each run on its own.
+    Many(&'a mut Vec<PathBuf>),
+}
+
+impl Commands {
+    /// The PNGs a command works on, for commands that take them one at a time.
+    pub fn inputs_mut(&mut self) -> Option<Inputs<'_>> {
+        if let Commands::Strip { png_files, .. }
+        | Commands::Edit { png_files, .. }
+        | Commands::Stamp { png_files, .. }
+        | Commands::Sign { png_files, .. }
+        | Commands::VerifySignature { png_files, .. }
+        | Commands::FixColor { png_files }
+        | Commands::Scrub { png_files }
+        | Commands::Print { png_files }
+        | Commands::Info { png_files }
+        | Commands::List { png_files }
+        | Commands::Messages { png_files }
+        | Commands::Verify { png_files, .. } = self
+        {
+            return Some(Inputs::Many(png_files));
+        }
+
+        let png_file = match self {
+            Commands::Encode { png_file, .. }
+            | Commands::Decode { png_file, .. }
+            | Commands::Remove { png_file, .. }
+            | Commands::Replace { png_file, .. }
+            | Commands::ImportChunk { png_file, .. }
+            | Commands::Inject { png_file, .. }
+            | Commands::Extract { png_file, .. }
+            | Commands::Hexdump { png_file, .. } => Some(png_file),
+            Commands::Meta { command } => match command {
+                MetaCommands::Set { png_file, .. } | MetaCommands::Unset { png_file, .. } => {
+                    Some(png_file)
+                }
+                MetaCommands::Find { .. } => None,
+            },
+            Commands::Payload { command } => match command {
+                PayloadCommands::Map { png_file } => Some(png_file),
+            },
+            Commands::Apng { command } => match command {
+                ApngCommands::Extract { png_file, .. }
+                | ApngCommands::ToGif { png_file, .. }
+                | ApngCommands::ToSheet { png_file, .. }
+                | ApngCommands::SetDelay { png_file, .. }
+                | ApngCommands::DropFrame { png_file, .. }
+                | ApngCommands::SetLoops { png_file, .. }
+                | ApngCommands::Retime { png_file, .. } => Some(png_file),
+                ApngCommands::Build { .. } => None,
+            },
+            _ => None,
+        };
+        png_file.map(Inputs::One)
+    }
+}
+
+/// Where exactly a new chunk goes, relative to the existing ones.
+#[derive(Args, Debug, Clone)]
+#[group(multiple = false)]
+pub struct Placement {
+    /// Insert at this chunk index (0-based)
+    #[arg(long)]
+    pub at: Option<usize>,
+
+    /// Insert before the first chunk of this type
+    #[arg(long, value_name = "CHUNK_TYPE")]
+    pub before: Option<String>,
+
+    /// Insert after the last chunk of this type
+    #[arg(long, value_name = "CHUNK_TYPE")]
+    pub after: Option<String>,
+}
+
+impl Placement {
+    pub fn position(&self) -> Option<Position> {
+        match (self.at, &self.before, &self.after) {
+            (Some(index), _, _) => Some(Position::At(index)),
+            (_, Some(chunk_type), _) => Some(Position::Before(chunk_type.clone())),
+            (_, _, Some(chunk_type)) => Some(Position::After(chunk_type.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// Where a decoded message goes instead of being printed, as raw bytes.
+#[derive(Args, Debug, Clone)]
+#[group(multiple = false)]
+pub struct SinkArgs {
+    /// Feed the message to this shell command's stdin, e.g. "tar xz"
+    #[arg(long, value_name = "COMMAND")]
+    pub pipe_to: Option<String>,
+
+    /// Send the message to a TCP server at HOST:PORT
+    #[arg(long, value_name = "ADDRESS")]
+    pub send_to: Option<String>,
+
+    /// Write the message to this file or named pipe
+    #[arg(long, value_name = "PATH")]
+    pub write_to: Option<PathBuf>,
+}
+
+impl SinkArgs {
+    pub fn sink(&self) -> Option<Sink> {
+        match (&self.pipe_to, &self.send_to, &self.write_to) {
+            (Some(command), _, _) => Some(Sink::Command(command.clone())),
+            (_, Some(address), _) => Some(Sink::Tcp(address.clone())),
+            (_, _, Some(path)) => Some(Sink::Path(path.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// How decode prints a message that may not be text.
+#[derive(Args, Debug, Clone)]
+#[group(multiple = false)]
+pub struct RenderingArgs {
+    /// Write the message bytes to stdout unchanged
+    #[arg(long, conflicts_with_all = ["pipe_to", "send_to", "write_to"])]
+    pub raw: bool,
+
+    /// Print the message as hex digits
+    #[arg(long, conflicts_with_all = ["pipe_to", "send_to", "write_to"])]
+    pub hex: bool,
+
+    /// Print the message as base64
+    #[arg(long, conflicts_with_all = ["pipe_to", "send_to", "write_to"])]
+    pub base64: bool,
+}
+
+impl RenderingArgs {
+    pub fn rendering(&self) -> Option<Rendering> {
+        match (self.raw, self.hex, self.base64) {
+            (true, _, _) => Some(Rendering::Raw),
+            (_, true, _) => Some(Rendering::Hex),
+            (_, _, true) => Some(Rendering::Base64),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ApngCommands {
+    /// Write every frame of an animation as a standalone PNG
+    Extract { png_file: PathBuf, out_dir: PathBuf },
+
+    /// Assemble an animated PNG from still frames (files or directories of PNGs)
+    Build {
+        #[arg(required = true)]
+        frames: Vec<PathBuf>,
+
+        /// How long each frame is shown, e.g. 100ms or 1.5s
+        #[arg(long, default_value = "100ms")]
+        delay: Delay,
+
+        /// Number of times to play the animation, 0 loops forever
+        #[arg(long, default_value_t = 0)]
+        loops: u32,
+    },
+
+    /// Convert an animated PNG into an animated GIF
+    ToGif {
+        png_file: PathBuf,
+        gif_file: PathBuf,
+    },
+
+    /// Compose the frames into a horizontal sprite sheet with a JSON timing manifest
+    ToSheet {
+        png_file: PathBuf,
+        sheet_file: PathBuf,
+
+        /// Where to write the manifest, defaults to the sheet path with a .json extension
+        #[arg(long)]
+        manifest: Option<PathBuf>,
+    },
+
+    /// Set how long frames are shown
+    SetDelay {
+        png_file: PathBuf,
+
+        /// New delay, e.g. 100ms or 1.5s
+        delay: Delay,
+
+        /// Only change this frame (0-based)
+        #[arg(long)]
+        frame: Option<usize>,
+    },
+
+    /// Remove a frame (0-based) from the animation
+    DropFrame { png_file: PathBuf, frame: usize },
+
+    /// Set how many times the animation plays, 0 loops forever
+    SetLoops { png_file: PathBuf, loops: u32 },
+
+    /// Change frame delays and the loop count without touching frame data
+    #[command(group(
+        ArgGroup::new("change")
+            .required(true)
+            .multiple(true)
+            .args(["delay", "speed", "loops"])
+    ))]
+    Retime {
+        png_file: PathBuf,
+
+        /// New delay for the frames, e.g. 100ms or 1.5s
+        #[arg(long)]
+        delay: Option<Delay>,
+
+        /// Play faster (2.0) or slower (0.5) by scaling the existing delays
+        #[arg(long, conflicts_with = "delay")]
+        speed: Option<f64>,
+
+        /// Only change this frame (0-based)
+        #[arg(long)]
+        frame: Option<usize>,
+
+        /// Number of times to play the animation, 0 loops forever
+        #[arg(long)]
+        loops: Option<u32>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum PayloadCommands {
+    /// Show which byte ranges of the file are reserved by pngme payloads
+    Map { png_file: PathBuf },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum MetaCommands {
+    /// List files under a directory whose text metadata matches
+    #[command(group(
+        ArgGroup::new("filter")
+            .required(true)
+            .multiple(true)
+            .args(["keyword", "value"])
+    ))]
+    Find {
+        dir: PathBuf,
+
+        /// Keyword to match, `*` and `?` wildcards are allowed
+        #[arg(long)]
+        keyword: Option<String>,
+
+        /// Value to match, `*` and `?` wildcards are allowed
+        #[arg(long)]
+        value: Option<String>,
+
+        /// Character set of tEXt/zTXt values: auto, utf8 or latin1
+        #[arg(long, default_value = "auto")]
+        charset: Charset,
+    },
+
+    /// Set the image offset (oFFs), physical scale (sCAL) or stereo layout (sTER)
+    #[command(group(
+        ArgGroup::new("values")
+            .required(true)
+            .multiple(true)
+            .args(["offset", "scale", "stereo"])
+    ))]
+    Set {
         png_file: PathBuf,
+
+        /// Offset on the page as X,Y with an optional unit: px (default) or um
+        #[arg(long, allow_hyphen_values = true)]
+        offset: Option<Offset>,
+
+        /// Size of a pixel as WIDTH,HEIGHT with an optional unit: m (default) or rad
+        #[arg(long)]
+        scale: Option<Scale>,
+
+        /// Side-by-side stereo layout: cross or diverging
+        #[arg(long)]
+        stereo: Option<Stereo>,
     },
+
+    /// Remove the image offset (oFFs), physical scale (sCAL) or stereo layout (sTER)
+    #[command(group(
+        ArgGroup::new("values")
+            .required(true)
+            .multiple(true)
+            .args(["offset", "scale", "stereo"])
+    ))]
+    Unset {
+        png_file: PathBuf,
+
+        #[arg(long)]
+        offset: bool,
+
+        #[arg(long)]
+        scale: bool,
+
+        #[arg(long)]
+        stereo: bool,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_span_refuses_sealing() {
+        // encode_span stores the message as it is, so these would embed it in the clear
+        for flags in [
+            &["--encrypt"][..],
+            &["--encrypt", "--password-file", "pw.txt"],
+            &["--decoy", "nothing here", "--decoy-password", "1234"],
+        ] {
+            let args = ["pngme", "encode", "a.png", "ruSt", "top secret message"]
+                .iter()
+                .chain(flags)
+                .chain(&["--span", "b.png"]);
+            assert!(Cli::try_parse_from(args).is_err(), "{:?}", flags);
+        }
+        assert!(
+            Cli::try_parse_from(["pngme", "encode", "a.png", "ruSt", "hi", "--span", "b.png"])
+                .is_ok()
+        );
+    }
 }
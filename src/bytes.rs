@@ -0,0 +1,97 @@
+//! Byte order helpers.
+//!
+//! PNG stores every multi-byte integer big-endian. All integer (de)serialization in the crate
+//! goes through these functions, so nothing depends on the byte order or pointer width of the
+//! host.
+
+/// Read a big-endian `u16` from the first two bytes. Panics if `bytes` is shorter.
+pub fn be_u16(bytes: &[u8]) -> u16 {
+    u16::from_be_bytes([bytes[0], bytes[1]])
+}
+
+/// Read a big-endian `u32` from the first four bytes. Panics if `bytes` is shorter.
+pub fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Read a big-endian `u64` from the first eight bytes. Panics if `bytes` is shorter.
+pub fn be_u64(bytes: &[u8]) -> u64 {
+    u64::from_be_bytes(bytes[..8].try_into().expect("8 bytes"))
+}
+
+/// Read a big-endian `u32` at `offset`, `None` if it runs past the end.
+pub fn read_be_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    bytes.get(offset..offset.checked_add(4)?).map(be_u32)
+}
+
+pub fn put_be_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+pub fn put_be_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+pub fn put_be_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+/// Read a little-endian `u16` from the first two bytes. Panics if `bytes` is shorter.
+pub fn le_u16(bytes: &[u8]) -> u16 {
+    u16::from_le_bytes([bytes[0], bytes[1]])
+}
+
+/// Read a little-endian `u32` from the first four bytes. Panics if `bytes` is shorter.
+pub fn le_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// A `u32` length read from a file as a `usize`, `None` where it doesn't fit (16-bit targets).
+pub fn to_usize(value: u32) -> Option<usize> {
+    usize::try_from(value).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Expected values are spelled out byte by byte so the tests mean the same on every target.
+
+    #[test]
+    fn test_big_endian() {
+        assert_eq!(be_u16(&[0x12, 0x34]), 0x1234);
+        assert_eq!(be_u32(&[0x12, 0x34, 0x56, 0x78, 0xff]), 0x1234_5678);
+        assert_eq!(be_u64(&[0, 0, 0, 1, 0x12, 0x34, 0x56, 0x78]), 0x1_1234_5678);
+
+        let mut out = vec![];
+        put_be_u16(&mut out, 0x1234);
+        put_be_u32(&mut out, 0x89ab_cdef);
+        put_be_u64(&mut out, 0x0102);
+        assert_eq!(
+            out,
+            [0x12, 0x34, 0x89, 0xab, 0xcd, 0xef, 0, 0, 0, 0, 0, 0, 0x01, 0x02]
+        );
+    }
+
+    #[test]
+    fn test_little_endian() {
+        assert_eq!(le_u16(&[0x34, 0x12]), 0x1234);
+        assert_eq!(le_u32(&[0x78, 0x56, 0x34, 0x12]), 0x1234_5678);
+    }
+
+    #[test]
+    fn test_checked_reads() {
+        let bytes = [0, 0, 0, 42, 7];
+        assert_eq!(read_be_u32(&bytes, 0), Some(42));
+        assert_eq!(read_be_u32(&bytes, 2), None);
+        assert_eq!(read_be_u32(&bytes, usize::MAX), None);
+    }
+
+    #[test]
+    fn test_png_signature_fields() {
+        // Length and type of the IHDR chunk as they appear in every PNG
+        let bytes = [0x00, 0x00, 0x00, 0x0d, b'I', b'H', b'D', b'R'];
+        assert_eq!(read_be_u32(&bytes, 0), Some(13));
+        assert_eq!(to_usize(13), Some(13));
+    }
+}
@@ -1,9 +1,14 @@
-use crate::{chunk_type, ihdr_chunk::IhdrChunk, Error, Result};
+use crate::{
+    bytes::{be_u32, put_be_u32},
+    chunk_type,
+    ihdr_chunk::IhdrChunk,
+    Error, Result,
+};
 use std::{fmt::Display, str::FromStr};
 
 use crate::chunk_type::ChunkType;
-use crc;
 
+#[allow(unused)]
 #[derive(Debug)]
 pub enum ChunkError {
     InvalidUtf8,
@@ -67,7 +72,7 @@ impl TryFrom<&[u8]> for Chunk {
         }
 
         // first 4 bytes is the length of the data
-        let len = u32::from_be_bytes([vc[0], vc[1], vc[2], vc[3]]);
+        let len = be_u32(&vc);
 
         // next 4 bytes is the chunk type
         let chunk_type_bytes = &vc[4..8];
@@ -80,12 +85,7 @@ impl TryFrom<&[u8]> for Chunk {
         let data = vc[8..vc.len() - 4].to_vec();
 
         // last 4 bytes is the crc
-        let crc = u32::from_be_bytes([
-            vc[vc.len() - 4],
-            vc[vc.len() - 3],
-            vc[vc.len() - 2],
-            vc[vc.len() - 1],
-        ]);
+        let crc = be_u32(&vc[vc.len() - 4..]);
 
         // create a CRC instance and validate the checksum
         let crc32 = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
@@ -145,10 +145,10 @@ impl Chunk {
     }
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
-        bytes.extend_from_slice(&self.len.to_be_bytes());
+        put_be_u32(&mut bytes, self.len);
         bytes.extend_from_slice(&self.chunk_type.bytes());
         bytes.extend_from_slice(&self.data);
-        bytes.extend_from_slice(&self.crc.to_be_bytes());
+        put_be_u32(&mut bytes, self.crc);
 
         bytes
     }
@@ -157,6 +157,10 @@ impl Chunk {
         self.len
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
     pub fn type_str(&self) -> String {
         self.chunk_type.code.iter().map(|&b| b as char).collect()
     }
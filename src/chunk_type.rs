@@ -98,6 +98,64 @@ impl ChunkType {
     }
 }
 
+/// Chunk types registered by the PNG specification and its APNG and extension registries.
+pub const KNOWN_TYPES: [&str; 30] = [
+    "IHDR", "PLTE", "IDAT", "IEND", "tRNS", "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "cICP", "mDCV",
+    "cLLI", "tEXt", "zTXt", "iTXt", "bKGD", "hIST", "pHYs", "sPLT", "eXIf", "tIME", "acTL", "fcTL",
+    "fdAT", "oFFs", "pCAL", "sCAL", "gIFg", "sTER",
+];
+
+fn edit_distance(a: &[u8], b: &[u8]) -> usize {
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, &ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// The chunk type `code` was most likely meant to be, or `None` if it looks fine as it is.
+///
+/// Catches registered types with the wrong case ("ihdr"), near misses of registered types
+/// ("tEXT1") and private types whose reserved bit (third letter) isn't uppercase.
+pub fn suggest(code: &str) -> Option<String> {
+    if KNOWN_TYPES.contains(&code) {
+        return None;
+    }
+    if let Some(known) = KNOWN_TYPES
+        .iter()
+        .find(|known| known.eq_ignore_ascii_case(code))
+    {
+        return Some(known.to_string());
+    }
+
+    if let Ok(chunk_type) = ChunkType::from_str(code) {
+        if chunk_type.is_reserved_bit_valid() {
+            return None;
+        }
+        let mut fixed = chunk_type.bytes();
+        fixed[2] = fixed[2].to_ascii_uppercase();
+        return Some(String::from_utf8_lossy(&fixed).into_owned());
+    }
+
+    let lowercase = code.to_ascii_lowercase();
+    KNOWN_TYPES
+        .iter()
+        .map(|known| {
+            let distance =
+                edit_distance(lowercase.as_bytes(), known.to_ascii_lowercase().as_bytes());
+            (distance, known)
+        })
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, known)| known.to_string())
+}
+
 #[derive(Debug)]
 pub enum ChunkTypeError {
     InvalidLength,
@@ -202,6 +260,18 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_suggestions() {
+        assert_eq!(suggest("IHDR"), None);
+        assert_eq!(suggest("ruSt"), None);
+        assert_eq!(suggest("ihdr").as_deref(), Some("IHDR"));
+        assert_eq!(suggest("TEXT").as_deref(), Some("tEXt"));
+        assert_eq!(suggest("rust").as_deref(), Some("ruSt"));
+        assert_eq!(suggest("tEXt1").as_deref(), Some("tEXt"));
+        assert_eq!(suggest("gAM4").as_deref(), Some("gAMA"));
+        assert_eq!(suggest("12345678"), None);
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -0,0 +1,171 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::Result;
+
+#[derive(Debug)]
+pub enum CodecError {
+    OddHexLength,
+    InvalidHexDigit(char),
+    InvalidBase64(String),
+}
+
+impl std::error::Error for CodecError {}
+
+impl std::fmt::Display for CodecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CodecError::OddHexLength => write!(f, "Hex input has an odd number of digits"),
+            CodecError::InvalidHexDigit(c) => write!(f, "Invalid hex digit {:?}", c),
+            CodecError::InvalidBase64(reason) => write!(f, "Invalid base64: {}", reason),
+        }
+    }
+}
+
+/// Parse hex digits into bytes. Whitespace and an optional `0x` prefix are ignored, so dumps can
+/// be pasted as they are.
+pub fn decode_hex(input: &str) -> Result<Vec<u8>> {
+    let input = input.trim();
+    let input = input
+        .strip_prefix("0x")
+        .or_else(|| input.strip_prefix("0X"))
+        .unwrap_or(input);
+
+    let digits = input
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| {
+            c.to_digit(16)
+                .map(|d| d as u8)
+                .ok_or(CodecError::InvalidHexDigit(c))
+        })
+        .collect::<std::result::Result<Vec<u8>, _>>()?;
+
+    if !digits.len().is_multiple_of(2) {
+        return Err(CodecError::OddHexLength.into());
+    }
+
+    Ok(digits
+        .chunks(2)
+        .map(|pair| (pair[0] << 4) | pair[1])
+        .collect())
+}
+
+/// Decode standard, padded base64. Whitespace is ignored.
+pub fn decode_base64(input: &str) -> Result<Vec<u8>> {
+    let input: String = input.chars().filter(|c| !c.is_whitespace()).collect();
+    Ok(STANDARD
+        .decode(input)
+        .map_err(|e| CodecError::InvalidBase64(e.to_string()))?)
+}
+
+/// Lowercase hex digits of `data`, the reverse of [`decode_hex`].
+pub fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Standard base64 of `data`, the reverse of [`decode_base64`].
+pub fn encode_base64(data: &[u8]) -> String {
+    STANDARD.encode(data)
+}
+
+/// How a binary payload is written to stdout, where printing it as text could fail or mangle it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rendering {
+    /// The bytes unchanged.
+    Raw,
+    Hex,
+    Base64,
+}
+
+impl Rendering {
+    /// What to write for `data`. The text renderings end in a newline.
+    pub fn render(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Rendering::Raw => data.to_vec(),
+            Rendering::Hex => format!("{}\n", encode_hex(data)).into_bytes(),
+            Rendering::Base64 => format!("{}\n", encode_base64(data)).into_bytes(),
+        }
+    }
+}
+
+/// An offset/hex/ASCII dump of `data`, 16 bytes per line, like `hexdump -C`. Offsets start at
+/// `base`.
+pub fn hexdump(data: &[u8], base: usize) -> String {
+    let mut out = String::new();
+    for (line, bytes) in data.chunks(16).enumerate() {
+        let hex: Vec<String> = (0..16)
+            .map(|idx| match bytes.get(idx) {
+                Some(byte) => format!("{:02x}", byte),
+                None => "  ".to_string(),
+            })
+            .collect();
+        let ascii: String = bytes
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+
+        out.push_str(&format!(
+            "{:08x}  {}  {}  |{}|\n",
+            base + line * 16,
+            hex[..8].join(" "),
+            hex[8..].join(" "),
+            ascii
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_hex() {
+        assert_eq!(decode_hex("89ab CDef").unwrap(), [0x89, 0xab, 0xcd, 0xef]);
+        assert_eq!(decode_hex("0x00ff").unwrap(), [0x00, 0xff]);
+        assert!(decode_hex("abc").is_err());
+        assert!(decode_hex("zz").is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let data = [0xff, 0x00, b'a'];
+        assert_eq!(Rendering::Raw.render(&data), data);
+        assert_eq!(Rendering::Hex.render(&data), b"ff0061\n");
+        assert_eq!(Rendering::Base64.render(&data), b"/wBh\n");
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let data = [0x00, 0x7f, 0x80, 0xff];
+        assert_eq!(encode_hex(&data), "007f80ff");
+        assert_eq!(decode_hex(&encode_hex(&data)).unwrap(), data);
+        assert_eq!(encode_base64(b"hello"), "aGVsbG8=");
+    }
+
+    #[test]
+    fn test_decode_base64() {
+        assert_eq!(decode_base64("aGVs\nbG8=").unwrap(), b"hello");
+        assert!(decode_base64("not base64!").is_err());
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let dump = hexdump(b"\x89PNG\r\n\x1a\n0123456789abcdefXY", 0x10);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "00000010  89 50 4e 47 0d 0a 1a 0a  30 31 32 33 34 35 36 37  |.PNG....01234567|",
+                "00000020  38 39 61 62 63 64 65 66  58 59                    |89abcdefXY|",
+            ]
+        );
+        assert_eq!(hexdump(&[], 0), "");
+    }
+}
@@ -1,122 +1,3395 @@
-use std::{fs, path::PathBuf, str::FromStr};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{Read, Write},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Mutex, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use clap::Parser;
+
+use rayon::prelude::*;
+use regex::RegexBuilder;
+use serde::Serialize;
+use serde_json::{json, Value};
+use zeroize::Zeroizing;
 
 use crate::{
-    args::{Cli, Commands},
+    apng::{self, Delay},
+    args::{ApngCommands, Cli, Commands, Inputs, MetaCommands, PayloadCommands},
+    bytes,
     chunk::Chunk,
-    chunk_type::ChunkType,
-    png::Png,
+    chunk_type::{self, ChunkType},
+    codec::{self, Rendering},
+    convert,
+    crypto::KdfParams,
+    daemon,
+    db::Db,
+    diff::{self, Change},
+    dupes::{self, Compare},
+    edit::{self, Edit},
+    envelope::Envelope,
+    exit::{self, CheckFailed, Code},
+    extension::{self, Extension, Offset, Scale, Stereo},
+    files,
+    format::{self, Format},
+    icc,
+    ihdr_chunk::IhdrChunk,
+    jobs::{self, Job},
+    limits::{self, Limits},
+    logging, memstats,
+    payload::{self, Decoy, EmbeddedFile, Keys, PayloadError},
+    phash,
+    png::{self, Png, PngError},
+    position::Position,
+    preset::{self, ArchivePrep, WebPrep},
+    progress::{self, Progress},
+    raster,
+    recipient::Identities,
+    registry, remote,
+    report::{Report, ReportFormat},
+    repro, scan, scrub, secret, selftest,
+    signature::{self, SignatureError},
+    sink::Sink,
+    slot::{self, Existing},
+    span,
+    stamp::Template,
+    stats::Stats,
+    style::{Color, Style},
+    summary::{Recorder, Status},
+    template::OutputTemplate,
+    text::{self, Charset, TextualChunk},
+    transform::{self, Transform},
     Result,
 };
 
+/// Whether `path` is `-`, standing for stdin when read and stdout when written.
+fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+fn check_signature(file_path: &Path) -> Result<()> {
+    if !files::has_png_signature(file_path)? {
+        return Err(PngError::NotPng(file_path.to_path_buf()).into());
+    }
+    Ok(())
+}
+
+/// Files written by the jobs of a `run` manifest so far, held back until they have all
+/// succeeded. `None` outside of `run`.
+static STAGED: Mutex<Option<BTreeMap<PathBuf, Vec<u8>>>> = Mutex::new(None);
+
+/// The contents a job of the current `run` has written to `path`, if any.
+fn staged(path: &Path) -> Option<Vec<u8>> {
+    let staged = STAGED.lock().unwrap_or_else(PoisonError::into_inner);
+    staged.as_ref()?.get(path).cloned()
+}
+
+fn read_png(file_path: &Path) -> Result<Png> {
+    if is_stdio(file_path) {
+        return Png::from_reader(std::io::stdin().lock());
+    }
+    if remote::is_url(file_path) || staged(file_path).is_some() {
+        return Png::from_reader(read_input(file_path)?.as_slice());
+    }
+    check_signature(file_path)?;
+
+    let size = fs::metadata(file_path)?.len();
+    let progress = Progress::bytes(size, &file_path.display().to_string());
+    match size >= progress::LARGE_FILE {
+        true => {
+            limits::active().check_file_size(size)?;
+            Png::from_reader(progress.wrap_read(fs::File::open(file_path)?))
+        }
+        false => Png::from_file(file_path),
+    }
+}
+
+/// The raw bytes of a PNG file, or of stdin for `-`, for commands that look past the parsed
+/// chunks.
+fn read_input(file_path: &Path) -> Result<Vec<u8>> {
+    let limits = limits::active();
+    if is_stdio(file_path) {
+        let mut file = vec![];
+        std::io::stdin()
+            .lock()
+            .take(limits.max_file_size.saturating_add(1))
+            .read_to_end(&mut file)?;
+        limits.check_file_size(file.len() as u64)?;
+        return Ok(file);
+    }
+    if let Some(file) = staged(file_path) {
+        return Ok(file);
+    }
+    if let Some(url) = file_path.to_str().filter(|_| remote::is_url(file_path)) {
+        let file = remote::fetch(url)?;
+        if !file.starts_with(&Png::STANDARD_HEADER) {
+            return Err(PngError::NotPng(file_path.to_path_buf()).into());
+        }
+        return Ok(file);
+    }
+    check_signature(file_path)?;
+
+    // Checked before allocating, and again in case the file grew since
+    let size = fs::metadata(file_path)?.len();
+    limits.check_file_size(size)?;
+    let progress = Progress::bytes(size, &file_path.display().to_string());
+    let mut file = Vec::with_capacity(size as usize);
+    progress
+        .wrap_read(fs::File::open(file_path)?)
+        .take(limits.max_file_size.saturating_add(1))
+        .read_to_end(&mut file)?;
+    limits.check_file_size(file.len() as u64)?;
+    Ok(file)
+}
+
+/// The message for `encode --file`: the file wrapped with its name and metadata (see
+/// [`payload::wrap_file`]), or whatever stdin holds for '-'.
+fn read_message_file(path: &Path) -> Result<Vec<u8>> {
+    if is_stdio(path) {
+        let mut message = vec![];
+        std::io::stdin().lock().read_to_end(&mut message)?;
+        return Ok(message);
+    }
+    Ok(payload::wrap_file(read_embedded_file(path)?))
+}
+
+/// A file to embed, with the name and modification time decode restores it with.
+fn read_embedded_file(path: &Path) -> Result<EmbeddedFile> {
+    let data = fs::read(path)?;
+    let modified = fs::metadata(path)?
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|elapsed| elapsed.as_secs());
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    Ok(EmbeddedFile::new(&name, data, modified))
+}
+
+fn read_png_bytes(file_path: &Path) -> Result<(Png, Vec<u8>)> {
+    let file = read_input(file_path)?;
+    let png = Png::from_reader(file.as_slice())?;
+
+    Ok((png, file))
+}
+
+/// Options shared by every command that writes PNGs.
+struct Output {
+    dry_run: bool,
+    /// A single file to write to instead of the input, `-o`.
+    path: Option<PathBuf>,
+    template: Option<OutputTemplate>,
+    /// Where to copy an input before it is overwritten, `--backup`.
+    backup: Option<OutputTemplate>,
+    recorder: Recorder,
+    fix_case: bool,
+    format: Format,
+    style: Style,
+    /// Report results through the exit code only.
+    check: bool,
+    preserve_times: bool,
+    /// Files verified ahead of time by [`prefetch`].
+    examined: RefCell<HashMap<PathBuf, Result<Verification>>>,
+}
+
+/// Formats of commands that report one document per file.
+const DOCUMENT_FORMATS: [Format; 3] = [Format::Text, Format::Json, Format::Jsonl];
+
+impl Output {
+    /// Where to write `content` derived from `file_path`, the `index`th file of a batch.
+    fn path_for(&self, file_path: &Path, content: &[u8], index: usize) -> PathBuf {
+        match (&self.path, &self.template) {
+            (Some(path), _) => path.clone(),
+            (None, Some(template)) => template.render(file_path, content, index),
+            (None, None) => file_path.to_path_buf(),
+        }
+    }
+
+    /// Whether the image read from `file_path` is written back to stdout rather than a file.
+    fn streams(&self, file_path: &Path) -> bool {
+        match (&self.path, &self.template) {
+            (Some(path), _) => is_stdio(path),
+            (None, Some(_)) => false,
+            (None, None) => is_stdio(file_path),
+        }
+    }
+
+    /// Fail if `--format` asks for something `command` can't produce.
+    fn expect_format(&self, command: &str, supported: &[Format]) -> Result<()> {
+        match supported.contains(&self.format) {
+            true => Ok(()),
+            false => {
+                Err(format!("--format {} isn't available for {}", self.format, command).into())
+            }
+        }
+    }
+
+    /// Fail if `-o` was given to a command that writes several files, they'd overwrite each other.
+    fn expect_many(&self) -> Result<()> {
+        match self.path {
+            Some(_) => Err(
+                "-o/--output names a single file, use --output-template for commands that write several"
+                    .into(),
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// Parse the type of a chunk about to be written, catching likely typos.
+    fn new_chunk_type(&self, input: &str) -> Result<ChunkType> {
+        match chunk_type::suggest(input) {
+            None => ChunkType::from_str(input),
+            Some(suggestion) if self.fix_case => {
+                eprintln!("Using chunk type {} instead of {}", suggestion, input);
+                ChunkType::from_str(&suggestion)
+            }
+            Some(suggestion) => Err(format!(
+                "Suspicious chunk type '{}', did you mean '{}'? Pass --fix-case to use it",
+                input, suggestion
+            )
+            .into()),
+        }
+    }
+}
+
+/// Write `bytes` to `path` atomically, or to stdout when `path` is `-`.
+fn write_file(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut staged = STAGED.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(staged) = staged.as_mut().filter(|_| !is_stdio(path)) {
+        staged.insert(path.to_path_buf(), bytes.to_vec());
+        return Ok(());
+    }
+    drop(staged);
+
+    if is_stdio(path) {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(bytes)?;
+        stdout.flush()?;
+    } else {
+        files::write_atomic(path, bytes)?;
+    }
+    Ok(())
+}
+
+/// Write a file that isn't a rewrite of an input PNG, or only say what would be written on a dry
+/// run. `before` is the size of the input it was made from, for the summary.
+///
+/// Returns whether a file was actually written, like [`save_png`].
+fn write_output(path: &Path, bytes: &[u8], before: Option<usize>, output: &Output) -> Result<bool> {
+    if output.dry_run {
+        println!(
+            "Dry run, would write {} bytes to {}",
+            bytes.len(),
+            path.display()
+        );
+        output
+            .recorder
+            .record(path, Status::DryRun, before, Some(bytes.len()));
+        return Ok(false);
+    }
+
+    write_file(path, bytes)?;
+    output
+        .recorder
+        .record(path, Status::Written, before, Some(bytes.len()));
+
+    Ok(!is_stdio(path))
+}
+
+/// Write `png` over `file_path` (or to `-o`, or where the output template says), or only show how it
+/// differs from `original` on a dry run. A `-` target sends the image to stdout.
+///
+/// Returns whether a file was actually written, so callers only report on success when stdout
+/// isn't carrying the image.
+fn save_png(
+    file_path: &Path,
+    index: usize,
+    original: &Png,
+    png: &Png,
+    output: &Output,
+) -> Result<bool> {
+    let (before, bytes) = (original.as_bytes().len(), png.as_bytes());
+
+    if output.dry_run {
+        println!("Dry run, {} was not modified:", file_path.display());
+        print!("{}", diff::render(original, png));
+        output
+            .recorder
+            .record(file_path, Status::DryRun, Some(before), Some(bytes.len()));
+        return Ok(false);
+    }
+
+    let target = output.path_for(file_path, &bytes, index);
+    if remote::is_url(&target) {
+        return Err(format!(
+            "Can't write back to {}, use -o to save the result",
+            target.display()
+        )
+        .into());
+    }
+    // Taken before writing, since an in-place edit replaces the file. Only local files have any
+    let local = !is_stdio(file_path) && !remote::is_url(file_path);
+    let times = match output.preserve_times && local {
+        true => Some(fs::metadata(file_path)?),
+        false => None,
+    };
+    if let Some(backup) = output.backup.as_ref().filter(|_| target == file_path) {
+        if !is_stdio(file_path) {
+            let backup = backup.render(file_path, &original.as_bytes(), index);
+            fs::copy(file_path, &backup)?;
+            tracing::info!("backed up {} to {}", file_path.display(), backup.display());
+        }
+    }
+    write_file(&target, &bytes)?;
+    if let Some(metadata) = times.filter(|_| !is_stdio(&target)) {
+        files::set_times(&target, &metadata)?;
+    }
+    output
+        .recorder
+        .record(&target, Status::Written, Some(before), Some(bytes.len()));
+
+    if is_stdio(&target) {
+        return Ok(false);
+    }
+    if target != file_path {
+        println!("Wrote {}", target.display());
+    }
+
+    Ok(true)
+}
+
+/// The options of `args` that commands write their results with.
+fn output_for(args: &Cli) -> Output {
+    Output {
+        dry_run: args.dry_run,
+        path: args.output.clone(),
+        template: args.output_template.clone(),
+        backup: args.backup.clone().filter(|_| !args.no_backup),
+        recorder: Recorder::default(),
+        fix_case: args.fix_case,
+        format: args.format,
+        style: Style::new(args.color),
+        check: args.check,
+        preserve_times: args.preserve_times,
+        examined: RefCell::default(),
+    }
+}
+
 pub fn run(args: &Cli) -> Result<()> {
-    match &args.command {
+    logging::init(logging::level(args.verbose, args.quiet));
+    progress::install(args.quiet || args.check);
+
+    let output = output_for(args);
+
+    if let Some(jobs) = args.jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build_global()?;
+    }
+
+    let result = if args.hardened {
+        limits::install(Limits::HARDENED);
+        dispatch_each(
+            &args.command,
+            &output,
+            args.recursive,
+            dispatch_catching_panics,
+        )
+    } else {
+        dispatch_each(&args.command, &output, args.recursive, dispatch)
+    };
+
+    if let Some(summary_file) = &args.summary_file {
+        output.recorder.finish(&result).write(summary_file)?;
+    }
+    if args.mem_stats {
+        eprintln!("{}", memstats::snapshot());
+    }
+
+    result
+}
+
+/// Point `command` at the single PNG `path`.
+fn set_input(command: &mut Commands, path: &Path) {
+    match command.inputs_mut() {
+        Some(Inputs::One(input)) => *input = path.to_path_buf(),
+        Some(Inputs::Many(inputs)) => *inputs = vec![path.to_path_buf()],
+        None => {}
+    }
+}
+
+/// The files `inputs` stand for: glob patterns expanded, directories searched for PNGs if
+/// `recursive` is set, anything else as is.
+fn expand_inputs(inputs: &[PathBuf], recursive: bool) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for input in inputs {
+        // A query string would otherwise pass for a glob pattern
+        if remote::is_url(input) {
+            files.push(input.clone());
+        } else if files::is_glob(input) {
+            files.extend(files::glob(input)?);
+        } else if input.is_dir() {
+            if !recursive {
+                return Err(format!(
+                    "{} is a directory, use -r to process the PNGs beneath it",
+                    input.display()
+                )
+                .into());
+            }
+            files.extend(files::png_files(input)?);
+        } else {
+            files.push(input.clone());
+        }
+    }
+    Ok(files)
+}
+
+/// Run `command` with `run` on each file its inputs expand to. A single plain file runs as
+/// is, anything more gets a header per file and a count at the end. One file failing doesn't
+/// stop the rest, the first failure decides the exit code.
+fn dispatch_each(
+    command: &Commands,
+    output: &Output,
+    recursive: bool,
+    run: fn(&Commands, &Output) -> Result<()>,
+) -> Result<()> {
+    let mut command = command.clone();
+    let inputs = match command.inputs_mut() {
+        Some(Inputs::One(input)) => vec![input.clone()],
+        Some(Inputs::Many(inputs)) => inputs.clone(),
+        None => return run(&command, output),
+    };
+    let files = expand_inputs(&inputs, recursive)?;
+    if files == inputs && files.len() == 1 {
+        return run(&command, output);
+    }
+    output.expect_many()?;
+
+    // Headers and totals would break up JSON and CSV
+    let headers = output.format == Format::Text && !output.check;
+    let mut failures = vec![];
+    let progress = Progress::files(files.len());
+    // Batches keep results coming while the thread pool works ahead
+    let batch_size = rayon::current_num_threads() * 4;
+    for (index, path) in files.iter().enumerate() {
+        if index % batch_size == 0 {
+            prefetch(
+                &command,
+                &files[index..(index + batch_size).min(files.len())],
+                output,
+            );
+        }
+
+        set_input(&mut command, path);
+        let result = progress.suspend(|| {
+            if headers {
+                if index > 0 {
+                    println!();
+                }
+                println!("==> {} <==", path.display());
+            }
+            run(&command, output)
+        });
+        if let Err(error) = result {
+            if !output.check && !error.is::<CheckFailed>() {
+                progress.suspend(|| eprintln!("{}: {}", path.display(), error));
+            }
+            failures.push(exit::code(&error));
+        }
+        progress.inc(1);
+    }
+    drop(progress);
+
+    if headers {
+        println!();
+        println!("{} file(s), {} failed", files.len(), failures.len());
+    }
+    match failures.first() {
+        Some(&code) => Err(CheckFailed(code).into()),
+        None => Ok(()),
+    }
+}
+
+/// Run a command, turning a panic into an error so one bad file can't take down the caller.
+fn dispatch_catching_panics(command: &Commands, output: &Output) -> Result<()> {
+    // The default hook would print the panic to stderr, it is reported as an error instead
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = panic::catch_unwind(AssertUnwindSafe(|| dispatch(command, output)));
+    panic::set_hook(previous);
+
+    result.unwrap_or_else(|payload| Err(internal_error(payload)))
+}
+
+fn internal_error(payload: Box<dyn std::any::Any + Send>) -> crate::Error {
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown cause".to_string());
+    format!("Internal error while processing: {}", message).into()
+}
+
+/// Run `command`. Commands taking several files get exactly one from [`dispatch_each`].
+fn dispatch(command: &Commands, output: &Output) -> Result<()> {
+    match command {
         Commands::Encode {
             png_file: file_path,
             chunk_type,
             message,
-        } => encode(file_path, chunk_type, message)?,
+            file,
+            add,
+            span,
+            encrypt,
+            password,
+            password_file,
+            decoy,
+            decoy_password,
+            recipient,
+            hmac_key,
+            key_file,
+            cipher,
+            kdf_memory,
+            kdf_iterations,
+            compress,
+            ecc,
+            chunk_size,
+            slot,
+            overwrite,
+            append,
+            frame,
+            transform,
+            placement,
+        } => {
+            let message = match (message, file) {
+                (Some(message), _) => message.as_bytes().to_vec(),
+                (None, Some(path)) => read_message_file(path)?,
+                (None, None) => payload::pack(
+                    add.iter()
+                        .map(|path| read_embedded_file(path))
+                        .collect::<Result<_>>()?,
+                )?,
+            };
+            let existing = match (overwrite, append) {
+                (true, _) => Existing::Overwrite,
+                (_, true) => Existing::Append,
+                _ => Existing::Refuse,
+            };
+            if span.is_empty() {
+                // Before encryption, which leaves nothing to compress
+                let compressed = |data: Vec<u8>| match compress {
+                    Some(compression) => compression.compress(&data),
+                    None => Ok(data),
+                };
+                let hmac_key = secret::given(hmac_key, key_file, None)?;
+                // A message stored bare, with no envelope, is left readable by any PNG tool
+                let enveloped = *encrypt
+                    || decoy.is_some()
+                    || !recipient.is_empty()
+                    || compress.is_some()
+                    || hmac_key.is_some();
+                let digested = |data: Vec<u8>| match enveloped {
+                    true => payload::add_digest(data),
+                    false => data,
+                };
+                // The decoy goes through the same steps, or undoing them would give it away
+                let prepared =
+                    |data: Vec<u8>| compressed(digested(transform::apply(data, transform)?));
+                let message = prepared(message)?;
+                let kdf = KdfParams::new(*kdf_memory, *kdf_iterations)?;
+                let cipher = cipher.unwrap_or_default();
+                let password = secret::given(password, password_file, Some(secret::PASSWORD_ENV))?;
+                let sealed = match decoy {
+                    _ if !recipient.is_empty() => Some(payload::seal_to(&message, recipient)?),
+                    Some(decoy) => {
+                        let password = secret::or_prompt_new(password, "password")?;
+                        let decoy_password = secret::given(decoy_password, &None, None)?;
+                        let decoy_password =
+                            secret::or_prompt_new(decoy_password, "decoy password")?;
+                        if *decoy_password == *password {
+                            return Err(PayloadError::SameDecoyPassword.into());
+                        }
+                        let decoy_message = prepared(decoy.as_bytes().to_vec())?;
+                        let decoy = Decoy {
+                            message: &decoy_message,
+                            password: &decoy_password,
+                        };
+                        Some(payload::seal_with_decoy(
+                            &message, &password, decoy, kdf, cipher,
+                        )?)
+                    }
+                    None if *encrypt => {
+                        let password = secret::or_prompt_new(password, "password")?;
+                        Some(payload::seal(&message, &password, kdf, cipher)?)
+                    }
+                    None => None,
+                };
+                let payload = match sealed {
+                    None if compress.is_none() && hmac_key.is_none() => message,
+                    sealed => {
+                        let mut envelope = sealed.unwrap_or_else(|| Envelope::new(message));
+                        envelope.compression = *compress;
+                        envelope.created =
+                            Some(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs());
+                        if let Some(key) = &hmac_key {
+                            envelope = payload::authenticate(envelope, key.as_bytes());
+                        }
+                        envelope.as_bytes()
+                    }
+                };
+                let payload = match ecc {
+                    Some(level) => payload::protect(&payload, *level),
+                    None => payload,
+                };
+                let layout = Layout {
+                    chunk_size: *chunk_size,
+                    frame: *frame,
+                    position: placement.position(),
+                    slot: *slot,
+                    existing,
+                };
+                encode(file_path, chunk_type, payload, &layout, output)?
+            } else {
+                encode_span(file_path, span, chunk_type, &message, existing, output)?
+            }
+        }
 
         Commands::Decode {
             png_file: file_path,
             chunk_type,
-        } => decode(file_path, chunk_type)?,
+            span,
+            password,
+            password_file,
+            identity,
+            hmac_key,
+            key_file,
+            charset,
+            frame,
+            slot,
+            all,
+            transform,
+            sink,
+            rendering,
+            out_dir,
+            list,
+            extract,
+        } => {
+            let identities = identity.as_deref().map(Identities::load).transpose()?;
+            let password = secret::given(password, password_file, Some(secret::PASSWORD_ENV))?;
+            let hmac_key = secret::given(hmac_key, key_file, None)?;
+            let options = DecodeOptions {
+                keys: Keys {
+                    password: password.as_deref().map(String::as_str),
+                    identities: identities.as_ref(),
+                    hmac_key: hmac_key.as_deref().map(String::as_bytes),
+                },
+                charset: *charset,
+                frame: *frame,
+                slot: (!*all).then_some(*slot),
+                transforms: transform,
+                sink: sink.sink(),
+                rendering: rendering.rendering(),
+                // Extracted files go to the current directory unless told otherwise
+                out_dir: match out_dir {
+                    None if !extract.is_empty()
+                        && output.path.is_none()
+                        && sink.sink().is_none()
+                        && rendering.rendering().is_none() =>
+                    {
+                        Some(PathBuf::from("."))
+                    }
+                    out_dir => out_dir.clone(),
+                },
+                list: *list,
+                extract,
+            };
+            if span.is_empty() {
+                decode(file_path, chunk_type, &options, output)?
+            } else {
+                decode_span(file_path, span, chunk_type, &options, output)?
+            }
+        }
 
         Commands::Remove {
             png_file: file_path,
             chunk_type,
-        } => remove(&file_path, &chunk_type)?,
+            all,
+            index,
+        } => match (chunk_type, index) {
+            (_, Some(index)) => remove_index(file_path, chunk_type.as_deref(), *index, output)?,
+            (Some(chunk_type), None) => remove(file_path, chunk_type, *all, output)?,
+            (None, None) => unreachable!("clap requires a chunk type or an index"),
+        },
+
+        Commands::Replace {
+            png_file,
+            chunk_type,
+            message,
+            index,
+        } => replace(png_file, chunk_type, message, *index, output)?,
+        Commands::Strip { png_files, keep } => strip(&png_files[0], keep, output)?,
+        Commands::Edit {
+            png_files,
+            strip,
+            keep,
+            strip_text,
+            remove,
+            set_text,
+            set_dpi,
+            encode,
+        } => {
+            let mut edits = vec![];
+            if *strip {
+                edits.push(Edit::Strip { keep: keep.clone() });
+            }
+            if *strip_text {
+                edits.push(Edit::StripText);
+            }
+            edits.extend(remove.iter().cloned().map(Edit::Remove));
+            edits.extend(set_text.iter().cloned().map(Edit::SetText));
+            edits.extend(set_dpi.map(Edit::SetDpi));
+            for pair in encode.chunks(2) {
+                let chunk_type = output.new_chunk_type(&pair[0])?;
+                let chunk = Chunk::new(chunk_type, pair[1].as_bytes().to_vec());
+                edits.push(Edit::Encode(chunk));
+            }
+            edit(&png_files[0], &edits, output)?
+        }
+        Commands::Stamp {
+            template,
+            png_files,
+        } => {
+            let template = Template::load(template)?;
+            let modified = match is_stdio(&png_files[0]) || remote::is_url(&png_files[0]) {
+                true => None,
+                false => Some(fs::metadata(&png_files[0])?.modified()?),
+            };
+            edit(&png_files[0], &template.edits(modified)?, output)?
+        }
+        Commands::WebPrep {
+            inputs,
+            keep,
+            no_optimize,
+            no_reorder,
+        } => {
+            let web = WebPrep {
+                keep: keep.clone(),
+                optimize: !no_optimize,
+                reorder: !no_reorder,
+            };
+            prepare(inputs, output, |png, _| web.apply(png))?
+        }
+        Commands::ArchivePrep {
+            inputs,
+            author,
+            comment,
+            no_timestamp,
+            no_seal,
+        } => {
+            let archive = ArchivePrep {
+                author: author.clone(),
+                comment: comment.clone(),
+                timestamp: !no_timestamp,
+                seal: !no_seal,
+            };
+            prepare(inputs, output, |png, path| {
+                let source = path.file_name().unwrap_or(path.as_os_str());
+                archive.apply(png, &source.to_string_lossy())
+            })?
+        }
+        Commands::Hexdump {
+            png_file,
+            chunk_type,
+            index,
+        } => hexdump(png_file, chunk_type, *index)?,
+        Commands::Explain { chunk_type } => print!("{}", registry::explain(chunk_type)?),
+        Commands::Scan { paths, db, report } => {
+            scan(paths, db.as_deref(), report.as_deref(), output)?
+        }
+        Commands::Stats { paths, top, db } => stats(paths, *top, db.as_deref(), output)?,
+        Commands::Dupes { paths, pixels } => dupes(paths, *pixels, output)?,
+        Commands::Phash {
+            paths,
+            near_dupes,
+            threshold,
+        } => phash(paths, *near_dupes, *threshold, output)?,
+        Commands::Grep {
+            pattern,
+            files,
+            ignore_case,
+            charset,
+        } => grep(pattern, files, *ignore_case, *charset, output)?,
+        Commands::Diff { a_file, b_file } => compare(a_file, b_file, output)?,
+        Commands::FixColor { png_files } => fix_color(&png_files[0], output)?,
+        Commands::CopyChunks {
+            src_file,
+            dst_file,
+            types,
+            allow_unsafe,
+        } => copy_chunks(src_file, dst_file, types, *allow_unsafe, output)?,
+        Commands::Scrub { png_files } => scrub(&png_files[0], output)?,
+        Commands::Print { png_files } => print(&png_files[0], output)?,
+        Commands::ImportChunk {
+            png_file,
+            chunk_type,
+            data_file,
+            hex,
+            base64,
+            transform,
+        } => {
+            let data = match (data_file, hex, base64) {
+                (Some(data_file), _, _) => fs::read(data_file)?,
+                (_, Some(hex), _) => codec::decode_hex(hex)?,
+                (_, _, Some(base64)) => codec::decode_base64(base64)?,
+                _ => unreachable!("clap requires one data source"),
+            };
+            let data = transform::apply(data, transform)?;
+            import_chunk(png_file, chunk_type, data, output)?
+        }
+        Commands::Inject {
+            png_file,
+            chunk_type,
+            data_file,
+            position,
+            placement,
+        } => {
+            let position = placement.position().unwrap_or(position.clone());
+            inject(png_file, chunk_type, data_file, &position, output)?
+        }
+        Commands::Extract {
+            png_file,
+            chunk_type,
+            index,
+            transform,
+        } => extract(png_file, chunk_type, *index, transform, output)?,
+        Commands::Info { png_files } => info(&png_files[0], output)?,
+        Commands::List { png_files } => list(&png_files[0], output)?,
+        Commands::Messages { png_files } => messages(&png_files[0], output)?,
+        Commands::Verify {
+            png_files,
+            dump_failing_chunk,
+        } => verify(&png_files[0], dump_failing_chunk.as_deref(), output)?,
+        Commands::Daemon { socket, cache_size } => {
+            println!("Listening on {}", socket.display());
+            daemon::serve(socket, *cache_size)?
+        }
+        Commands::Selftest => selftest()?,
+        Commands::Sign { key, png_files } => sign(&png_files[0], key, output)?,
+        Commands::VerifySignature { pubkey, png_files } => verify_signature(&png_files[0], pubkey)?,
+        Commands::Run { manifest } => run_jobs(manifest, output)?,
+        Commands::Meta { command } => match command {
+            MetaCommands::Find {
+                dir,
+                keyword,
+                value,
+                charset,
+            } => meta_find(dir, keyword.as_deref(), value.as_deref(), *charset, output)?,
+            MetaCommands::Set {
+                png_file,
+                offset,
+                scale,
+                stereo,
+            } => meta_set(png_file, *offset, *scale, *stereo, output)?,
+            MetaCommands::Unset {
+                png_file,
+                offset,
+                scale,
+                stereo,
+            } => meta_unset(png_file, *offset, *scale, *stereo, output)?,
+        },
+        Commands::Payload { command } => match command {
+            PayloadCommands::Map { png_file } => payload_map(png_file)?,
+        },
+        Commands::Apng { command } => match command {
+            ApngCommands::Extract { png_file, out_dir } => apng_extract(png_file, out_dir, output)?,
+            ApngCommands::Build {
+                frames,
+                delay,
+                loops,
+            } => apng_build(frames, *delay, *loops, output)?,
+            ApngCommands::ToGif { png_file, gif_file } => apng_to_gif(png_file, gif_file, output)?,
+            ApngCommands::ToSheet {
+                png_file,
+                sheet_file,
+                manifest,
+            } => apng_to_sheet(png_file, sheet_file, manifest.as_deref(), output)?,
+            ApngCommands::SetDelay {
+                png_file,
+                delay,
+                frame,
+            } => apng_retime(png_file, Some(*delay), None, *frame, None, output)?,
+            ApngCommands::DropFrame { png_file, frame } => {
+                apng_drop_frame(png_file, *frame, output)?
+            }
+            ApngCommands::SetLoops { png_file, loops } => {
+                apng_retime(png_file, None, None, None, Some(*loops), output)?
+            }
+            ApngCommands::Retime {
+                png_file,
+                delay,
+                speed,
+                frame,
+                loops,
+            } => apng_retime(png_file, *delay, *speed, *frame, *loops, output)?,
+        },
+    }
+
+    Ok(())
+}
+
+/// Where encode puts the chunks of a message.
+struct Layout {
+    /// Largest chunk before the message is split over several.
+    chunk_size: u32,
+    frame: Option<usize>,
+    position: Option<Position>,
+    /// Replace the message in this slot, or add one when it is the next free slot.
+    slot: Option<usize>,
+    /// What happens to messages of the chunk type already there, when no slot is given.
+    existing: Existing,
+}
+
+fn encode(
+    file_path: &Path,
+    chunk_type: &str,
+    payload: Vec<u8>,
+    layout: &Layout,
+    output: &Output,
+) -> Result<()> {
+    let original = read_png(file_path)?;
+    let mut png = original.clone();
+
+    let chunk_type = output.new_chunk_type(chunk_type)?;
+    let payloads = match payload.len() > layout.chunk_size as usize {
+        true => span::split_chunks(&payload, layout.chunk_size as usize)?
+            .iter()
+            .map(Envelope::as_bytes)
+            .collect(),
+        false => vec![payload],
+    };
+
+    let vacated = match layout.slot {
+        Some(_) => None,
+        None => slot::make_room(&mut png, &chunk_type.to_string(), layout.existing)?,
+    };
+    let mut messages = slot::messages(png.chunks(), &chunk_type.to_string());
+    let index = match (layout.slot, layout.frame, &layout.position) {
+        (Some(slot), _, _) if slot < messages.len() => {
+            let replaced = messages.remove(slot);
+            let bytes: usize = replaced
+                .iter()
+                .map(|&index| png.chunks()[index].data().len())
+                .sum();
+            tracing::warn!(
+                "Overwriting the {} message in slot {}, {} bytes",
+                chunk_type,
+                slot,
+                bytes
+            );
+            for &index in replaced.iter().rev() {
+                png.chunks.remove(index);
+            }
+            Some(replaced[0])
+        }
+        (Some(slot), _, _) => {
+            slot::check(slot, messages.len())?;
+            None
+        }
+        (None, Some(frame), _) => Some(apng::frame_range(&png, frame)?.end),
+        (None, None, Some(position)) => Some(position.index(&png)?),
+        (None, None, None) => vacated,
+    };
+    let count = payloads.len();
+    for (offset, payload) in payloads.into_iter().enumerate() {
+        let chunk = Chunk::new(chunk_type.clone(), payload);
+        match index {
+            Some(index) => png.insert_chunk(index + offset, chunk),
+            None => png.append_chunk(chunk),
+        }
+    }
+
+    // An image over the active limits couldn't be read back under them
+    let limits = limits::active();
+    limits.check_png(&png)?;
+    limits.check_file_size(png.as_bytes().len() as u64)?;
 
-        Commands::Print { png_file } => print(png_file)?,
-        Commands::Verify { png_file } => verify(png_file)?,
+    if save_png(file_path, 0, &original, &png, output)? {
+        match count {
+            1 => println!("Message encoded successfully!"),
+            _ => println!("Message encoded successfully across {} chunks!", count),
+        }
     }
 
     Ok(())
 }
 
-fn encode(file_path: &PathBuf, chunk_type: &str, message: &str) -> Result<()> {
-    if file_path.extension().unwrap() != "png" {
-        return Err("This program takes only PNG files".into());
+/// How decode reads a message and where it goes.
+struct DecodeOptions<'a> {
+    keys: Keys<'a>,
+    charset: Charset,
+    frame: Option<usize>,
+    /// Which of several messages of the chunk type, `None` for all of them.
+    slot: Option<usize>,
+    transforms: &'a [Transform],
+    sink: Option<Sink>,
+    rendering: Option<Rendering>,
+    /// Where messages embedded from files are restored under their own names.
+    out_dir: Option<PathBuf>,
+    /// Only show the names and metadata of embedded files.
+    list: bool,
+    /// Only restore the embedded files with these names.
+    extract: &'a [String],
+}
+
+/// What decode found in a chunk.
+enum Decoded {
+    Text(TextualChunk),
+    Bytes(Vec<u8>),
+    /// A message embedded with `encode --file` or `--add`, the hashes already checked.
+    Files(Vec<EmbeddedFile>),
+    /// One piece of a message spread over several images.
+    Part {
+        index: u16,
+        total: u16,
+    },
+    Missing,
+}
+
+impl Decoded {
+    /// `Decoded::Files` for a message that came from files, `Decoded::Bytes` otherwise.
+    fn from_message(message: Vec<u8>) -> Result<Self> {
+        Ok(match payload::unwrap_files(&message)? {
+            Some(files) => Decoded::Files(files),
+            None => Decoded::Bytes(message),
+        })
+    }
+
+    /// The bytes of the message, if one was found. For files, only when there is just one.
+    fn message(&self) -> Option<&[u8]> {
+        match self {
+            Decoded::Text(text) => Some(text.text.as_bytes()),
+            Decoded::Bytes(message) => Some(message),
+            Decoded::Files(files) if files.len() == 1 => Some(&files[0].data),
+            Decoded::Files(_) | Decoded::Part { .. } | Decoded::Missing => None,
+        }
+    }
+}
+
+/// Print a decoded message, or hand its raw bytes to the sink.
+fn deliver(
+    decoded: Decoded,
+    chunk_type: &str,
+    options: &DecodeOptions,
+    output: &Output,
+) -> Result<()> {
+    if output.check {
+        return match decoded {
+            Decoded::Missing => Err(CheckFailed(Code::NotFound).into()),
+            _ => Ok(()),
+        };
+    }
+
+    let decoded = match decoded {
+        Decoded::Files(files) if !options.extract.is_empty() => {
+            Decoded::Files(select_files(files, options.extract)?)
+        }
+        decoded => decoded,
+    };
+    let is_message = matches!(decoded, Decoded::Text(_) | Decoded::Bytes(_));
+    if (options.list || !options.extract.is_empty()) && is_message {
+        return Err(
+            "This message wasn't embedded from files, there are none to list or extract".into(),
+        );
+    }
+
+    match (&options.out_dir, &decoded) {
+        (Some(dir), Decoded::Files(files)) => return restore_files(dir, files, output),
+        (Some(_), Decoded::Text(_) | Decoded::Bytes(_)) => {
+            return Err("This message wasn't embedded from a file, write it out with --out".into())
+        }
+        (None, Decoded::Files(files))
+            if files.len() > 1
+                && !options.list
+                && (options.sink.is_some()
+                    || output.path.is_some()
+                    || options.rendering.is_some()) =>
+        {
+            return Err(format!(
+                "The message holds {} files, pick one with --extract or restore them all with --out-dir",
+                files.len()
+            )
+            .into());
+        }
+        _ => {}
+    }
+
+    let found = !matches!(decoded, Decoded::Missing);
+    match (&options.sink, decoded.message().filter(|_| !options.list)) {
+        (Some(sink), Some(message)) => sink.send(message)?,
+        (None, Some(message)) if output.path.is_some() => {
+            save_message(message, chunk_type, output)?
+        }
+        (None, Some(message)) if options.rendering.is_some() => show_message(message, options)?,
+        _ if output.format.is_json() => output
+            .format
+            .print_json(&report(&decoded, chunk_type, options))?,
+        _ => print_decoded(decoded, options)?,
+    }
+
+    match found {
+        true => Ok(()),
+        false => Err(CheckFailed(Code::NotFound).into()),
+    }
+}
+
+fn decode(
+    file_path: &Path,
+    chunk_type: &str,
+    options: &DecodeOptions,
+    output: &Output,
+) -> Result<()> {
+    output.expect_format("decode", &DOCUMENT_FORMATS)?;
+    let png = read_png(file_path)?;
+
+    let scope = match options.frame {
+        Some(frame) => &png.chunks()[apng::frame_range(&png, frame)?],
+        None => png.chunks(),
+    };
+    let messages = slot::messages(scope, chunk_type);
+    let Some(slot) = options.slot else {
+        return deliver_all(scope, &messages, chunk_type, options, output);
+    };
+
+    let chunks: Vec<&Chunk> = match messages.get(slot) {
+        Some(message) => message.iter().map(|&index| &scope[index]).collect(),
+        None => vec![],
+    };
+    let decoded = decode_message(&chunks, chunk_type, options)?;
+    deliver(decoded, chunk_type, options, output)
+}
+
+/// The message held by `chunks`, the chunks of one slot.
+fn decode_message(chunks: &[&Chunk], chunk_type: &str, options: &DecodeOptions) -> Result<Decoded> {
+    // A message too large for one chunk was split over several
+    let joined = span::join_chunks(chunks.iter().map(|chunk| chunk.data()))?;
+    let found = joined
+        .as_deref()
+        .or_else(|| chunks.first().map(|chunk| chunk.data()));
+    // Error correction covers everything else, so it comes off first
+    let repaired = found.map(payload::repair).transpose()?.flatten();
+    if let Some((_, count @ 1..)) = repaired {
+        tracing::warn!(
+            "Repaired {} damaged byte(s) of the {} message",
+            count,
+            chunk_type
+        );
     }
+    let found = repaired.as_ref().map(|(data, _)| data.as_slice()).or(found);
 
-    let file = fs::read(file_path)?;
+    Ok(match found {
+        Some(data) if Envelope::is_envelope(data) => {
+            let envelope = Envelope::try_from(data)?;
+            match envelope.part {
+                Some(part) => Decoded::Part {
+                    index: part.index,
+                    total: part.total,
+                },
+                // Files embedded with nothing else asked for are stored in their own envelope
+                None if envelope.file.is_some() || envelope.manifest.is_some() => {
+                    Decoded::from_message(data.to_vec())?
+                }
+                None => Decoded::from_message(transform::undo(
+                    open_payload(envelope, options.keys)?.to_vec(),
+                    options.transforms,
+                )?)?,
+            }
+        }
+        Some(_) if options.keys.hmac_key.is_some() => return Err(PayloadError::MacMissing.into()),
+        Some(_) if joined.is_none() && repaired.is_none() && text::is_text_chunk(chunk_type) => {
+            Decoded::Text(TextualChunk::parse(chunks[0], options.charset)?)
+        }
+        Some(data) => Decoded::from_message(transform::undo(data.to_vec(), options.transforms)?)?,
+        None => Decoded::Missing,
+    })
+}
+
+/// Print every message of the chunk type for `decode --all`. One that can't be decoded, say
+/// because it was encrypted with another password, doesn't keep the rest from showing.
+fn deliver_all(
+    scope: &[Chunk],
+    messages: &[Vec<usize>],
+    chunk_type: &str,
+    options: &DecodeOptions,
+    output: &Output,
+) -> Result<()> {
+    if output.path.is_some() {
+        return Err(
+            "decode --all prints every message, pick one with --slot to write it to a file".into(),
+        );
+    }
+    if output.check {
+        return match messages.is_empty() {
+            true => Err(CheckFailed(Code::NotFound).into()),
+            false => Ok(()),
+        };
+    }
+
+    let mut reports = vec![];
+    for (slot, message) in messages.iter().enumerate() {
+        let chunks: Vec<&Chunk> = message.iter().map(|&index| &scope[index]).collect();
+        let decoded = decode_message(&chunks, chunk_type, options);
+        if output.format.is_json() {
+            let mut report = match decoded {
+                Ok(decoded) => report(&decoded, chunk_type, options),
+                Err(e) => {
+                    json!({ "chunk_type": chunk_type, "found": true, "error": e.to_string() })
+                }
+            };
+            report["slot"] = json!(slot);
+            reports.push(report);
+            continue;
+        }
+
+        println!("Slot {}:", slot);
+        match (decoded, &options.out_dir) {
+            (Ok(Decoded::Files(files)), Some(dir)) => restore_files(dir, &files, output)?,
+            (Ok(decoded), _) => print_decoded(decoded, options)?,
+            (Err(e), _) => println!("Could not decode it: {}", e),
+        }
+    }
+
+    if output.format.is_json() {
+        output
+            .format
+            .print_json(&json!({ "chunk_type": chunk_type, "messages": reports }))?;
+    } else if messages.is_empty() {
+        println!("No message hidden in this image with this chunk type");
+    }
+
+    match messages.is_empty() {
+        true => Err(CheckFailed(Code::NotFound).into()),
+        false => Ok(()),
+    }
+}
 
-    let mut png = Png::try_from(file.as_slice())?;
+/// A decoded message for `--format json`.
+fn report(decoded: &Decoded, chunk_type: &str, options: &DecodeOptions) -> serde_json::Value {
+    match decoded {
+        Decoded::Text(text) => json!({
+            "chunk_type": chunk_type,
+            "found": true,
+            "keyword": text.keyword,
+            "language": text.language,
+            "translated_keyword": text.translated_keyword,
+            "message": text.text,
+        }),
+        Decoded::Bytes(message) => json!({
+            "chunk_type": chunk_type,
+            "found": true,
+            "message": text::decode_bytes(message, options.charset, false).ok(),
+            "data": codec::encode_base64(message),
+        }),
+        Decoded::Files(files) => {
+            let files: Vec<_> = files
+                .iter()
+                .map(|file| {
+                    let mut entry = json!({
+                        "name": file.info.name,
+                        "size": file.info.size,
+                        "modified": file.info.modified,
+                        "sha256": codec::encode_hex(&file.info.sha256),
+                    });
+                    if !options.list {
+                        entry["data"] = json!(codec::encode_base64(&file.data));
+                    }
+                    entry
+                })
+                .collect();
+            json!({ "chunk_type": chunk_type, "found": true, "files": files })
+        }
+        Decoded::Part { index, total } => json!({
+            "chunk_type": chunk_type,
+            "found": true,
+            "part": index + 1,
+            "total": total,
+        }),
+        Decoded::Missing => json!({ "chunk_type": chunk_type, "found": false }),
+    }
+}
 
-    let chunk_type = ChunkType::from_str(chunk_type)?;
-    let chunk = Chunk::new(chunk_type, message.as_bytes().to_vec());
+/// Print a decoded message for a person to read.
+fn print_decoded(decoded: Decoded, options: &DecodeOptions) -> Result<()> {
+    match decoded {
+        Decoded::Text(text) => {
+            println!("Keyword: {}", text.keyword);
+            println!("Message: {:?}", text.text);
+        }
+        Decoded::Bytes(message) => {
+            let message = text::decode_bytes(&message, options.charset, false).map_err(|e| {
+                format!(
+                    "{}, decode binary messages with --raw, --hex, --base64 or --out",
+                    e
+                )
+            })?;
+            println!("Message: {:?}", message);
+        }
+        Decoded::Files(files) => {
+            println!("{:>10}  {:<23}  name", "size", "modified");
+            for file in &files {
+                let modified = file.info.modified.map(preset::format_time);
+                println!(
+                    "{:>10}  {:<23}  {}",
+                    file.info.size,
+                    modified.as_deref().unwrap_or("-"),
+                    file.info.name
+                );
+            }
+            if !options.list {
+                println!("Restore the files with --out-dir, or some of them with --extract NAME");
+            }
+        }
+        Decoded::Part { index, total } => {
+            println!(
+                "This image holds part {} of {} of a spanned message, decode it with --span",
+                index + 1,
+                total
+            );
+        }
+        Decoded::Missing => {
+            println!("No message hidden in this image with this chunk type");
+        }
+    }
+    Ok(())
+}
 
-    png.append_chunk(chunk);
+/// The files of a message named in `names`, in the order they were embedded.
+fn select_files(files: Vec<EmbeddedFile>, names: &[String]) -> Result<Vec<EmbeddedFile>> {
+    let missing = names
+        .iter()
+        .find(|&name| !files.iter().any(|file| &file.info.name == name));
+    if let Some(name) = missing {
+        return Err(format!("The message holds no file named {}", name).into());
+    }
+    Ok(files
+        .into_iter()
+        .filter(|file| names.contains(&file.info.name))
+        .collect())
+}
 
-    let _ = fs::write(file_path, png.as_bytes());
+/// Write files embedded with `encode --file` or `--add` back under their original names in
+/// `dir`, with their modification times.
+fn restore_files(dir: &Path, files: &[EmbeddedFile], output: &Output) -> Result<()> {
+    // The names come from the image, they mustn't lead anywhere but into `dir`
+    for file in files {
+        let name = Path::new(&file.info.name);
+        if name.file_name() != Some(name.as_os_str()) {
+            return Err(format!("Refusing to restore a file named {:?}", file.info.name).into());
+        }
+    }
+    if !output.dry_run {
+        fs::create_dir_all(dir)?;
+    }
 
-    println!("Message encoded successfully!");
+    for file in files {
+        let path = dir.join(&file.info.name);
+        if write_output(&path, &file.data, None, output)? {
+            if let (Some(modified), None) = (file.info.modified, staged(&path)) {
+                files::set_modified(&path, modified)?;
+            }
+            println!(
+                "Restored {} ({} bytes, SHA-256 verified) to {}",
+                file.info.name,
+                file.data.len(),
+                path.display()
+            );
+        }
+    }
+    Ok(())
+}
 
+/// Write a decoded message to `--out` as raw bytes, which printing it would mangle when it is
+/// binary or huge.
+fn save_message(message: &[u8], chunk_type: &str, output: &Output) -> Result<()> {
+    let Some(path) = &output.path else {
+        return Ok(());
+    };
+    if write_output(path, message, None, output)? {
+        println!(
+            "Wrote the {} byte message from {} to {}",
+            message.len(),
+            chunk_type,
+            path.display()
+        );
+    }
     Ok(())
 }
 
-fn decode(file_path: &PathBuf, chunk_type: &str) -> Result<()> {
-    if file_path.extension().unwrap() != "png" {
-        return Err("This program takes only PNG files".into());
+/// Write a decoded message to stdout the way --raw, --hex or --base64 asked for.
+fn show_message(message: &[u8], options: &DecodeOptions) -> Result<()> {
+    match options.rendering {
+        Some(rendering) => write_file(Path::new("-"), &rendering.render(message)),
+        None => Ok(()),
     }
+}
+
+/// [`payload::open`], asking on the terminal for a password or HMAC key the envelope needs but
+/// the command line left out.
+fn open_payload(envelope: Envelope, keys: Keys) -> Result<Zeroizing<Vec<u8>>> {
+    let password = match (&envelope.sealed, keys.password) {
+        (Some(_), None) => Some(secret::prompt("password")?),
+        _ => None,
+    };
+    let hmac_key = match (&envelope.mac, keys.hmac_key) {
+        (Some(_), None) => Some(secret::prompt("HMAC key")?),
+        _ => None,
+    };
 
-    let file = fs::read(file_path)?;
+    let keys = Keys {
+        password: keys.password.or(password.as_deref().map(String::as_str)),
+        hmac_key: keys.hmac_key.or(hmac_key.as_deref().map(String::as_bytes)),
+        ..keys
+    };
+    payload::open(envelope, keys)
+}
+
+fn encode_span(
+    file_path: &PathBuf,
+    span: &[PathBuf],
+    chunk_type: &str,
+    message: &[u8],
+    existing: Existing,
+    output: &Output,
+) -> Result<()> {
+    output.expect_many()?;
+    let paths: Vec<&PathBuf> = std::iter::once(file_path).chain(span).collect();
+
+    let mut pngs = Vec::with_capacity(paths.len());
+    let mut weights = Vec::with_capacity(paths.len());
+    for path in &paths {
+        let png = read_png(path)?;
+        weights.push(png.as_bytes().len() as u64);
+        pngs.push(png);
+    }
 
-    let png = Png::try_from(file.as_slice())?;
+    let chunk_type = output.new_chunk_type(chunk_type)?;
+    let parts = span::split(message, &weights)?;
 
-    match png.chunk_by_type(chunk_type) {
-        Some(chunk) => {
-            println!("Message: {:?}", chunk.data_as_string().unwrap());
+    let mut written = 0;
+    for (idx, ((path, original), part)) in paths.iter().zip(pngs).zip(parts).enumerate() {
+        let mut png = original.clone();
+        let chunk = Chunk::new(chunk_type.clone(), part.as_bytes());
+        match slot::make_room(&mut png, &chunk_type.to_string(), existing)? {
+            Some(index) => png.insert_chunk(index, chunk),
+            None => png.append_chunk(chunk),
         }
-        None => println!("No message hidden in this image with this chunk type"),
+        if save_png(path, idx, &original, &png, output)? {
+            written += 1;
+        }
+    }
+
+    if written > 0 {
+        println!("Message encoded successfully across {} images!", written);
     }
 
     Ok(())
 }
 
-fn remove(file_path: &PathBuf, chunk_type: &str) -> Result<()> {
-    if file_path.extension().unwrap() != "png" {
-        return Err("This program takes only PNG files".into());
+fn decode_span(
+    file_path: &PathBuf,
+    span: &[PathBuf],
+    chunk_type: &str,
+    options: &DecodeOptions,
+    output: &Output,
+) -> Result<()> {
+    output.expect_format("decode", &DOCUMENT_FORMATS)?;
+    let mut parts = vec![];
+    for path in std::iter::once(file_path).chain(span) {
+        let png = read_png(path)?;
+
+        let chunk = png
+            .chunk_by_type(chunk_type)
+            .ok_or_else(|| format!("No message part in {}", path.display()))?;
+        parts.push(Envelope::try_from(chunk.data())?);
     }
 
-    let file = fs::read(file_path)?;
+    let message = span::join(parts)?;
+    deliver(Decoded::from_message(message)?, chunk_type, options, output)
+}
+
+fn remove(file_path: &Path, chunk_type: &str, all: bool, output: &Output) -> Result<()> {
+    let original = read_png(file_path)?;
+    let mut png = original.clone();
 
-    let mut png = Png::try_from(file.as_slice())?;
+    if all {
+        let removed = png.remove_chunks(chunk_type);
+        if removed == 0 {
+            return Err(format!("No {} chunk to remove", chunk_type).into());
+        }
+        if save_png(file_path, 0, &original, &png, output)? {
+            println!("Removed {} {} chunk(s)", removed, chunk_type);
+        }
+        return Ok(());
+    }
 
     png.remove_first_chunk(chunk_type)?;
 
-    let _ = fs::write(file_path, png.as_bytes())?;
+    if save_png(file_path, 0, &original, &png, output)? {
+        println!("Message has been removed successfully!");
+    }
+
+    Ok(())
+}
+
+fn replace(
+    file_path: &Path,
+    chunk_type: &str,
+    message: &str,
+    index: usize,
+    output: &Output,
+) -> Result<()> {
+    let original = read_png(file_path)?;
+    let mut png = original.clone();
+
+    let idx = png
+        .nth_chunk_index(chunk_type, index)
+        .ok_or_else(|| format!("No {} chunk at index {}", chunk_type, index))?;
+
+    // Text chunks keep their keyword (and language), only the text changes
+    let data = if text::is_text_chunk(chunk_type) {
+        let mut text = TextualChunk::parse(&png.chunks()[idx], Charset::Auto)?;
+        text.text = message.to_string();
+        text.to_data(chunk_type)?
+    } else {
+        message.as_bytes().to_vec()
+    };
+    png.replace_chunk_data(idx, data);
 
-    println!("Message has been removed successfully!");
+    if save_png(file_path, 0, &original, &png, output)? {
+        println!("Replaced {} chunk {} in place", chunk_type, index);
+    }
 
     Ok(())
 }
 
-fn print(file_path: &PathBuf) -> Result<()> {
-    if file_path.extension().unwrap() != "png" {
-        return Err("This program takes only PNG files".into());
+fn remove_index(
+    file_path: &Path,
+    chunk_type: Option<&str>,
+    index: usize,
+    output: &Output,
+) -> Result<()> {
+    let original = read_png(file_path)?;
+    let mut png = original.clone();
+
+    let idx = match chunk_type {
+        Some(chunk_type) => png
+            .nth_chunk_index(chunk_type, index)
+            .ok_or_else(|| format!("No {} chunk at index {}", chunk_type, index))?,
+        None if index < png.chunks().len() => index,
+        None => {
+            return Err(format!(
+                "No chunk at index {}, the image has {}",
+                index,
+                png.chunks().len()
+            )
+            .into())
+        }
+    };
+    let removed = png.chunks.remove(idx);
+
+    if save_png(file_path, 0, &original, &png, output)? {
+        println!("Removed {} chunk at index {}", removed.chunk_type(), idx);
     }
 
-    let file = fs::read(file_path)?;
+    Ok(())
+}
+
+fn edit(file_path: &Path, edits: &[Edit], output: &Output) -> Result<()> {
+    let original = read_png(file_path)?;
+    let mut png = original.clone();
 
-    let png = Png::try_from(file.as_slice())?;
-    println!("{}", png);
+    let done = edit::apply(&mut png, edits)?;
+
+    if save_png(file_path, 0, &original, &png, output)? {
+        for description in done {
+            println!("{}", description);
+        }
+    }
 
     Ok(())
 }
 
-fn verify(file_path: &PathBuf) -> Result<()> {
-    if file_path.extension().unwrap() != "png" {
-        return Err("This program takes only PNG files".into());
+fn strip(file_path: &Path, keep: &[String], output: &Output) -> Result<()> {
+    let original = read_png(file_path)?;
+    let mut png = original.clone();
+
+    let removed = png.strip_ancillary(keep);
+
+    if save_png(file_path, 0, &original, &png, output)? {
+        let mut types: Vec<String> = removed
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        types.sort();
+        types.dedup();
+        println!(
+            "Stripped {} chunk(s){}",
+            removed.len(),
+            if types.is_empty() {
+                String::new()
+            } else {
+                format!(": {}", types.join(", "))
+            }
+        );
     }
 
-    let file = fs::read(file_path)?;
+    Ok(())
+}
+
+/// Run a preset over every PNG in `inputs`, reporting what it did to each file.
+fn prepare(
+    inputs: &[PathBuf],
+    output: &Output,
+    preset: impl Fn(&mut Png, &Path) -> Result<Vec<String>>,
+) -> Result<()> {
+    let mut paths = vec![];
+    for input in inputs {
+        paths.extend(files::png_files(input)?);
+    }
+    if paths.len() > 1 {
+        output.expect_many()?;
+    }
 
-    let png = Png::try_from(file.as_slice())?;
+    for (idx, path) in paths.iter().enumerate() {
+        let original = read_png(path)?;
+        let mut png = original.clone();
+        let report = preset(&mut png, path)?;
 
-    if png.verify() {
-        println!("File is a valid PNG");
-    } else {
-        println!("File is not a valid PNG");
+        if save_png(path, idx, &original, &png, output)? {
+            match report.is_empty() {
+                true => println!("{}: nothing to do", path.display()),
+                false => println!("{}: {}", path.display(), report.join(", ")),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn compare(a_path: &Path, b_path: &Path, output: &Output) -> Result<()> {
+    let (a, b) = (read_png(a_path)?, read_png(b_path)?);
+    let same = diff::diff(&a, &b)
+        .iter()
+        .all(|change| matches!(change, Change::Unchanged { .. }));
+
+    if !output.check {
+        println!("--- {}", a_path.display());
+        println!("+++ {}", b_path.display());
+        print!("{}", diff::render(&a, &b));
+    }
+
+    match same {
+        true => Ok(()),
+        false => Err(CheckFailed(Code::Differs).into()),
+    }
+}
+
+/// Whether chunks that depend on the critical chunks can move between `a` and `b`: the pixel
+/// format and palette must match, the dimensions may differ.
+fn same_color_setup(a: &Png, b: &Png) -> bool {
+    let data = |png: &Png, chunk_type| {
+        png.chunk_by_type(chunk_type)
+            .map(|chunk| chunk.data().to_vec())
+    };
+    let format = |png: &Png| data(png, "IHDR").and_then(|ihdr| ihdr.get(8..10).map(<[u8]>::to_vec));
+    format(a) == format(b) && data(a, "PLTE") == data(b, "PLTE")
+}
+
+fn copy_chunks(
+    src_path: &Path,
+    dst_path: &Path,
+    types: &[String],
+    allow_unsafe: bool,
+    output: &Output,
+) -> Result<()> {
+    let src = read_png(src_path)?;
+    let original = read_png(dst_path)?;
+    let mut png = original.clone();
+    let compatible = same_color_setup(&src, &original);
+
+    let mut copied = 0;
+    for chunk in src.chunks() {
+        let chunk_type = chunk.chunk_type();
+        if !types.contains(&chunk_type.to_string()) {
+            continue;
+        }
+        if chunk_type.is_critical() {
+            return Err(format!("Refusing to copy critical chunk {}", chunk_type).into());
+        }
+        if !chunk_type.is_safe_to_copy() && !compatible && !allow_unsafe {
+            eprintln!(
+                "Skipping {}: unsafe to copy between images with different color setups, pass --allow-unsafe to copy anyway",
+                chunk_type
+            );
+            continue;
+        }
+
+        png.insert_ordered(chunk.clone());
+        copied += 1;
+    }
+
+    if copied == 0 {
+        return Err(format!(
+            "No chunks of type {} in {}",
+            types.join(", "),
+            src_path.display()
+        )
+        .into());
+    }
+
+    if save_png(dst_path, 0, &original, &png, output)? {
+        println!(
+            "Copied {} chunk(s) from {} to {}",
+            copied,
+            src_path.display(),
+            dst_path.display()
+        );
+    }
+
+    Ok(())
+}
+
+fn scrub(file_path: &Path, output: &Output) -> Result<()> {
+    let original = read_png(file_path)?;
+    let mut png = original.clone();
+
+    let report = scrub::scrub(&mut png)?;
+    // An image piped through stdout is passed on even when there is nothing to change
+    if report.is_empty() && !output.streams(file_path) {
+        println!("Nothing to scrub in {}", file_path.display());
+        return Ok(());
+    }
+
+    if save_png(file_path, 0, &original, &png, output)? {
+        for line in &report {
+            println!("{}", line);
+        }
+        println!("Scrubbed {} item(s)", report.len());
+    }
+
+    Ok(())
+}
+
+/// Report a `finding` of a command that searches many files: a line right away for JSON lines,
+/// or kept in `findings` for one document at the end with JSON.
+fn report_finding(finding: Value, findings: &mut Vec<Value>, output: &Output) -> Result<()> {
+    match output.format {
+        Format::Jsonl => output.format.print_json(&finding),
+        _ => {
+            findings.push(finding);
+            Ok(())
+        }
+    }
+}
+
+fn print(file_path: &Path, output: &Output) -> Result<()> {
+    output.expect_format("print", &DOCUMENT_FORMATS)?;
+    let png = read_png(file_path)?;
+
+    if output.format.is_json() {
+        let chunks: Vec<_> = png
+            .chunks()
+            .iter()
+            .zip(png.chunk_offsets())
+            .enumerate()
+            .map(|(index, (chunk, offset))| {
+                json!({
+                    "index": index,
+                    "offset": offset,
+                    "type": chunk.chunk_type().to_string(),
+                    "length": chunk.length(),
+                    "crc": chunk.crc(),
+                    "data": codec::encode_base64(chunk.data()),
+                })
+            })
+            .collect();
+        return output.format.print_json(&json!({
+            "file": file_path.display().to_string(),
+            "chunks": chunks,
+        }));
+    }
+
+    print!("{}", png);
+
+    Ok(())
+}
+
+fn import_chunk(file_path: &Path, chunk_type: &str, data: Vec<u8>, output: &Output) -> Result<()> {
+    let original = read_png(file_path)?;
+    let mut png = original.clone();
+
+    let len = data.len();
+    let chunk_type = output.new_chunk_type(chunk_type)?;
+    png.append_chunk(Chunk::new(chunk_type.clone(), data));
+
+    if save_png(file_path, 0, &original, &png, output)? {
+        println!("Imported a {} chunk with {} bytes of data", chunk_type, len);
+    }
+
+    Ok(())
+}
+
+fn inject(
+    file_path: &Path,
+    chunk_type: &str,
+    data_file: &Path,
+    position: &Position,
+    output: &Output,
+) -> Result<()> {
+    let original = read_png(file_path)?;
+    let mut png = original.clone();
+
+    let data = fs::read(data_file)?;
+    let len = data.len();
+    let index = position.index(&png)?;
+    let chunk_type = output.new_chunk_type(chunk_type)?;
+    png.insert_chunk(index, Chunk::new(chunk_type.clone(), data));
+
+    if save_png(file_path, 0, &original, &png, output)? {
+        println!(
+            "Injected a {} chunk with {} bytes of data at index {}",
+            chunk_type, len, index
+        );
+    }
+
+    Ok(())
+}
+
+fn extract(
+    file_path: &Path,
+    chunk_type: &str,
+    index: usize,
+    transforms: &[Transform],
+    output: &Output,
+) -> Result<()> {
+    let png = read_png(file_path)?;
+
+    let idx = png
+        .nth_chunk_index(chunk_type, index)
+        .ok_or_else(|| format!("No {} chunk at index {}", chunk_type, index))?;
+    let data = transform::undo(png.chunks()[idx].data().to_vec(), transforms)?;
+
+    match &output.path {
+        Some(out_file) if !is_stdio(out_file) => {
+            if write_output(out_file, &data, None, output)? {
+                println!(
+                    "Wrote {} bytes of {} chunk {} to {}",
+                    data.len(),
+                    chunk_type,
+                    index,
+                    out_file.display()
+                );
+            }
+        }
+        _ => write_file(Path::new("-"), &data)?,
+    }
+
+    Ok(())
+}
+
+fn hexdump(file_path: &Path, chunk_type: &str, index: usize) -> Result<()> {
+    let png = read_png(file_path)?;
+
+    let idx = png
+        .nth_chunk_index(chunk_type, index)
+        .ok_or_else(|| format!("No {} chunk at index {}", chunk_type, index))?;
+    let chunk = &png.chunks()[idx];
+
+    println!(
+        "{} chunk {} (chunk {} of the file), {} bytes",
+        chunk_type,
+        index,
+        idx,
+        chunk.length()
+    );
+    print!("{}", codec::hexdump(chunk.data(), 0));
+
+    Ok(())
+}
+
+fn info(file_path: &Path, output: &Output) -> Result<()> {
+    output.expect_format("info", &DOCUMENT_FORMATS)?;
+    let png = read_png(file_path)?;
+
+    let ihdr = png.chunk_by_type("IHDR").ok_or("Image has no IHDR chunk")?;
+    let ihdr = IhdrChunk::try_from(ihdr.clone())?;
+
+    let idat: Vec<&Chunk> = png
+        .chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type().to_string() == "IDAT")
+        .collect();
+    let compressed: usize = idat.iter().map(|chunk| chunk.length() as usize).sum();
+
+    // Ancillary chunk types in order of first appearance, with how often they occur
+    let mut ancillary: Vec<(String, usize)> = vec![];
+    for chunk in png.chunks() {
+        if chunk.chunk_type().is_critical() {
+            continue;
+        }
+        let chunk_type = chunk.chunk_type().to_string();
+        match ancillary.iter_mut().find(|(seen, _)| *seen == chunk_type) {
+            Some((_, count)) => *count += 1,
+            None => ancillary.push((chunk_type, 1)),
+        }
+    }
+
+    let animation = apng::Animation::try_from(&png).ok();
+    let profile = png.chunk_by_type("iCCP").map(icc::Profile::try_from);
+    let extensions = extension::describe(&png);
+
+    if output.format.is_json() {
+        return output.format.print_json(&json!({
+            "file": file_path.display().to_string(),
+            "size": png.as_bytes().len(),
+            "width": ihdr.width(),
+            "height": ihdr.height(),
+            "color_type": ihdr.color_type_name(),
+            "color_type_code": ihdr.color_type(),
+            "bit_depth": ihdr.bit_depth(),
+            "interlaced": ihdr.interlace_method() == 1,
+            "idat_chunks": idat.len(),
+            "compressed_size": compressed,
+            "animation": animation.map(|animation| json!({
+                "frames": animation.frames.len(),
+                "plays": animation.control.num_plays,
+            })),
+            "profile": profile.map(|profile| match profile {
+                Ok(profile) => json!({ "name": profile.name, "size": profile.data.len() }),
+                Err(e) => json!({ "error": e.to_string() }),
+            }),
+            "extensions": extensions
+                .iter()
+                .map(|(label, value)| (label.to_string(), json!(value)))
+                .collect::<serde_json::Map<_, _>>(),
+            "ancillary": ancillary
+                .iter()
+                .map(|(chunk_type, count)| json!({ "type": chunk_type, "count": count }))
+                .collect::<Vec<_>>(),
+        }));
+    }
+
+    println!("File:        {}", file_path.display());
+    println!("Size:        {} bytes", png.as_bytes().len());
+    println!("Dimensions:  {}x{}", ihdr.width(), ihdr.height());
+    println!(
+        "Pixels:      {} ({}), {}-bit",
+        ihdr.color_type_name(),
+        ihdr.color_type(),
+        ihdr.bit_depth()
+    );
+    println!(
+        "Interlace:   {}",
+        if ihdr.interlace_method() == 1 {
+            "Adam7"
+        } else {
+            "none"
+        }
+    );
+    println!(
+        "Image data:  {} IDAT chunk(s), {} bytes compressed",
+        idat.len(),
+        compressed
+    );
+    if let Some(animation) = animation {
+        println!(
+            "Animation:   {} frames, plays: {}",
+            animation.frames.len(),
+            animation.control.num_plays
+        );
+    }
+    if let Some(profile) = profile {
+        match profile {
+            Ok(profile) => println!(
+                "Profile:     {} ({} bytes)",
+                profile.name,
+                profile.data.len()
+            ),
+            Err(e) => println!("Profile:     unreadable ({})", e),
+        }
+    }
+    for (label, value) in extensions {
+        println!("{:<13}{}", format!("{}:", label), value);
+    }
+    println!(
+        "Ancillary:   {}",
+        if ancillary.is_empty() {
+            "none".to_string()
+        } else {
+            ancillary
+                .iter()
+                .map(|(chunk_type, count)| match count {
+                    1 => chunk_type.clone(),
+                    _ => format!("{} x{}", chunk_type, count),
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+    );
+
+    Ok(())
+}
+
+/// One line of `list`.
+#[derive(Serialize)]
+struct ChunkRow {
+    index: usize,
+    offset: usize,
+    #[serde(rename = "type")]
+    chunk_type: String,
+    length: u32,
+    /// The CRC stored in the file, which may not match the data.
+    crc: u32,
+    flags: Vec<&'static str>,
+}
+
+fn chunk_rows(png: &Png, file: &[u8]) -> Vec<ChunkRow> {
+    png.chunks()
+        .iter()
+        .zip(png.chunk_offsets())
+        .enumerate()
+        .map(|(index, (chunk, offset))| {
+            // `Png` recomputes CRCs while parsing, the file tells us what was actually stored
+            let crc_at = offset + 8 + chunk.length() as usize;
+            let stored = bytes::read_be_u32(file, crc_at).unwrap_or_default();
+
+            let chunk_type = chunk.chunk_type();
+            let mut flags = vec![if chunk_type.is_critical() {
+                "critical"
+            } else {
+                "ancillary"
+            }];
+            if !chunk_type.is_public() {
+                flags.push("private");
+            }
+            if !chunk_type.is_reserved_bit_valid() {
+                flags.push("reserved");
+            }
+            if chunk_type.is_safe_to_copy() {
+                flags.push("safe-to-copy");
+            }
+            if stored != chunk.crc() {
+                flags.push("bad-crc");
+            }
+
+            ChunkRow {
+                index,
+                offset,
+                chunk_type: chunk_type.to_string(),
+                length: chunk.length(),
+                crc: stored,
+                flags,
+            }
+        })
+        .collect()
+}
+
+fn list(file_path: &Path, output: &Output) -> Result<()> {
+    let (png, file) = read_png_bytes(file_path)?;
+    let rows = chunk_rows(&png, &file);
+
+    if output.format == Format::Jsonl {
+        #[derive(Serialize)]
+        struct FileRow<'a> {
+            file: &'a str,
+            #[serde(flatten)]
+            row: &'a ChunkRow,
+        }
+
+        let file = file_path.display().to_string();
+        for row in &rows {
+            output.format.print_json(&FileRow { file: &file, row })?;
+        }
+        return Ok(());
+    }
+    if output.format.is_json() {
+        return output.format.print_json(&json!({
+            "file": file_path.display().to_string(),
+            "chunks": rows,
+        }));
+    }
+    if let Some(separator) = output.format.separator() {
+        let header = ["file", "index", "offset", "type", "length", "crc", "flags"];
+        println!("{}", format::row(&header, separator));
+        for row in rows {
+            let fields = [
+                file_path.display().to_string(),
+                row.index.to_string(),
+                row.offset.to_string(),
+                row.chunk_type,
+                row.length.to_string(),
+                format!("{:#010x}", row.crc),
+                row.flags.join(" "),
+            ];
+            println!("{}", format::row(&fields, separator));
+        }
+        return Ok(());
+    }
+
+    println!(
+        "{:>5} {:>10} {:<4} {:>10} {:>10}  flags",
+        "index", "offset", "type", "length", "crc"
+    );
+    let style = output.style;
+    for row in rows {
+        let type_color = match row.flags.contains(&"critical") {
+            true => Color::Red,
+            false => Color::Dim,
+        };
+        let crc = format!("{:>#10x}", row.crc);
+        let flags: Vec<String> = row
+            .flags
+            .iter()
+            .map(|&flag| match flag {
+                "bad-crc" => style.paint(flag, Color::Red),
+                "private" | "reserved" => style.paint(flag, Color::Yellow),
+                _ => flag.to_string(),
+            })
+            .collect();
+        println!(
+            "{:>5} {:>10} {} {:>10} {}  {}",
+            row.index,
+            row.offset,
+            style.paint(format!("{:<4}", row.chunk_type), type_color),
+            row.length,
+            match row.flags.contains(&"bad-crc") {
+                true => style.paint(crc, Color::Red),
+                false => crc,
+            },
+            flags.join(",")
+        );
+    }
+
+    Ok(())
+}
+
+/// What verify finds in a file, worked out apart from reporting it so that files can be
+/// examined in parallel.
+struct Verification {
+    /// Why the file doesn't parse, if it doesn't.
+    parsed: Result<()>,
+    valid: bool,
+    failures: Vec<repro::Failure>,
+    /// Written by [`verify`], which knows about --dry-run.
+    dumps: Vec<repro::Dump>,
+    warnings: Vec<String>,
+    seal: Option<bool>,
+}
+
+fn examine(file_path: &Path, dump_dir: Option<&Path>) -> Result<Verification> {
+    let file = read_input(file_path)?;
+    examine_file(file_path, &file, dump_dir)
+}
+
+/// The checks of [`examine`] on `file`, already read from `file_path`.
+fn examine_file(file_path: &Path, file: &[u8], dump_dir: Option<&Path>) -> Result<Verification> {
+    let png = Png::from_reader(file);
+    let failures = repro::find(file);
+    let valid = failures.is_empty() && png.as_ref().is_ok_and(Png::verify);
+
+    let dumps = match dump_dir {
+        Some(dir) => failures
+            .iter()
+            .map(|failure| repro::dump(dir, file_path, file, failure))
+            .collect::<Result<_>>()?,
+        None => vec![],
+    };
+
+    let mut warnings = vec![];
+    let mut seal = None;
+    if let Ok(png) = &png {
+        match icc::check(png) {
+            Some(Ok(found)) => warnings.extend(found.iter().map(|w| format!("iCCP {}", w))),
+            Some(Err(e)) => warnings.push(format!("iCCP is unreadable: {}", e)),
+            None => {}
+        }
+        seal = preset::check_seal(png);
+        if seal == Some(false) {
+            warnings.push("the file changed after it was sealed".to_string());
+        }
+    }
+
+    Ok(Verification {
+        parsed: png.map(drop),
+        valid,
+        failures,
+        dumps,
+        warnings,
+        seal,
+    })
+}
+
+/// What a [`Verification`] found wrong with a file, as errors and warnings.
+fn verification_problems(verification: Verification) -> (Vec<String>, Vec<String>) {
+    let mut errors: Vec<String> = verification
+        .failures
+        .into_iter()
+        .map(|failure| failure.reason)
+        .collect();
+    if let Err(error) = verification.parsed {
+        errors.push(error.to_string());
+    }
+    if !verification.valid && errors.is_empty() {
+        errors.push("not a valid PNG".to_string());
+    }
+    (errors, verification.warnings)
+}
+
+/// Examine the files of a batch `command` is about to verify on the rayon thread pool, leaving
+/// the results for [`verify`] to report in order.
+fn prefetch(command: &Commands, batch: &[PathBuf], output: &Output) {
+    let Commands::Verify {
+        dump_failing_chunk, ..
+    } = command
+    else {
+        return;
+    };
+    if rayon::current_num_threads() < 2 {
+        return;
+    }
+
+    let examined: Vec<_> = batch
+        .par_iter()
+        .filter(|path| !is_stdio(path))
+        .map(|path| {
+            // A panic would otherwise escape on a pool thread, outside any --hardened guard
+            let examined = panic::catch_unwind(|| examine(path, dump_failing_chunk.as_deref()))
+                .unwrap_or_else(|payload| Err(internal_error(payload)));
+            (path.clone(), examined)
+        })
+        .collect();
+    output.examined.borrow_mut().extend(examined);
+}
+
+fn verify(file_path: &Path, dump_dir: Option<&Path>, output: &Output) -> Result<()> {
+    output.expect_format("verify", &DOCUMENT_FORMATS)?;
+    let prefetched = output.examined.borrow_mut().remove(file_path);
+    let Verification {
+        parsed: png,
+        valid,
+        failures,
+        dumps,
+        warnings,
+        seal,
+    } = match prefetched {
+        Some(examined) => examined?,
+        None => examine(file_path, dump_dir)?,
+    };
+
+    let mut dumped = vec![];
+    if let (Some(dir), false) = (dump_dir, dumps.is_empty() || output.dry_run) {
+        fs::create_dir_all(dir)?;
+    }
+    for dump in dumps {
+        let written = write_output(&dump.chunk_path, &dump.chunk, None, output)?;
+        write_output(&dump.context_path, &dump.context, None, output)?;
+        if written {
+            dumped.push(dump.chunk_path);
+        }
+    }
+
+    let outcome = match failures.iter().any(|failure| failure.stored_crc.is_some()) {
+        _ if valid => Ok(()),
+        true => Err(CheckFailed(Code::BadCrc).into()),
+        false => Err(CheckFailed(Code::Invalid).into()),
+    };
+    if output.check {
+        png?;
+        return outcome;
+    }
+
+    if output.format.is_json() {
+        output.format.print_json(&json!({
+            "file": file_path.display().to_string(),
+            "valid": valid,
+            "failures": failures,
+            "warnings": warnings,
+            "seal": seal,
+            "dumped": dumped,
+        }))?;
+        png?;
+        return outcome;
+    }
+
+    let style = output.style;
+    match valid {
+        true => println!("{}", style.paint("File is a valid PNG", Color::Green)),
+        false => println!("{}", style.paint("File is not a valid PNG", Color::Red)),
+    }
+    for failure in &failures {
+        println!("  {}", style.paint(&failure.reason, Color::Red));
+    }
+    for path in &dumped {
+        println!("Wrote {}", path.display());
+    }
+
+    png?;
+    for warning in &warnings {
+        println!("{} {}", style.paint("Warning:", Color::Yellow), warning);
+    }
+    if seal == Some(true) {
+        println!("{}", style.paint("Seal intact", Color::Green));
+    }
+
+    outcome
+}
+
+fn sign(file_path: &Path, key_path: &Path, output: &Output) -> Result<()> {
+    let key = signature::load_signing_key(key_path)?;
+    let original = read_png(file_path)?;
+    let mut png = original.clone();
+    signature::sign(&mut png, &key)?;
+
+    if save_png(file_path, 0, &original, &png, output)? {
+        println!("Signed {}", file_path.display());
+    }
+    Ok(())
+}
+
+fn verify_signature(file_path: &Path, pubkey_path: &Path) -> Result<()> {
+    let key = signature::load_verifying_key(pubkey_path)?;
+    let png = read_png(file_path)?;
+
+    match signature::verify(&png, &key) {
+        Ok(()) => {
+            println!("{}: signature OK", file_path.display());
+            Ok(())
+        }
+        Err(error) => {
+            println!("{}: {}", file_path.display(), error);
+            let code = match error.downcast_ref() {
+                Some(SignatureError::Unsigned) => Code::NotFound,
+                Some(_) => Code::Invalid,
+                None => return Err(error),
+            };
+            Err(CheckFailed(code).into())
+        }
+    }
+}
+
+fn fix_color(file_path: &Path, output: &Output) -> Result<()> {
+    let original = read_png(file_path)?;
+
+    let problem = match icc::check(&original) {
+        None if !output.streams(file_path) => {
+            println!("{} has no embedded color profile", file_path.display());
+            return Ok(());
+        }
+        Some(Ok(warnings)) if warnings.is_empty() && !output.streams(file_path) => {
+            println!("Color profile looks fine");
+            return Ok(());
+        }
+        // An image piped through stdout is passed on even when there is nothing to fix
+        None => return save_png(file_path, 0, &original, &original, output).map(drop),
+        Some(Ok(warnings)) if warnings.is_empty() => {
+            return save_png(file_path, 0, &original, &original, output).map(drop)
+        }
+        // A profile next to sRGB is redundant rather than broken, dropping it is enough
+        Some(Ok(warnings)) => warnings
+            .iter()
+            .map(|w| w.to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+        Some(Err(e)) => e.to_string(),
+    };
+
+    let mut png = original.clone();
+    png.remove_chunks("iCCP");
+    png.insert_ordered(icc::srgb_chunk());
+
+    if save_png(file_path, 0, &original, &png, output)? {
+        println!("Replaced color profile ({}) with sRGB", problem);
+    }
+
+    Ok(())
+}
+
+fn selftest() -> Result<()> {
+    let results = selftest::run();
+
+    let mut failed = 0;
+    for (name, result) in &results {
+        match result {
+            Ok(()) => println!("PASS  {}", name),
+            Err(error) => {
+                println!("FAIL  {}: {}", name, error);
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!("{} of {} checks failed", failed, results.len()).into());
+    }
+    println!("All {} checks passed", results.len());
+
+    Ok(())
+}
+
+fn meta_set(
+    file_path: &Path,
+    offset: Option<Offset>,
+    scale: Option<Scale>,
+    stereo: Option<Stereo>,
+    output: &Output,
+) -> Result<()> {
+    let original = read_png(file_path)?;
+    let mut png = original.clone();
+
+    let chunks = [
+        offset.map(|offset| offset.to_chunk()),
+        scale.map(|scale| scale.to_chunk()),
+        stereo.map(|stereo| stereo.to_chunk()),
+    ];
+    for chunk in chunks.into_iter().flatten() {
+        png.insert_ordered(chunk);
+    }
+
+    save_png(file_path, 0, &original, &png, output)?;
+    Ok(())
+}
+
+fn meta_unset(
+    file_path: &Path,
+    offset: bool,
+    scale: bool,
+    stereo: bool,
+    output: &Output,
+) -> Result<()> {
+    let original = read_png(file_path)?;
+    let mut png = original.clone();
+
+    let chunk_types = [
+        (offset, Offset::CHUNK_TYPE),
+        (scale, Scale::CHUNK_TYPE),
+        (stereo, Stereo::CHUNK_TYPE),
+    ];
+    let removed: usize = chunk_types
+        .into_iter()
+        .filter(|(selected, _)| *selected)
+        .map(|(_, chunk_type)| png.remove_chunks(chunk_type))
+        .sum();
+
+    if removed == 0 && !output.streams(file_path) {
+        println!("Nothing to remove from {}", file_path.display());
+        return Ok(());
+    }
+
+    if save_png(file_path, 0, &original, &png, output)? {
+        println!("Removed {} chunk(s)", removed);
+    }
+    Ok(())
+}
+
+fn meta_find(
+    dir: &Path,
+    keyword: Option<&str>,
+    value: Option<&str>,
+    charset: Charset,
+    output: &Output,
+) -> Result<()> {
+    output.expect_format("meta find", &DOCUMENT_FORMATS)?;
+    let mut matches = 0;
+    let mut findings = vec![];
+
+    for path in files::png_files(dir)? {
+        let png = match Png::from_file(&path) {
+            Ok(png) => png,
+            Err(error) => {
+                eprintln!("Skipping {}: {}", path.display(), error);
+                output.recorder.skip(&path, &error.to_string());
+                continue;
+            }
+        };
+        let size = png.as_bytes().len();
+        output
+            .recorder
+            .record(&path, Status::Read, Some(size), Some(size));
+
+        for chunk in png.chunks() {
+            if !text::is_text_chunk(&chunk.chunk_type().to_string()) {
+                continue;
+            }
+            let Ok(text) = TextualChunk::parse(chunk, charset) else {
+                continue;
+            };
+
+            if keyword.is_none_or(|pattern| files::wildcard_match(pattern, &text.keyword))
+                && value.is_none_or(|pattern| files::wildcard_match(pattern, &text.text))
+            {
+                matches += 1;
+                if output.format.is_json() {
+                    let finding = json!({
+                        "file": path.display().to_string(),
+                        "keyword": text.keyword,
+                        "text": text.text,
+                    });
+                    report_finding(finding, &mut findings, output)?;
+                } else {
+                    println!("{}: {}={}", path.display(), text.keyword, text.text);
+                }
+            }
+        }
+    }
+
+    match output.format {
+        Format::Json => output.format.print_json(&json!({ "matches": findings }))?,
+        Format::Jsonl => {}
+        _ => println!("{} match(es)", matches),
+    }
+
+    Ok(())
+}
+
+fn grep(
+    pattern: &str,
+    roots: &[PathBuf],
+    ignore_case: bool,
+    charset: Charset,
+    output: &Output,
+) -> Result<()> {
+    let regex = RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .build()?;
+    output.expect_format("grep", &DOCUMENT_FORMATS)?;
+    let mut findings = vec![];
+
+    for root in roots {
+        for path in files::png_files(root)? {
+            let png = match Png::from_file(&path) {
+                Ok(png) => png,
+                Err(error) => {
+                    eprintln!("Skipping {}: {}", path.display(), error);
+                    output.recorder.skip(&path, &error.to_string());
+                    continue;
+                }
+            };
+            let size = png.as_bytes().len();
+            output
+                .recorder
+                .record(&path, Status::Read, Some(size), Some(size));
+
+            for chunk in png.chunks() {
+                let chunk_type = chunk.chunk_type().to_string();
+                if !text::is_text_chunk(&chunk_type) {
+                    continue;
+                }
+                let Ok(text) = TextualChunk::parse(chunk, charset) else {
+                    continue;
+                };
+
+                for line in text.text.lines().filter(|line| regex.is_match(line)) {
+                    if output.format.is_json() {
+                        let finding = json!({
+                            "file": path.display().to_string(),
+                            "chunk_type": chunk_type,
+                            "keyword": text.keyword,
+                            "line": line,
+                        });
+                        report_finding(finding, &mut findings, output)?;
+                        continue;
+                    }
+                    println!(
+                        "{}: {} {}: {}",
+                        path.display(),
+                        chunk_type,
+                        text.keyword,
+                        line
+                    );
+                }
+            }
+        }
+    }
+
+    if output.format == Format::Json {
+        output.format.print_json(&json!({ "matches": findings }))?;
+    }
+
+    Ok(())
+}
+
+fn scan(
+    paths: &[PathBuf],
+    db: Option<&Path>,
+    report_path: Option<&Path>,
+    output: &Output,
+) -> Result<()> {
+    let report_format = report_path.map(ReportFormat::from_path).transpose()?;
+    let files = expand_inputs(paths, true)?;
+    let db_path = db;
+    let db = open_db(db_path, output)?;
+    let mut report = report_path.map(|_| {
+        let inputs: Vec<_> = paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect();
+        Report::new(&format!("PNG audit of {}", inputs.join(", ")))
+    });
+    let keep_chunks = db.is_some() || report.is_some();
+    let verify = report.is_some();
+    let mut findings = vec![];
+    let mut flagged = 0;
+    let mut read = 0;
+
+    if let Some(separator) = output.format.separator() {
+        let header = ["file", "offset", "kind", "type", "detail"];
+        println!("{}", format::row(&header, separator));
+    }
+
+    let progress = Progress::files(files.len());
+    // Files are scanned a batch at a time on the thread pool and reported in order
+    for batch in files.chunks(rayon::current_num_threads() * 4) {
+        let scanned: Vec<_> = batch
+            .par_iter()
+            .map(|path| {
+                let file = read_input(path)?;
+                let found = scan::scan(&file)?;
+                // The chunks only go into the database and report, and a file with trailing data
+                // may not parse
+                let png = keep_chunks
+                    .then(|| Png::from_reader(file.as_slice()).ok())
+                    .flatten();
+                let verification = verify
+                    .then(|| examine_file(path, &file, None))
+                    .transpose()?;
+                Ok((file, png, found, verification))
+            })
+            .collect::<Vec<Result<_>>>();
+
+        progress.inc(batch.len() as u64);
+
+        progress.suspend(|| -> Result<()> {
+            for (path, scanned) in batch.iter().zip(scanned) {
+                let (file, png, found, verification) = match scanned {
+                    Ok(scanned) => scanned,
+                    Err(error) => {
+                        eprintln!("Skipping {}: {}", path.display(), error);
+                        output.recorder.skip(path, &error.to_string());
+                        if let Some(report) = &mut report {
+                            report.add_problems(path, vec![error.to_string()], vec![]);
+                        }
+                        continue;
+                    }
+                };
+                output
+                    .recorder
+                    .record(path, Status::Read, Some(file.len()), Some(file.len()));
+                read += 1;
+                flagged += usize::from(!found.is_empty());
+                if let Some(db) = &db {
+                    let id = db.add_file(path, &file, png.as_ref())?;
+                    db.add_findings(id, &found)?;
+                }
+                if let Some(report) = &mut report {
+                    if let Some(png) = &png {
+                        report.stats.add(path, file.len() as u64, png);
+                    }
+                    report.add_findings(path, &found);
+                    if let Some(verification) = verification {
+                        let (errors, warnings) = verification_problems(verification);
+                        report.add_problems(path, errors, warnings);
+                    }
+                }
+
+                for finding in found {
+                    let file = path.display().to_string();
+                    if let Some(separator) = output.format.separator() {
+                        let fields = [
+                            file,
+                            finding.offset.to_string(),
+                            finding.kind.to_string(),
+                            finding.chunk_type.unwrap_or_default(),
+                            finding.detail,
+                        ];
+                        println!("{}", format::row(&fields, separator));
+                    } else if output.format.is_json() {
+                        let mut finding = serde_json::to_value(finding)?;
+                        finding["file"] = file.into();
+                        report_finding(finding, &mut findings, output)?;
+                    } else {
+                        println!(
+                            "{}: {} at offset {}: {}",
+                            file,
+                            output.style.paint(finding.kind, Color::Yellow),
+                            finding.offset,
+                            finding.detail
+                        );
+                    }
+                }
+            }
+            Ok(())
+        })?;
+    }
+    drop(progress);
+    finish_db(db, db_path, read, output)?;
+    if let (Some(report), Some(path), Some(format)) = (report, report_path, report_format) {
+        if write_output(path, report.render(format).as_bytes(), None, output)? {
+            tracing::info!("wrote report to {}", path.display());
+        }
+    }
+
+    match output.format {
+        Format::Json => output.format.print_json(&json!({ "findings": findings }))?,
+        Format::Text => println!("{} of {} file(s) flagged", flagged, files.len()),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Open the `--db` database, which a dry run leaves alone.
+fn open_db(path: Option<&Path>, output: &Output) -> Result<Option<Db>> {
+    match output.dry_run {
+        true => Ok(None),
+        false => path.map(Db::open).transpose(),
+    }
+}
+
+/// Commit the `--db` database, or on a dry run say what would have gone into it.
+fn finish_db(db: Option<Db>, path: Option<&Path>, files: usize, output: &Output) -> Result<()> {
+    if let Some(db) = db {
+        return db.commit();
+    }
+    if let Some(path) = path.filter(|_| output.dry_run) {
+        println!(
+            "Dry run, would record {} file(s) in {}",
+            files,
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Read every PNG in `files` a batch at a time on the thread pool, turning each into a value
+/// with `f` there, then handing it to `each` in the order of `files`. Files that can't be read
+/// are skipped.
+fn read_each<T: Send>(
+    files: &[PathBuf],
+    output: &Output,
+    f: impl Fn(Png, &[u8]) -> Result<T> + Sync,
+    mut each: impl FnMut(&Path, u64, T),
+) {
+    let progress = Progress::files(files.len());
+    for batch in files.chunks(rayon::current_num_threads() * 4) {
+        let results: Vec<_> = batch
+            .par_iter()
+            .map(|path| {
+                let (png, file) = read_png_bytes(path)?;
+                Ok((file.len(), f(png, &file)?))
+            })
+            .collect::<Vec<Result<_>>>();
+
+        progress.inc(batch.len() as u64);
+
+        for (path, result) in batch.iter().zip(results) {
+            match result {
+                Ok((size, value)) => {
+                    output
+                        .recorder
+                        .record(path, Status::Read, Some(size), Some(size));
+                    each(path, size as u64, value);
+                }
+                Err(error) => {
+                    progress.suspend(|| eprintln!("Skipping {}: {}", path.display(), error));
+                    output.recorder.skip(path, &error.to_string());
+                }
+            }
+        }
+    }
+}
+
+fn stats(paths: &[PathBuf], top: usize, db: Option<&Path>, output: &Output) -> Result<()> {
+    output.expect_format("stats", &DOCUMENT_FORMATS)?;
+    let files = expand_inputs(paths, true)?;
+    let db_path = db;
+    let db = open_db(db_path, output)?;
+    let keep_file = db.is_some();
+
+    let mut stats = Stats::new(top);
+    let mut read = 0;
+    let mut written = Ok(());
+    read_each(
+        &files,
+        output,
+        // The file is only kept around to be hashed for the database
+        |png, file| Ok((png, keep_file.then(|| file.to_vec()))),
+        |path, size, (png, file)| {
+            stats.add(path, size, &png);
+            read += 1;
+            if let (Some(db), Some(file), Ok(())) = (&db, file, &written) {
+                written = db.add_file(path, &file, Some(&png)).map(|_| ());
+            }
+        },
+    );
+    written?;
+    finish_db(db, db_path, read, output)?;
+
+    match output.format {
+        Format::Text => print!("{}", stats),
+        _ => output.format.print_json(&stats)?,
+    }
+
+    Ok(())
+}
+
+fn dupes(paths: &[PathBuf], pixels: bool, output: &Output) -> Result<()> {
+    output.expect_format("dupes", &DOCUMENT_FORMATS)?;
+    let files = expand_inputs(paths, true)?;
+    let compare = match pixels {
+        true => Compare::Pixels,
+        false => Compare::ImageData,
+    };
+
+    let mut hashed = vec![];
+    read_each(
+        &files,
+        output,
+        |png, _| dupes::content_hash(&png, compare),
+        |path, size, hash| {
+            let member = dupes::Member {
+                path: path.to_path_buf(),
+                size,
+            };
+            hashed.push((member, hash));
+        },
+    );
+
+    let groups = dupes::group(hashed);
+    let wasted: u64 = groups.iter().map(|group| group.wasted).sum();
+    if output.format.is_json() {
+        return output
+            .format
+            .print_json(&json!({ "groups": groups, "wasted": wasted }));
+    }
+
+    for group in &groups {
+        println!(
+            "{} copies, {} wasted:",
+            group.files.len(),
+            png::human_size(group.wasted as usize)
+        );
+        for member in &group.files {
+            println!(
+                "  {:>11}  {}",
+                png::human_size(member.size as usize),
+                member.path.display()
+            );
+        }
+    }
+    println!(
+        "{} group(s) of duplicates, {} wasted",
+        groups.len(),
+        png::human_size(wasted as usize)
+    );
+
+    Ok(())
+}
+
+fn phash(paths: &[PathBuf], near_dupes: bool, threshold: u32, output: &Output) -> Result<()> {
+    output.expect_format("phash", &DOCUMENT_FORMATS)?;
+    let files = expand_inputs(paths, true)?;
+
+    let mut hashed: Vec<(PathBuf, u64)> = vec![];
+    read_each(
+        &files,
+        output,
+        |png, _| Ok(phash::phash(&raster::decode(&png)?)),
+        |path, _, hash| {
+            if !near_dupes {
+                match output.format {
+                    Format::Text => println!("{:016x}  {}", hash, path.display()),
+                    Format::Jsonl => {
+                        let entry = json!({ "path": path, "hash": format!("{:016x}", hash) });
+                        let _ = output.format.print_json(&entry);
+                    }
+                    _ => {}
+                }
+            }
+            hashed.push((path.to_path_buf(), hash));
+        },
+    );
+
+    if !near_dupes {
+        if output.format == Format::Json {
+            let entries: Vec<_> = hashed
+                .iter()
+                .map(|(path, hash)| json!({ "path": path, "hash": format!("{:016x}", hash) }))
+                .collect();
+            output.format.print_json(&json!({ "files": entries }))?;
+        }
+        return Ok(());
+    }
+
+    let hashes: Vec<u64> = hashed.iter().map(|(_, hash)| *hash).collect();
+    let clusters = phash::cluster(&hashes, threshold);
+    let members = |cluster: &[usize]| -> Vec<Value> {
+        let first = hashes[cluster[0]];
+        cluster
+            .iter()
+            .map(|&i| {
+                let (path, hash) = &hashed[i];
+                json!({
+                    "path": path,
+                    "hash": format!("{:016x}", hash),
+                    "distance": phash::distance(first, *hash),
+                })
+            })
+            .collect()
+    };
+
+    match output.format {
+        Format::Text => {
+            for cluster in &clusters {
+                println!("{} similar images:", cluster.len());
+                for &i in cluster {
+                    let (path, hash) = &hashed[i];
+                    let distance = phash::distance(hashes[cluster[0]], *hash);
+                    println!("  {:016x} {:>3}  {}", hash, distance, path.display());
+                }
+            }
+            println!(
+                "{} cluster(s) of near duplicates among {} file(s)",
+                clusters.len(),
+                hashed.len()
+            );
+        }
+        Format::Jsonl => {
+            for cluster in &clusters {
+                output.format.print_json(&members(cluster))?;
+            }
+        }
+        _ => {
+            let clusters: Vec<_> = clusters.iter().map(|cluster| members(cluster)).collect();
+            output.format.print_json(&json!({ "clusters": clusters }))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Run the jobs listed in `manifest` in order. Files they write are only written once every
+/// job has succeeded, so a failing job leaves everything as it was.
+fn run_jobs(manifest: &Path, output: &Output) -> Result<()> {
+    let jobs = jobs::parse(&fs::read_to_string(manifest)?)?;
+
+    *STAGED.lock().unwrap_or_else(PoisonError::into_inner) = Some(BTreeMap::new());
+    let result = jobs
+        .iter()
+        .enumerate()
+        .try_for_each(|(index, job)| run_job(index + 1, job, output));
+    let staged = STAGED
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take()
+        .unwrap_or_default();
+    if let Err(error) = result {
+        if !staged.is_empty() {
+            eprintln!("No files were written");
+        }
+        return Err(error);
+    }
+
+    for (path, bytes) in &staged {
+        files::write_atomic(path, bytes)?;
+    }
+    if output.format == Format::Text && !output.check {
+        println!("{} job(s) done, wrote {} file(s)", jobs.len(), staged.len());
+    }
+    Ok(())
+}
+
+/// Run the `number`th job of a manifest with the options of `output`, and its own.
+fn run_job(number: usize, job: &Job, output: &Output) -> Result<()> {
+    let args =
+        Cli::try_parse_from(std::iter::once("pngme").chain(job.args.iter().map(String::as_str)))
+            .map_err(|error| {
+                format!(
+                    "Job {} ({}): {}",
+                    number,
+                    job.op,
+                    error.render().to_string().trim_end()
+                )
+            })?;
+    let mut job_output = output_for(&args);
+    job_output.dry_run |= output.dry_run;
+    job_output.check |= output.check;
+
+    if job_output.format == Format::Text && !job_output.check {
+        println!("==> Job {}: {} <==", number, job);
+    }
+    // The error keeps its exit code, the job it came from goes to stderr
+    dispatch_each(&args.command, &job_output, args.recursive, dispatch).inspect_err(|_| {
+        eprintln!("Job {} ({}) failed", number, job);
+    })
+}
+
+/// A message found by `messages`.
+#[derive(Serialize)]
+struct MessageRow {
+    chunk_type: String,
+    slot: usize,
+    /// More than one for a message split over several chunks.
+    chunks: usize,
+    /// Chunk data in total, headers of the payload included.
+    size: usize,
+    /// Seconds since the Unix epoch, for messages embedded with a payload header.
+    created: Option<u64>,
+    payload: String,
+}
+
+fn message_rows(png: &Png) -> Vec<MessageRow> {
+    let mut rows = vec![];
+    for chunk_type in slot::message_types(png.chunks()) {
+        let messages = slot::messages(png.chunks(), &chunk_type);
+        for (slot, message) in messages.iter().enumerate() {
+            let chunks: Vec<&Chunk> = message.iter().map(|&index| &png.chunks()[index]).collect();
+            let joined = span::join_chunks(chunks.iter().map(|chunk| chunk.data()));
+            let data = match &joined {
+                Ok(Some(joined)) => joined.as_slice(),
+                _ => chunks[0].data(),
+            };
+
+            // Look through error correction to the payload it protects
+            let protection = Envelope::try_from(data)
+                .ok()
+                .filter(|envelope| envelope.protection.is_some())
+                .map(|envelope| payload::describe(&envelope));
+            let repaired = payload::repair(data).ok().flatten();
+            let data = repaired.as_ref().map_or(data, |(data, _)| data.as_slice());
+
+            let (created, mut payload) = match Envelope::is_envelope(data) {
+                true => match Envelope::try_from(data) {
+                    Ok(envelope) => (envelope.created, payload::describe(&envelope)),
+                    Err(e) => (None, format!("unreadable: {}", e)),
+                },
+                false => (None, "plain".to_string()),
+            };
+            if let Some(protection) = protection {
+                payload = format!("{}, {}", payload, protection);
+            }
+            rows.push(MessageRow {
+                chunk_type: chunk_type.clone(),
+                slot,
+                chunks: chunks.len(),
+                size: chunks.iter().map(|chunk| chunk.data().len()).sum(),
+                created,
+                payload,
+            });
+        }
+    }
+    rows
+}
+
+fn messages(file_path: &Path, output: &Output) -> Result<()> {
+    output.expect_format("messages", &DOCUMENT_FORMATS)?;
+    let png = read_png(file_path)?;
+    let rows = message_rows(&png);
+
+    if output.format == Format::Jsonl {
+        #[derive(Serialize)]
+        struct FileRow<'a> {
+            file: &'a str,
+            #[serde(flatten)]
+            row: &'a MessageRow,
+        }
+
+        let file = file_path.display().to_string();
+        for row in &rows {
+            output.format.print_json(&FileRow { file: &file, row })?;
+        }
+        return Ok(());
+    }
+    if output.format.is_json() {
+        return output.format.print_json(&json!({
+            "file": file_path.display().to_string(),
+            "messages": rows,
+        }));
+    }
+
+    if rows.is_empty() {
+        println!("No messages hidden in {}", file_path.display());
+        return Ok(());
+    }
+    println!(
+        "{:<4}  {:>4}  {:>10}  {:<23}  payload",
+        "type", "slot", "size", "created"
+    );
+    for row in rows {
+        let created = row.created.map(preset::format_time);
+        let payload = match row.chunks {
+            1 => row.payload,
+            chunks => format!("{} chunks, {}", chunks, row.payload),
+        };
+        println!(
+            "{:<4}  {:>4}  {:>10}  {:<23}  {}",
+            row.chunk_type,
+            row.slot,
+            row.size,
+            created.as_deref().unwrap_or("-"),
+            payload
+        );
+    }
+
+    Ok(())
+}
+
+fn payload_map(file_path: &Path) -> Result<()> {
+    let png = read_png(file_path)?;
+    let file_len = png.as_bytes().len();
+
+    println!("{:>10} {:>10}  {:<4}  payload", "offset", "length", "type");
+
+    let mut reserved = 0;
+    let mut payloads = 0;
+    for (chunk, offset) in png.chunks().iter().zip(png.chunk_offsets()) {
+        let len = chunk.as_bytes().len();
+        let description = if Envelope::is_envelope(chunk.data()) {
+            reserved += len;
+            payloads += 1;
+            match Envelope::try_from(chunk.data()) {
+                Ok(envelope) => payload::describe(&envelope),
+                Err(e) => format!("unreadable: {}", e),
+            }
+        } else {
+            "-".to_string()
+        };
+
+        println!(
+            "{:>10} {:>10}  {:<4}  {}",
+            offset,
+            len,
+            chunk.chunk_type(),
+            description
+        );
+    }
+
+    println!(
+        "Reserved: {} payload chunk(s), {} of {} bytes ({:.1}%)",
+        payloads,
+        reserved,
+        file_len,
+        reserved as f64 * 100.0 / file_len as f64
+    );
+    match limits::active().free_capacity(file_len as u64, png.chunks().len()) {
+        Some(free) => println!(
+            "Free: {} bytes for new payloads under the active limits",
+            free
+        ),
+        None => println!("Free: no limit on new payloads, --hardened sets one"),
+    }
+
+    Ok(())
+}
+
+fn apng_extract(file_path: &Path, out_dir: &Path, output: &Output) -> Result<()> {
+    output.expect_many()?;
+    let png = read_png(file_path)?;
+
+    let animation = apng::Animation::try_from(&png)?;
+    let frames = apng::render_frames(&png)?;
+
+    if !output.dry_run {
+        fs::create_dir_all(out_dir)?;
+    }
+    for (idx, frame) in frames.iter().enumerate() {
+        let bytes = raster::encode(frame)?.as_bytes();
+        let path = output.path_for(&out_dir.join(format!("frame_{:03}.png", idx)), &bytes, idx);
+        write_output(&path, &bytes, None, output)?;
+    }
+
+    // The default image is shown by viewers without APNG support, keep it when it isn't a frame
+    if !animation.default_image_is_first_frame {
+        let bytes = raster::encode(&raster::decode(&png)?)?.as_bytes();
+        let path = output.path_for(&out_dir.join("default.png"), &bytes, frames.len());
+        write_output(&path, &bytes, None, output)?;
+    }
+    if output.dry_run {
+        return Ok(());
+    }
+
+    println!(
+        "Extracted {} frames (plays: {}) to {}",
+        frames.len(),
+        animation.control.num_plays,
+        out_dir.display()
+    );
+
+    Ok(())
+}
+
+fn apng_build(inputs: &[PathBuf], delay: Delay, loops: u32, output: &Output) -> Result<()> {
+    let output_file = output
+        .path
+        .clone()
+        .ok_or("apng build needs -o/--output to know where to write the animation")?;
+
+    let mut paths = vec![];
+    for input in inputs {
+        if input.is_dir() {
+            let mut entries: Vec<PathBuf> = fs::read_dir(input)?
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| files::has_png_signature(path).unwrap_or(false))
+                .collect();
+            entries.sort();
+            paths.extend(entries);
+        } else {
+            paths.push(input.clone());
+        }
+    }
+
+    let mut pngs = Vec::with_capacity(paths.len());
+    for path in &paths {
+        let png = read_png(path)?;
+        let size = png.as_bytes().len();
+        output
+            .recorder
+            .record(path, Status::Read, Some(size), Some(size));
+        pngs.push(png);
+    }
+
+    // Frames that share a pixel format are stored as they are, anything else is converted to RGBA
+    let animation = match apng::build_from_pngs(&pngs, delay, loops) {
+        Err(e) if matches!(e.downcast_ref(), Some(apng::ApngError::MismatchedFormat)) => {
+            eprintln!("{}, converting every frame to RGBA", e);
+            let frames = pngs
+                .iter()
+                .map(raster::decode)
+                .collect::<Result<Vec<_>>>()?;
+            apng::build(&frames, delay, loops)?
+        }
+        result => result?,
+    };
+    let bytes = animation.as_bytes();
+    if !write_output(&output_file, &bytes, None, output)? {
+        return Ok(());
+    }
+
+    println!(
+        "Built {} with {} frames, {} per frame",
+        output_file.display(),
+        pngs.len(),
+        delay
+    );
+
+    Ok(())
+}
+
+fn apng_to_gif(file_path: &Path, gif_path: &Path, output: &Output) -> Result<()> {
+    let png = read_png(file_path)?;
+
+    let mut gif = vec![];
+    convert::apng_to_gif(&png, &mut gif)?;
+    if !write_output(gif_path, &gif, Some(png.as_bytes().len()), output)? {
+        return Ok(());
+    }
+
+    println!(
+        "Converted {} to {}",
+        file_path.display(),
+        gif_path.display()
+    );
+
+    Ok(())
+}
+
+fn apng_to_sheet(
+    file_path: &Path,
+    sheet_path: &Path,
+    manifest_path: Option<&Path>,
+    output: &Output,
+) -> Result<()> {
+    let png = read_png(file_path)?;
+    let (sheet, manifest) = convert::apng_to_sheet(&png)?;
+
+    let manifest_path = manifest_path
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| sheet_path.with_extension("json"));
+    let sheet = sheet.as_bytes();
+    let before = Some(png.as_bytes().len());
+    let manifest_json = serde_json::to_string_pretty(&manifest)?;
+    let written = write_output(sheet_path, &sheet, before, output)?;
+    if !write_output(&manifest_path, manifest_json.as_bytes(), None, output)? || !written {
+        return Ok(());
+    }
+
+    println!(
+        "Wrote a {} frame sheet to {} and its manifest to {}",
+        manifest.frames.len(),
+        sheet_path.display(),
+        manifest_path.display()
+    );
+
+    Ok(())
+}
+
+fn apng_drop_frame(file_path: &Path, frame: usize, output: &Output) -> Result<()> {
+    let original = read_png(file_path)?;
+    let mut png = original.clone();
+
+    apng::drop_frame(&mut png, frame)?;
+
+    if save_png(file_path, 0, &original, &png, output)? {
+        println!("Dropped frame {}", frame);
+    }
+
+    Ok(())
+}
+
+fn apng_retime(
+    file_path: &Path,
+    delay: Option<Delay>,
+    speed: Option<f64>,
+    frame: Option<usize>,
+    loops: Option<u32>,
+    output: &Output,
+) -> Result<()> {
+    let original = read_png(file_path)?;
+    let mut png = original.clone();
+
+    if let Some(speed) = speed {
+        if speed <= 0.0 || !speed.is_finite() {
+            return Err("Speed must be a positive number".into());
+        }
+    }
+
+    let mut report = vec![];
+    if delay.is_some() || speed.is_some() {
+        let changed = apng::set_delays(&mut png, frame, |current| match (delay, speed) {
+            (Some(delay), _) => delay,
+            (None, Some(speed)) => {
+                Delay::from_millis(current.millis() as f64 / speed).unwrap_or(current)
+            }
+            (None, None) => current,
+        })?;
+        report.push(format!("Retimed {} frame(s)", changed));
+    }
+
+    if let Some(loops) = loops {
+        apng::set_plays(&mut png, loops)?;
+        report.push(format!("Set loop count to {}", loops));
+    }
+
+    if save_png(file_path, 0, &original, &png, output)? {
+        for line in &report {
+            println!("{}", line);
+        }
     }
 
     Ok(())
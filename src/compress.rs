@@ -0,0 +1,121 @@
+use std::{io::Write, str::FromStr};
+
+use flate2::{write::ZlibEncoder, Compression as Level};
+
+use crate::{limits, Result};
+
+#[derive(Debug)]
+pub enum CompressError {
+    Unknown(String),
+    Unsupported(u8),
+}
+
+impl std::error::Error for CompressError {}
+
+impl std::fmt::Display for CompressError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CompressError::Unknown(name) => {
+                write!(f, "Unknown compression '{}', expected zlib or zstd", name)
+            }
+            CompressError::Unsupported(id) => {
+                write!(f, "Payload uses an unsupported compression (id {})", id)
+            }
+        }
+    }
+}
+
+/// How a payload was compressed before being encrypted and embedded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Zlib,
+    /// Smaller and faster than zlib on most text.
+    Zstd,
+}
+
+impl Compression {
+    const ZSTD_LEVEL: i32 = 19;
+
+    /// Identifies the compression in the payload header.
+    pub fn id(self) -> u8 {
+        match self {
+            Compression::Zlib => 1,
+            Compression::Zstd => 2,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Result<Self> {
+        match id {
+            1 => Ok(Compression::Zlib),
+            2 => Ok(Compression::Zstd),
+            _ => Err(CompressError::Unsupported(id).into()),
+        }
+    }
+
+    pub fn compress(self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(match self {
+            Compression::Zlib => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Level::best());
+                encoder.write_all(data)?;
+                encoder.finish()?
+            }
+            Compression::Zstd => zstd::encode_all(data, Self::ZSTD_LEVEL)?,
+        })
+    }
+
+    /// Reverse of [`Compression::compress`], bounded by the active inflate limit.
+    pub fn decompress(self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Compression::Zlib => limits::inflate(data, u64::MAX),
+            Compression::Zstd => limits::unzstd(data),
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = CompressError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, CompressError> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "zlib" | "deflate" => Ok(Compression::Zlib),
+            "zstd" | "zstandard" => Ok(Compression::Zstd),
+            _ => Err(CompressError::Unknown(s.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Compression::Zlib => write!(f, "zlib"),
+            Compression::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let text = "the quick brown fox jumps over the lazy dog ".repeat(50);
+        for compression in [Compression::Zlib, Compression::Zstd] {
+            let compressed = compression.compress(text.as_bytes()).unwrap();
+            assert!(compressed.len() < text.len() / 4);
+            assert_eq!(
+                compression.decompress(&compressed).unwrap(),
+                text.as_bytes()
+            );
+            assert_eq!(Compression::from_id(compression.id()).unwrap(), compression);
+        }
+        assert!(Compression::Zstd.decompress(b"not zstd").is_err());
+    }
+
+    #[test]
+    fn test_parse_compression() {
+        assert_eq!("ZSTD".parse::<Compression>().unwrap(), Compression::Zstd);
+        assert!("brotli".parse::<Compression>().is_err());
+        assert!(Compression::from_id(0).is_err());
+    }
+}
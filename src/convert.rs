@@ -0,0 +1,200 @@
+use std::io::Write;
+
+use gif::{Encoder, Frame, Repeat};
+use serde::Serialize;
+
+use crate::{
+    apng::{self, Animation},
+    limits,
+    png::Png,
+    raster::{self, Image},
+    Result,
+};
+
+/// NeuQuant sampling factor: 1 is the best quality, 30 the fastest.
+const QUANTIZE_SPEED: i32 = 10;
+
+#[derive(Debug)]
+pub enum ConvertError {
+    TooLargeForGif(u32, u32),
+    TooLargeForSheet(usize),
+}
+
+impl std::error::Error for ConvertError {}
+
+impl std::fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConvertError::TooLargeForGif(width, height) => write!(
+                f,
+                "A {}x{} animation is larger than GIF allows (65535x65535)",
+                width, height
+            ),
+            ConvertError::TooLargeForSheet(frames) => {
+                write!(f, "{} frames don't fit side by side in one PNG", frames)
+            }
+        }
+    }
+}
+
+/// Convert an animated PNG into an animated GIF with the same frame timings and loop count.
+///
+/// Every frame is composited onto the full canvas and quantized to its own 256 color palette.
+pub fn apng_to_gif<W: Write>(png: &Png, writer: W) -> Result<()> {
+    let animation = Animation::try_from(png)?;
+    let frames = apng::render_frames(png)?;
+
+    let (width, height) = frames
+        .first()
+        .map(|frame| (frame.width, frame.height))
+        .unwrap_or_default();
+    let too_large = || ConvertError::TooLargeForGif(width, height);
+    let gif_width = u16::try_from(width).map_err(|_| too_large())?;
+    let gif_height = u16::try_from(height).map_err(|_| too_large())?;
+
+    let mut encoder = Encoder::new(writer, gif_width, gif_height, &[])?;
+
+    // GIF counts repeats after the first play, APNG counts plays
+    match animation.control.num_plays {
+        0 => encoder.set_repeat(Repeat::Infinite)?,
+        1 => {}
+        plays => encoder.set_repeat(Repeat::Finite((plays - 1).min(u16::MAX as u32) as u16))?,
+    }
+
+    for (image, frame) in frames.into_iter().zip(&animation.frames) {
+        let mut pixels = image.pixels;
+        let mut gif_frame =
+            Frame::from_rgba_speed(gif_width, gif_height, &mut pixels, QUANTIZE_SPEED);
+        // GIF delays are in hundredths of a second
+        gif_frame.delay = (frame.control.delay().millis() / 10).min(u16::MAX as u64) as u16;
+        encoder.write_frame(&gif_frame)?;
+    }
+
+    Ok(())
+}
+
+/// Where a frame sits in a sprite sheet and how long it is shown.
+#[derive(Debug, Serialize)]
+pub struct SheetFrame {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub delay_ms: u64,
+}
+
+/// The timing manifest written next to a sprite sheet.
+#[derive(Debug, Serialize)]
+pub struct SheetManifest {
+    pub frame_width: u32,
+    pub frame_height: u32,
+    /// 0 loops forever.
+    pub loops: u32,
+    pub frames: Vec<SheetFrame>,
+}
+
+/// Lay out the composited frames of an animated PNG left to right in a single still image.
+pub fn apng_to_sheet(png: &Png) -> Result<(Png, SheetManifest)> {
+    let animation = Animation::try_from(png)?;
+    let frames = apng::render_frames(png)?;
+
+    let (width, height) = frames
+        .first()
+        .map(|frame| (frame.width, frame.height))
+        .unwrap_or_default();
+    let sheet_width = u32::try_from(frames.len())
+        .ok()
+        .and_then(|count| count.checked_mul(width))
+        .ok_or(ConvertError::TooLargeForSheet(frames.len()))?;
+    limits::active().check_dimensions(sheet_width, height)?;
+
+    let mut sheet = Image::new(sheet_width, height);
+    let mut manifest = SheetManifest {
+        frame_width: width,
+        frame_height: height,
+        loops: animation.control.num_plays,
+        frames: Vec::with_capacity(frames.len()),
+    };
+
+    for (idx, (image, frame)) in frames.iter().zip(&animation.frames).enumerate() {
+        let left = idx as u32 * width;
+        for y in 0..height {
+            for x in 0..width {
+                sheet.set_pixel(left + x, y, image.pixel(x, y));
+            }
+        }
+        manifest.frames.push(SheetFrame {
+            x: left,
+            y: 0,
+            width,
+            height,
+            delay_ms: frame.control.delay().millis(),
+        });
+    }
+
+    Ok((raster::encode(&sheet)?, manifest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apng::Delay;
+
+    fn solid(rgba: [u8; 4]) -> Image {
+        let mut image = Image::new(4, 3);
+        for y in 0..3 {
+            for x in 0..4 {
+                image.set_pixel(x, y, rgba);
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn test_apng_to_gif() {
+        let frames = vec![solid([255, 0, 0, 255]), solid([0, 0, 255, 255])];
+        let png = apng::build(
+            &frames,
+            Delay {
+                num: 250,
+                den: 1000,
+            },
+            0,
+        )
+        .unwrap();
+
+        let mut gif = vec![];
+        apng_to_gif(&png, &mut gif).unwrap();
+
+        let mut decoder = gif::DecodeOptions::new().read_info(gif.as_slice()).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (4, 3));
+
+        let mut delays = vec![];
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        assert_eq!(delays, vec![25, 25]);
+    }
+
+    #[test]
+    fn test_apng_to_sheet() {
+        let frames = vec![solid([255, 0, 0, 255]), solid([0, 0, 255, 255])];
+        let png = apng::build(&frames, Delay { num: 1, den: 10 }, 3).unwrap();
+
+        let (sheet, manifest) = apng_to_sheet(&png).unwrap();
+        let sheet = raster::decode(&sheet).unwrap();
+
+        assert_eq!((sheet.width, sheet.height), (8, 3));
+        assert_eq!(sheet.pixel(3, 0), [255, 0, 0, 255]);
+        assert_eq!(sheet.pixel(4, 2), [0, 0, 255, 255]);
+        assert_eq!(manifest.loops, 3);
+        assert_eq!(
+            manifest
+                .frames
+                .iter()
+                .map(|frame| (frame.x, frame.delay_ms))
+                .collect::<Vec<_>>(),
+            [(0, 100), (4, 100)]
+        );
+    }
+}
@@ -0,0 +1,408 @@
+use std::str::FromStr;
+
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Nonce,
+};
+use chacha20poly1305::ChaCha20Poly1305;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use crate::{
+    bytes::{put_be_u32, read_be_u32, to_usize},
+    Result,
+};
+
+pub const SALT_LEN: usize = 16;
+pub const MAC_LEN: usize = 32;
+/// Slots in every passphrase-encrypted body, whether or not there is a decoy.
+pub const SLOTS: usize = 2;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+
+/// Largest Argon2 memory cost accepted from a payload header, so a crafted image can't make
+/// decoding allocate without bound.
+pub const MAX_KDF_MEMORY: u32 = 4 * 1024 * 1024;
+pub const MAX_KDF_ITERATIONS: u32 = 1024;
+
+#[derive(Debug)]
+pub enum CryptoError {
+    UnknownCipher(String),
+    UnsupportedCipher(u8),
+    Random,
+    InvalidKdfParams,
+    KeyDerivation,
+    Encryption,
+    /// Wrong passphrase or tampered ciphertext, an AEAD can't tell the two apart.
+    Decryption,
+    InvalidLayout,
+    /// The data or its tag changed, or the key is not the one the tag was made with.
+    MacMismatch,
+}
+
+impl std::error::Error for CryptoError {}
+
+impl std::fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CryptoError::UnknownCipher(cipher) => write!(
+                f,
+                "Unknown cipher '{}', expected aes-gcm or chacha20",
+                cipher
+            ),
+            CryptoError::UnsupportedCipher(id) => {
+                write!(f, "Payload uses an unsupported cipher (id {})", id)
+            }
+            CryptoError::Random => write!(f, "Could not gather random bytes from the OS"),
+            CryptoError::InvalidKdfParams => write!(
+                f,
+                "Argon2 memory must be between {} and {} KiB and iterations between 1 and {}",
+                argon2::Params::MIN_M_COST,
+                MAX_KDF_MEMORY,
+                MAX_KDF_ITERATIONS
+            ),
+            CryptoError::KeyDerivation => write!(f, "Could not derive a key from the passphrase"),
+            CryptoError::Encryption => write!(f, "Could not encrypt the payload"),
+            CryptoError::Decryption => {
+                write!(f, "Wrong passphrase or the payload has been tampered with")
+            }
+            CryptoError::InvalidLayout => write!(f, "Encrypted payload has an invalid layout"),
+            CryptoError::MacMismatch => write!(
+                f,
+                "HMAC check failed: the message was altered or the key is wrong"
+            ),
+        }
+    }
+}
+
+/// The AEAD used to encrypt payloads. Both take a 256-bit key and a 96-bit nonce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Cipher {
+    #[default]
+    AesGcm,
+    /// Faster than AES-GCM on CPUs without AES instructions.
+    ChaCha20,
+}
+
+impl Cipher {
+    /// Identifies the cipher in the payload header.
+    pub fn id(self) -> u8 {
+        match self {
+            Cipher::AesGcm => 0,
+            Cipher::ChaCha20 => 1,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Result<Self> {
+        match id {
+            0 => Ok(Cipher::AesGcm),
+            1 => Ok(Cipher::ChaCha20),
+            _ => Err(CryptoError::UnsupportedCipher(id).into()),
+        }
+    }
+}
+
+impl FromStr for Cipher {
+    type Err = CryptoError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, CryptoError> {
+        match s.to_ascii_lowercase().replace(['-', '_'], "").as_str() {
+            "aesgcm" | "aes256gcm" => Ok(Cipher::AesGcm),
+            "chacha20" | "chacha20poly1305" => Ok(Cipher::ChaCha20),
+            _ => Err(CryptoError::UnknownCipher(s.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for Cipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Cipher::AesGcm => write!(f, "AES-256-GCM"),
+            Cipher::ChaCha20 => write!(f, "ChaCha20-Poly1305"),
+        }
+    }
+}
+
+pub fn random_bytes(len: usize) -> Result<Vec<u8>> {
+    let mut bytes = vec![0u8; len];
+    getrandom::getrandom(&mut bytes).map_err(|_| CryptoError::Random)?;
+    Ok(bytes)
+}
+
+/// Argon2id cost parameters, stored in the payload header next to the salt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    /// Memory cost in KiB.
+    pub memory: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl KdfParams {
+    /// Argon2id with the given costs, falling back to the defaults for those left out.
+    pub fn new(memory: Option<u32>, iterations: Option<u32>) -> Result<Self> {
+        let default = Self::default();
+        let params = Self {
+            memory: memory.unwrap_or(default.memory),
+            iterations: iterations.unwrap_or(default.iterations),
+            ..default
+        };
+        params.validate()?;
+        Ok(params)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let memory = argon2::Params::MIN_M_COST.max(8 * self.parallelism)..=MAX_KDF_MEMORY;
+        if !memory.contains(&self.memory)
+            || !(1..=MAX_KDF_ITERATIONS).contains(&self.iterations)
+            || self.parallelism == 0
+        {
+            return Err(CryptoError::InvalidKdfParams.into());
+        }
+        Ok(())
+    }
+}
+
+/// The `argon2` crate defaults, which is also what payloads without stored parameters used.
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            memory: argon2::Params::DEFAULT_M_COST,
+            iterations: argon2::Params::DEFAULT_T_COST,
+            parallelism: argon2::Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl std::fmt::Display for KdfParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Argon2id, {} KiB, {} iterations, {} lanes",
+            self.memory, self.iterations, self.parallelism
+        )
+    }
+}
+
+/// Stretch a passphrase into a 256-bit key with Argon2id. The key is wiped when dropped.
+pub fn derive_key(
+    passphrase: &str,
+    salt: &[u8],
+    kdf: &KdfParams,
+) -> Result<Zeroizing<[u8; KEY_LEN]>> {
+    kdf.validate()?;
+    let params = argon2::Params::new(kdf.memory, kdf.iterations, kdf.parallelism, None)
+        .map_err(|_| CryptoError::InvalidKdfParams)?;
+
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+        .map_err(|_| CryptoError::KeyDerivation)?;
+    Ok(key)
+}
+
+/// Encrypt with `cipher`, returning `nonce || ciphertext || tag`.
+pub fn seal(cipher: Cipher, key: &[u8; KEY_LEN], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let nonce = random_bytes(NONCE_LEN)?;
+    let nonce_ref = Nonce::from_slice(&nonce);
+
+    let ciphertext = match cipher {
+        Cipher::AesGcm => Aes256Gcm::new(key.into()).encrypt(nonce_ref, plaintext),
+        Cipher::ChaCha20 => ChaCha20Poly1305::new(key.into()).encrypt(nonce_ref, plaintext),
+    }
+    .map_err(|_| CryptoError::Encryption)?;
+
+    Ok([nonce, ciphertext].concat())
+}
+
+/// Reverse of [`seal`]. The plaintext is wiped when dropped.
+pub fn open(cipher: Cipher, key: &[u8; KEY_LEN], sealed: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+    if sealed.len() < NONCE_LEN + TAG_LEN {
+        return Err(CryptoError::InvalidLayout.into());
+    }
+
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::from_slice(nonce);
+
+    let plaintext = match cipher {
+        Cipher::AesGcm => Aes256Gcm::new(key.into()).decrypt(nonce, ciphertext),
+        Cipher::ChaCha20 => ChaCha20Poly1305::new(key.into()).decrypt(nonce, ciphertext),
+    }
+    .map_err(|_| CryptoError::Decryption)?;
+    Ok(Zeroizing::new(plaintext))
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Hmac<Sha256> {
+    let mut mac =
+        <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac
+}
+
+/// HMAC-SHA256 of `data` under `key`.
+pub fn mac(key: &[u8], data: &[u8]) -> [u8; MAC_LEN] {
+    hmac(key, data).finalize().into_bytes().into()
+}
+
+/// Check a tag made by [`mac`], in constant time.
+pub fn verify_mac(key: &[u8], data: &[u8], tag: &[u8]) -> Result<()> {
+    hmac(key, data)
+        .verify_slice(tag)
+        .map_err(|_| CryptoError::MacMismatch.into())
+}
+
+/// Encrypt up to [`SLOTS`] secrets, each under its own passphrase, into equally sized slots.
+///
+/// Every slot is padded to the length of the largest secret, a slot left over is filled with
+/// random bytes under a random key, and the slots are stored in random order. So without a
+/// passphrase there is no telling which slot holds the real message, which one holds the decoy
+/// or whether there is a decoy at all.
+pub fn seal_slots(
+    salt: &[u8],
+    kdf: &KdfParams,
+    cipher: Cipher,
+    secrets: &[(&str, &[u8])],
+) -> Result<Vec<u8>> {
+    let padded_len = 4 + secrets
+        .iter()
+        .map(|(_, data)| data.len())
+        .max()
+        .unwrap_or(0);
+    if secrets.len() > SLOTS {
+        return Err(CryptoError::InvalidLayout.into());
+    }
+
+    let mut slots = Vec::with_capacity(SLOTS);
+    for (passphrase, data) in secrets {
+        // Sized up front so that no unwiped copy is left behind by a reallocation
+        let mut plaintext = Zeroizing::new(Vec::with_capacity(padded_len));
+        put_be_u32(&mut plaintext, data.len() as u32);
+        plaintext.extend_from_slice(data);
+        plaintext.resize(padded_len, 0);
+
+        let key = derive_key(passphrase, salt, kdf)?;
+        slots.push(seal(cipher, &key, &plaintext)?);
+    }
+    while slots.len() < SLOTS {
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        key.copy_from_slice(&random_bytes(KEY_LEN)?);
+        slots.push(seal(cipher, &key, &random_bytes(padded_len)?)?);
+    }
+
+    // Fisher-Yates shuffle driven by OS randomness
+    let random = random_bytes(slots.len())?;
+    for idx in (1..slots.len()).rev() {
+        slots.swap(idx, random[idx] as usize % (idx + 1));
+    }
+
+    Ok(slots.concat())
+}
+
+/// Try `passphrase` against each of the `count` slots, returning the first secret it unlocks.
+pub fn open_slots(
+    salt: &[u8],
+    kdf: &KdfParams,
+    cipher: Cipher,
+    count: usize,
+    body: &[u8],
+    passphrase: &str,
+) -> Result<Zeroizing<Vec<u8>>> {
+    if count == 0 || !body.len().is_multiple_of(count) {
+        return Err(CryptoError::InvalidLayout.into());
+    }
+
+    let key = derive_key(passphrase, salt, kdf)?;
+    for slot in body.chunks(body.len() / count) {
+        let Ok(plaintext) = open(cipher, &key, slot) else {
+            continue;
+        };
+
+        let len = read_be_u32(&plaintext, 0)
+            .and_then(to_usize)
+            .ok_or(CryptoError::InvalidLayout)?;
+        let data = plaintext
+            .get(4..4usize.saturating_add(len))
+            .ok_or(CryptoError::InvalidLayout)?;
+        return Ok(Zeroizing::new(data.to_vec()));
+    }
+
+    Err(CryptoError::Decryption.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open() {
+        let key = [7u8; KEY_LEN];
+        for cipher in [Cipher::AesGcm, Cipher::ChaCha20] {
+            let sealed = seal(cipher, &key, b"secret").unwrap();
+            assert_eq!(*open(cipher, &key, &sealed).unwrap(), b"secret");
+            assert!(open(cipher, &[8u8; KEY_LEN], &sealed).is_err());
+        }
+
+        let sealed = seal(Cipher::ChaCha20, &key, b"secret").unwrap();
+        assert!(open(Cipher::AesGcm, &key, &sealed).is_err());
+    }
+
+    #[test]
+    fn test_slots_are_equally_sized_and_open_with_either_passphrase() {
+        let salt = random_bytes(SALT_LEN).unwrap();
+        let kdf = KdfParams::default();
+        let body = seal_slots(
+            &salt,
+            &kdf,
+            Cipher::AesGcm,
+            &[("real", b"the real message"), ("decoy", b"hi mom")],
+        )
+        .unwrap();
+
+        assert_eq!(body.len() % SLOTS, 0);
+        assert_eq!(
+            *open_slots(&salt, &kdf, Cipher::AesGcm, 2, &body, "real").unwrap(),
+            b"the real message"
+        );
+        assert_eq!(
+            *open_slots(&salt, &kdf, Cipher::AesGcm, 2, &body, "decoy").unwrap(),
+            b"hi mom"
+        );
+        assert!(open_slots(&salt, &kdf, Cipher::AesGcm, 2, &body, "wrong").is_err());
+    }
+
+    #[test]
+    fn test_mac() {
+        let tag = mac(b"key", b"message");
+        assert!(verify_mac(b"key", b"message", &tag).is_ok());
+        assert!(verify_mac(b"key", b"massage", &tag).is_err());
+        assert!(verify_mac(b"other key", b"message", &tag).is_err());
+        assert!(verify_mac(b"key", b"message", &tag[..16]).is_err());
+    }
+
+    #[test]
+    fn test_cipher_from_str() {
+        assert_eq!("aes-gcm".parse::<Cipher>().unwrap(), Cipher::AesGcm);
+        assert_eq!("ChaCha20".parse::<Cipher>().unwrap(), Cipher::ChaCha20);
+        assert!("des".parse::<Cipher>().is_err());
+        assert_eq!(
+            Cipher::from_id(Cipher::ChaCha20.id()).unwrap(),
+            Cipher::ChaCha20
+        );
+        assert!(Cipher::from_id(9).is_err());
+    }
+
+    #[test]
+    fn test_kdf_params() {
+        let salt = random_bytes(SALT_LEN).unwrap();
+        let cheap = KdfParams::new(Some(64), Some(1)).unwrap();
+        assert_ne!(
+            derive_key("pw", &salt, &cheap).unwrap(),
+            derive_key("pw", &salt, &KdfParams::default()).unwrap()
+        );
+        assert!(KdfParams::new(Some(1), None).is_err());
+        assert!(KdfParams::new(None, Some(0)).is_err());
+        assert!(KdfParams::new(Some(MAX_KDF_MEMORY + 1), None).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    ihdr_chunk::IhdrChunk,
+    png::Png,
+    text::{self, Charset},
+    Result,
+};
+
+/// Parsed files kept in memory, most recently used last.
+///
+/// An entry is only reused while the file's modification time is unchanged.
+pub struct Cache {
+    capacity: usize,
+    entries: VecDeque<(PathBuf, SystemTime, Arc<Png>)>,
+    hits: u64,
+    misses: u64,
+}
+
+impl Cache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: VecDeque::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn get(&mut self, path: &Path) -> Result<Arc<Png>> {
+        let path = fs::canonicalize(path)?;
+        let modified = fs::metadata(&path)?.modified()?;
+
+        if let Some(idx) = self
+            .entries
+            .iter()
+            .position(|(cached, _, _)| *cached == path)
+        {
+            let entry = self.entries.remove(idx).expect("index is in bounds");
+            if entry.1 == modified {
+                self.hits += 1;
+                let png = entry.2.clone();
+                self.entries.push_back(entry);
+                return Ok(png);
+            }
+        }
+
+        self.misses += 1;
+        let png = Arc::new(Png::from_file(&path)?);
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((path, modified, png.clone()));
+
+        Ok(png)
+    }
+}
+
+/// One request, sent as a line of JSON such as `{"op": "info", "path": "image.png"}`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "kebab-case")]
+enum Request {
+    Info { path: PathBuf },
+    Chunks { path: PathBuf },
+    Decode { path: PathBuf, chunk_type: String },
+    Stats,
+}
+
+fn info(png: &Png) -> Result<Value> {
+    let ihdr = png.chunk_by_type("IHDR").ok_or("Image has no IHDR chunk")?;
+    let ihdr = IhdrChunk::try_from(ihdr.clone())?;
+
+    Ok(json!({
+        "width": ihdr.width(),
+        "height": ihdr.height(),
+        "bit_depth": ihdr.bit_depth(),
+        "color_type": ihdr.color_type_name(),
+        "interlaced": ihdr.interlace_method() == 1,
+        "chunks": png.chunks().len(),
+        "size": png.as_bytes().len(),
+    }))
+}
+
+fn chunks(png: &Png) -> Value {
+    png.chunks()
+        .iter()
+        .zip(png.chunk_offsets())
+        .enumerate()
+        .map(|(index, (chunk, offset))| {
+            json!({
+                "index": index,
+                "offset": offset,
+                "type": chunk.chunk_type().to_string(),
+                "length": chunk.length(),
+                "crc": chunk.crc(),
+            })
+        })
+        .collect()
+}
+
+fn respond(cache: &mut Cache, request: Request) -> Result<Value> {
+    match request {
+        Request::Info { path } => info(cache.get(&path)?.as_ref()),
+        Request::Chunks { path } => Ok(chunks(cache.get(&path)?.as_ref())),
+        Request::Decode { path, chunk_type } => {
+            let png = cache.get(&path)?;
+            let chunk = png
+                .chunk_by_type(&chunk_type)
+                .ok_or_else(|| format!("No {} chunk", chunk_type))?;
+            Ok(json!(text::decode_bytes(
+                chunk.data(),
+                Charset::Auto,
+                false
+            )?))
+        }
+        Request::Stats => Ok(json!({
+            "entries": cache.entries.len(),
+            "capacity": cache.capacity,
+            "hits": cache.hits,
+            "misses": cache.misses,
+        })),
+    }
+}
+
+/// Answer one request line with one response line, `{"ok": ...}` or `{"error": ...}`.
+pub fn handle(cache: &mut Cache, line: &str) -> String {
+    let response = serde_json::from_str::<Request>(line)
+        .map_err(Into::into)
+        .and_then(|request| respond(cache, request));
+
+    match response {
+        Ok(value) => json!({ "ok": value }).to_string(),
+        Err(error) => json!({ "error": error.to_string() }).to_string(),
+    }
+}
+
+/// Serve requests on a Unix socket at `socket` until the process is killed.
+///
+/// Connections are handled one after the other, each may send any number of requests.
+#[cfg(unix)]
+pub fn serve(socket: &Path, capacity: usize) -> Result<()> {
+    use std::os::unix::net::UnixListener;
+
+    // A socket file left behind by a previous run would make bind fail
+    if socket.exists() {
+        fs::remove_file(socket)?;
+    }
+    let listener = UnixListener::bind(socket)?;
+    let mut cache = Cache::new(capacity);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                eprintln!("Connection failed: {}", error);
+                continue;
+            }
+        };
+
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else { break };
+            if line.trim().is_empty() {
+                continue;
+            }
+            if writeln!(writer, "{}", handle(&mut cache, &line)).is_err() {
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn serve(_socket: &Path, _capacity: usize) -> Result<()> {
+    Err("The daemon needs Unix domain sockets, which this platform lacks".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raster::{self, Image};
+
+    fn write_png(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("pngme-daemon-{}-{}", std::process::id(), name));
+        let png = raster::encode(&Image::new(3, 2)).unwrap();
+        fs::write(&path, png.as_bytes()).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_cache_hits_and_evicts() {
+        let (a, b) = (write_png("a.png"), write_png("b.png"));
+        let mut cache = Cache::new(1);
+
+        cache.get(&a).unwrap();
+        cache.get(&a).unwrap();
+        cache.get(&b).unwrap();
+        cache.get(&a).unwrap();
+        assert_eq!((cache.hits, cache.misses), (1, 3));
+
+        fs::remove_file(a).unwrap();
+        fs::remove_file(b).unwrap();
+    }
+
+    #[test]
+    fn test_handle() {
+        let path = write_png("c.png");
+        let mut cache = Cache::new(4);
+
+        let request = json!({ "op": "info", "path": path }).to_string();
+        let response: Value = serde_json::from_str(&handle(&mut cache, &request)).unwrap();
+        assert_eq!(response["ok"]["width"], 3);
+        assert_eq!(response["ok"]["color_type"], "truecolor+alpha");
+
+        let request = json!({ "op": "decode", "path": path, "chunk_type": "tEXt" }).to_string();
+        let response: Value = serde_json::from_str(&handle(&mut cache, &request)).unwrap();
+        assert!(response["error"].is_string());
+
+        let response: Value = serde_json::from_str(&handle(&mut cache, "nonsense")).unwrap();
+        assert!(response["error"].is_string());
+
+        fs::remove_file(path).unwrap();
+    }
+}
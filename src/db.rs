@@ -0,0 +1,183 @@
+//! SQLite export of sweeps over many files, `--db`, so results can be queried with SQL
+//! afterwards instead of grepping through reports.
+
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    codec,
+    dupes::{self, Compare},
+    ihdr_chunk::IhdrChunk,
+    png::Png,
+    scan::Finding,
+    Result,
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS files (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL UNIQUE,
+    size INTEGER NOT NULL,
+    sha256 TEXT NOT NULL,
+    -- Hash of the image data alone, shared by files that differ only in metadata
+    image_hash TEXT,
+    width INTEGER,
+    height INTEGER,
+    color_type TEXT
+);
+CREATE TABLE IF NOT EXISTS chunks (
+    file_id INTEGER NOT NULL REFERENCES files(id),
+    idx INTEGER NOT NULL,
+    offset INTEGER NOT NULL,
+    type TEXT NOT NULL,
+    length INTEGER NOT NULL,
+    crc INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS findings (
+    file_id INTEGER NOT NULL REFERENCES files(id),
+    offset INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    chunk_type TEXT,
+    detail TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS chunks_file ON chunks(file_id);
+CREATE INDEX IF NOT EXISTS findings_file ON findings(file_id);
+";
+
+/// A results database, written in one transaction that [`Db::commit`] ends.
+pub struct Db {
+    connection: Connection,
+}
+
+impl Db {
+    /// Open or create the database at `path`. Files already in it are updated as they are
+    /// added again, so a sweep can be rerun into the same database, and `scan` and `stats`
+    /// can fill in the same one.
+    pub fn open(path: &Path) -> Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+        connection.execute_batch("BEGIN")?;
+        Ok(Db { connection })
+    }
+
+    /// Record the PNG file at `path` with contents `file`, and its chunks if it parsed as
+    /// `png`. Returns the id to attach findings to.
+    pub fn add_file(&self, path: &Path, file: &[u8], png: Option<&Png>) -> Result<i64> {
+        let path = path.display().to_string();
+        let sha256 = codec::encode_hex(&Sha256::digest(file));
+        let ihdr = png
+            .and_then(|png| png.chunk_by_type("IHDR"))
+            .and_then(|chunk| IhdrChunk::try_from(chunk.clone()).ok());
+        let image_hash = png.and_then(|png| dupes::content_hash(png, Compare::ImageData).ok());
+
+        let id = self.connection.query_row(
+            "INSERT INTO files (path, size, sha256, image_hash, width, height, color_type)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT (path) DO UPDATE SET size = ?2, sha256 = ?3, image_hash = ?4,
+                 width = ?5, height = ?6, color_type = ?7
+             RETURNING id",
+            params![
+                path,
+                file.len() as i64,
+                sha256,
+                image_hash,
+                ihdr.as_ref().map(|ihdr| ihdr.width()),
+                ihdr.as_ref().map(|ihdr| ihdr.height()),
+                ihdr.as_ref().map(|ihdr| ihdr.color_type_name()),
+            ],
+            |row| row.get(0),
+        )?;
+
+        self.connection
+            .execute("DELETE FROM chunks WHERE file_id = ?1", params![id])?;
+
+        if let Some(png) = png {
+            let mut insert = self.connection.prepare_cached(
+                "INSERT INTO chunks (file_id, idx, offset, type, length, crc)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for (index, (chunk, offset)) in png.chunks().iter().zip(png.chunk_offsets()).enumerate()
+            {
+                insert.execute(params![
+                    id,
+                    index as i64,
+                    offset as i64,
+                    chunk.chunk_type().to_string(),
+                    chunk.length(),
+                    chunk.crc(),
+                ])?;
+            }
+        }
+        Ok(id)
+    }
+
+    /// Replace the findings of the file `file_id`.
+    pub fn add_findings(&self, file_id: i64, findings: &[Finding]) -> Result<()> {
+        self.connection
+            .execute("DELETE FROM findings WHERE file_id = ?1", params![file_id])?;
+        let mut insert = self.connection.prepare_cached(
+            "INSERT INTO findings (file_id, offset, kind, chunk_type, detail)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        for finding in findings {
+            insert.execute(params![
+                file_id,
+                finding.offset as i64,
+                finding.kind.to_string(),
+                finding.chunk_type,
+                finding.detail,
+            ])?;
+        }
+        Ok(())
+    }
+
+    pub fn commit(self) -> Result<()> {
+        self.connection.execute_batch("COMMIT")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        raster::{self, Image},
+        scan::Kind,
+    };
+
+    #[test]
+    fn test_db() {
+        let path = std::env::temp_dir().join(format!("pngme-db-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let png = raster::encode(&Image::new(2, 3)).unwrap();
+        let file = png.as_bytes();
+        let findings = [Finding {
+            kind: Kind::TrailingData,
+            offset: file.len(),
+            chunk_type: None,
+            detail: "6 bytes after IEND".to_string(),
+        }];
+
+        // A second sweep replaces the rows of the first
+        for _ in 0..2 {
+            let db = Db::open(&path).unwrap();
+            let id = db.add_file(Path::new("a.png"), &file, Some(&png)).unwrap();
+            db.add_findings(id, &findings).unwrap();
+            db.commit().unwrap();
+        }
+        // Adding the file alone, as stats does, keeps the findings of scan
+        let db = Db::open(&path).unwrap();
+        db.add_file(Path::new("a.png"), &file, Some(&png)).unwrap();
+        db.commit().unwrap();
+
+        let connection = Connection::open(&path).unwrap();
+        let count = |sql: &str| -> i64 { connection.query_row(sql, [], |row| row.get(0)).unwrap() };
+        assert_eq!(count("SELECT COUNT(*) FROM files"), 1);
+        assert_eq!(count("SELECT COUNT(*) FROM chunks"), 3);
+        assert_eq!(count("SELECT COUNT(*) FROM findings"), 1);
+        assert_eq!(count("SELECT height FROM files"), 3);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
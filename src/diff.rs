@@ -0,0 +1,308 @@
+use std::fmt::Write;
+
+use sha2::{Digest, Sha256};
+
+use crate::{chunk::Chunk, png::Png};
+
+/// What happened to a chunk between two versions of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Unchanged {
+        before: usize,
+        after: usize,
+    },
+    Added {
+        after: usize,
+    },
+    Removed {
+        before: usize,
+    },
+    /// Same chunk type at the same place in the sequence, but different data.
+    Modified {
+        before: usize,
+        after: usize,
+    },
+    /// Identical chunk at a different place in the sequence.
+    Moved {
+        before: usize,
+        after: usize,
+    },
+}
+
+/// One step through both chunk sequences.
+enum Step {
+    Match(usize, usize),
+    Add(usize),
+    Remove(usize),
+}
+
+/// Walk a longest common subsequence of `old` and `new`.
+fn steps(old: &[Vec<u8>], new: &[Vec<u8>]) -> Vec<Step> {
+    // lcs[i][j] is the LCS length of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut steps = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            steps.push(Step::Match(i, j));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            steps.push(Step::Add(j));
+            j += 1;
+        } else {
+            steps.push(Step::Remove(i));
+            i += 1;
+        }
+    }
+    steps
+}
+
+/// Align the chunk sequences of `before` and `after`.
+///
+/// Identical chunks are matched with a longest common subsequence. Identical chunks left over on
+/// both sides were moved; between two matches, the remaining removed and added chunks of the
+/// same type are paired up as modifications.
+pub fn diff(before: &Png, after: &Png) -> Vec<Change> {
+    let old: Vec<Vec<u8>> = before.chunks().iter().map(Chunk::as_bytes).collect();
+    let new: Vec<Vec<u8>> = after.chunks().iter().map(Chunk::as_bytes).collect();
+    let steps = steps(&old, &new);
+
+    // moved_to[j] is the old index of the identical chunk that moved to new index j
+    let mut moved_to = vec![None; new.len()];
+    let mut moved_from = vec![false; old.len()];
+    for step in &steps {
+        if let Step::Remove(i) = *step {
+            let target = steps.iter().find_map(|step| match *step {
+                Step::Add(j) if moved_to[j].is_none() && new[j] == old[i] => Some(j),
+                _ => None,
+            });
+            if let Some(j) = target {
+                moved_to[j] = Some(i);
+                moved_from[i] = true;
+            }
+        }
+    }
+
+    let mut changes = vec![];
+    let mut removed = vec![];
+    let mut added = vec![];
+
+    for step in steps {
+        match step {
+            Step::Match(i, j) => {
+                flush(before, after, &mut removed, &mut added, &mut changes);
+                changes.push(Change::Unchanged {
+                    before: i,
+                    after: j,
+                });
+            }
+            Step::Add(j) => match moved_to[j] {
+                Some(i) => {
+                    flush(before, after, &mut removed, &mut added, &mut changes);
+                    changes.push(Change::Moved {
+                        before: i,
+                        after: j,
+                    });
+                }
+                None => added.push(j),
+            },
+            Step::Remove(i) if !moved_from[i] => removed.push(i),
+            Step::Remove(_) => {}
+        }
+    }
+    flush(before, after, &mut removed, &mut added, &mut changes);
+
+    changes
+}
+
+/// Turn a run of removed and added chunks into changes, pairing same-typed ones as modified.
+fn flush(
+    before: &Png,
+    after: &Png,
+    removed: &mut Vec<usize>,
+    added: &mut Vec<usize>,
+    changes: &mut Vec<Change>,
+) {
+    for old_idx in removed.drain(..) {
+        let old_type = before.chunks()[old_idx].chunk_type();
+        match added
+            .iter()
+            .position(|&new_idx| after.chunks()[new_idx].chunk_type() == old_type)
+        {
+            Some(pos) => changes.push(Change::Modified {
+                before: old_idx,
+                after: added.remove(pos),
+            }),
+            None => changes.push(Change::Removed { before: old_idx }),
+        }
+    }
+    changes.extend(added.drain(..).map(|after| Change::Added { after }));
+}
+
+/// Length, CRC and the start of the SHA-256 of a chunk's data.
+fn fingerprint(chunk: &Chunk) -> String {
+    let hash: String = Sha256::digest(chunk.data())[..4]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!(
+        "{} bytes, crc {:#010x}, sha256 {}",
+        chunk.length(),
+        chunk.crc(),
+        hash
+    )
+}
+
+/// A human readable summary of everything that differs between `before` and `after`.
+pub fn render(before: &Png, after: &Png) -> String {
+    let changes = diff(before, after);
+
+    let mut out = String::new();
+    let (mut added, mut removed, mut modified, mut moved, mut unchanged) = (0, 0, 0, 0, 0);
+
+    for change in changes {
+        match change {
+            Change::Unchanged { .. } => unchanged += 1,
+            Change::Added { after: idx } => {
+                added += 1;
+                let chunk = &after.chunks()[idx];
+                let _ = writeln!(
+                    out,
+                    "+ [{:>3}] {}  {}",
+                    idx,
+                    chunk.chunk_type(),
+                    fingerprint(chunk)
+                );
+            }
+            Change::Removed { before: idx } => {
+                removed += 1;
+                let chunk = &before.chunks()[idx];
+                let _ = writeln!(
+                    out,
+                    "- [{:>3}] {}  {}",
+                    idx,
+                    chunk.chunk_type(),
+                    fingerprint(chunk)
+                );
+            }
+            Change::Modified {
+                before: old_idx,
+                after: new_idx,
+            } => {
+                modified += 1;
+                let (old, new) = (&before.chunks()[old_idx], &after.chunks()[new_idx]);
+                let _ = writeln!(
+                    out,
+                    "~ [{:>3}] {}  {} -> {}",
+                    new_idx,
+                    new.chunk_type(),
+                    fingerprint(old),
+                    fingerprint(new)
+                );
+            }
+            Change::Moved {
+                before: old_idx,
+                after: new_idx,
+            } => {
+                moved += 1;
+                let _ = writeln!(
+                    out,
+                    "> [{:>3}] {}  moved from [{}]",
+                    new_idx,
+                    after.chunks()[new_idx].chunk_type(),
+                    old_idx
+                );
+            }
+        }
+    }
+
+    let (old_size, new_size) = (before.as_bytes().len(), after.as_bytes().len());
+    let _ = write!(
+        out,
+        "{} added, {} removed, {} modified, {} unchanged",
+        added, removed, modified, unchanged
+    );
+    let _ = match moved {
+        0 => writeln!(out),
+        _ => writeln!(out, ", {} moved", moved),
+    };
+    let _ = writeln!(
+        out,
+        "File size: {} -> {} bytes ({:+})",
+        old_size,
+        new_size,
+        new_size as i64 - old_size as i64
+    );
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn chunk(code: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(code).unwrap(), data.as_bytes().to_vec())
+    }
+
+    fn png(chunks: &[(&str, &str)]) -> Png {
+        Png::from_chunks(chunks.iter().map(|(c, d)| chunk(c, d)).collect())
+    }
+
+    #[test]
+    fn test_identical() {
+        let a = png(&[("IHDR", "a"), ("IEND", "")]);
+        assert!(diff(&a, &a)
+            .iter()
+            .all(|change| matches!(change, Change::Unchanged { .. })));
+    }
+
+    #[test]
+    fn test_added_removed_modified() {
+        let before = png(&[("IHDR", "a"), ("tEXt", "old"), ("ruSt", "x"), ("IEND", "")]);
+        let after = png(&[("IHDR", "a"), ("tEXt", "new"), ("IEND", ""), ("zzZz", "y")]);
+
+        let changes = diff(&before, &after);
+        assert!(changes.contains(&Change::Modified {
+            before: 1,
+            after: 1
+        }));
+        assert!(changes.contains(&Change::Removed { before: 2 }));
+        assert!(changes.contains(&Change::Added { after: 3 }));
+
+        let rendered = render(&before, &after);
+        assert!(rendered.contains("1 added, 1 removed, 1 modified, 2 unchanged"));
+    }
+
+    #[test]
+    fn test_moved() {
+        let before = png(&[("IHDR", "a"), ("tEXt", "t"), ("IDAT", "d"), ("IEND", "")]);
+        let after = png(&[("IHDR", "a"), ("IDAT", "d"), ("tEXt", "t"), ("IEND", "")]);
+
+        let changes = diff(&before, &after);
+        assert_eq!(
+            changes
+                .iter()
+                .filter(|change| matches!(change, Change::Moved { .. }))
+                .count(),
+            1
+        );
+        assert!(!changes
+            .iter()
+            .any(|change| matches!(change, Change::Added { .. } | Change::Removed { .. })));
+        assert!(render(&before, &after).contains("3 unchanged, 1 moved"));
+    }
+}
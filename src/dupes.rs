@@ -0,0 +1,146 @@
+//! Files that hold the same image under different metadata, found by hashing what they show
+//! rather than the whole file.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{bytes::put_be_u32, codec, png::Png, raster, Result};
+
+/// What two files must share to count as duplicates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compare {
+    /// The IHDR and the compressed image data, byte for byte. Cheap, but misses the same image
+    /// compressed differently.
+    ImageData,
+    /// The decoded RGBA pixels, whatever the encoding.
+    Pixels,
+}
+
+/// Hex SHA-256 of the part of `png` that `compare` looks at.
+pub fn content_hash(png: &Png, compare: Compare) -> Result<String> {
+    let mut hasher = Sha256::new();
+    match compare {
+        Compare::ImageData => {
+            for chunk in png.chunks() {
+                let code = chunk.chunk_type().to_string();
+                if matches!(code.as_str(), "IHDR" | "PLTE" | "tRNS" | "IDAT") {
+                    hasher.update(code.as_bytes());
+                    hasher.update(chunk.data());
+                }
+            }
+        }
+        Compare::Pixels => {
+            let image = raster::decode(png)?;
+            let mut size = vec![];
+            put_be_u32(&mut size, image.width);
+            put_be_u32(&mut size, image.height);
+            hasher.update(size);
+            hasher.update(&image.pixels);
+        }
+    }
+    Ok(codec::encode_hex(&hasher.finalize()))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Member {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// Files with the same content hash.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Group {
+    pub hash: String,
+    /// Smallest first, the one worth keeping.
+    pub files: Vec<Member>,
+    /// Bytes freed by keeping only the first file.
+    pub wasted: u64,
+}
+
+/// Groups of two or more `files` that share a hash, most wasted bytes first.
+pub fn group(files: Vec<(Member, String)>) -> Vec<Group> {
+    let mut by_hash: HashMap<String, Vec<Member>> = HashMap::new();
+    for (copy, hash) in files {
+        by_hash.entry(hash).or_default().push(copy);
+    }
+
+    let mut groups: Vec<Group> = by_hash
+        .into_iter()
+        .filter(|(_, files)| files.len() > 1)
+        .map(|(hash, mut files)| {
+            files.sort_by(|a, b| a.size.cmp(&b.size).then(a.path.cmp(&b.path)));
+            let wasted = files[1..].iter().map(|copy| copy.size).sum();
+            Group {
+                hash,
+                files,
+                wasted,
+            }
+        })
+        .collect();
+    groups.sort_by(|a, b| {
+        b.wasted
+            .cmp(&a.wasted)
+            .then(a.files[0].path.cmp(&b.files[0].path))
+    });
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chunk::Chunk,
+        chunk_type::ChunkType,
+        raster::{self, Image},
+    };
+    use std::str::FromStr;
+
+    #[test]
+    fn test_content_hash() {
+        let mut image = Image::new(4, 4);
+        image.set_pixel(1, 2, [255, 0, 0, 255]);
+        let png = raster::encode(&image).unwrap();
+
+        let mut tagged = png.clone();
+        tagged.insert_chunk(
+            1,
+            Chunk::new(
+                ChunkType::from_str("tEXt").unwrap(),
+                b"Title\0copy".to_vec(),
+            ),
+        );
+        for compare in [Compare::ImageData, Compare::Pixels] {
+            assert_eq!(
+                content_hash(&png, compare).unwrap(),
+                content_hash(&tagged, compare).unwrap()
+            );
+        }
+
+        image.set_pixel(0, 0, [0, 0, 255, 255]);
+        let other = raster::encode(&image).unwrap();
+        assert_ne!(
+            content_hash(&png, Compare::Pixels).unwrap(),
+            content_hash(&other, Compare::Pixels).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_group() {
+        let copy = |path: &str, size| Member {
+            path: PathBuf::from(path),
+            size,
+        };
+        let groups = group(vec![
+            (copy("a.png", 300), "x".to_string()),
+            (copy("b.png", 100), "x".to_string()),
+            (copy("c.png", 50), "y".to_string()),
+            (copy("d.png", 120), "x".to_string()),
+        ]);
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].files[0].path, PathBuf::from("b.png"));
+        assert_eq!(groups[0].wasted, 420);
+    }
+}
@@ -0,0 +1,391 @@
+//! Reed-Solomon error correction over GF(2^8), so a payload survives a few damaged bytes.
+//!
+//! The data is cut into blocks of at most `255 - parity` bytes, each of which gets `parity`
+//! bytes of its own and can then have up to `parity / 2` bytes repaired. The blocks are stored
+//! interleaved, byte 0 of every block, then byte 1 and so on, so that a run of damaged bytes is
+//! shared between blocks rather than overwhelming one.
+
+use std::str::FromStr;
+
+use crate::Result;
+
+#[derive(Debug)]
+pub enum EccError {
+    Unknown(String),
+    Unsupported(u8),
+    Unrecoverable,
+}
+
+impl std::error::Error for EccError {}
+
+impl std::fmt::Display for EccError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EccError::Unknown(name) => write!(
+                f,
+                "Unknown error correction level '{}', expected low, medium or high",
+                name
+            ),
+            EccError::Unsupported(id) => write!(
+                f,
+                "Payload uses an unsupported error correction level (id {})",
+                id
+            ),
+            EccError::Unrecoverable => write!(
+                f,
+                "The message is too damaged for its error correction to repair"
+            ),
+        }
+    }
+}
+
+/// How much parity protects a payload, traded against its size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EccLevel {
+    /// Repairs up to 8 bytes in every 239, about 7% larger.
+    Low,
+    /// Repairs up to 16 bytes in every 223, about 14% larger.
+    Medium,
+    /// Repairs up to 32 bytes in every 191, about 34% larger.
+    High,
+}
+
+impl EccLevel {
+    /// Parity bytes added to each block.
+    pub fn parity(self) -> usize {
+        match self {
+            EccLevel::Low => 16,
+            EccLevel::Medium => 32,
+            EccLevel::High => 64,
+        }
+    }
+
+    /// Identifies the level in the payload header.
+    pub fn id(self) -> u8 {
+        match self {
+            EccLevel::Low => 1,
+            EccLevel::Medium => 2,
+            EccLevel::High => 3,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Result<Self> {
+        match id {
+            1 => Ok(EccLevel::Low),
+            2 => Ok(EccLevel::Medium),
+            3 => Ok(EccLevel::High),
+            _ => Err(EccError::Unsupported(id).into()),
+        }
+    }
+}
+
+impl FromStr for EccLevel {
+    type Err = EccError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, EccError> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "low" => Ok(EccLevel::Low),
+            "medium" => Ok(EccLevel::Medium),
+            "high" => Ok(EccLevel::High),
+            _ => Err(EccError::Unknown(s.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for EccLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EccLevel::Low => write!(f, "low"),
+            EccLevel::Medium => write!(f, "medium"),
+            EccLevel::High => write!(f, "high"),
+        }
+    }
+}
+
+/// `data` followed by its parity, interleaved as described in the module documentation.
+pub fn protect(data: &[u8], level: EccLevel) -> Vec<u8> {
+    let parity = level.parity();
+    let (blocks, block_len) = shape(data.len(), parity);
+    let generator = generator(parity);
+
+    let codewords: Vec<Vec<u8>> = (0..blocks)
+        .map(|block| {
+            let start = (block * block_len).min(data.len());
+            let end = (start + block_len).min(data.len());
+            let mut codeword = data[start..end].to_vec();
+            codeword.resize(block_len, 0);
+            let parity = remainder(&codeword, &generator);
+            codeword.extend(parity);
+            codeword
+        })
+        .collect();
+
+    interleave(&codewords, block_len + parity)
+}
+
+/// Reverse of [`protect`] for data that was `len` bytes long, repairing what it can. Also
+/// returns how many bytes had to be repaired.
+pub fn repair(coded: &[u8], len: usize, level: EccLevel) -> Result<(Vec<u8>, usize)> {
+    let parity = level.parity();
+    let (blocks, block_len) = shape(len, parity);
+    let codeword_len = block_len + parity;
+    if blocks.checked_mul(codeword_len) != Some(coded.len()) {
+        return Err(EccError::Unrecoverable.into());
+    }
+
+    let mut data = Vec::with_capacity(blocks * block_len);
+    let mut repaired = 0;
+    for block in 0..blocks {
+        let mut codeword: Vec<u8> = (0..codeword_len)
+            .map(|index| coded[index * blocks + block])
+            .collect();
+        repaired += correct(&mut codeword, parity)?;
+        data.extend_from_slice(&codeword[..block_len]);
+    }
+
+    data.truncate(len);
+    Ok((data, repaired))
+}
+
+/// The number of blocks `len` bytes are cut into and the length of each, the last padded with
+/// zeros.
+fn shape(len: usize, parity: usize) -> (usize, usize) {
+    let blocks = len.div_ceil(255 - parity).max(1);
+    (blocks, len.div_ceil(blocks))
+}
+
+fn interleave(codewords: &[Vec<u8>], codeword_len: usize) -> Vec<u8> {
+    (0..codeword_len)
+        .flat_map(|index| codewords.iter().map(move |codeword| codeword[index]))
+        .collect()
+}
+
+/// Exponentials and logarithms of the field generated by 2 with the polynomial 0x11d. The
+/// exponentials are repeated so a sum of two logarithms can index them directly.
+struct Tables {
+    exp: [u8; 512],
+    log: [u8; 256],
+}
+
+const TABLES: Tables = {
+    let mut exp = [0; 512];
+    let mut log = [0; 256];
+    let mut x: u16 = 1;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = x as u8;
+        exp[i + 255] = x as u8;
+        log[x as usize] = i as u8;
+        x <<= 1;
+        if x & 0x100 != 0 {
+            x ^= 0x11d;
+        }
+        i += 1;
+    }
+    exp[510] = exp[0];
+    exp[511] = exp[1];
+    Tables { exp, log }
+};
+
+fn mul(a: u8, b: u8) -> u8 {
+    match (a, b) {
+        (0, _) | (_, 0) => 0,
+        _ => TABLES.exp[TABLES.log[a as usize] as usize + TABLES.log[b as usize] as usize],
+    }
+}
+
+fn div(a: u8, b: u8) -> u8 {
+    match a {
+        0 => 0,
+        _ => TABLES.exp[TABLES.log[a as usize] as usize + 255 - TABLES.log[b as usize] as usize],
+    }
+}
+
+/// 2 to the power `power`, which may be negative.
+fn alpha(power: i64) -> u8 {
+    TABLES.exp[power.rem_euclid(255) as usize]
+}
+
+/// Value at `x` of a polynomial stored highest degree first.
+fn eval_high(poly: &[u8], x: u8) -> u8 {
+    poly.iter().fold(0, |acc, &coef| mul(acc, x) ^ coef)
+}
+
+/// Value at `x` of a polynomial stored lowest degree first.
+fn eval_low(poly: &[u8], x: u8) -> u8 {
+    poly.iter().rev().fold(0, |acc, &coef| mul(acc, x) ^ coef)
+}
+
+/// The product of `(x - 2^i)` for every `i` below `parity`, highest degree first.
+fn generator(parity: usize) -> Vec<u8> {
+    let mut generator = vec![1];
+    for i in 0..parity {
+        let root = alpha(i as i64);
+        let mut next = vec![0; generator.len() + 1];
+        for (j, &coef) in generator.iter().enumerate() {
+            next[j] ^= coef;
+            next[j + 1] ^= mul(coef, root);
+        }
+        generator = next;
+    }
+    generator
+}
+
+/// The parity of `message`: the remainder of `message * x^parity` divided by `generator`.
+fn remainder(message: &[u8], generator: &[u8]) -> Vec<u8> {
+    let parity = generator.len() - 1;
+    let mut work = message.to_vec();
+    work.resize(message.len() + parity, 0);
+    for i in 0..message.len() {
+        let coef = work[i];
+        if coef != 0 {
+            for (j, &g) in generator.iter().enumerate().skip(1) {
+                work[i + j] ^= mul(g, coef);
+            }
+        }
+    }
+    work.split_off(message.len())
+}
+
+/// Repair `codeword` in place with Berlekamp-Massey, a Chien search and Forney's formula,
+/// returning the number of bytes repaired.
+fn correct(codeword: &mut [u8], parity: usize) -> Result<usize> {
+    let syndromes: Vec<u8> = (0..parity)
+        .map(|i| eval_high(codeword, alpha(i as i64)))
+        .collect();
+    if syndromes.iter().all(|&syndrome| syndrome == 0) {
+        return Ok(0);
+    }
+
+    // Error locator, lowest degree first
+    let mut locator = vec![1];
+    let mut previous = vec![1];
+    let mut errors = 0;
+    let mut shift = 1;
+    let mut last_discrepancy = 1;
+    for n in 0..parity {
+        let discrepancy = (1..=errors).fold(syndromes[n], |acc, i| {
+            acc ^ mul(*locator.get(i).unwrap_or(&0), syndromes[n - i])
+        });
+        if discrepancy == 0 {
+            shift += 1;
+            continue;
+        }
+
+        let scale = div(discrepancy, last_discrepancy);
+        let mut next = locator.clone();
+        next.resize(next.len().max(previous.len() + shift), 0);
+        for (i, &coef) in previous.iter().enumerate() {
+            next[i + shift] ^= mul(scale, coef);
+        }
+        if 2 * errors <= n {
+            previous = std::mem::replace(&mut locator, next);
+            errors = n + 1 - errors;
+            last_discrepancy = discrepancy;
+            shift = 1;
+        } else {
+            locator = next;
+            shift += 1;
+        }
+    }
+    if 2 * errors > parity {
+        return Err(EccError::Unrecoverable.into());
+    }
+
+    // The byte at `index` is the coefficient of x^(len - 1 - index)
+    let len = codeword.len();
+    let positions: Vec<usize> = (0..len)
+        .filter(|&power| eval_low(&locator, alpha(-(power as i64))) == 0)
+        .collect();
+    if positions.len() != errors {
+        return Err(EccError::Unrecoverable.into());
+    }
+
+    let mut evaluator = vec![0; parity];
+    for (i, &syndrome) in syndromes.iter().enumerate() {
+        for (j, &coef) in locator.iter().enumerate().take(parity - i) {
+            evaluator[i + j] ^= mul(syndrome, coef);
+        }
+    }
+    let derivative: Vec<u8> = locator
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(i, &coef)| if i % 2 == 1 { coef } else { 0 })
+        .collect();
+
+    for &power in &positions {
+        let inverse = alpha(-(power as i64));
+        let denominator = eval_low(&derivative, inverse);
+        if denominator == 0 {
+            return Err(EccError::Unrecoverable.into());
+        }
+        let magnitude = mul(
+            alpha(power as i64),
+            div(eval_low(&evaluator, inverse), denominator),
+        );
+        codeword[len - 1 - power] ^= magnitude;
+    }
+
+    // A codeword damaged beyond repair can look repairable, the syndromes tell
+    match (0..parity).all(|i| eval_high(codeword, alpha(i as i64)) == 0) {
+        true => Ok(positions.len()),
+        false => Err(EccError::Unrecoverable.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i * 7 % 251) as u8).collect();
+        for level in [EccLevel::Low, EccLevel::Medium, EccLevel::High] {
+            let coded = protect(&data, level);
+            assert_eq!(
+                repair(&coded, data.len(), level).unwrap(),
+                (data.clone(), 0)
+            );
+        }
+        let coded = protect(b"", EccLevel::Low);
+        assert_eq!(repair(&coded, 0, EccLevel::Low).unwrap(), (vec![], 0));
+    }
+
+    #[test]
+    fn test_repair() {
+        let data = b"meet at dawn by the old oak tree".repeat(20);
+        let mut coded = protect(&data, EccLevel::Low);
+        // A run of damage, spread over the interleaved blocks
+        for byte in &mut coded[100..120] {
+            *byte ^= 0x5a;
+        }
+        coded[3] = 0;
+        coded[500] ^= 1;
+
+        let (repaired, count) = repair(&coded, data.len(), EccLevel::Low).unwrap();
+        assert_eq!(repaired, data);
+        assert_eq!(count, 22);
+    }
+
+    #[test]
+    fn test_too_damaged() {
+        let data = b"meet at dawn".to_vec();
+        let mut coded = protect(&data, EccLevel::Low);
+        for byte in &mut coded[..9] {
+            *byte ^= 0xff;
+        }
+        assert!(repair(&coded, data.len(), EccLevel::Low).is_err());
+        assert!(repair(&coded[1..], data.len(), EccLevel::Low).is_err());
+    }
+
+    #[test]
+    fn test_level() {
+        assert_eq!(EccLevel::from_str("Medium").unwrap(), EccLevel::Medium);
+        assert_eq!(
+            EccLevel::from_id(EccLevel::High.id()).unwrap(),
+            EccLevel::High
+        );
+        assert!(EccLevel::from_str("max").is_err());
+    }
+}
@@ -0,0 +1,240 @@
+//! Several edits to one image in a single pass, for `edit`, so a file is read and written once
+//! however many changes it gets.
+
+use std::str::FromStr;
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    extension::{Density, Extension},
+    png::Png,
+    preset,
+    text::{self, Charset, TextualChunk},
+    Result,
+};
+
+#[derive(Debug)]
+pub enum EditError {
+    InvalidText(String),
+    InvalidDpi(String),
+}
+
+impl std::error::Error for EditError {}
+
+impl std::fmt::Display for EditError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EditError::InvalidText(text) => {
+                write!(f, "Invalid text '{}', expected KEYWORD=TEXT", text)
+            }
+            EditError::InvalidDpi(dpi) => {
+                write!(f, "Invalid DPI '{}', expected a positive number", dpi)
+            }
+        }
+    }
+}
+
+/// A keyword and its text, given as `KEYWORD=TEXT`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEntry {
+    pub keyword: String,
+    pub text: String,
+}
+
+impl TextEntry {
+    /// Keywords are 1 to 79 bytes long.
+    pub fn new(keyword: &str, text: &str) -> std::result::Result<Self, EditError> {
+        match (1..=79).contains(&keyword.len()) {
+            true => Ok(TextEntry {
+                keyword: keyword.to_string(),
+                text: text.to_string(),
+            }),
+            false => Err(EditError::InvalidText(format!("{}={}", keyword, text))),
+        }
+    }
+}
+
+impl FromStr for TextEntry {
+    type Err = EditError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, EditError> {
+        let (keyword, text) = s
+            .split_once('=')
+            .ok_or_else(|| EditError::InvalidText(s.to_string()))?;
+        TextEntry::new(keyword, text)
+    }
+}
+
+/// Dots per inch for pHYs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dpi(pub f64);
+
+impl Dpi {
+    pub fn new(dpi: f64) -> std::result::Result<Self, EditError> {
+        match dpi.is_finite() && dpi > 0.0 {
+            true => Ok(Dpi(dpi)),
+            false => Err(EditError::InvalidDpi(dpi.to_string())),
+        }
+    }
+}
+
+impl FromStr for Dpi {
+    type Err = EditError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, EditError> {
+        let dpi = s
+            .parse::<f64>()
+            .map_err(|_| EditError::InvalidDpi(s.to_string()))?;
+        Dpi::new(dpi)
+    }
+}
+
+/// One change to an image.
+#[derive(Debug, Clone)]
+pub enum Edit {
+    /// Remove ancillary chunks other than those listed.
+    Strip {
+        keep: Vec<String>,
+    },
+    StripText,
+    Remove(String),
+    /// Replace the text chunks with this keyword by one holding the new text.
+    SetText(TextEntry),
+    SetDpi(Dpi),
+    /// Replace tIME with this many seconds since the Unix epoch.
+    SetTime(u64),
+    /// Append a chunk, as `encode` does.
+    Encode(Chunk),
+}
+
+/// Put `entry` in a tEXt chunk, or an iTXt one when the text isn't Latin-1.
+fn text_chunk(entry: &TextEntry) -> Result<Chunk> {
+    let text = TextualChunk {
+        keyword: entry.keyword.clone(),
+        language: None,
+        translated_keyword: None,
+        text: entry.text.clone(),
+    };
+    let (chunk_type, data) = match text.to_data("tEXt") {
+        Ok(data) => ("tEXt", data),
+        Err(_) => ("iTXt", text.to_data("iTXt")?),
+    };
+    Ok(Chunk::new(ChunkType::from_str(chunk_type)?, data))
+}
+
+/// Remove the text chunks of `png` whose keyword is `keyword`, or all of them for `None`.
+fn remove_text(png: &mut Png, keyword: Option<&str>) -> usize {
+    let keep: Vec<Chunk> = png
+        .chunks()
+        .iter()
+        .filter(|chunk| {
+            let code = chunk.chunk_type().to_string();
+            if !text::is_text_chunk(&code) {
+                return true;
+            }
+            match keyword {
+                None => false,
+                Some(keyword) => TextualChunk::parse(chunk, Charset::Auto)
+                    .map_or(true, |text| text.keyword != keyword),
+            }
+        })
+        .cloned()
+        .collect();
+    let removed = png.chunks().len() - keep.len();
+    *png = Png::from_chunks(keep);
+    removed
+}
+
+/// Apply `edits` to `png` in order, describing what each one did.
+pub fn apply(png: &mut Png, edits: &[Edit]) -> Result<Vec<String>> {
+    let mut done = vec![];
+    for edit in edits {
+        let description = match edit {
+            Edit::Strip { keep } => {
+                let removed = png.strip_ancillary(keep);
+                format!("Stripped {} ancillary chunk(s)", removed.len())
+            }
+            Edit::StripText => {
+                format!("Stripped {} text chunk(s)", remove_text(png, None))
+            }
+            Edit::Remove(chunk_type) => {
+                let removed = png.remove_chunks(chunk_type);
+                format!("Removed {} {} chunk(s)", removed, chunk_type)
+            }
+            Edit::SetText(entry) => {
+                remove_text(png, Some(&entry.keyword));
+                png.insert_ordered(text_chunk(entry)?);
+                format!("Set {}", entry.keyword)
+            }
+            Edit::SetDpi(Dpi(dpi)) => {
+                png.remove_chunks(Density::CHUNK_TYPE);
+                let density = Density::from_dpi(*dpi);
+                png.insert_ordered(density.to_chunk());
+                format!("Set density to {}", density)
+            }
+            Edit::SetTime(secs) => {
+                png.remove_chunks("tIME");
+                let chunk = Chunk::new(ChunkType::from_str("tIME")?, preset::time_data(*secs));
+                png.insert_ordered(chunk);
+                "Set tIME".to_string()
+            }
+            Edit::Encode(chunk) => {
+                png.append_chunk(chunk.clone());
+                format!("Encoded a {} chunk", chunk.chunk_type())
+            }
+        };
+        done.push(description);
+    }
+    Ok(done)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raster::{self, Image};
+
+    fn types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut png = raster::encode(&Image::new(2, 2)).unwrap();
+        let comment = TextEntry::from_str("Comment=old").unwrap();
+        png.insert_ordered(text_chunk(&comment).unwrap());
+        png.insert_ordered(Chunk::new(ChunkType::from_str("gAMA").unwrap(), vec![0; 4]));
+
+        let edits = [
+            Edit::StripText,
+            Edit::Remove("gAMA".to_string()),
+            Edit::SetText(TextEntry::from_str("Author=Zoë").unwrap()),
+            Edit::SetText(TextEntry::from_str("Author=Jo").unwrap()),
+            Edit::SetDpi(Dpi::from_str("300").unwrap()),
+            Edit::Encode(Chunk::new(
+                ChunkType::from_str("ruSt").unwrap(),
+                b"msg".to_vec(),
+            )),
+        ];
+        let done = apply(&mut png, &edits).unwrap();
+
+        assert_eq!(done[0], "Stripped 1 text chunk(s)");
+        assert_eq!(
+            types(&png),
+            ["IHDR", "pHYs", "IDAT", "tEXt", "IEND", "ruSt"]
+        );
+        let author = TextualChunk::parse(png.chunk_by_type("tEXt").unwrap(), Charset::Auto);
+        assert_eq!(author.unwrap().text, "Jo");
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(TextEntry::from_str("no separator").is_err());
+        assert!(TextEntry::from_str("=empty keyword").is_err());
+        assert_eq!(TextEntry::from_str("a=b=c").unwrap().text, "b=c");
+        assert!(Dpi::from_str("-1").is_err());
+        assert!(Dpi::from_str("72.5").is_ok());
+    }
+}
@@ -0,0 +1,499 @@
+use crate::{
+    bytes::{be_u16, be_u32, be_u64, put_be_u16, put_be_u32, put_be_u64, read_be_u32, to_usize},
+    compress::Compression,
+    crypto::{Cipher, KdfParams},
+    ecc::EccLevel,
+    Result,
+};
+
+/// Every pngme payload that carries more than a bare message starts with this marker.
+const MAGIC: [u8; 6] = *b"pngme\0";
+const VERSION: u8 = 1;
+
+/// Tags of the TLV fields stored in front of the body.
+///
+/// Unknown tags are skipped when reading, so newer versions can add fields without breaking
+/// older readers.
+const TAG_END: u8 = 0;
+const TAG_PART: u8 = 1;
+const TAG_KDF: u8 = 3;
+const TAG_CIPHER: u8 = 4;
+const TAG_RECIPIENTS: u8 = 5;
+const TAG_COMPRESSION: u8 = 7;
+const TAG_CREATED: u8 = 8;
+const TAG_FILE: u8 = 9;
+const TAG_MANIFEST: u8 = 10;
+const TAG_DIGEST: u8 = 11;
+const TAG_PROTECTION: u8 = 12;
+const TAG_SALT: u8 = 13;
+const TAG_HEADER_MAC: u8 = 14;
+
+#[derive(Debug)]
+pub enum EnvelopeError {
+    UnsupportedVersion(u8),
+    Truncated,
+    InvalidField(u8),
+}
+
+impl std::error::Error for EnvelopeError {}
+
+impl std::fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EnvelopeError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported payload envelope version {}", version)
+            }
+            EnvelopeError::Truncated => write!(f, "Payload envelope is truncated"),
+            EnvelopeError::InvalidField(tag) => {
+                write!(f, "Invalid payload envelope field with tag {}", tag)
+            }
+        }
+    }
+}
+
+/// Identifies one piece of a payload that was spread over several images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Part {
+    /// Shared by every part of the same payload: the CRC-32 of the whole payload.
+    pub id: u32,
+    pub index: u16,
+    pub total: u16,
+}
+
+impl Part {
+    const LENGTH: usize = 8;
+
+    fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::LENGTH);
+        put_be_u32(&mut bytes, self.id);
+        put_be_u16(&mut bytes, self.index);
+        put_be_u16(&mut bytes, self.total);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != Self::LENGTH {
+            return Err(EnvelopeError::InvalidField(TAG_PART).into());
+        }
+
+        Ok(Self {
+            id: be_u32(&bytes[0..4]),
+            index: be_u16(&bytes[4..6]),
+            total: be_u16(&bytes[6..8]),
+        })
+    }
+}
+
+/// Describes a body protected by Reed-Solomon parity (see [`crate::ecc::protect`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Protection {
+    pub level: EccLevel,
+    /// Length of the body before the parity was added.
+    pub len: u64,
+}
+
+impl Protection {
+    const LENGTH: usize = 9;
+
+    fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::LENGTH);
+        bytes.push(self.level.id());
+        put_be_u64(&mut bytes, self.len);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        match bytes.split_first() {
+            Some((&id, len)) if bytes.len() == Self::LENGTH => Ok(Self {
+                level: EccLevel::from_id(id)?,
+                len: be_u64(len),
+            }),
+            _ => Err(EnvelopeError::InvalidField(TAG_PROTECTION).into()),
+        }
+    }
+}
+
+/// Describes a body made of passphrase-encrypted slots (see [`crate::crypto::seal_slots`]).
+///
+/// Only the salt is stored: the number of slots would tell a decoy apart from a lone message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sealed {
+    pub salt: Vec<u8>,
+    /// Stored in a field of its own. Payloads written before it existed used the defaults.
+    pub kdf: KdfParams,
+    /// Also stored in a field of its own, AES-256-GCM when missing.
+    pub cipher: Cipher,
+}
+
+impl Sealed {
+    fn from_salt(salt: &[u8]) -> Result<Self> {
+        match salt.is_empty() {
+            true => Err(EnvelopeError::InvalidField(TAG_SALT).into()),
+            false => Ok(Self {
+                salt: salt.to_vec(),
+                kdf: KdfParams::default(),
+                cipher: Cipher::default(),
+            }),
+        }
+    }
+}
+
+/// The file a message was read from, kept so decode can restore it under its name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileInfo {
+    pub name: String,
+    pub size: u64,
+    /// Modification time in seconds since the Unix epoch, when the platform reported one.
+    pub modified: Option<u64>,
+    /// SHA-256 of the contents.
+    pub sha256: [u8; 32],
+}
+
+impl FileInfo {
+    /// Stored for a missing modification time.
+    const UNKNOWN_TIME: u64 = u64::MAX;
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.sha256.to_vec();
+        put_be_u64(&mut bytes, self.size);
+        put_be_u64(&mut bytes, self.modified.unwrap_or(Self::UNKNOWN_TIME));
+        bytes.extend_from_slice(self.name.as_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let invalid = || EnvelopeError::InvalidField(TAG_FILE);
+        if bytes.len() < 48 {
+            return Err(invalid().into());
+        }
+
+        let modified = be_u64(&bytes[40..]);
+        Ok(Self {
+            name: String::from_utf8(bytes[48..].to_vec()).map_err(|_| invalid())?,
+            size: be_u64(&bytes[32..]),
+            modified: (modified != Self::UNKNOWN_TIME).then_some(modified),
+            sha256: bytes[..32].try_into().expect("32 bytes"),
+        })
+    }
+}
+
+/// Each entry of a manifest is a u32 length followed by a [`FileInfo`].
+fn manifest_to_bytes(manifest: &[FileInfo]) -> Vec<u8> {
+    let mut bytes = vec![];
+    for file in manifest {
+        let entry = file.to_bytes();
+        put_be_u32(&mut bytes, entry.len() as u32);
+        bytes.extend_from_slice(&entry);
+    }
+    bytes
+}
+
+fn manifest_from_bytes(mut bytes: &[u8]) -> Result<Vec<FileInfo>> {
+    let mut manifest = vec![];
+    while !bytes.is_empty() {
+        let entry = read_be_u32(bytes, 0)
+            .and_then(to_usize)
+            .and_then(|len| bytes.get(4..4usize.checked_add(len)?))
+            .ok_or(EnvelopeError::InvalidField(TAG_MANIFEST))?;
+        manifest.push(FileInfo::from_bytes(entry)?);
+        bytes = &bytes[4 + entry.len()..];
+    }
+    Ok(manifest)
+}
+
+const KDF_LENGTH: usize = 12;
+
+fn kdf_to_bytes(kdf: &KdfParams) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(KDF_LENGTH);
+    put_be_u32(&mut bytes, kdf.memory);
+    put_be_u32(&mut bytes, kdf.iterations);
+    put_be_u32(&mut bytes, kdf.parallelism);
+    bytes
+}
+
+fn kdf_from_bytes(bytes: &[u8]) -> Result<KdfParams> {
+    if bytes.len() != KDF_LENGTH {
+        return Err(EnvelopeError::InvalidField(TAG_KDF).into());
+    }
+
+    let kdf = KdfParams {
+        memory: be_u32(&bytes[0..4]),
+        iterations: be_u32(&bytes[4..8]),
+        parallelism: be_u32(&bytes[8..12]),
+    };
+    kdf.validate()
+        .map_err(|_| EnvelopeError::InvalidField(TAG_KDF))?;
+    Ok(kdf)
+}
+
+/// A payload together with the header fields describing how it was stored.
+///
+/// Layout: `MAGIC`, a version byte, a list of `tag, u32 length, value` fields terminated by a
+/// zero tag, and finally the body itself.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Envelope {
+    pub part: Option<Part>,
+    pub sealed: Option<Sealed>,
+    /// Set when the body is an age file encrypted to this many public keys
+    /// (see [`crate::recipient::encrypt`]).
+    pub recipients: Option<u16>,
+    /// HMAC-SHA256 of the rest of the envelope (see [`Envelope::authenticated_bytes`]).
+    pub mac: Option<Vec<u8>>,
+    /// Applied to the message before any encryption.
+    pub compression: Option<Compression>,
+    /// When the payload was embedded, in seconds since the Unix epoch.
+    pub created: Option<u64>,
+    /// Set when the body is the contents of this file (see [`crate::payload::wrap_file`]).
+    pub file: Option<FileInfo>,
+    /// Set when the body is several files one after the other, these ones in this order (see
+    /// [`crate::payload::pack`]).
+    pub manifest: Option<Vec<FileInfo>>,
+    /// SHA-256 of the body (see [`crate::payload::add_digest`]).
+    pub digest: Option<[u8; 32]>,
+    /// Set when the body is a whole payload, header and all, followed by parity (see
+    /// [`crate::payload::protect`]).
+    pub protection: Option<Protection>,
+    pub body: Vec<u8>,
+}
+
+impl Envelope {
+    pub fn new(body: Vec<u8>) -> Self {
+        Self {
+            body,
+            ..Default::default()
+        }
+    }
+
+    /// Returns true if `data` starts with the envelope marker.
+    pub fn is_envelope(data: &[u8]) -> bool {
+        data.starts_with(&MAGIC)
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        self.to_bytes(true)
+    }
+
+    /// What the HMAC covers: the envelope as stored, without the HMAC itself.
+    pub fn authenticated_bytes(&self) -> Vec<u8> {
+        self.to_bytes(false)
+    }
+
+    fn to_bytes(&self, with_mac: bool) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+
+        if let Some(part) = self.part {
+            write_field(&mut bytes, TAG_PART, &part.to_bytes());
+        }
+        if let Some(sealed) = &self.sealed {
+            write_field(&mut bytes, TAG_SALT, &sealed.salt);
+            write_field(&mut bytes, TAG_KDF, &kdf_to_bytes(&sealed.kdf));
+            write_field(&mut bytes, TAG_CIPHER, &[sealed.cipher.id()]);
+        }
+        if let Some(recipients) = self.recipients {
+            let mut field = vec![];
+            put_be_u16(&mut field, recipients);
+            write_field(&mut bytes, TAG_RECIPIENTS, &field);
+        }
+        if let Some(mac) = self.mac.as_ref().filter(|_| with_mac) {
+            write_field(&mut bytes, TAG_HEADER_MAC, mac);
+        }
+        if let Some(compression) = self.compression {
+            write_field(&mut bytes, TAG_COMPRESSION, &[compression.id()]);
+        }
+        if let Some(created) = self.created {
+            let mut field = vec![];
+            put_be_u64(&mut field, created);
+            write_field(&mut bytes, TAG_CREATED, &field);
+        }
+        if let Some(file) = &self.file {
+            write_field(&mut bytes, TAG_FILE, &file.to_bytes());
+        }
+        if let Some(manifest) = &self.manifest {
+            write_field(&mut bytes, TAG_MANIFEST, &manifest_to_bytes(manifest));
+        }
+        if let Some(digest) = &self.digest {
+            write_field(&mut bytes, TAG_DIGEST, digest);
+        }
+        if let Some(protection) = self.protection {
+            write_field(&mut bytes, TAG_PROTECTION, &protection.to_bytes());
+        }
+
+        bytes.push(TAG_END);
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+}
+
+fn write_field(bytes: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    bytes.push(tag);
+    put_be_u32(bytes, value.len() as u32);
+    bytes.extend_from_slice(value);
+}
+
+impl TryFrom<&[u8]> for Envelope {
+    type Error = crate::Error;
+
+    fn try_from(value: &[u8]) -> Result<Self> {
+        if !Envelope::is_envelope(value) {
+            return Err(EnvelopeError::Truncated.into());
+        }
+
+        let mut idx = MAGIC.len();
+        let version = *value.get(idx).ok_or(EnvelopeError::Truncated)?;
+        if version != VERSION {
+            return Err(EnvelopeError::UnsupportedVersion(version).into());
+        }
+        idx += 1;
+
+        let mut envelope = Envelope::default();
+        let mut kdf = None;
+        let mut cipher = None;
+
+        loop {
+            let tag = *value.get(idx).ok_or(EnvelopeError::Truncated)?;
+            idx += 1;
+            if tag == TAG_END {
+                break;
+            }
+
+            let len = read_be_u32(value, idx)
+                .and_then(to_usize)
+                .ok_or(EnvelopeError::Truncated)?;
+            idx += 4;
+
+            let field = value
+                .get(idx..idx.saturating_add(len))
+                .ok_or(EnvelopeError::Truncated)?;
+            idx += len;
+
+            match tag {
+                TAG_PART => envelope.part = Some(Part::from_bytes(field)?),
+                TAG_SALT => envelope.sealed = Some(Sealed::from_salt(field)?),
+                TAG_KDF => kdf = Some(kdf_from_bytes(field)?),
+                TAG_CIPHER => match field {
+                    [id] => cipher = Some(Cipher::from_id(*id)?),
+                    _ => return Err(EnvelopeError::InvalidField(TAG_CIPHER).into()),
+                },
+                TAG_RECIPIENTS => match field {
+                    [_, _] => envelope.recipients = Some(be_u16(field)),
+                    _ => return Err(EnvelopeError::InvalidField(TAG_RECIPIENTS).into()),
+                },
+                TAG_HEADER_MAC => envelope.mac = Some(field.to_vec()),
+                TAG_COMPRESSION => match field {
+                    [id] => envelope.compression = Some(Compression::from_id(*id)?),
+                    _ => return Err(EnvelopeError::InvalidField(TAG_COMPRESSION).into()),
+                },
+                TAG_CREATED => match field.len() {
+                    8 => envelope.created = Some(be_u64(field)),
+                    _ => return Err(EnvelopeError::InvalidField(TAG_CREATED).into()),
+                },
+                TAG_FILE => envelope.file = Some(FileInfo::from_bytes(field)?),
+                TAG_MANIFEST => envelope.manifest = Some(manifest_from_bytes(field)?),
+                TAG_DIGEST => match <[u8; 32]>::try_from(field) {
+                    Ok(digest) => envelope.digest = Some(digest),
+                    Err(_) => return Err(EnvelopeError::InvalidField(TAG_DIGEST).into()),
+                },
+                TAG_PROTECTION => envelope.protection = Some(Protection::from_bytes(field)?),
+                _ => {}
+            }
+        }
+
+        if let Some(sealed) = &mut envelope.sealed {
+            sealed.kdf = kdf.unwrap_or_default();
+            sealed.cipher = cipher.unwrap_or_default();
+        }
+
+        envelope.body = value[idx..].to_vec();
+        Ok(envelope)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let envelope = Envelope {
+            part: Some(Part {
+                id: 42,
+                index: 1,
+                total: 3,
+            }),
+            sealed: Some(Sealed {
+                salt: vec![1, 2, 3],
+                kdf: KdfParams::new(Some(1024), Some(4)).unwrap(),
+                cipher: Cipher::ChaCha20,
+            }),
+            recipients: Some(2),
+            mac: Some(vec![7; 32]),
+            compression: Some(Compression::Zstd),
+            created: Some(1_700_000_000),
+            file: Some(FileInfo {
+                name: "notes.txt".to_string(),
+                size: 5,
+                modified: None,
+                sha256: [3; 32],
+            }),
+            manifest: Some(vec![
+                FileInfo {
+                    name: "a.txt".to_string(),
+                    size: 2,
+                    modified: Some(7),
+                    sha256: [4; 32],
+                },
+                FileInfo {
+                    name: "b.txt".to_string(),
+                    size: 3,
+                    modified: None,
+                    sha256: [5; 32],
+                },
+            ]),
+            digest: Some([6; 32]),
+            protection: Some(Protection {
+                level: EccLevel::Medium,
+                len: 1 << 40,
+            }),
+            body: b"hello".to_vec(),
+        };
+
+        let bytes = envelope.as_bytes();
+        assert!(Envelope::is_envelope(&bytes));
+        assert_eq!(Envelope::try_from(bytes.as_slice()).unwrap(), envelope);
+    }
+
+    #[test]
+    fn test_unknown_fields_are_skipped() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        write_field(&mut bytes, 200, b"from the future");
+        bytes.push(TAG_END);
+        bytes.extend_from_slice(b"body");
+
+        let envelope = Envelope::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(envelope.body, b"body");
+        assert!(envelope.part.is_none());
+    }
+
+    #[test]
+    fn test_sealed_without_kdf_or_cipher_uses_defaults() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        write_field(&mut bytes, TAG_SALT, &[9, 9, 9]);
+        bytes.push(TAG_END);
+
+        let envelope = Envelope::try_from(bytes.as_slice()).unwrap();
+        let sealed = envelope.sealed.unwrap();
+        assert_eq!(sealed.salt, [9, 9, 9]);
+        assert_eq!(sealed.kdf, KdfParams::default());
+        assert_eq!(sealed.cipher, Cipher::AesGcm);
+    }
+
+    #[test]
+    fn test_truncated_envelope() {
+        let bytes = Envelope::new(b"body".to_vec()).as_bytes();
+        assert!(Envelope::try_from(&bytes[..MAGIC.len() + 1]).is_err());
+        assert!(!Envelope::is_envelope(b"plain message"));
+    }
+}
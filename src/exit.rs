@@ -0,0 +1,80 @@
+//! Exit codes, so scripts can branch on what verify, decode and diff found without parsing
+//! their output.
+
+use crate::{
+    chunk::ChunkError, chunk_type::ChunkTypeError, png::PngError, remote::RemoteError,
+    stream::StreamError, Error,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Code {
+    Success = 0,
+    /// Anything not covered below, such as bad arguments.
+    Error = 1,
+    /// Not a PNG, or the chunks don't form a valid one.
+    Invalid = 2,
+    /// A chunk's stored CRC doesn't match its contents.
+    BadCrc = 3,
+    /// The chunk or message asked for isn't there.
+    NotFound = 4,
+    /// Reading or writing a file, or downloading one, failed.
+    Io = 5,
+    /// Two files compared by diff aren't the same.
+    Differs = 6,
+}
+
+/// A check that ran but didn't pass. The command has already said why, so this carries no
+/// message of its own beyond the outcome.
+#[derive(Debug)]
+pub struct CheckFailed(pub Code);
+
+impl std::error::Error for CheckFailed {}
+
+impl std::fmt::Display for CheckFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let outcome = match self.0 {
+            Code::Success => "passed",
+            Code::Error => "failed",
+            Code::Invalid => "not a valid PNG",
+            Code::BadCrc => "CRC mismatch",
+            Code::NotFound => "not found",
+            Code::Io => "I/O error",
+            Code::Differs => "files differ",
+        };
+        write!(f, "{}", outcome)
+    }
+}
+
+/// The exit code for a command that failed with `error`.
+pub fn code(error: &Error) -> Code {
+    if let Some(CheckFailed(code)) = error.downcast_ref() {
+        *code
+    } else if error.is::<std::io::Error>() || error.is::<RemoteError>() {
+        Code::Io
+    } else if let Some(ChunkError::InvalidCrc) = error.downcast_ref() {
+        Code::BadCrc
+    } else if error.is::<PngError>()
+        || error.is::<ChunkError>()
+        || error.is::<ChunkTypeError>()
+        || error.is::<StreamError>()
+    {
+        Code::Invalid
+    } else {
+        Code::Error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code() {
+        let io: Error = std::io::Error::from(std::io::ErrorKind::NotFound).into();
+        assert_eq!(code(&io), Code::Io);
+        assert_eq!(code(&ChunkError::InvalidCrc.into()), Code::BadCrc);
+        assert_eq!(code(&PngError::TooShort.into()), Code::Invalid);
+        assert_eq!(code(&CheckFailed(Code::Differs).into()), Code::Differs);
+        assert_eq!(code(&"bad argument".into()), Code::Error);
+    }
+}
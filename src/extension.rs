@@ -0,0 +1,507 @@
+//! The registered extension chunks used by scientific and scanning software: oFFs (image
+//! offset), sCAL (physical scale) and sTER (stereo layout), along with pHYs (pixel density)
+//! from the core specification.
+
+use std::{fmt::Display, str::FromStr};
+
+use crate::{
+    bytes::{be_u32, put_be_u32},
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    png::Png,
+    Result,
+};
+
+#[derive(Debug)]
+pub enum ExtensionError {
+    WrongLength {
+        chunk_type: &'static str,
+        len: usize,
+    },
+    UnknownUnit {
+        chunk_type: &'static str,
+        unit: String,
+    },
+    InvalidValue {
+        chunk_type: &'static str,
+        value: String,
+    },
+}
+
+impl std::error::Error for ExtensionError {}
+
+impl Display for ExtensionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ExtensionError::WrongLength { chunk_type, len } => {
+                write!(f, "{} chunk has invalid length {}", chunk_type, len)
+            }
+            ExtensionError::UnknownUnit { chunk_type, unit } => {
+                write!(f, "Unknown {} unit '{}'", chunk_type, unit)
+            }
+            ExtensionError::InvalidValue { chunk_type, value } => {
+                write!(f, "Invalid {} value '{}'", chunk_type, value)
+            }
+        }
+    }
+}
+
+/// A chunk with a fixed, typed layout.
+pub trait Extension: Sized + Display {
+    const CHUNK_TYPE: &'static str;
+
+    fn from_data(data: &[u8]) -> Result<Self>;
+    fn to_data(&self) -> Vec<u8>;
+
+    fn to_chunk(&self) -> Chunk {
+        // The chunk type constants are valid codes
+        Chunk::new(
+            ChunkType::from_str(Self::CHUNK_TYPE).unwrap(),
+            self.to_data(),
+        )
+    }
+
+    /// The value of the first chunk of this type in `png`, if there is one.
+    fn read(png: &Png) -> Option<Result<Self>> {
+        png.chunk_by_type(Self::CHUNK_TYPE)
+            .map(|chunk| Self::from_data(chunk.data()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OffsetUnit {
+    Pixel,
+    Micrometer,
+}
+
+/// oFFs: where the image's top-left corner sits on a larger page or canvas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Offset {
+    pub x: i32,
+    pub y: i32,
+    pub unit: OffsetUnit,
+}
+
+impl Extension for Offset {
+    const CHUNK_TYPE: &'static str = "oFFs";
+
+    fn from_data(data: &[u8]) -> Result<Self> {
+        if data.len() != 9 {
+            return Err(ExtensionError::WrongLength {
+                chunk_type: Self::CHUNK_TYPE,
+                len: data.len(),
+            }
+            .into());
+        }
+
+        let unit = match data[8] {
+            0 => OffsetUnit::Pixel,
+            1 => OffsetUnit::Micrometer,
+            unit => {
+                return Err(ExtensionError::UnknownUnit {
+                    chunk_type: Self::CHUNK_TYPE,
+                    unit: unit.to_string(),
+                }
+                .into())
+            }
+        };
+
+        Ok(Offset {
+            x: be_u32(&data[0..4]) as i32,
+            y: be_u32(&data[4..8]) as i32,
+            unit,
+        })
+    }
+
+    fn to_data(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(9);
+        put_be_u32(&mut data, self.x as u32);
+        put_be_u32(&mut data, self.y as u32);
+        data.push(match self.unit {
+            OffsetUnit::Pixel => 0,
+            OffsetUnit::Micrometer => 1,
+        });
+        data
+    }
+}
+
+impl Display for Offset {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let unit = match self.unit {
+            OffsetUnit::Pixel => "px",
+            OffsetUnit::Micrometer => "um",
+        };
+        write!(f, "{}, {} {}", self.x, self.y, unit)
+    }
+}
+
+/// Parses `X,Y` or `X,Y,UNIT` where the unit is `px` (the default) or `um`.
+impl FromStr for Offset {
+    type Err = ExtensionError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, ExtensionError> {
+        let invalid = || ExtensionError::InvalidValue {
+            chunk_type: Self::CHUNK_TYPE,
+            value: s.to_string(),
+        };
+
+        let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+        let (x, y, unit) = match parts[..] {
+            [x, y] => (x, y, "px"),
+            [x, y, unit] => (x, y, unit),
+            _ => return Err(invalid()),
+        };
+
+        Ok(Offset {
+            x: x.parse().map_err(|_| invalid())?,
+            y: y.parse().map_err(|_| invalid())?,
+            unit: match unit {
+                "px" | "pixel" => OffsetUnit::Pixel,
+                "um" | "micrometer" => OffsetUnit::Micrometer,
+                _ => {
+                    return Err(ExtensionError::UnknownUnit {
+                        chunk_type: Self::CHUNK_TYPE,
+                        unit: unit.to_string(),
+                    })
+                }
+            },
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleUnit {
+    Meter,
+    Radian,
+}
+
+/// sCAL: the physical width and height of one pixel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scale {
+    pub width: f64,
+    pub height: f64,
+    pub unit: ScaleUnit,
+}
+
+/// sCAL stores positive floating point numbers as ASCII text.
+fn parse_scale_value(text: &str) -> Option<f64> {
+    text.parse::<f64>()
+        .ok()
+        .filter(|value| value.is_finite() && *value > 0.0)
+}
+
+impl Extension for Scale {
+    const CHUNK_TYPE: &'static str = "sCAL";
+
+    fn from_data(data: &[u8]) -> Result<Self> {
+        let invalid = || ExtensionError::InvalidValue {
+            chunk_type: Self::CHUNK_TYPE,
+            value: String::from_utf8_lossy(data).into_owned(),
+        };
+
+        let (&unit, values) = data.split_first().ok_or(ExtensionError::WrongLength {
+            chunk_type: Self::CHUNK_TYPE,
+            len: 0,
+        })?;
+        let unit = match unit {
+            1 => ScaleUnit::Meter,
+            2 => ScaleUnit::Radian,
+            unit => {
+                return Err(ExtensionError::UnknownUnit {
+                    chunk_type: Self::CHUNK_TYPE,
+                    unit: unit.to_string(),
+                }
+                .into())
+            }
+        };
+
+        let values = std::str::from_utf8(values).map_err(|_| invalid())?;
+        let (width, height) = values.split_once('\0').ok_or_else(invalid)?;
+
+        Ok(Scale {
+            width: parse_scale_value(width).ok_or_else(invalid)?,
+            height: parse_scale_value(height).ok_or_else(invalid)?,
+            unit,
+        })
+    }
+
+    fn to_data(&self) -> Vec<u8> {
+        let mut data = vec![match self.unit {
+            ScaleUnit::Meter => 1,
+            ScaleUnit::Radian => 2,
+        }];
+        data.extend_from_slice(self.width.to_string().as_bytes());
+        data.push(0);
+        data.extend_from_slice(self.height.to_string().as_bytes());
+        data
+    }
+}
+
+impl Display for Scale {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let unit = match self.unit {
+            ScaleUnit::Meter => "m",
+            ScaleUnit::Radian => "rad",
+        };
+        write!(f, "{} x {} {} per pixel", self.width, self.height, unit)
+    }
+}
+
+/// Parses `WIDTH,HEIGHT` or `WIDTH,HEIGHT,UNIT` where the unit is `m` (the default) or `rad`.
+impl FromStr for Scale {
+    type Err = ExtensionError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, ExtensionError> {
+        let invalid = || ExtensionError::InvalidValue {
+            chunk_type: Self::CHUNK_TYPE,
+            value: s.to_string(),
+        };
+
+        let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+        let (width, height, unit) = match parts[..] {
+            [width, height] => (width, height, "m"),
+            [width, height, unit] => (width, height, unit),
+            _ => return Err(invalid()),
+        };
+
+        Ok(Scale {
+            width: parse_scale_value(width).ok_or_else(invalid)?,
+            height: parse_scale_value(height).ok_or_else(invalid)?,
+            unit: match unit {
+                "m" | "meter" => ScaleUnit::Meter,
+                "rad" | "radian" => ScaleUnit::Radian,
+                _ => {
+                    return Err(ExtensionError::UnknownUnit {
+                        chunk_type: Self::CHUNK_TYPE,
+                        unit: unit.to_string(),
+                    })
+                }
+            },
+        })
+    }
+}
+
+/// Meters in an inch, to convert pHYs densities to and from dots per inch.
+const METERS_PER_INCH: f64 = 0.0254;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DensityUnit {
+    /// Only the ratio of `x` to `y` is known, the pixel aspect ratio.
+    Unknown,
+    Meter,
+}
+
+/// pHYs: how many pixels fit in a unit of length, which is where image editors get the DPI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Density {
+    pub x: u32,
+    pub y: u32,
+    pub unit: DensityUnit,
+}
+
+impl Density {
+    /// Square pixels at `dpi` dots per inch, rounded to whole pixels per meter.
+    pub fn from_dpi(dpi: f64) -> Self {
+        let per_meter = (dpi / METERS_PER_INCH).round() as u32;
+        Density {
+            x: per_meter,
+            y: per_meter,
+            unit: DensityUnit::Meter,
+        }
+    }
+}
+
+impl Extension for Density {
+    const CHUNK_TYPE: &'static str = "pHYs";
+
+    fn from_data(data: &[u8]) -> Result<Self> {
+        if data.len() != 9 {
+            return Err(ExtensionError::WrongLength {
+                chunk_type: Self::CHUNK_TYPE,
+                len: data.len(),
+            }
+            .into());
+        }
+
+        let unit = match data[8] {
+            0 => DensityUnit::Unknown,
+            1 => DensityUnit::Meter,
+            unit => {
+                return Err(ExtensionError::UnknownUnit {
+                    chunk_type: Self::CHUNK_TYPE,
+                    unit: unit.to_string(),
+                }
+                .into())
+            }
+        };
+
+        Ok(Density {
+            x: be_u32(&data[0..4]),
+            y: be_u32(&data[4..8]),
+            unit,
+        })
+    }
+
+    fn to_data(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(9);
+        put_be_u32(&mut data, self.x);
+        put_be_u32(&mut data, self.y);
+        data.push(match self.unit {
+            DensityUnit::Unknown => 0,
+            DensityUnit::Meter => 1,
+        });
+        data
+    }
+}
+
+impl Display for Density {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.unit {
+            DensityUnit::Unknown => write!(f, "aspect ratio {}:{}", self.x, self.y),
+            DensityUnit::Meter if self.x == self.y => {
+                let dpi = self.x as f64 * METERS_PER_INCH;
+                write!(f, "{} pixels per meter ({:.0} dpi)", self.x, dpi)
+            }
+            DensityUnit::Meter => write!(f, "{} x {} pixels per meter", self.x, self.y),
+        }
+    }
+}
+
+/// sTER: the image holds a left and right view side by side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stereo {
+    /// The right-eye view is on the left.
+    CrossFuse,
+    /// The left-eye view is on the left.
+    DivergingFuse,
+}
+
+impl Extension for Stereo {
+    const CHUNK_TYPE: &'static str = "sTER";
+
+    fn from_data(data: &[u8]) -> Result<Self> {
+        match data {
+            [0] => Ok(Stereo::CrossFuse),
+            [1] => Ok(Stereo::DivergingFuse),
+            [mode] => Err(ExtensionError::InvalidValue {
+                chunk_type: Self::CHUNK_TYPE,
+                value: mode.to_string(),
+            }
+            .into()),
+            _ => Err(ExtensionError::WrongLength {
+                chunk_type: Self::CHUNK_TYPE,
+                len: data.len(),
+            }
+            .into()),
+        }
+    }
+
+    fn to_data(&self) -> Vec<u8> {
+        vec![match self {
+            Stereo::CrossFuse => 0,
+            Stereo::DivergingFuse => 1,
+        }]
+    }
+}
+
+impl Display for Stereo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Stereo::CrossFuse => write!(f, "cross"),
+            Stereo::DivergingFuse => write!(f, "diverging"),
+        }
+    }
+}
+
+impl FromStr for Stereo {
+    type Err = ExtensionError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, ExtensionError> {
+        match s {
+            "cross" => Ok(Stereo::CrossFuse),
+            "diverging" => Ok(Stereo::DivergingFuse),
+            _ => Err(ExtensionError::InvalidValue {
+                chunk_type: Self::CHUNK_TYPE,
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+/// Describe every extension chunk in `png` as `(label, value)` pairs, in a fixed order.
+pub fn describe(png: &Png) -> Vec<(&'static str, String)> {
+    fn show<T: Extension>(png: &Png) -> Option<String> {
+        T::read(png).map(|value| match value {
+            Ok(value) => value.to_string(),
+            Err(e) => format!("invalid ({})", e),
+        })
+    }
+
+    [
+        ("Offset", show::<Offset>(png)),
+        ("Scale", show::<Scale>(png)),
+        ("Stereo", show::<Stereo>(png)),
+    ]
+    .into_iter()
+    .filter_map(|(label, value)| Some((label, value?)))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offset_round_trip() {
+        let offset: Offset = "-12,34,um".parse().unwrap();
+        assert_eq!(offset.unit, OffsetUnit::Micrometer);
+        assert_eq!(Offset::from_data(&offset.to_data()).unwrap(), offset);
+        assert_eq!(offset.to_string(), "-12, 34 um");
+    }
+
+    #[test]
+    fn test_scale_round_trip() {
+        let scale: Scale = "0.0001,0.00025".parse().unwrap();
+        assert_eq!(scale.to_data(), b"\x010.0001\x000.00025");
+        assert_eq!(Scale::from_data(&scale.to_data()).unwrap(), scale);
+        assert_eq!(Scale::from_data(b"\x021.5E-3\x002").unwrap().width, 0.0015);
+    }
+
+    #[test]
+    fn test_density() {
+        let density = Density::from_dpi(300.0);
+        assert_eq!(density.x, 11811);
+        assert_eq!(Density::from_data(&density.to_data()).unwrap(), density);
+        assert_eq!(density.to_string(), "11811 pixels per meter (300 dpi)");
+        assert!(Density::from_data(&[0; 8]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        assert!("1,2,cm".parse::<Offset>().is_err());
+        assert!("-1,2".parse::<Scale>().is_err());
+        assert!(Scale::from_data(b"\x011.0").is_err());
+        assert!(Stereo::from_data(&[2]).is_err());
+        assert!(Offset::from_data(&[0; 8]).is_err());
+    }
+
+    #[test]
+    fn test_describe() {
+        let png = Png::from_chunks(vec![
+            Stereo::DivergingFuse.to_chunk(),
+            Offset {
+                x: 1,
+                y: 2,
+                unit: OffsetUnit::Pixel,
+            }
+            .to_chunk(),
+        ]);
+        assert_eq!(
+            describe(&png),
+            [
+                ("Offset", "1, 2 px".to_string()),
+                ("Stereo", "diverging".to_string())
+            ]
+        );
+    }
+}
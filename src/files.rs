@@ -0,0 +1,351 @@
+use std::{
+    collections::HashSet,
+    fs,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{png::Png, Result};
+
+/// Whether the file at `path` starts with the PNG signature, whatever it is called.
+pub fn has_png_signature(path: &Path) -> Result<bool> {
+    let mut signature = [0; 8];
+    match fs::File::open(path)?.read_exact(&mut signature) {
+        Ok(()) => Ok(signature == Png::STANDARD_HEADER),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Every PNG file beneath `root`, recognized by its signature, in a stable (sorted) order.
+///
+/// If `root` is a file it is returned as is, so commands can accept either. Symlinked
+/// directories are followed, but each directory is only read once, so a link back up the tree
+/// doesn't loop forever.
+pub fn png_files(root: &Path) -> Result<Vec<PathBuf>> {
+    if !root.is_dir() {
+        return Ok(vec![root.to_path_buf()]);
+    }
+
+    let mut files = vec![];
+    let mut visited = HashSet::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        if !visited.insert(fs::canonicalize(&dir)?) {
+            continue;
+        }
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if has_png_signature(&path).unwrap_or(false) {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Replace the contents of `path` with `bytes` so that readers only ever see the old or the new
+/// file, never a partial one.
+///
+/// The data goes to a temporary file next to the target, which is then renamed over it. An
+/// existing target keeps its permissions, and a symlink is followed rather than replaced.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let name = target
+        .file_name()
+        .ok_or_else(|| format!("{} is not a file path", path.display()))?;
+    let temp = target.with_file_name(format!(
+        ".{}.{}-{}.tmp",
+        name.to_string_lossy(),
+        process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let written = (|| -> Result<()> {
+        // The temp file gets the permissions of the target before any data goes in, so it is
+        // never readable by more users than the target, not even for a moment
+        let permissions = fs::metadata(&target)
+            .ok()
+            .map(|metadata| metadata.permissions());
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        if let Some(permissions) = &permissions {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            options.mode(permissions.mode());
+        }
+        let mut file = options.open(&temp)?;
+        if let Some(permissions) = permissions {
+            file.set_permissions(permissions)?;
+        }
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        tracing::debug!(
+            "wrote {}, renaming it over {}",
+            temp.display(),
+            target.display()
+        );
+        fs::rename(&temp, &target)?;
+        Ok(())
+    })();
+
+    match &written {
+        Ok(()) => tracing::info!("wrote {} bytes to {}", bytes.len(), target.display()),
+        Err(e) => {
+            tracing::debug!("writing {} failed: {}", target.display(), e);
+            let _ = fs::remove_file(&temp);
+        }
+    }
+    written
+}
+
+/// Give `path` the access and modification times recorded in `metadata`.
+pub fn set_times(path: &Path, metadata: &fs::Metadata) -> Result<()> {
+    let times = fs::FileTimes::new()
+        .set_accessed(metadata.accessed()?)
+        .set_modified(metadata.modified()?);
+    // The owner may set times through a read-only handle, so read-only files work too
+    fs::File::open(path)?.set_times(times)?;
+    Ok(())
+}
+
+/// Set the modification time of `path` to `secs` seconds since the Unix epoch.
+pub fn set_modified(path: &Path, secs: u64) -> Result<()> {
+    let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+    fs::File::open(path)?.set_times(fs::FileTimes::new().set_modified(modified))?;
+    Ok(())
+}
+
+/// Whether `path` holds wildcards for [`glob`] rather than naming a file.
+pub fn is_glob(path: &Path) -> bool {
+    let text = path.to_string_lossy();
+    text.contains(['*', '?']) && !path.exists()
+}
+
+fn components(path: &Path) -> Vec<String> {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Whether the components of `path` match those of `pattern`, where `**` stands for any number
+/// of directories and the rest follow [`wildcard_match`] within one component.
+fn glob_match(pattern: &[String], path: &[String]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skip| glob_match(rest, &path[skip..]))
+        }
+        Some((first, rest)) => path
+            .split_first()
+            .is_some_and(|(name, path)| wildcard_match(first, name) && glob_match(rest, path)),
+    }
+}
+
+/// The files matching `pattern`, in a stable (sorted) order, for shells that leave patterns such
+/// as `assets/**/*.png` unexpanded.
+pub fn glob(pattern: &Path) -> Result<Vec<PathBuf>> {
+    let pattern = components(pattern);
+    // The walk starts at the deepest directory named without wildcards
+    let literal = pattern
+        .iter()
+        .take_while(|component| !component.contains(['*', '?']))
+        .count();
+    let base: PathBuf = pattern[..literal].iter().collect();
+    let max_depth = match pattern.iter().any(|component| component == "**") {
+        true => usize::MAX,
+        false => pattern.len() - literal,
+    };
+
+    let mut files = vec![];
+    let mut pending = vec![(base, 0)];
+    while let Some((dir, depth)) = pending.pop() {
+        let entries = match dir.as_os_str().is_empty() {
+            true => fs::read_dir(".")?,
+            false => fs::read_dir(&dir)?,
+        };
+        for entry in entries {
+            let path = dir.join(entry?.file_name());
+            if path.is_dir() {
+                if depth + 1 < max_depth {
+                    pending.push((path, depth + 1));
+                }
+            } else if glob_match(&pattern, &components(&path)) {
+                files.push(path);
+            }
+        }
+    }
+
+    if files.is_empty() {
+        return Err(format!("No files match {}", pattern.join("/")).into());
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Shell-style wildcard matching where `*` matches any run of characters and `?` exactly one.
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("Adobe*", "Adobe ImageReady"));
+        assert!(wildcard_match("*Photoshop*", "Adobe Photoshop CC"));
+        assert!(wildcard_match("v?.0", "v2.0"));
+        assert!(wildcard_match("*", ""));
+        assert!(!wildcard_match("Adobe*", "GIMP 2.10"));
+        assert!(!wildcard_match("a?c", "ac"));
+    }
+
+    #[test]
+    fn test_png_signature() {
+        let dir = std::env::temp_dir().join(format!("pngme-sniff-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let png = dir.join("no-extension");
+        fs::write(&png, Png::from_chunks(vec![]).as_bytes()).unwrap();
+        let text = dir.join("fake.png");
+        fs::write(&text, "not an image").unwrap();
+
+        assert!(has_png_signature(&png).unwrap());
+        assert!(!has_png_signature(&text).unwrap());
+        assert_eq!(png_files(&dir).unwrap(), [png]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_png_files_symlink_loop() {
+        let dir = std::env::temp_dir().join(format!("pngme-loop-{}", process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        let png = dir.join("sub").join("image.png");
+        fs::write(&png, Png::from_chunks(vec![]).as_bytes()).unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("sub").join("up")).unwrap();
+
+        assert_eq!(png_files(&dir).unwrap(), [png]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = std::env::temp_dir().join(format!("pngme-atomic-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.png");
+
+        write_atomic(&path, b"first").unwrap();
+        write_atomic(&path, b"second").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        // Nothing but the target is left behind
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rewrite_keeps_metadata() {
+        let dir = std::env::temp_dir().join(format!("pngme-metadata-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.png");
+        fs::write(&path, b"first").unwrap();
+
+        let old = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        let mut permissions = fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&path, permissions).unwrap();
+        let before = fs::metadata(&path).unwrap();
+
+        write_atomic(&path, b"second").unwrap();
+        set_times(&path, &before).unwrap();
+
+        let after = fs::metadata(&path).unwrap();
+        assert!(after.permissions().readonly());
+        assert_eq!(after.modified().unwrap(), old);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomic_keeps_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("pngme-mode-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("secret.png");
+        fs::write(&path, b"first").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+
+        write_atomic(&path, b"second").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_glob() {
+        let pattern = |text: &str| components(Path::new(text));
+        assert!(glob_match(&pattern("a/**/*.png"), &pattern("a/b/c/x.png")));
+        assert!(glob_match(&pattern("a/**/*.png"), &pattern("a/x.png")));
+        assert!(!glob_match(&pattern("a/*.png"), &pattern("a/b/x.png")));
+
+        let found = glob(Path::new("examples/*.png")).unwrap();
+        assert_eq!(found, png_files(Path::new("examples")).unwrap());
+        assert!(glob(Path::new("examples/*.nothing")).is_err());
+    }
+
+    #[test]
+    fn test_png_files_finds_examples() {
+        let files = png_files(Path::new("examples")).unwrap();
+        assert!(files.len() >= 3);
+        assert!(files.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+}
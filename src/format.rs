@@ -0,0 +1,144 @@
+//! How commands that report on files present their results.
+
+use std::{io::Write, str::FromStr};
+
+use serde::Serialize;
+
+use crate::Result;
+
+#[derive(Debug)]
+pub enum FormatError {
+    Unknown(String),
+}
+
+impl std::error::Error for FormatError {}
+
+impl std::fmt::Display for FormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FormatError::Unknown(format) => {
+                write!(
+                    f,
+                    "Unknown output format '{}', expected text, json, jsonl, csv or tsv",
+                    format
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// Aligned, human-readable lines.
+    #[default]
+    Text,
+    /// One pretty-printed JSON document per command.
+    Json,
+    /// One compact JSON object per line, per finding where a command has several, so results
+    /// can be streamed.
+    Jsonl,
+    /// Comma-separated rows with a header, for spreadsheets.
+    Csv,
+    /// Tab-separated rows with a header, for awk and friends.
+    Tsv,
+}
+
+impl FromStr for Format {
+    type Err = FormatError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, FormatError> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "jsonl" | "ndjson" => Ok(Format::Jsonl),
+            "csv" => Ok(Format::Csv),
+            "tsv" => Ok(Format::Tsv),
+            _ => Err(FormatError::Unknown(s.to_string())),
+        }
+    }
+}
+
+impl Format {
+    /// The field separator of a tabular format.
+    pub fn separator(self) -> Option<char> {
+        match self {
+            Format::Csv => Some(','),
+            Format::Tsv => Some('\t'),
+            Format::Text | Format::Json | Format::Jsonl => None,
+        }
+    }
+
+    pub fn is_json(self) -> bool {
+        matches!(self, Format::Json | Format::Jsonl)
+    }
+
+    /// Print `value` to stdout, pretty for JSON and on a single line for JSON lines.
+    pub fn print_json(self, value: &impl Serialize) -> Result<()> {
+        let mut stdout = std::io::stdout().lock();
+        match self {
+            Format::Jsonl => serde_json::to_writer(&mut stdout, value)?,
+            _ => serde_json::to_writer_pretty(&mut stdout, value)?,
+        }
+        writeln!(stdout)?;
+        // Consumers of a stream act on each line as it arrives
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Format::Text => "text",
+            Format::Json => "json",
+            Format::Jsonl => "jsonl",
+            Format::Csv => "csv",
+            Format::Tsv => "tsv",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Join `fields` into one line separated by `separator`.
+///
+/// CSV fields holding the separator, quotes or line breaks are quoted. TSV has no quoting, so
+/// tabs and line breaks become spaces.
+pub fn row<S: AsRef<str>>(fields: &[S], separator: char) -> String {
+    let escape = |field: &str| {
+        if separator == '\t' {
+            field.replace(['\t', '\n', '\r'], " ")
+        } else if field.contains([separator, '"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    };
+
+    fields
+        .iter()
+        .map(|field| escape(field.as_ref()))
+        .collect::<Vec<_>>()
+        .join(&separator.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!(Format::from_str("JSON").unwrap(), Format::Json);
+        assert_eq!(Format::from_str("text").unwrap(), Format::Text);
+        assert!(Format::from_str("jsonl").unwrap().is_json());
+        assert!(Format::from_str("yaml").is_err());
+    }
+
+    #[test]
+    fn test_row() {
+        assert_eq!(
+            row(&["a", "b,c", "say \"hi\""], ','),
+            "a,\"b,c\",\"say \"\"hi\"\"\""
+        );
+        assert_eq!(row(&["a", "b\tc"], '\t'), "a\tb c");
+    }
+}
@@ -0,0 +1,240 @@
+//! Sanity checks for embedded ICC color profiles (iCCP), which viewers are quick to reject.
+
+use std::fmt::Display;
+
+use crate::{
+    bytes::be_u32, chunk::Chunk, chunk_type::ChunkType, ihdr_chunk::IhdrChunk, limits, png::Png,
+    Result,
+};
+
+const HEADER_LEN: usize = 128;
+const SIGNATURE: &[u8; 4] = b"acsp";
+
+#[derive(Debug)]
+pub enum IccError {
+    MissingKeyword,
+    UnknownCompression(u8),
+}
+
+impl std::error::Error for IccError {}
+
+impl Display for IccError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            IccError::MissingKeyword => write!(f, "iCCP chunk has no profile name"),
+            IccError::UnknownCompression(method) => {
+                write!(f, "iCCP chunk uses unknown compression method {}", method)
+            }
+        }
+    }
+}
+
+/// Something about a profile that makes viewers likely to ignore it or the whole image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    TooShort(usize),
+    SizeMismatch {
+        declared: usize,
+        actual: usize,
+    },
+    MissingSignature,
+    UnsupportedVersion(u8),
+    UnsupportedColorSpace(String),
+    ColorSpaceMismatch {
+        profile: String,
+        image: &'static str,
+    },
+    AlongsideSrgb,
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Warning::TooShort(len) => write!(
+                f,
+                "profile is {} bytes, shorter than the {} byte ICC header",
+                len, HEADER_LEN
+            ),
+            Warning::SizeMismatch { declared, actual } => write!(
+                f,
+                "header declares {} bytes but the profile is {} bytes",
+                declared, actual
+            ),
+            Warning::MissingSignature => write!(f, "header lacks the 'acsp' signature"),
+            Warning::UnsupportedVersion(major) => {
+                write!(f, "ICC version {} is not widely supported", major)
+            }
+            Warning::UnsupportedColorSpace(space) => {
+                write!(f, "color space '{}' is not allowed in PNG", space)
+            }
+            Warning::ColorSpaceMismatch { profile, image } => {
+                write!(f, "{} profile on a {} image", profile.trim_end(), image)
+            }
+            Warning::AlongsideSrgb => write!(f, "is redundant next to an sRGB chunk"),
+        }
+    }
+}
+
+/// A decompressed iCCP chunk.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+impl TryFrom<&Chunk> for Profile {
+    type Error = crate::Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let data = chunk.data();
+        let nul = data
+            .iter()
+            .position(|&byte| byte == 0)
+            .filter(|&nul| nul > 0)
+            .ok_or(IccError::MissingKeyword)?;
+
+        match data.get(nul + 1) {
+            Some(0) => {}
+            Some(&method) => return Err(IccError::UnknownCompression(method).into()),
+            None => return Err(IccError::UnknownCompression(0xff).into()),
+        }
+
+        Ok(Profile {
+            name: data[..nul].iter().map(|&byte| byte as char).collect(),
+            data: limits::inflate(&data[nul + 2..], u64::MAX)?,
+        })
+    }
+}
+
+impl Profile {
+    /// Problems with the profile header, given the image it is embedded in.
+    pub fn check(&self, ihdr: &IhdrChunk) -> Vec<Warning> {
+        let data = &self.data;
+        if data.len() < HEADER_LEN {
+            return vec![Warning::TooShort(data.len())];
+        }
+
+        let mut warnings = vec![];
+        let declared = be_u32(&data[0..4]) as usize;
+        if declared != data.len() {
+            warnings.push(Warning::SizeMismatch {
+                declared,
+                actual: data.len(),
+            });
+        }
+        if &data[36..40] != SIGNATURE {
+            warnings.push(Warning::MissingSignature);
+        }
+        if !matches!(data[8], 2 | 4) {
+            warnings.push(Warning::UnsupportedVersion(data[8]));
+        }
+
+        let space = String::from_utf8_lossy(&data[16..20]).into_owned();
+        let image = match ihdr.color_type() {
+            0 | 4 => "grayscale",
+            _ => "color",
+        };
+        match (space.as_str(), image) {
+            ("RGB ", "color") | ("GRAY", "grayscale") => {}
+            ("RGB ", _) | ("GRAY", _) => warnings.push(Warning::ColorSpaceMismatch {
+                profile: space,
+                image,
+            }),
+            _ => warnings.push(Warning::UnsupportedColorSpace(space)),
+        }
+
+        warnings
+    }
+}
+
+/// Check the embedded profile of `png`, if it has one. A profile that can't even be decompressed
+/// is an error.
+pub fn check(png: &Png) -> Option<Result<Vec<Warning>>> {
+    let chunk = png.chunk_by_type("iCCP")?;
+    let ihdr = png.chunk_by_type("IHDR")?;
+
+    Some((|| {
+        let ihdr = IhdrChunk::try_from(ihdr.clone())?;
+        let mut warnings = Profile::try_from(chunk)?.check(&ihdr);
+        if png.chunk_by_type("sRGB").is_some() {
+            warnings.push(Warning::AlongsideSrgb);
+        }
+        Ok(warnings)
+    })())
+}
+
+/// An sRGB chunk with the perceptual rendering intent, the usual stand-in for a broken profile.
+pub fn srgb_chunk() -> Chunk {
+    Chunk::new(ChunkType::try_from(*b"sRGB").unwrap(), vec![0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bytes::put_be_u32, text};
+
+    fn profile(space: &[u8; 4], len: usize) -> Vec<u8> {
+        let mut data = vec![];
+        put_be_u32(&mut data, len as u32);
+        data.resize(len, 0);
+        data[8] = 4;
+        data[16..20].copy_from_slice(space);
+        data[36..40].copy_from_slice(SIGNATURE);
+        data
+    }
+
+    fn iccp(profile: &[u8]) -> Chunk {
+        let mut data = b"test\0\0".to_vec();
+        data.extend(text::deflate(profile).unwrap());
+        Chunk::new(ChunkType::try_from(*b"iCCP").unwrap(), data)
+    }
+
+    fn ihdr(color_type: u8) -> IhdrChunk {
+        let data = vec![0, 0, 0, 1, 0, 0, 0, 1, 8, color_type, 0, 0, 0];
+        IhdrChunk::try_from(Chunk::new(ChunkType::try_from(*b"IHDR").unwrap(), data)).unwrap()
+    }
+
+    #[test]
+    fn test_valid_profile() {
+        let profile = Profile::try_from(&iccp(&profile(b"RGB ", 200))).unwrap();
+        assert_eq!(profile.name, "test");
+        assert!(profile.check(&ihdr(2)).is_empty());
+    }
+
+    #[test]
+    fn test_profile_warnings() {
+        let mut data = profile(b"CMYK", 200);
+        data[36] = b'x';
+        data.truncate(150);
+        let cmyk = Profile::try_from(&iccp(&data)).unwrap();
+        assert_eq!(
+            cmyk.check(&ihdr(6)),
+            [
+                Warning::SizeMismatch {
+                    declared: 200,
+                    actual: 150
+                },
+                Warning::MissingSignature,
+                Warning::UnsupportedColorSpace("CMYK".to_string()),
+            ]
+        );
+
+        let gray = Profile::try_from(&iccp(&profile(b"GRAY", 128))).unwrap();
+        assert_eq!(
+            gray.check(&ihdr(2)),
+            [Warning::ColorSpaceMismatch {
+                profile: "GRAY".to_string(),
+                image: "color"
+            }]
+        );
+    }
+
+    #[test]
+    fn test_broken_compression() {
+        let chunk = Chunk::new(
+            ChunkType::try_from(*b"iCCP").unwrap(),
+            b"x\0\0junk".to_vec(),
+        );
+        assert!(Profile::try_from(&chunk).is_err());
+    }
+}
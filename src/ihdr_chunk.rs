@@ -1,6 +1,6 @@
 use std::fmt::Display;
 
-use crate::{chunk::Chunk, Error, Result};
+use crate::{bytes::be_u32, chunk::Chunk, Error, Result};
 
 #[derive(Debug)]
 pub struct IhdrChunk {
@@ -29,6 +29,34 @@ pub struct IhdrChunk {
 
 impl IhdrChunk {
     const CHUNK_LENGTH: u32 = 13;
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    pub fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+    pub fn color_type(&self) -> u8 {
+        self.color_type
+    }
+    pub fn interlace_method(&self) -> u8 {
+        self.interlace_method
+    }
+
+    /// Human readable name of the color type.
+    pub fn color_type_name(&self) -> &'static str {
+        match self.color_type {
+            0 => "grayscale",
+            2 => "truecolor",
+            3 => "indexed",
+            4 => "grayscale+alpha",
+            6 => "truecolor+alpha",
+            _ => "unknown",
+        }
+    }
 }
 
 impl Display for IhdrChunk {
@@ -76,8 +104,8 @@ impl TryFrom<Chunk> for IhdrChunk {
         }
 
         Ok(IhdrChunk {
-            width: u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
-            height: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            width: be_u32(&bytes[0..4]),
+            height: be_u32(&bytes[4..8]),
             bit_depth: bytes[8],
             color_type: bytes[9],
             compression_method: bytes[10],
@@ -0,0 +1,190 @@
+//! Manifests of jobs for `run`, so a multi-file workflow is a file rather than a shell script.
+//!
+//! Each `[[job]]` table names the command in `op`, the image in `file` (or several in
+//! `files`), the remaining positional arguments in `args`, and any option by its long name:
+//!
+//! ```toml
+//! [[job]]
+//! op = "encode"
+//! file = "cat.png"
+//! args = ["ruSt", "hello"]
+//! output = "out/cat.png"
+//!
+//! [[job]]
+//! op = "strip"
+//! files = ["a.png", "b.png"]
+//! keep = ["tRNS", "gAMA"]
+//! ```
+
+use serde::Deserialize;
+use toml::{Table, Value};
+
+use crate::Result;
+
+#[derive(Debug)]
+pub enum JobError {
+    MissingOp(usize),
+    Nested(usize),
+    BadValue { job: usize, key: String },
+}
+
+impl std::error::Error for JobError {}
+
+impl std::fmt::Display for JobError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            JobError::MissingOp(job) => write!(f, "Job {} has no op", job),
+            JobError::Nested(job) => write!(f, "Job {} can't run another manifest", job),
+            JobError::BadValue { job, key } => {
+                write!(
+                    f,
+                    "Job {} sets {} to a table, expected a string, number, boolean or array",
+                    job, key
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    #[serde(default, rename = "job")]
+    jobs: Vec<Table>,
+}
+
+/// One job, as the command line arguments that would run it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    pub op: String,
+    pub args: Vec<String>,
+}
+
+impl std::fmt::Display for Job {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.args.join(" "))
+    }
+}
+
+/// Plain text for a value in an argument list.
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Integer(number) => Some(number.to_string()),
+        Value::Float(number) => Some(number.to_string()),
+        Value::Datetime(time) => Some(time.to_string()),
+        Value::Boolean(_) | Value::Array(_) | Value::Table(_) => None,
+    }
+}
+
+/// The jobs listed in the manifest `manifest`, numbered from 1 in errors.
+pub fn parse(manifest: &str) -> Result<Vec<Job>> {
+    let manifest: Manifest = toml::from_str(manifest)?;
+    let mut jobs = vec![];
+    for (number, mut table) in (1..).zip(manifest.jobs) {
+        let bad_value = |key: &str| JobError::BadValue {
+            job: number,
+            key: key.to_string(),
+        };
+        let op = match table.remove("op") {
+            Some(Value::String(op)) => op,
+            _ => return Err(JobError::MissingOp(number).into()),
+        };
+        if op == "run" {
+            return Err(JobError::Nested(number).into());
+        }
+
+        let mut args = vec![op.clone()];
+        // Positionals first, in the order the commands take them
+        for key in ["file", "files", "args"] {
+            match table.remove(key) {
+                None => {}
+                Some(Value::Array(values)) => {
+                    for value in &values {
+                        args.push(text(value).ok_or_else(|| bad_value(key))?);
+                    }
+                }
+                Some(value) => args.push(text(&value).ok_or_else(|| bad_value(key))?),
+            }
+        }
+
+        for (key, value) in table {
+            let flag = format!("--{}", key.replace('_', "-"));
+            match value {
+                Value::Boolean(true) => args.push(flag),
+                Value::Boolean(false) => {}
+                Value::Array(values) => {
+                    for value in &values {
+                        args.push(flag.clone());
+                        args.push(text(value).ok_or_else(|| bad_value(&key))?);
+                    }
+                }
+                value => {
+                    args.push(flag);
+                    args.push(text(&value).ok_or_else(|| bad_value(&key))?);
+                }
+            }
+        }
+        jobs.push(Job { op, args });
+    }
+    Ok(jobs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let jobs = parse(
+            r#"
+            [[job]]
+            op = "encode"
+            file = "cat.png"
+            args = ["ruSt", "hello"]
+            output = "out/cat.png"
+
+            [[job]]
+            op = "strip"
+            files = ["a.png", "b.png"]
+            keep = ["tRNS", "gAMA"]
+            dry_run = true
+            fix_case = false
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            jobs[0].args,
+            [
+                "encode",
+                "cat.png",
+                "ruSt",
+                "hello",
+                "--output",
+                "out/cat.png"
+            ]
+        );
+        assert_eq!(
+            jobs[1].args,
+            [
+                "strip",
+                "a.png",
+                "b.png",
+                "--dry-run",
+                "--keep",
+                "tRNS",
+                "--keep",
+                "gAMA"
+            ]
+        );
+        assert_eq!(jobs[1].op, "strip");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("[[job]]\nfile = \"a.png\"").is_err());
+        assert!(parse("[[job]]\nop = \"run\"").is_err());
+        assert!(parse("[[job]]\nop = \"verify\"\nlimits = { size = 1 }").is_err());
+        assert!(parse("").unwrap().is_empty());
+    }
+}
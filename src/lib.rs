@@ -0,0 +1,66 @@
+//! Read, inspect and rewrite PNG files chunk by chunk.
+//!
+//! The `pngme` binary is a thin layer over this crate: [`png::Png`] holds the chunk sequence,
+//! [`chunk::Chunk`] and [`chunk_type::ChunkType`] the individual chunks, and [`ordering`] the
+//! spec's placement rules for checking a file before it is written. [`stream`] visits the chunks
+//! of a file one at a time without holding it in memory.
+
+pub mod apng;
+pub mod args;
+pub mod bytes;
+pub mod chunk;
+pub mod chunk_type;
+mod codec;
+pub mod commands;
+mod compress;
+mod convert;
+mod crypto;
+mod daemon;
+mod db;
+mod diff;
+mod dupes;
+mod ecc;
+mod edit;
+mod envelope;
+pub mod exit;
+mod extension;
+mod files;
+mod format;
+mod icc;
+pub mod ihdr_chunk;
+mod jobs;
+pub mod limits;
+mod logging;
+pub mod memstats;
+pub mod ordering;
+mod payload;
+mod phash;
+pub mod png;
+mod position;
+mod preset;
+mod progress;
+pub mod raster;
+mod recipient;
+mod registry;
+mod remote;
+mod report;
+mod repro;
+mod scan;
+mod scrub;
+mod secret;
+mod selftest;
+mod signature;
+mod sink;
+mod slot;
+mod span;
+mod stamp;
+mod stats;
+pub mod stream;
+mod style;
+mod summary;
+mod template;
+pub mod text;
+mod transform;
+
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,256 @@
+//! Resource limits for parsing untrusted images.
+//!
+//! By default nothing is limited beyond what the format itself implies. `--hardened` installs
+//! [`Limits::HARDENED`], under which pngme guarantees that:
+//!
+//! * files, chunk counts, chunk sizes, image dimensions and frame counts are bounded before
+//!   anything proportional to them is allocated,
+//! * every zlib stream (image data, zTXt/iTXt text, iCCP profiles), gzip transform and
+//!   compressed payload is inflated into a bounded buffer, so decompression bombs fail early instead of exhausting
+//!   memory,
+//! * binary metadata (ICC headers, eXIf) is only read through bounds-checked accessors,
+//! * a panic anywhere in a command is reported as an ordinary error instead of aborting.
+
+use std::{io::Read, sync::OnceLock};
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+
+use crate::{png::Png, Result};
+
+#[derive(Debug)]
+pub enum LimitError {
+    FileTooLarge { size: u64, max: u64 },
+    TooManyChunks { count: usize, max: usize },
+    ChunkTooLarge { size: usize, max: usize },
+    TooManyPixels { width: u32, height: u32 },
+    TooManyFrames { count: u32, max: u32 },
+    InflatedTooLarge { max: u64 },
+}
+
+impl std::error::Error for LimitError {}
+
+impl std::fmt::Display for LimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LimitError::FileTooLarge { size, max } => {
+                write!(f, "File is {} bytes, the limit is {}", size, max)
+            }
+            LimitError::TooManyChunks { count, max } => {
+                write!(f, "File has {} chunks, the limit is {}", count, max)
+            }
+            LimitError::ChunkTooLarge { size, max } => {
+                write!(f, "Chunk holds {} bytes, the limit is {}", size, max)
+            }
+            LimitError::TooManyPixels { width, height } => {
+                write!(
+                    f,
+                    "Image of {}x{} pixels exceeds the pixel limit",
+                    width, height
+                )
+            }
+            LimitError::TooManyFrames { count, max } => {
+                write!(f, "Animation has {} frames, the limit is {}", count, max)
+            }
+            LimitError::InflatedTooLarge { max } => {
+                write!(f, "Compressed data inflates to more than {} bytes", max)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub max_file_size: u64,
+    pub max_chunks: usize,
+    pub max_chunk_size: usize,
+    pub max_pixels: u64,
+    pub max_frames: u32,
+    /// Upper bound for any single inflated zlib stream.
+    pub max_inflated: u64,
+}
+
+impl Limits {
+    pub const UNLIMITED: Limits = Limits {
+        max_file_size: u64::MAX,
+        max_chunks: usize::MAX,
+        max_chunk_size: usize::MAX,
+        max_pixels: u64::MAX,
+        max_frames: u32::MAX,
+        max_inflated: u64::MAX,
+    };
+
+    pub const HARDENED: Limits = Limits {
+        max_file_size: 64 << 20,
+        max_chunks: 10_000,
+        max_chunk_size: 16 << 20,
+        max_pixels: 32 << 20,
+        max_frames: 1_000,
+        max_inflated: 256 << 20,
+    };
+
+    /// The largest payload that can still be added, in bytes of chunk data, to a file of
+    /// `file_len` bytes holding `chunks` chunks. `None` when nothing limits it.
+    pub fn free_capacity(&self, file_len: u64, chunks: usize) -> Option<u64> {
+        // Length, type and CRC of every chunk added
+        const CHUNK_OVERHEAD: u64 = 12;
+        if self.max_file_size == u64::MAX && self.max_chunks == usize::MAX {
+            return None;
+        }
+
+        let chunk_size = self.max_chunk_size.min(i32::MAX as usize) as u64;
+        let by_count = (self.max_chunks.saturating_sub(chunks) as u64).saturating_mul(chunk_size);
+        let room = self.max_file_size.saturating_sub(file_len);
+        let by_size = room - CHUNK_OVERHEAD * room.div_ceil(chunk_size + CHUNK_OVERHEAD);
+        Some(by_count.min(by_size))
+    }
+
+    pub fn check_file_size(&self, size: u64) -> Result<()> {
+        if size > self.max_file_size {
+            return Err(LimitError::FileTooLarge {
+                size,
+                max: self.max_file_size,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    pub fn check_png(&self, png: &Png) -> Result<()> {
+        let count = png.chunks().len();
+        if count > self.max_chunks {
+            return Err(LimitError::TooManyChunks {
+                count,
+                max: self.max_chunks,
+            }
+            .into());
+        }
+
+        if let Some(chunk) = png
+            .chunks()
+            .iter()
+            .find(|chunk| chunk.data().len() > self.max_chunk_size)
+        {
+            return Err(LimitError::ChunkTooLarge {
+                size: chunk.data().len(),
+                max: self.max_chunk_size,
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    pub fn check_dimensions(&self, width: u32, height: u32) -> Result<()> {
+        if width as u64 * height as u64 > self.max_pixels {
+            return Err(LimitError::TooManyPixels { width, height }.into());
+        }
+        Ok(())
+    }
+
+    pub fn check_frames(&self, count: u32) -> Result<()> {
+        if count > self.max_frames {
+            return Err(LimitError::TooManyFrames {
+                count,
+                max: self.max_frames,
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+static ACTIVE: OnceLock<Limits> = OnceLock::new();
+
+/// Make `limits` apply to the rest of the process. Only the first call has an effect.
+pub fn install(limits: Limits) {
+    let _ = ACTIVE.set(limits);
+}
+
+/// The limits in effect, [`Limits::UNLIMITED`] unless others were installed.
+pub fn active() -> &'static Limits {
+    ACTIVE.get().unwrap_or(&Limits::UNLIMITED)
+}
+
+/// Inflate a zlib stream, failing once the output would exceed `max` bytes or the active
+/// inflate limit, whichever is lower.
+pub fn inflate(data: &[u8], max: u64) -> Result<Vec<u8>> {
+    read_bounded(ZlibDecoder::new(data), max.min(active().max_inflated))
+}
+
+/// Decompress a gzip stream, failing once the output would exceed the active inflate limit.
+pub fn gunzip(data: &[u8]) -> Result<Vec<u8>> {
+    read_bounded(GzDecoder::new(data), active().max_inflated)
+}
+
+fn read_bounded(reader: impl Read, max: u64) -> Result<Vec<u8>> {
+    let mut out = vec![];
+    reader.take(max.saturating_add(1)).read_to_end(&mut out)?;
+
+    if out.len() as u64 > max {
+        return Err(LimitError::InflatedTooLarge { max }.into());
+    }
+
+    Ok(out)
+}
+
+/// Decompress a zstd stream, failing once the output would exceed the active inflate limit.
+pub fn unzstd(data: &[u8]) -> Result<Vec<u8>> {
+    read_bounded(zstd::Decoder::new(data)?, active().max_inflated)
+}
+
+/// Inflate at most the first `len` bytes of a zlib stream, ignoring anything after them.
+///
+/// For streams whose useful size is known up front, such as image data.
+pub fn inflate_prefix(data: &[u8], len: u64) -> Result<Vec<u8>> {
+    let max = active().max_inflated;
+    if len > max {
+        return Err(LimitError::InflatedTooLarge { max }.into());
+    }
+
+    let mut out = vec![];
+    ZlibDecoder::new(data).take(len).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn deflate(data: &[u8]) -> Vec<u8> {
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_bounded_inflate() {
+        let bomb = deflate(&vec![0u8; 1 << 20]);
+        assert!(inflate(&bomb, 1 << 20).is_ok());
+        assert!(inflate(&bomb, 1000).is_err());
+        assert_eq!(inflate_prefix(&bomb, 1000).unwrap().len(), 1000);
+    }
+
+    #[test]
+    fn test_hardened_limits() {
+        let limits = Limits::HARDENED;
+        assert!(limits.check_dimensions(4096, 4096).is_ok());
+        assert!(limits.check_dimensions(100_000, 100_000).is_err());
+        assert!(limits.check_frames(5_000).is_err());
+        assert!(limits.check_file_size(1 << 30).is_err());
+        assert!(Limits::UNLIMITED
+            .check_dimensions(u32::MAX, u32::MAX)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_free_capacity() {
+        let limits = Limits::HARDENED;
+        assert_eq!(limits.free_capacity((64 << 20) - 100, 3), Some(88));
+        assert_eq!(limits.free_capacity(1000, 10_000), Some(0));
+        assert_eq!(limits.free_capacity(64 << 20, 3), Some(0));
+        assert!(limits.free_capacity(1000, 3).unwrap() > 60 << 20);
+        assert_eq!(Limits::UNLIMITED.free_capacity(1000, 3), None);
+    }
+}
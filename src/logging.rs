@@ -0,0 +1,44 @@
+//! Diagnostics on stderr for `-v` and `-q`, separate from the output of commands.
+//!
+//! Parsing, CRC checks and file writes are traced at debug and trace level, so a malformed
+//! image can be followed chunk by chunk with `-vv` or `-vvv`.
+
+use tracing::Level;
+
+/// The most detailed level shown for `verbose` (the number of `-v`s), or `None` to show nothing.
+pub fn level(verbose: u8, quiet: bool) -> Option<Level> {
+    match (quiet, verbose) {
+        (true, _) => None,
+        (false, 0) => Some(Level::WARN),
+        (false, 1) => Some(Level::INFO),
+        (false, 2) => Some(Level::DEBUG),
+        (false, _) => Some(Level::TRACE),
+    }
+}
+
+/// Send diagnostics up to `level` to stderr. Does nothing if a subscriber is already set.
+pub fn init(level: Option<Level>) {
+    let Some(level) = level else {
+        return;
+    };
+
+    let _ = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(std::io::stderr)
+        .with_target(false)
+        .without_time()
+        .try_init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level() {
+        assert_eq!(level(0, false), Some(Level::WARN));
+        assert_eq!(level(2, false), Some(Level::DEBUG));
+        assert_eq!(level(7, false), Some(Level::TRACE));
+        assert_eq!(level(3, true), None);
+    }
+}
@@ -1,26 +1,26 @@
 use std::process;
 
-use args::Cli;
 use clap::Parser;
-use commands::run;
+use pngme::{
+    args::Cli,
+    commands::run,
+    exit::{self, CheckFailed},
+    Result,
+};
 
-mod args;
-mod chunk;
-mod chunk_type;
-mod commands;
-mod png;
-mod ihdr_chunk;
-mod idat_chunk;
-
-pub type Error = Box<dyn std::error::Error>;
-pub type Result<T> = std::result::Result<T, Error>;
+#[cfg(feature = "mem-stats")]
+#[global_allocator]
+static ALLOCATOR: pngme::memstats::CountingAllocator = pngme::memstats::CountingAllocator;
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
     if let Err(error) = run(&cli) {
-        eprintln!("An error occurred: {}", error);
-        process::exit(1);
+        // A failed check was reported by the command itself
+        if !cli.check && !error.is::<CheckFailed>() {
+            eprintln!("An error occurred: {}", error);
+        }
+        process::exit(exit::code(&error) as i32);
     }
 
     Ok(())
@@ -0,0 +1,159 @@
+//! Memory usage of the running process, for `--mem-stats`.
+//!
+//! Peak RSS comes from the OS where it can be read (Linux). Allocation totals need the
+//! [`CountingAllocator`], which only exists with the `mem-stats` feature since it puts a couple of
+//! atomic operations on every allocation.
+
+use std::fmt::Display;
+
+#[cfg(feature = "mem-stats")]
+pub use counting::CountingAllocator;
+
+#[cfg(feature = "mem-stats")]
+mod counting {
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        sync::atomic::{AtomicU64, Ordering},
+    };
+
+    pub(super) static ALLOCATED: AtomicU64 = AtomicU64::new(0);
+    pub(super) static CURRENT: AtomicU64 = AtomicU64::new(0);
+    pub(super) static PEAK: AtomicU64 = AtomicU64::new(0);
+
+    /// The system allocator, counting the bytes it hands out. Install it with
+    /// `#[global_allocator]` for [`super::snapshot`] to report allocations.
+    pub struct CountingAllocator;
+
+    fn track(size: u64) {
+        ALLOCATED.fetch_add(size, Ordering::Relaxed);
+        let current = CURRENT.fetch_add(size, Ordering::Relaxed) + size;
+        PEAK.fetch_max(current, Ordering::Relaxed);
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = System.alloc(layout);
+            if !ptr.is_null() {
+                track(layout.size() as u64);
+            }
+            ptr
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            let ptr = System.alloc_zeroed(layout);
+            if !ptr.is_null() {
+                track(layout.size() as u64);
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout);
+            CURRENT.fetch_sub(layout.size() as u64, Ordering::Relaxed);
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let new = System.realloc(ptr, layout, new_size);
+            if !new.is_null() {
+                CURRENT.fetch_sub(layout.size() as u64, Ordering::Relaxed);
+                track(new_size as u64);
+            }
+            new
+        }
+    }
+}
+
+/// Memory figures for the process so far. `None` where they can't be measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub peak_rss: Option<u64>,
+    /// Sum of all allocations, including ones freed since.
+    pub allocated: Option<u64>,
+    /// Most heap memory in use at any one time.
+    pub peak_heap: Option<u64>,
+}
+
+pub fn snapshot() -> Stats {
+    #[cfg(feature = "mem-stats")]
+    let (allocated, peak_heap) = {
+        use std::sync::atomic::Ordering;
+        (
+            Some(counting::ALLOCATED.load(Ordering::Relaxed)),
+            Some(counting::PEAK.load(Ordering::Relaxed)),
+        )
+    };
+    #[cfg(not(feature = "mem-stats"))]
+    let (allocated, peak_heap) = (None, None);
+
+    Stats {
+        peak_rss: std::fs::read_to_string("/proc/self/status")
+            .ok()
+            .and_then(|status| parse_peak_rss(&status)),
+        allocated,
+        peak_heap,
+    }
+}
+
+/// The `VmHWM` (high water mark of the resident set) line of `/proc/<pid>/status`, in bytes.
+fn parse_peak_rss(status: &str) -> Option<u64> {
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kib: u64 = line["VmHWM:".len()..]
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+fn mib(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1 << 20) as f64)
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let unavailable = "unavailable";
+        let counted = "needs a build with --features mem-stats";
+
+        writeln!(
+            f,
+            "Peak RSS:        {}",
+            self.peak_rss.map_or(unavailable.into(), mib)
+        )?;
+        writeln!(
+            f,
+            "Peak heap:       {}",
+            self.peak_heap.map_or(counted.into(), mib)
+        )?;
+        write!(
+            f,
+            "Total allocated: {}",
+            self.allocated.map_or(counted.into(), mib)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_peak_rss() {
+        let status = "Name:\tpngme\nVmPeak:\t   10000 kB\nVmHWM:\t    2048 kB\nVmRSS:\t 1024 kB\n";
+        assert_eq!(parse_peak_rss(status), Some(2 << 20));
+        assert_eq!(parse_peak_rss("Name:\tpngme\n"), None);
+    }
+
+    #[test]
+    fn test_display() {
+        let stats = Stats {
+            peak_rss: Some(3 << 20),
+            allocated: None,
+            peak_heap: Some(1 << 19),
+        };
+        let text = stats.to_string();
+        assert!(text.contains("Peak RSS:        3.0 MiB"));
+        assert!(text.contains("Peak heap:       0.5 MiB"));
+        assert!(text.contains("--features mem-stats"));
+    }
+}
@@ -0,0 +1,277 @@
+//! The chunk ordering rules of the PNG specification, as data.
+//!
+//! ```
+//! use std::str::FromStr;
+//! use pngme::{chunk_type::ChunkType, ordering};
+//!
+//! let gama = ordering::constraint_for(&ChunkType::from_str("gAMA").unwrap());
+//! assert!(gama.unique && gama.before_plte && gama.before_idat);
+//! ```
+
+use crate::{chunk_type::ChunkType, png::Png};
+
+/// Where a chunk type may appear and how often.
+///
+/// `before_*`/`after_*` only apply when the file has the chunk they refer to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Constraint {
+    /// Must be the first chunk.
+    pub first: bool,
+    /// Must be the last chunk.
+    pub last: bool,
+    /// At most one chunk of this type.
+    pub unique: bool,
+    pub before_plte: bool,
+    pub after_plte: bool,
+    pub before_idat: bool,
+    pub after_idat: bool,
+    /// All chunks of this type must follow each other without other chunks in between.
+    pub consecutive: bool,
+}
+
+/// The placement rules for `chunk_type`. Unknown types may appear anywhere between IHDR and
+/// IEND, any number of times.
+pub fn constraint_for(chunk_type: &ChunkType) -> Constraint {
+    let unique = Constraint {
+        unique: true,
+        ..Default::default()
+    };
+
+    match &chunk_type.bytes() {
+        b"IHDR" => Constraint {
+            first: true,
+            ..unique
+        },
+        b"IEND" => Constraint {
+            last: true,
+            ..unique
+        },
+        b"PLTE" => Constraint {
+            before_idat: true,
+            ..unique
+        },
+        b"IDAT" => Constraint {
+            consecutive: true,
+            ..Default::default()
+        },
+        b"cHRM" | b"gAMA" | b"iCCP" | b"sBIT" | b"sRGB" | b"cICP" | b"mDCV" | b"cLLI" => {
+            Constraint {
+                before_plte: true,
+                before_idat: true,
+                ..unique
+            }
+        }
+        b"bKGD" | b"hIST" | b"tRNS" => Constraint {
+            after_plte: true,
+            before_idat: true,
+            ..unique
+        },
+        b"pHYs" | b"eXIf" | b"acTL" | b"oFFs" | b"pCAL" | b"sCAL" | b"sTER" => Constraint {
+            before_idat: true,
+            ..unique
+        },
+        b"sPLT" => Constraint {
+            before_idat: true,
+            ..Default::default()
+        },
+        b"fdAT" => Constraint {
+            after_idat: true,
+            ..Default::default()
+        },
+        b"tIME" => unique,
+        _ => Constraint::default(),
+    }
+}
+
+/// The latest index at which a chunk of `chunk_type` can be inserted into `png` without breaking
+/// its placement rules, assuming the file is otherwise in order.
+pub fn insertion_index(png: &Png, chunk_type: &ChunkType) -> usize {
+    let chunks = png.chunks();
+    let position = |code: &str| {
+        chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type().to_string() == code)
+    };
+    let constraint = constraint_for(chunk_type);
+
+    if constraint.first {
+        return 0;
+    }
+    let end = position("IEND").unwrap_or(chunks.len());
+    if constraint.last {
+        return end;
+    }
+
+    let mut index = end;
+    if constraint.before_plte {
+        index = index.min(position("PLTE").unwrap_or(end));
+    }
+    if constraint.before_idat {
+        index = index.min(position("IDAT").unwrap_or(end));
+    }
+    index
+}
+
+/// A rule broken by the chunk at `index`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub index: usize,
+    pub chunk_type: String,
+    pub rule: Rule,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    First,
+    Last,
+    Unique,
+    BeforePlte,
+    AfterPlte,
+    BeforeIdat,
+    AfterIdat,
+    Consecutive,
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let rule = match self.rule {
+            Rule::First => "must be the first chunk",
+            Rule::Last => "must be the last chunk",
+            Rule::Unique => "must not appear more than once",
+            Rule::BeforePlte => "must come before PLTE",
+            Rule::AfterPlte => "must come after PLTE",
+            Rule::BeforeIdat => "must come before the image data",
+            Rule::AfterIdat => "must come after the image data",
+            Rule::Consecutive => "chunks must be consecutive",
+        };
+        write!(f, "[{}] {} {}", self.index, self.chunk_type, rule)
+    }
+}
+
+/// Every placement rule the chunks of `png` break, in file order.
+pub fn check(png: &Png) -> Vec<Violation> {
+    let chunks = png.chunks();
+    let position = |code: &str| {
+        chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type().to_string() == code)
+    };
+    let plte = position("PLTE");
+    let first_idat = position("IDAT");
+    let last_idat = chunks
+        .iter()
+        .rposition(|chunk| chunk.chunk_type().to_string() == "IDAT");
+
+    let mut violations = vec![];
+    for (index, chunk) in chunks.iter().enumerate() {
+        let chunk_type = chunk.chunk_type().to_string();
+        let constraint = constraint_for(chunk.chunk_type());
+        let first_of_type = position(&chunk_type);
+
+        let broken = [
+            (constraint.first && index != 0, Rule::First),
+            (constraint.last && index + 1 != chunks.len(), Rule::Last),
+            (
+                constraint.unique && first_of_type != Some(index),
+                Rule::Unique,
+            ),
+            (
+                constraint.before_plte && plte.is_some_and(|plte| index > plte),
+                Rule::BeforePlte,
+            ),
+            (
+                constraint.after_plte && plte.is_some_and(|plte| index < plte),
+                Rule::AfterPlte,
+            ),
+            (
+                constraint.before_idat && first_idat.is_some_and(|idat| index > idat),
+                Rule::BeforeIdat,
+            ),
+            (
+                constraint.after_idat && last_idat.is_some_and(|idat| index < idat),
+                Rule::AfterIdat,
+            ),
+            (
+                constraint.consecutive
+                    && index > 0
+                    && first_of_type != Some(index)
+                    && chunks[index - 1].chunk_type().to_string() != chunk_type,
+                Rule::Consecutive,
+            ),
+        ];
+
+        violations.extend(
+            broken
+                .into_iter()
+                .filter(|(broken, _)| *broken)
+                .map(|(_, rule)| Violation {
+                    index,
+                    chunk_type: chunk_type.clone(),
+                    rule,
+                }),
+        );
+    }
+
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use std::str::FromStr;
+
+    fn png(types: &[&str]) -> Png {
+        Png::from_chunks(
+            types
+                .iter()
+                .map(|code| Chunk::new(ChunkType::from_str(code).unwrap(), vec![]))
+                .collect(),
+        )
+    }
+
+    fn rules(types: &[&str]) -> Vec<(usize, Rule)> {
+        check(&png(types))
+            .into_iter()
+            .map(|violation| (violation.index, violation.rule))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_order() {
+        assert!(
+            rules(&["IHDR", "gAMA", "PLTE", "tRNS", "IDAT", "IDAT", "tEXt", "IEND"]).is_empty()
+        );
+    }
+
+    #[test]
+    fn test_insertion_index() {
+        let file = png(&["IHDR", "gAMA", "PLTE", "IDAT", "IDAT", "IEND"]);
+        let at = |code| insertion_index(&file, &ChunkType::from_str(code).unwrap());
+
+        assert_eq!(at("iCCP"), 2);
+        assert_eq!(at("tRNS"), 3);
+        assert_eq!(at("tEXt"), 5);
+        assert_eq!(at("IHDR"), 0);
+    }
+
+    #[test]
+    fn test_violations() {
+        assert_eq!(
+            rules(&["IDAT", "IHDR", "gAMA", "IEND"]),
+            [(1, Rule::First), (2, Rule::BeforeIdat)]
+        );
+        assert_eq!(
+            rules(&["IHDR", "PLTE", "gAMA", "IDAT", "IEND"]),
+            [(2, Rule::BeforePlte)]
+        );
+        assert_eq!(
+            rules(&["IHDR", "IDAT", "tEXt", "IDAT", "IEND", "ruSt"]),
+            [(3, Rule::Consecutive), (4, Rule::Last)]
+        );
+        assert_eq!(
+            rules(&["IHDR", "tIME", "tIME", "IDAT", "IEND"]),
+            [(2, Rule::Unique)]
+        );
+    }
+}
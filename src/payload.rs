@@ -0,0 +1,634 @@
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use crate::{
+    crypto::{self, Cipher, KdfParams},
+    ecc::{self, EccError, EccLevel},
+    envelope::{Envelope, FileInfo, Protection, Sealed},
+    recipient::{self, Identities, Recipient, RecipientError},
+    Result,
+};
+
+#[derive(Debug)]
+pub enum PayloadError {
+    PasswordRequired,
+    MacKeyRequired,
+    MacMissing,
+    FileCorrupted(String),
+    DuplicateFile(String),
+    Corrupted,
+    SameDecoyPassword,
+}
+
+impl std::error::Error for PayloadError {}
+
+impl std::fmt::Display for PayloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PayloadError::PasswordRequired => {
+                write!(f, "This message is encrypted, a password is required")
+            }
+            PayloadError::MacKeyRequired => write!(
+                f,
+                "This message carries an HMAC, the key is required to check it"
+            ),
+            PayloadError::MacMissing => write!(
+                f,
+                "This message carries no HMAC, so it can't be checked against the key"
+            ),
+            PayloadError::FileCorrupted(name) => write!(
+                f,
+                "The contents of {} don't match the hash recorded when it was embedded",
+                name
+            ),
+            PayloadError::DuplicateFile(name) => {
+                write!(
+                    f,
+                    "There are two files named {}, names must be unique",
+                    name
+                )
+            }
+            PayloadError::SameDecoyPassword => write!(
+                f,
+                "The decoy password must differ from the password, or only one message could be opened"
+            ),
+            PayloadError::Corrupted => write!(
+                f,
+                "The message is corrupted, it doesn't match the SHA-256 recorded when it was embedded"
+            ),
+        }
+    }
+}
+
+/// Everything that may be needed to open a payload.
+#[derive(Default, Clone, Copy)]
+pub struct Keys<'a> {
+    pub password: Option<&'a str>,
+    pub identities: Option<&'a Identities>,
+    pub hmac_key: Option<&'a [u8]>,
+}
+
+/// A second, innocuous message that is revealed by its own passphrase.
+pub struct Decoy<'a> {
+    pub message: &'a [u8],
+    pub password: &'a str,
+}
+
+/// Encrypt `message` with `cipher` so that only `password` reveals it.
+pub fn seal(message: &[u8], password: &str, kdf: KdfParams, cipher: Cipher) -> Result<Envelope> {
+    let salt = crypto::random_bytes(crypto::SALT_LEN)?;
+    let body = crypto::seal_slots(&salt, &kdf, cipher, &[(password, message)])?;
+
+    Ok(Envelope {
+        sealed: Some(Sealed { salt, kdf, cipher }),
+        body,
+        ..Default::default()
+    })
+}
+
+/// Encrypt `message` next to a decoy so that either passphrase reveals "a" message.
+///
+/// The envelope looks just like one from [`seal`], which fills the second slot with random
+/// bytes, so someone holding the decoy passphrase can't prove a second message exists.
+pub fn seal_with_decoy(
+    message: &[u8],
+    password: &str,
+    decoy: Decoy,
+    kdf: KdfParams,
+    cipher: Cipher,
+) -> Result<Envelope> {
+    // Both slots would open with the same key, and only the first would ever be found
+    if password == decoy.password {
+        return Err(PayloadError::SameDecoyPassword.into());
+    }
+    let salt = crypto::random_bytes(crypto::SALT_LEN)?;
+    let body = crypto::seal_slots(
+        &salt,
+        &kdf,
+        cipher,
+        &[(password, message), (decoy.password, decoy.message)],
+    )?;
+
+    Ok(Envelope {
+        sealed: Some(Sealed { salt, kdf, cipher }),
+        body,
+        ..Default::default()
+    })
+}
+
+/// Encrypt `message` so that only the holders of the private keys of `recipients` can read it.
+///
+/// The message is encrypted once under a random file key, which is then wrapped for each
+/// recipient, so the payload grows by a small stanza per key rather than a copy per key.
+pub fn seal_to(message: &[u8], recipients: &[Recipient]) -> Result<Envelope> {
+    Ok(Envelope {
+        recipients: Some(recipients.len() as u16),
+        body: recipient::encrypt(message, recipients)?,
+        ..Default::default()
+    })
+}
+
+/// Make the envelope tamper-evident with an HMAC-SHA256 under `key` of its header and body.
+/// Every header field is final by then.
+pub fn authenticate(mut envelope: Envelope, key: &[u8]) -> Envelope {
+    envelope.mac = None;
+    envelope.mac = Some(crypto::mac(key, &envelope.authenticated_bytes()).to_vec());
+    envelope
+}
+
+/// Recover the message held by an envelope, checking its HMAC and decrypting it when necessary.
+/// The message is wiped when dropped.
+pub fn open(envelope: Envelope, keys: Keys) -> Result<Zeroizing<Vec<u8>>> {
+    match (&envelope.mac, keys.hmac_key) {
+        (Some(mac), Some(key)) => crypto::verify_mac(key, &envelope.authenticated_bytes(), mac)?,
+        (Some(_), None) => return Err(PayloadError::MacKeyRequired.into()),
+        (None, Some(_)) => return Err(PayloadError::MacMissing.into()),
+        (None, None) => {}
+    }
+
+    let message = match &envelope.sealed {
+        _ if envelope.recipients.is_some() => {
+            let identities = keys.identities.ok_or(RecipientError::IdentityRequired)?;
+            recipient::decrypt(&envelope.body, identities)?
+        }
+        Some(sealed) => {
+            let password = keys.password.ok_or(PayloadError::PasswordRequired)?;
+            crypto::open_slots(
+                &sealed.salt,
+                &sealed.kdf,
+                sealed.cipher,
+                crypto::SLOTS,
+                &envelope.body,
+                password,
+            )?
+        }
+        None => Zeroizing::new(envelope.body),
+    };
+
+    let message = match envelope.compression {
+        Some(compression) => Zeroizing::new(compression.decompress(&message)?),
+        None => message,
+    };
+    check_digest(message)
+}
+
+/// Record the SHA-256 of `message` alongside it, so that a payload damaged after it was
+/// embedded is reported as such rather than as a failure to decrypt or decompress. The result
+/// is the message that gets compressed and encrypted, so the hash gives nothing away.
+pub fn add_digest(message: Vec<u8>) -> Vec<u8> {
+    Envelope {
+        digest: Some(Sha256::digest(&message).into()),
+        ..Envelope::new(message)
+    }
+    .as_bytes()
+}
+
+/// Reverse of [`add_digest`]. Messages embedded without a digest are returned as they are.
+fn check_digest(message: Zeroizing<Vec<u8>>) -> Result<Zeroizing<Vec<u8>>> {
+    if !Envelope::is_envelope(&message) {
+        return Ok(message);
+    }
+    let envelope = Envelope::try_from(message.as_slice())?;
+    let Some(digest) = envelope.digest else {
+        return Ok(message);
+    };
+
+    let body = Zeroizing::new(envelope.body);
+    match <[u8; 32]>::from(Sha256::digest(body.as_slice())) == digest {
+        true => Ok(body),
+        false => Err(PayloadError::Corrupted.into()),
+    }
+}
+
+/// Protect a whole payload, envelope header included, with Reed-Solomon parity.
+pub fn protect(payload: &[u8], level: EccLevel) -> Vec<u8> {
+    Envelope {
+        protection: Some(Protection {
+            level,
+            len: payload.len() as u64,
+        }),
+        ..Envelope::new(ecc::protect(payload, level))
+    }
+    .as_bytes()
+}
+
+/// Reverse of [`protect`], repairing what the parity allows. Returns the payload and how many
+/// bytes were repaired, or `None` for a payload that wasn't protected.
+pub fn repair(data: &[u8]) -> Result<Option<(Vec<u8>, usize)>> {
+    if !Envelope::is_envelope(data) {
+        return Ok(None);
+    }
+    let envelope = Envelope::try_from(data)?;
+    let Some(protection) = envelope.protection else {
+        return Ok(None);
+    };
+
+    let len = usize::try_from(protection.len).map_err(|_| EccError::Unrecoverable)?;
+    ecc::repair(&envelope.body, len, protection.level).map(Some)
+}
+
+/// A file stored in a message, along with its name, size, modification time and hash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddedFile {
+    pub info: FileInfo,
+    pub data: Vec<u8>,
+}
+
+impl EmbeddedFile {
+    pub fn new(name: &str, data: Vec<u8>, modified: Option<u64>) -> Self {
+        let info = FileInfo {
+            name: name.to_string(),
+            size: data.len() as u64,
+            modified,
+            sha256: Sha256::digest(&data).into(),
+        };
+        Self { info, data }
+    }
+
+    fn verify(&self) -> Result<()> {
+        let sha256: [u8; 32] = Sha256::digest(&self.data).into();
+        match sha256 == self.info.sha256 && self.data.len() as u64 == self.info.size {
+            true => Ok(()),
+            false => Err(PayloadError::FileCorrupted(self.info.name.clone()).into()),
+        }
+    }
+}
+
+/// Wrap a file with its metadata. The result is the message that gets compressed and
+/// encrypted, so the name is as private as the contents.
+pub fn wrap_file(file: EmbeddedFile) -> Vec<u8> {
+    Envelope {
+        file: Some(file.info),
+        ..Envelope::new(file.data)
+    }
+    .as_bytes()
+}
+
+/// Pack several files into one message: a manifest with the metadata of each, then their
+/// contents one after the other.
+pub fn pack(files: Vec<EmbeddedFile>) -> Result<Vec<u8>> {
+    for (index, file) in files.iter().enumerate() {
+        if files[..index]
+            .iter()
+            .any(|other| other.info.name == file.info.name)
+        {
+            return Err(PayloadError::DuplicateFile(file.info.name.clone()).into());
+        }
+    }
+
+    let manifest = files.iter().map(|file| file.info.clone()).collect();
+    let body = files.into_iter().flat_map(|file| file.data).collect();
+    Ok(Envelope {
+        manifest: Some(manifest),
+        ..Envelope::new(body)
+    }
+    .as_bytes())
+}
+
+/// Reverse of [`wrap_file`] and [`pack`], `None` for a message that didn't come from files.
+/// Fails if any of the contents no longer match their hash.
+pub fn unwrap_files(message: &[u8]) -> Result<Option<Vec<EmbeddedFile>>> {
+    if !Envelope::is_envelope(message) {
+        return Ok(None);
+    }
+    let envelope = Envelope::try_from(message)?;
+
+    let files = match (envelope.file, envelope.manifest) {
+        (Some(info), _) => vec![EmbeddedFile {
+            info,
+            data: envelope.body,
+        }],
+        (None, Some(manifest)) => {
+            let mut files = Vec::with_capacity(manifest.len());
+            let mut rest = envelope.body.as_slice();
+            for info in manifest {
+                let size = usize::try_from(info.size)
+                    .ok()
+                    .filter(|&size| size <= rest.len())
+                    .ok_or_else(|| PayloadError::FileCorrupted(info.name.clone()))?;
+                let (data, tail) = rest.split_at(size);
+                rest = tail;
+                files.push(EmbeddedFile {
+                    info,
+                    data: data.to_vec(),
+                });
+            }
+            files
+        }
+        (None, None) => return Ok(None),
+    };
+
+    for file in &files {
+        file.verify()?;
+    }
+    Ok(Some(files))
+}
+
+/// A one line description of what an envelope holds, without decrypting anything.
+pub fn describe(envelope: &Envelope) -> String {
+    let mut parts = vec![];
+    if let Some(part) = envelope.part {
+        parts.push(format!("part {} of {}", part.index + 1, part.total));
+    }
+    if let Some(sealed) = &envelope.sealed {
+        parts.push(format!("encrypted ({}, {})", sealed.cipher, sealed.kdf));
+    }
+    if let Some(recipients) = envelope.recipients {
+        parts.push(format!("encrypted to {} public key(s)", recipients));
+    }
+    if let Some(compression) = envelope.compression {
+        parts.push(format!("{} compressed", compression));
+    }
+    if let Some(protection) = envelope.protection {
+        parts.push(format!("{} Reed-Solomon protection", protection.level));
+    }
+    if envelope.mac.is_some() {
+        parts.push("HMAC-SHA256".to_string());
+    }
+    if let Some(file) = &envelope.file {
+        parts.push(format!("file {} ({} bytes)", file.name, file.size));
+    }
+    if let Some(manifest) = &envelope.manifest {
+        let size: u64 = manifest.iter().map(|file| file.size).sum();
+        parts.push(format!("{} files ({} bytes)", manifest.len(), size));
+    }
+    if parts.is_empty() {
+        parts.push("plain".to_string());
+    }
+    parts.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compress::Compression;
+
+    fn password(password: &str) -> Keys<'_> {
+        Keys {
+            password: Some(password),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_wrap_file() {
+        let file = EmbeddedFile::new("notes.txt", b"remember the milk".to_vec(), Some(1_000));
+        let wrapped = wrap_file(file.clone());
+        assert_eq!(unwrap_files(&wrapped).unwrap().unwrap(), [file]);
+        assert!(unwrap_files(b"a plain message").unwrap().is_none());
+
+        let mut corrupted = wrapped.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert!(unwrap_files(&corrupted).is_err());
+    }
+
+    #[test]
+    fn test_pack() {
+        let files = vec![
+            EmbeddedFile::new("a.txt", b"first".to_vec(), None),
+            EmbeddedFile::new("empty", vec![], Some(5)),
+            EmbeddedFile::new("b.jpg", vec![0xff; 100], None),
+        ];
+        let packed = pack(files.clone()).unwrap();
+        assert_eq!(unwrap_files(&packed).unwrap().unwrap(), files);
+
+        let mut corrupted = packed.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert!(unwrap_files(&corrupted).is_err());
+
+        let twice = vec![files[0].clone(), files[0].clone()];
+        assert!(pack(twice).is_err());
+    }
+
+    #[test]
+    fn test_seal_round_trip() {
+        let envelope = seal(
+            b"meet at dawn",
+            "hunter2",
+            KdfParams::default(),
+            Cipher::ChaCha20,
+        )
+        .unwrap();
+        let envelope = Envelope::try_from(envelope.as_bytes().as_slice()).unwrap();
+
+        assert!(!envelope.body.windows(4).any(|window| window == b"dawn"));
+        assert_eq!(
+            *open(envelope.clone(), password("hunter2")).unwrap(),
+            b"meet at dawn"
+        );
+        assert!(open(envelope.clone(), password("hunter3")).is_err());
+        assert!(open(envelope, Keys::default()).is_err());
+    }
+
+    #[test]
+    fn test_digest() {
+        let message = add_digest(b"meet at dawn".to_vec());
+        let envelope = seal(&message, "hunter2", KdfParams::default(), Cipher::AesGcm).unwrap();
+        assert_eq!(
+            *open(envelope, password("hunter2")).unwrap(),
+            b"meet at dawn"
+        );
+
+        let mut damaged = add_digest(b"meet at dawn".to_vec());
+        *damaged.last_mut().unwrap() ^= 1;
+        let envelope = seal(&damaged, "hunter2", KdfParams::default(), Cipher::AesGcm).unwrap();
+        let error = open(envelope, password("hunter2")).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<PayloadError>(),
+            Some(PayloadError::Corrupted)
+        ));
+    }
+
+    #[test]
+    fn test_protect() {
+        let payload = add_digest(b"meet at dawn".to_vec());
+        let mut protected = protect(&payload, EccLevel::Low);
+        let envelope = Envelope::try_from(protected.as_slice()).unwrap();
+        assert_eq!(describe(&envelope), "low Reed-Solomon protection");
+
+        let end = protected.len();
+        protected[end - 3] ^= 0xff;
+        assert_eq!(repair(&protected).unwrap(), Some((payload, 1)));
+        assert_eq!(repair(b"meet at dawn").unwrap(), None);
+    }
+
+    #[test]
+    fn test_compressed() {
+        let message = "all work and no play ".repeat(100);
+        let compression = Compression::Zstd;
+        let mut envelope = seal(
+            &compression.compress(message.as_bytes()).unwrap(),
+            "hunter2",
+            KdfParams::default(),
+            Cipher::AesGcm,
+        )
+        .unwrap();
+        envelope.compression = Some(compression);
+        let envelope = Envelope::try_from(envelope.as_bytes().as_slice()).unwrap();
+
+        assert!(envelope.body.len() < message.len() / 4);
+        assert!(describe(&envelope).ends_with("zstd compressed"));
+        assert_eq!(
+            *open(envelope, password("hunter2")).unwrap(),
+            message.as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let envelope = authenticate(Envelope::new(b"pay bob 10".to_vec()), b"key");
+        let with_key = Keys {
+            hmac_key: Some(b"key"),
+            ..Default::default()
+        };
+        assert_eq!(*open(envelope.clone(), with_key).unwrap(), b"pay bob 10");
+        assert!(open(envelope.clone(), Keys::default()).is_err());
+
+        let mut tampered = envelope.clone();
+        tampered.body = b"pay bob 99".to_vec();
+        assert!(open(tampered, with_key).is_err());
+
+        let stripped = Envelope::new(b"pay bob 99".to_vec());
+        assert!(open(stripped, with_key).is_err());
+    }
+
+    #[test]
+    fn test_authenticate_covers_header() {
+        let with_key = Keys {
+            hmac_key: Some(b"key"),
+            ..Default::default()
+        };
+        let envelope = authenticate(
+            Envelope {
+                created: Some(1_700_000_000),
+                ..Envelope::new(b"pay bob 10".to_vec())
+            },
+            b"key",
+        );
+        let envelope = Envelope::try_from(envelope.as_bytes().as_slice()).unwrap();
+        assert!(open(envelope.clone(), with_key).is_ok());
+
+        let mut tampered = envelope.clone();
+        tampered.created = Some(1_800_000_000);
+        assert!(open(tampered, with_key).is_err());
+        let mut tampered = envelope;
+        tampered.compression = Some(Compression::Zlib);
+        assert!(open(tampered, with_key).is_err());
+
+        // An HMAC of the body alone leaves the header open to tampering
+        let body_only = Envelope {
+            mac: Some(crypto::mac(b"key", b"pay bob 10").to_vec()),
+            ..Envelope::new(b"pay bob 10".to_vec())
+        };
+        let body_only = Envelope::try_from(body_only.as_bytes().as_slice()).unwrap();
+        assert!(open(body_only, with_key).is_err());
+    }
+
+    #[test]
+    fn test_seal_to_recipient() {
+        let envelope = seal_to(b"drop box", &[]);
+        assert!(envelope.is_err());
+
+        let identity = age::x25519::Identity::generate();
+        let recipient = identity.to_public().to_string().parse().unwrap();
+        let envelope = seal_to(b"drop box", &[recipient]).unwrap();
+        let envelope = Envelope::try_from(envelope.as_bytes().as_slice()).unwrap();
+
+        assert_eq!(describe(&envelope), "encrypted to 1 public key(s)");
+        assert!(open(envelope.clone(), password("drop box")).is_err());
+    }
+
+    #[test]
+    fn test_seal_to_several_recipients() {
+        let alice = age::x25519::Identity::generate();
+        let bob = age::x25519::Identity::generate();
+        let recipients = [&alice, &bob].map(|identity| Recipient::Age(identity.to_public()));
+        let envelope = seal_to(b"team notes", &recipients).unwrap();
+        assert_eq!(describe(&envelope), "encrypted to 2 public key(s)");
+
+        for identity in [alice, bob] {
+            let identities = Identities::from(identity);
+            let keys = Keys {
+                identities: Some(&identities),
+                ..Default::default()
+            };
+            assert_eq!(*open(envelope.clone(), keys).unwrap(), b"team notes");
+        }
+    }
+
+    #[test]
+    fn test_decoy_round_trip() {
+        let decoy = Decoy {
+            message: b"grocery list",
+            password: "1234",
+        };
+        let envelope = seal_with_decoy(
+            b"meet at dawn",
+            "hunter2",
+            decoy,
+            KdfParams::default(),
+            Cipher::AesGcm,
+        )
+        .unwrap();
+        let envelope = Envelope::try_from(envelope.as_bytes().as_slice()).unwrap();
+
+        assert_eq!(
+            *open(envelope.clone(), password("hunter2")).unwrap(),
+            b"meet at dawn"
+        );
+        assert_eq!(
+            *open(envelope.clone(), password("1234")).unwrap(),
+            b"grocery list"
+        );
+        assert!(open(envelope.clone(), Keys::default()).is_err());
+        assert!(describe(&envelope).starts_with("encrypted (AES-256-GCM"));
+    }
+
+    #[test]
+    fn test_decoy_needs_its_own_password() {
+        let decoy = Decoy {
+            message: b"grocery list",
+            password: "hunter2",
+        };
+        let error = seal_with_decoy(
+            b"meet at dawn",
+            "hunter2",
+            decoy,
+            KdfParams::default(),
+            Cipher::AesGcm,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<PayloadError>(),
+            Some(PayloadError::SameDecoyPassword)
+        ));
+    }
+
+    #[test]
+    fn test_decoy_is_indistinguishable() {
+        let kdf = KdfParams::new(Some(1024), Some(1)).unwrap();
+        let alone = seal(b"meet at dawn", "hunter2", kdf, Cipher::AesGcm).unwrap();
+        let decoy = Decoy {
+            message: b"grocery list",
+            password: "1234",
+        };
+        let with_decoy =
+            seal_with_decoy(b"meet at dawn", "hunter2", decoy, kdf, Cipher::AesGcm).unwrap();
+
+        assert_eq!(alone.body.len(), with_decoy.body.len());
+        assert_eq!(describe(&alone), describe(&with_decoy));
+        // Same fields in the same places, differing only in the random salt
+        let header = |envelope: &Envelope| {
+            Envelope {
+                sealed: envelope.sealed.clone().map(|sealed| Sealed {
+                    salt: vec![0; sealed.salt.len()],
+                    ..sealed
+                }),
+                body: vec![],
+                ..envelope.clone()
+            }
+            .as_bytes()
+        };
+        assert_eq!(header(&alone), header(&with_decoy));
+    }
+}
@@ -0,0 +1,160 @@
+//! Perceptual hashes, which stay close for images that look alike even after resizing,
+//! recompression or small edits, unlike the exact hashes of [`crate::dupes`].
+
+use std::f64::consts::PI;
+
+use crate::raster::Image;
+
+/// Side of the grayscale thumbnail the DCT runs over.
+const SAMPLE: usize = 32;
+
+/// Side of the block of lowest frequencies that make up the hash.
+const LOW: usize = 8;
+
+/// Default number of differing bits up to which two images count as near duplicates.
+pub const DEFAULT_THRESHOLD: u32 = 10;
+
+/// Luminance of `image` averaged down to a [`SAMPLE`] square, composited over white.
+fn thumbnail(image: &Image) -> Vec<f64> {
+    let (width, height) = (image.width.max(1) as usize, image.height.max(1) as usize);
+    let mut samples = vec![0.0; SAMPLE * SAMPLE];
+    if image.width == 0 || image.height == 0 {
+        return samples;
+    }
+
+    for (i, sample) in samples.iter_mut().enumerate() {
+        let (sx, sy) = (i % SAMPLE, i / SAMPLE);
+        // Every cell covers at least one pixel, even when the image is smaller than the sample
+        let x0 = sx * width / SAMPLE;
+        let x1 = ((sx + 1) * width / SAMPLE).max(x0 + 1);
+        let y0 = sy * height / SAMPLE;
+        let y1 = ((sy + 1) * height / SAMPLE).max(y0 + 1);
+
+        let mut sum = 0.0;
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let [r, g, b, a] = image.pixel(x as u32, y as u32);
+                let luma = 0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64;
+                let alpha = a as f64 / 255.0;
+                sum += luma * alpha + 255.0 * (1.0 - alpha);
+            }
+        }
+        *sample = sum / ((x1 - x0) * (y1 - y0)) as f64;
+    }
+    samples
+}
+
+/// The 64 bit perceptual hash of `image`: which of the lowest DCT frequencies of its thumbnail
+/// are above their median.
+pub fn phash(image: &Image) -> u64 {
+    let samples = thumbnail(image);
+    let cosines: Vec<f64> = (0..LOW * SAMPLE)
+        .map(|i| {
+            let (u, x) = (i / SAMPLE, i % SAMPLE);
+            ((2 * x + 1) as f64 * u as f64 * PI / (2 * SAMPLE) as f64).cos()
+        })
+        .collect();
+
+    let mut coefficients = [0.0; LOW * LOW];
+    for (i, coefficient) in coefficients.iter_mut().enumerate() {
+        let (u, v) = (i % LOW, i / LOW);
+        let mut sum = 0.0;
+        for y in 0..SAMPLE {
+            for x in 0..SAMPLE {
+                sum += samples[y * SAMPLE + x] * cosines[u * SAMPLE + x] * cosines[v * SAMPLE + y];
+            }
+        }
+        *coefficient = sum;
+    }
+
+    // The DC term is the overall brightness, which says nothing about what the image shows
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let median = sorted[sorted.len() / 2];
+
+    coefficients
+        .iter()
+        .enumerate()
+        .filter(|&(i, &coefficient)| i > 0 && coefficient > median)
+        .fold(0, |hash, (i, _)| hash | 1 << i)
+}
+
+/// Number of bits that differ between two hashes.
+pub fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Indices of `hashes` grouped into clusters of two or more, where each member is within
+/// `threshold` of at least one other. Clusters and their members keep the order of `hashes`.
+pub fn cluster(hashes: &[u64], threshold: u32) -> Vec<Vec<usize>> {
+    fn root(parents: &mut [usize], mut i: usize) -> usize {
+        while parents[i] != i {
+            parents[i] = parents[parents[i]];
+            i = parents[i];
+        }
+        i
+    }
+
+    let mut parents: Vec<usize> = (0..hashes.len()).collect();
+    for i in 0..hashes.len() {
+        for j in i + 1..hashes.len() {
+            if distance(hashes[i], hashes[j]) <= threshold {
+                let (a, b) = (root(&mut parents, i), root(&mut parents, j));
+                parents[a.max(b)] = a.min(b);
+            }
+        }
+    }
+
+    let mut clusters: Vec<Vec<usize>> = vec![vec![]; hashes.len()];
+    for i in 0..hashes.len() {
+        let root = root(&mut parents, i);
+        clusters[root].push(i);
+    }
+    clusters.retain(|members| members.len() > 1);
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A diagonal gradient with a dark square, `size` pixels wide.
+    fn picture(size: u32) -> Image {
+        let mut image = Image::new(size, size);
+        for y in 0..size {
+            for x in 0..size {
+                let shade = ((x + y) * 255 / (2 * size)) as u8;
+                let dark = x > size / 4 && x < size / 2 && y > size / 2;
+                let value = if dark { 20 } else { shade };
+                image.set_pixel(x, y, [value, value, value, 255]);
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn test_phash() {
+        let original = phash(&picture(64));
+        assert!(distance(original, phash(&picture(48))) <= DEFAULT_THRESHOLD);
+
+        let mut edited = picture(64);
+        edited.set_pixel(3, 3, [255, 0, 0, 255]);
+        assert!(distance(original, phash(&edited)) <= DEFAULT_THRESHOLD);
+
+        let mut flipped = Image::new(64, 64);
+        let source = picture(64);
+        for y in 0..64 {
+            for x in 0..64 {
+                flipped.set_pixel(63 - x, 63 - y, source.pixel(x, y));
+            }
+        }
+        assert!(distance(original, phash(&flipped)) > DEFAULT_THRESHOLD);
+    }
+
+    #[test]
+    fn test_cluster() {
+        let hashes = [0b0000, 0xffff_0000, 0b0011, 0b1111, 0xffff_0001];
+        assert_eq!(cluster(&hashes, 2), [vec![0, 2, 3], vec![1, 4]]);
+        assert!(cluster(&hashes, 0).is_empty());
+    }
+}
@@ -1,11 +1,19 @@
-use std::str::FromStr;
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use crate::{
+    bytes,
     chunk::Chunk,
     chunk_type::{self, ChunkType},
-    Error, Result,
+    ihdr_chunk::IhdrChunk,
+    limits, ordering, Error, Result,
 };
 
+#[derive(Debug, Clone)]
 pub struct Png {
     pub chunks: Vec<Chunk>,
 }
@@ -15,6 +23,9 @@ pub enum PngError {
     InvalidPngHeader,
     InvalidChunk,
     TooShort,
+    InvalidOrder(Vec<ordering::Violation>),
+    /// A file that doesn't start with the PNG signature.
+    NotPng(PathBuf),
 }
 
 impl std::error::Error for PngError {}
@@ -25,12 +36,23 @@ impl std::fmt::Display for PngError {
             PngError::InvalidPngHeader => write!(f, "Invalid PNG header"),
             PngError::InvalidChunk => write!(f, "Invalid PNG chunk"),
             PngError::TooShort => write!(f, "Data is too short"),
+            PngError::InvalidOrder(violations) => {
+                write!(f, "Chunks are out of order:")?;
+                for violation in violations {
+                    write!(f, "\n  {}", violation)?;
+                }
+                Ok(())
+            }
+            PngError::NotPng(path) => write!(f, "{} is not a PNG file", path.display()),
         }
     }
 }
 
 impl Png {
-    const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+    pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+    /// Ancillary chunks that make up an APNG animation.
+    pub const ANIMATION_CHUNKS: [&str; 3] = ["acTL", "fcTL", "fdAT"];
 
     pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
         // FIXME: what if there are no chunks?
@@ -42,9 +64,44 @@ impl Png {
         Png { chunks }
     }
 
+    /// Read and parse a file, enforcing the active [`limits`].
+    pub fn from_file(path: &Path) -> Result<Png> {
+        let limits = limits::active();
+        limits.check_file_size(fs::metadata(path)?.len())?;
+
+        tracing::debug!("reading {}", path.display());
+        let png = Png::try_from(fs::read(path)?.as_slice())?;
+        limits.check_png(&png)?;
+
+        Ok(png)
+    }
+
+    /// Read and parse a whole stream, such as stdin, enforcing the active [`limits`].
+    ///
+    /// The file size limit is checked while reading, so an oversized stream is never buffered
+    /// in full.
+    pub fn from_reader(reader: impl Read) -> Result<Png> {
+        let limits = limits::active();
+
+        let mut bytes = vec![];
+        reader
+            .take(limits.max_file_size.saturating_add(1))
+            .read_to_end(&mut bytes)?;
+        limits.check_file_size(bytes.len() as u64)?;
+
+        let png = Png::try_from(bytes.as_slice())?;
+        limits.check_png(&png)?;
+
+        Ok(png)
+    }
+
     pub fn append_chunk(&mut self, chunk: Chunk) {
         self.chunks.push(chunk);
     }
+
+    pub fn insert_chunk(&mut self, index: usize, chunk: Chunk) {
+        self.chunks.insert(index, chunk);
+    }
     pub fn remove_first_chunk(&mut self, chunk_type: &str) -> Result<()> {
         let idx = self
             .chunks
@@ -60,6 +117,27 @@ impl Png {
         }
     }
 
+    /// Remove every chunk of type `chunk_type`, returning how many were removed.
+    pub fn remove_chunks(&mut self, chunk_type: &str) -> usize {
+        let before = self.chunks.len();
+        self.chunks
+            .retain(|chunk| chunk.chunk_type().to_string() != chunk_type);
+        before - self.chunks.len()
+    }
+
+    /// Remove every ancillary chunk whose type isn't in `keep`, returning the removed chunks.
+    /// The chunks of an animated PNG always stay: without them only its first frame would show.
+    pub fn strip_ancillary(&mut self, keep: &[String]) -> Vec<Chunk> {
+        let (kept, removed) = self.chunks.drain(..).partition(|chunk| {
+            let chunk_type = chunk.chunk_type().to_string();
+            chunk.chunk_type().is_critical()
+                || Self::ANIMATION_CHUNKS.contains(&chunk_type.as_str())
+                || keep.contains(&chunk_type)
+        });
+        self.chunks = kept;
+        removed
+    }
+
     pub fn header(&self) -> &[u8] {
         &Self::STANDARD_HEADER
     }
@@ -74,6 +152,35 @@ impl Png {
             .find(|chunk| chunk.chunk_type().to_string() == chunk_type)
     }
 
+    /// Index of the `n`th (0-based) chunk of type `chunk_type`.
+    pub fn nth_chunk_index(&self, chunk_type: &str, n: usize) -> Option<usize> {
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| chunk.chunk_type().to_string() == chunk_type)
+            .nth(n)
+            .map(|(idx, _)| idx)
+    }
+
+    /// Swap the data of the chunk at `index`, keeping its type and place in the file.
+    pub fn replace_chunk_data(&mut self, index: usize, data: Vec<u8>) {
+        let chunk_type = self.chunks[index].chunk_type().clone();
+        self.chunks[index] = Chunk::new(chunk_type, data);
+    }
+
+    /// Byte offset of every chunk within the serialized file.
+    pub fn chunk_offsets(&self) -> Vec<usize> {
+        let mut offset = Self::STANDARD_HEADER.len();
+        self.chunks
+            .iter()
+            .map(|chunk| {
+                let start = offset;
+                offset += chunk.as_bytes().len();
+                start
+            })
+            .collect()
+    }
+
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut bytes = self.header().to_vec();
         for chunk in &self.chunks {
@@ -103,7 +210,39 @@ impl Png {
             return false;
         }
 
-        return true;
+        true
+    }
+
+    /// Insert `chunk` where the ordering rules allow it. If only one chunk of its type is allowed,
+    /// an existing one is replaced in place. Returns the chunk's index.
+    pub fn insert_ordered(&mut self, chunk: Chunk) -> usize {
+        let constraint = ordering::constraint_for(chunk.chunk_type());
+        let existing = self
+            .chunks
+            .iter()
+            .position(|other| other.chunk_type() == chunk.chunk_type());
+
+        match existing {
+            Some(index) if constraint.unique => {
+                self.chunks[index] = chunk;
+                index
+            }
+            _ => {
+                let index = ordering::insertion_index(self, chunk.chunk_type());
+                self.chunks.insert(index, chunk);
+                index
+            }
+        }
+    }
+
+    /// Check the chunk sequence against the spec's ordering rules, see [`ordering`].
+    pub fn check_order(&self) -> Result<()> {
+        let violations = ordering::check(self);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(PngError::InvalidOrder(violations).into())
+        }
     }
 
     pub fn get_color_type(&self) -> u8 {
@@ -126,48 +265,170 @@ impl TryFrom<&[u8]> for Png {
             return Err(PngError::InvalidPngHeader.into());
         }
 
+        tracing::debug!("parsing {} bytes", value.len());
         let mut idx = Png::STANDARD_HEADER.len();
 
         let mut chunks: Vec<Chunk> = vec![];
 
         while idx < value.len() {
-            let current_chunk_length =
-                u32::from_be_bytes([value[idx], value[idx + 1], value[idx + 2], value[idx + 3]])
-                    as usize;
+            let offset = idx;
+            let current_chunk_length = bytes::read_be_u32(value, idx)
+                .and_then(bytes::to_usize)
+                .ok_or(PngError::TooShort)?;
             idx += 4;
 
-            let chunk_type = std::str::from_utf8(&value[idx..idx + 4])?;
+            let chunk_type = value.get(idx..idx + 4).ok_or(PngError::TooShort)?;
+            let chunk_type = std::str::from_utf8(chunk_type)?;
             idx += 4;
 
-            let chunk_data = &value[idx..idx + current_chunk_length];
+            let chunk_data = idx
+                .checked_add(current_chunk_length)
+                .and_then(|end| value.get(idx..end))
+                .ok_or(PngError::TooShort)?;
             idx += current_chunk_length;
 
             // CRC is the last 4 bytes of the chunk
-            idx += 4;
+            idx = idx.checked_add(4).ok_or(PngError::TooShort)?;
+            if idx > value.len() {
+                return Err(PngError::TooShort.into());
+            }
 
             let chunk = Chunk::new(ChunkType::from_str(chunk_type)?, chunk_data.to_vec());
+            tracing::trace!(
+                "[{}] {} at offset {}, {} bytes",
+                chunks.len(),
+                chunk_type,
+                offset,
+                current_chunk_length
+            );
+
+            // The CRC is recomputed rather than trusted, but a mismatch explains odd files
+            let stored = bytes::read_be_u32(value, idx - 4).unwrap_or_default();
+            if stored != chunk.crc() {
+                tracing::debug!(
+                    "[{}] {} at offset {}: stored CRC {:#010x} doesn't match {:#010x}",
+                    chunks.len(),
+                    chunk_type,
+                    offset,
+                    stored,
+                    chunk.crc()
+                );
+            }
 
             chunks.push(chunk);
         }
 
-        return Ok(Png::from_chunks(chunks));
+        tracing::debug!("parsed {} chunks", chunks.len());
+        Ok(Png::from_chunks(chunks))
+    }
+}
+
+/// Longest data preview in the table of [`Png`]'s `Display`, in characters.
+const PREVIEW_WIDTH: usize = 32;
+
+/// `bytes` in the largest binary unit that keeps the number at or above 1.
+pub fn human_size(bytes: usize) -> String {
+    let units = ["KiB", "MiB", "GiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, units[unit])
+}
+
+/// The data of `chunk` in a few words: the image header decoded, anything else as printable
+/// ASCII cut to [`PREVIEW_WIDTH`].
+fn preview(chunk: &Chunk) -> String {
+    if chunk.chunk_type().to_string() == "IHDR" {
+        if let Ok(ihdr) = IhdrChunk::try_from(chunk.clone()) {
+            return format!(
+                "{}x{} {}-bit {}{}",
+                ihdr.width(),
+                ihdr.height(),
+                ihdr.bit_depth(),
+                ihdr.color_type_name(),
+                if ihdr.interlace_method() == 1 {
+                    ", interlaced"
+                } else {
+                    ""
+                }
+            );
+        }
+    }
+
+    let text: String = chunk
+        .data()
+        .iter()
+        .map(|&byte| match byte {
+            0x20..=0x7e => byte as char,
+            _ => '.',
+        })
+        .collect();
+    match text.len() > PREVIEW_WIDTH {
+        true => format!("{}...", &text[..PREVIEW_WIDTH - 3]),
+        false => text,
     }
 }
 
+/// A table of the chunks, one row each except for runs of IDAT chunks, which share one.
 impl std::fmt::Display for Png {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let mut chunks = String::new();
-        for chunk in &self.chunks {
-            chunks.push_str(&format!("{}", chunk));
+        writeln!(
+            f,
+            "{:>7} {:>10}  {:<9} {:>10}  {:<10}  data",
+            "index", "offset", "type", "size", "crc"
+        )?;
+
+        let offsets = self.chunk_offsets();
+        let mut index = 0;
+        while index < self.chunks.len() {
+            let chunk = &self.chunks[index];
+            let chunk_type = chunk.chunk_type().to_string();
+
+            let run = match chunk_type.as_str() {
+                "IDAT" => self.chunks[index..]
+                    .iter()
+                    .take_while(|chunk| chunk.chunk_type().to_string() == "IDAT")
+                    .count(),
+                _ => 1,
+            };
+            if run > 1 {
+                let last = index + run - 1;
+                let size: usize = self.chunks[index..=last]
+                    .iter()
+                    .map(|chunk| chunk.length() as usize)
+                    .sum();
+                writeln!(
+                    f,
+                    "{:>7} {:>10}  {:<9} {:>10}  {:<10}  {} chunks",
+                    format!("{}-{}", index, last),
+                    offsets[index],
+                    format!("IDAT x{}", run),
+                    human_size(size),
+                    "-",
+                    run
+                )?;
+            } else {
+                writeln!(
+                    f,
+                    "{:>7} {:>10}  {:<9} {:>10}  {:#010x}  {}",
+                    index,
+                    offsets[index],
+                    chunk_type,
+                    human_size(chunk.length() as usize),
+                    chunk.crc(),
+                    preview(chunk)
+                )?;
+            }
+            index += run;
         }
 
-        //
-        // let first_chunk = png.chunks[0].clone();
-        // let ihdr_chunk = IhdrChunk::try_from(first_chunk)?;
-        //
-        // println!("IHDR Chunk: {:?}", ihdr_chunk);
-
-        write!(f, "{}", chunks)
+        Ok(())
     }
 }
 
@@ -226,6 +487,15 @@ mod tests {
         assert!(png.is_ok());
     }
 
+    #[test]
+    fn test_from_reader() {
+        let bytes = testing_png().as_bytes();
+
+        let png = Png::from_reader(bytes.as_slice()).unwrap();
+        assert_eq!(png.as_bytes(), bytes);
+        assert!(Png::from_reader(&bytes[..20]).is_err());
+    }
+
     #[test]
     fn test_invalid_header() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -299,12 +569,139 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_remove_chunks() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("miDl", "another one").unwrap());
+
+        assert_eq!(png.remove_chunks("miDl"), 2);
+        assert_eq!(png.chunks().len(), 2);
+        assert_eq!(png.remove_chunks("miDl"), 0);
+    }
+
+    #[test]
+    fn test_strip_ancillary() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "").unwrap(),
+            chunk_from_strings("gAMA", "").unwrap(),
+            chunk_from_strings("tEXt", "").unwrap(),
+            chunk_from_strings("IDAT", "").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+
+        let removed = png.strip_ancillary(&["gAMA".to_string()]);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].chunk_type().to_string(), "tEXt");
+
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "gAMA", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_strip_ancillary_keeps_animation() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "").unwrap(),
+            chunk_from_strings("acTL", "").unwrap(),
+            chunk_from_strings("fcTL", "").unwrap(),
+            chunk_from_strings("IDAT", "").unwrap(),
+            chunk_from_strings("tEXt", "").unwrap(),
+            chunk_from_strings("fcTL", "").unwrap(),
+            chunk_from_strings("fdAT", "").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+
+        let removed = png.strip_ancillary(&[]);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].chunk_type().to_string(), "tEXt");
+
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(
+            types,
+            ["IHDR", "acTL", "fcTL", "IDAT", "fcTL", "fdAT", "IEND"]
+        );
+    }
+
+    #[test]
+    fn test_insert_ordered() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "").unwrap(),
+            chunk_from_strings("iCCP", "old").unwrap(),
+            chunk_from_strings("IDAT", "").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+
+        assert_eq!(
+            png.insert_ordered(chunk_from_strings("iCCP", "new").unwrap()),
+            1
+        );
+        assert_eq!(
+            png.insert_ordered(chunk_from_strings("gAMA", "").unwrap()),
+            2
+        );
+        assert_eq!(
+            png.insert_ordered(chunk_from_strings("tEXt", "").unwrap()),
+            4
+        );
+
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "iCCP", "gAMA", "IDAT", "tEXt", "IEND"]);
+        assert_eq!(png.chunks()[1].data(), b"new");
+    }
+
+    #[test]
+    fn test_replace_chunk_data() {
+        let mut png = testing_png();
+        let idx = png.nth_chunk_index("miDl", 0).unwrap();
+        png.replace_chunk_data(idx, b"replaced".to_vec());
+
+        let chunk = &png.chunks()[idx];
+        assert_eq!(chunk.data(), b"replaced");
+        assert_eq!(chunk.length(), 8);
+        assert_eq!(idx, 1);
+        assert!(png.nth_chunk_index("miDl", 1).is_none());
+    }
+
+    #[test]
+    fn test_check_order() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert!(png.check_order().is_ok());
+
+        png.insert_chunk(0, chunk_from_strings("IEND", "").unwrap());
+        assert!(png.check_order().is_err());
+    }
+
+    #[test]
+    fn test_chunk_offsets() {
+        let png = testing_png();
+        let offsets = png.chunk_offsets();
+        assert_eq!(offsets, vec![8, 8 + 12 + 20, 8 + 12 + 20 + 12 + 18]);
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
         assert!(png.is_ok());
     }
 
+    #[test]
+    fn test_truncated_png_is_an_error() {
+        for len in [9, 12, 20, PNG_FILE.len() - 1] {
+            assert!(Png::try_from(&PNG_FILE[..len]).is_err());
+        }
+    }
+
     #[test]
     fn test_as_bytes() {
         let png = Png::try_from(&PNG_FILE[..]).unwrap();
@@ -331,6 +728,25 @@ mod tests {
         let _png_string = format!("{}", png);
     }
 
+    #[test]
+    fn test_display_table() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let idat = png.chunk_by_type("IDAT").unwrap().clone();
+        let at = png.nth_chunk_index("IDAT", 0).unwrap();
+        png.insert_chunk(at, idat.clone());
+        png.insert_chunk(at, idat);
+
+        let table = png.to_string();
+        assert!(table.contains("50x50 8-bit truecolor+alpha"));
+        assert!(table.contains("IDAT x3"));
+        assert_eq!(
+            table.lines().filter(|line| line.contains("IDAT")).count(),
+            1
+        );
+        assert!(table.lines().all(|line| line.len() < 100));
+        assert_eq!(human_size(1536), "1.5 KiB");
+    }
+
     // This is the raw bytes for a shrunken version of the `dice.png` image on Wikipedia
     const PNG_FILE: [u8; 4803] = [
         137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 50, 0, 0, 0, 50, 8,
@@ -0,0 +1,151 @@
+use std::str::FromStr;
+
+use crate::{png::Png, Result};
+
+#[derive(Debug)]
+pub enum PositionError {
+    Unknown(String),
+    MissingChunk(String),
+    IndexOutOfBounds { index: usize, len: usize },
+}
+
+impl std::error::Error for PositionError {}
+
+impl std::fmt::Display for PositionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PositionError::Unknown(position) => write!(
+                f,
+                "Unknown position '{}', expected after-ihdr, before-plte, before-idat, after-idat, before-iend or end",
+                position
+            ),
+            PositionError::MissingChunk(chunk_type) => {
+                write!(f, "Image has no {} chunk to position against", chunk_type)
+            }
+            PositionError::IndexOutOfBounds { index, len } => write!(
+                f,
+                "Cannot insert at index {}, the image has {} chunks",
+                index, len
+            ),
+        }
+    }
+}
+
+/// A structural place in the chunk sequence to insert a new chunk at.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Position {
+    AfterIhdr,
+    /// Before PLTE, falling back to before the image data for images without a palette.
+    BeforePlte,
+    BeforeIdat,
+    AfterIdat,
+    #[default]
+    BeforeIend,
+    /// After every chunk, even IEND. Decoders stop at IEND, so most tools won't see it.
+    End,
+    /// At this chunk index, shifting the chunk there (and all after it) back.
+    At(usize),
+    /// Before the first chunk of this type.
+    Before(String),
+    /// After the last chunk of this type.
+    After(String),
+}
+
+impl FromStr for Position {
+    type Err = PositionError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, PositionError> {
+        match s.to_ascii_lowercase().replace('_', "-").as_str() {
+            "after-ihdr" => Ok(Position::AfterIhdr),
+            "before-plte" => Ok(Position::BeforePlte),
+            "before-idat" => Ok(Position::BeforeIdat),
+            "after-idat" => Ok(Position::AfterIdat),
+            "before-iend" => Ok(Position::BeforeIend),
+            "end" => Ok(Position::End),
+            _ => Err(PositionError::Unknown(s.to_string())),
+        }
+    }
+}
+
+fn first(png: &Png, chunk_type: &str) -> Result<usize> {
+    png.chunks()
+        .iter()
+        .position(|chunk| chunk.chunk_type().to_string() == chunk_type)
+        .ok_or_else(|| PositionError::MissingChunk(chunk_type.to_string()).into())
+}
+
+fn last(png: &Png, chunk_type: &str) -> Result<usize> {
+    png.chunks()
+        .iter()
+        .rposition(|chunk| chunk.chunk_type().to_string() == chunk_type)
+        .ok_or_else(|| PositionError::MissingChunk(chunk_type.to_string()).into())
+}
+
+impl Position {
+    /// The chunk index a new chunk goes to so that it ends up at this position.
+    pub fn index(&self, png: &Png) -> Result<usize> {
+        match self {
+            Position::AfterIhdr => Ok(first(png, "IHDR")? + 1),
+            Position::BeforePlte => first(png, "PLTE").or_else(|_| first(png, "IDAT")),
+            Position::BeforeIdat => first(png, "IDAT"),
+            Position::AfterIdat => Ok(last(png, "IDAT")? + 1),
+            Position::BeforeIend => first(png, "IEND"),
+            Position::End => Ok(png.chunks().len()),
+            Position::At(index) if *index <= png.chunks().len() => Ok(*index),
+            Position::At(index) => Err(PositionError::IndexOutOfBounds {
+                index: *index,
+                len: png.chunks().len(),
+            }
+            .into()),
+            Position::Before(chunk_type) => first(png, chunk_type),
+            Position::After(chunk_type) => Ok(last(png, chunk_type)? + 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk::Chunk, chunk_type::ChunkType};
+
+    fn png(types: &[&str]) -> Png {
+        Png::from_chunks(
+            types
+                .iter()
+                .map(|code| Chunk::new(ChunkType::from_str(code).unwrap(), vec![]))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_positions() {
+        let png = png(&["IHDR", "gAMA", "IDAT", "IDAT", "tEXt", "IEND"]);
+        assert_eq!(Position::AfterIhdr.index(&png).unwrap(), 1);
+        assert_eq!(Position::BeforePlte.index(&png).unwrap(), 2);
+        assert_eq!(Position::BeforeIdat.index(&png).unwrap(), 2);
+        assert_eq!(Position::AfterIdat.index(&png).unwrap(), 4);
+        assert_eq!(Position::BeforeIend.index(&png).unwrap(), 5);
+        assert_eq!(Position::End.index(&png).unwrap(), 6);
+    }
+
+    #[test]
+    fn test_relative_positions() {
+        let png = png(&["IHDR", "PLTE", "IDAT", "IDAT", "IEND"]);
+        assert_eq!(Position::At(0).index(&png).unwrap(), 0);
+        assert_eq!(Position::At(5).index(&png).unwrap(), 5);
+        assert!(Position::At(6).index(&png).is_err());
+        assert_eq!(Position::Before("PLTE".into()).index(&png).unwrap(), 1);
+        assert_eq!(Position::After("IDAT".into()).index(&png).unwrap(), 4);
+        assert!(Position::After("tEXt".into()).index(&png).is_err());
+    }
+
+    #[test]
+    fn test_parse_and_missing_chunks() {
+        assert_eq!(
+            Position::from_str("before_idat").unwrap(),
+            Position::BeforeIdat
+        );
+        assert!(Position::from_str("middle").is_err());
+        assert!(Position::BeforeIdat.index(&png(&["IHDR", "IEND"])).is_err());
+    }
+}
@@ -0,0 +1,359 @@
+//! Multi-step workflows run as one command: preparing images for the web and for archiving.
+
+use std::{
+    io::Write,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use flate2::{write::ZlibEncoder, Compression};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    bytes::{be_u16, put_be_u16},
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    limits,
+    ordering::{self, Rule},
+    png::Png,
+    text::TextualChunk,
+    Result,
+};
+
+/// Ancillary chunks web-prep keeps: color management, transparency and animation.
+const WEB_CHUNKS: [&str; 9] = [
+    "sRGB", "iCCP", "gAMA", "cHRM", "cICP", "tRNS", "acTL", "fcTL", "fdAT",
+];
+
+/// Private chunk holding the SHA-256 of the rest of the file. Unsafe to copy, since any change
+/// to the file invalidates it.
+pub const SEAL_CHUNK: &str = "seAL";
+
+/// Strip what browsers don't need, recompress the image data and fix the chunk order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebPrep {
+    /// Ancillary chunk types to keep on top of [`WEB_CHUNKS`].
+    pub keep: Vec<String>,
+    pub optimize: bool,
+    pub reorder: bool,
+}
+
+impl WebPrep {
+    /// Run the steps on `png`, returning a line for each one that changed something.
+    pub fn apply(&self, png: &mut Png) -> Result<Vec<String>> {
+        let mut report = vec![];
+
+        let keep: Vec<String> = WEB_CHUNKS
+            .iter()
+            .map(|code| code.to_string())
+            .chain(self.keep.iter().cloned())
+            .collect();
+        let removed = png.strip_ancillary(&keep);
+        if !removed.is_empty() {
+            report.push(format!("stripped {} chunk(s)", removed.len()));
+        }
+
+        if self.optimize {
+            if let Some((before, after)) = optimize_idat(png)? {
+                report.push(format!("image data {} -> {} bytes", before, after));
+            }
+        }
+
+        if self.reorder {
+            let moved = normalize_order(png);
+            if moved > 0 {
+                report.push(format!("moved {} chunk(s) into place", moved));
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// Record where an image came from and seal it against later changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchivePrep {
+    pub author: Option<String>,
+    pub comment: Option<String>,
+    pub timestamp: bool,
+    pub seal: bool,
+}
+
+impl ArchivePrep {
+    /// Run the steps on `png`, read from a file called `source`. Sealing comes last so the seal
+    /// covers everything else.
+    pub fn apply(&self, png: &mut Png, source: &str) -> Result<Vec<String>> {
+        let mut report = vec![];
+
+        let software = format!("pngme {}", env!("CARGO_PKG_VERSION"));
+        let fields = [
+            ("Source", Some(source)),
+            ("Software", Some(software.as_str())),
+            ("Author", self.author.as_deref()),
+            ("Comment", self.comment.as_deref()),
+        ];
+        for (keyword, value) in fields {
+            if let Some(value) = value {
+                set_text(png, keyword, value)?;
+                report.push(format!("{}: {}", keyword, value));
+            }
+        }
+
+        if self.timestamp {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+            png.insert_ordered(Chunk::new(ChunkType::from_str("tIME")?, time_data(now)));
+            report.push("set tIME".to_string());
+        }
+
+        if self.seal {
+            report.push(format!("sealed with sha256 {}", &seal(png)?[..16]));
+        }
+
+        Ok(report)
+    }
+}
+
+/// Replace the iTXt chunks called `keyword` with one holding `text`.
+fn set_text(png: &mut Png, keyword: &str, text: &str) -> Result<()> {
+    png.chunks.retain(|chunk| {
+        !crate::text::is_text_chunk(&chunk.chunk_type().to_string())
+            || TextualChunk::parse(chunk, Default::default())
+                .map_or(true, |parsed| parsed.keyword != keyword)
+    });
+
+    let chunk = TextualChunk {
+        keyword: keyword.to_string(),
+        language: None,
+        translated_keyword: None,
+        text: text.to_string(),
+    };
+    png.insert_ordered(Chunk::new(
+        ChunkType::from_str("iTXt")?,
+        chunk.to_data("iTXt")?,
+    ));
+    Ok(())
+}
+
+/// Merge the IDAT chunks into one and recompress them at the best zlib level. Returns the old
+/// and new data size, or `None` if nothing was gained or the chunks aren't consecutive.
+fn optimize_idat(png: &mut Png) -> Result<Option<(usize, usize)>> {
+    let positions: Vec<usize> = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| chunk.chunk_type().to_string() == "IDAT")
+        .map(|(idx, _)| idx)
+        .collect();
+    let (Some(&first), Some(&last)) = (positions.first(), positions.last()) else {
+        return Ok(None);
+    };
+    if last - first + 1 != positions.len() {
+        return Ok(None);
+    }
+
+    let compressed: Vec<u8> = png.chunks()[first..=last]
+        .iter()
+        .flat_map(|chunk| chunk.data().iter().copied())
+        .collect();
+    let raw = limits::inflate(&compressed, u64::MAX)?;
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(&raw)?;
+    let recompressed = encoder.finish()?;
+
+    let data = if recompressed.len() < compressed.len() {
+        recompressed
+    } else if positions.len() > 1 {
+        compressed.clone()
+    } else {
+        return Ok(None);
+    };
+
+    let (before, after) = (compressed.len(), data.len());
+    png.chunks.splice(
+        first..=last,
+        [Chunk::new(ChunkType::from_str("IDAT")?, data)],
+    );
+    Ok(Some((before, after)))
+}
+
+/// Move chunks that break a placement rule to where [`Png::insert_ordered`] would put them,
+/// which also drops all but the last of a unique type. Returns how many chunks were moved.
+fn normalize_order(png: &mut Png) -> usize {
+    let movable = [
+        Rule::Unique,
+        Rule::BeforePlte,
+        Rule::AfterPlte,
+        Rule::BeforeIdat,
+    ];
+
+    let mut moved = 0;
+    // Every move fixes a violation without adding one, the bound only guards against surprises
+    for _ in 0..png.chunks().len() {
+        let Some(violation) = ordering::check(png)
+            .into_iter()
+            .find(|violation| movable.contains(&violation.rule))
+        else {
+            break;
+        };
+
+        let chunk = png.chunks.remove(violation.index);
+        png.insert_ordered(chunk);
+        moved += 1;
+    }
+    moved
+}
+
+/// The data of a tIME chunk for `secs` seconds since the Unix epoch, in UTC.
+pub fn time_data(secs: u64) -> Vec<u8> {
+    let days = (secs / 86_400) as i64;
+    let secs_of_day = secs % 86_400;
+
+    // Days to a civil date, from Howard Hinnant's `civil_from_days`
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    let mut data = vec![];
+    put_be_u16(&mut data, year as u16);
+    data.extend_from_slice(&[
+        month as u8,
+        day as u8,
+        (secs_of_day / 3_600) as u8,
+        (secs_of_day / 60 % 60) as u8,
+        (secs_of_day % 60) as u8,
+    ]);
+    data
+}
+
+/// `secs` seconds since the Unix epoch as "YYYY-MM-DD HH:MM:SS UTC".
+pub fn format_time(secs: u64) -> String {
+    let data = time_data(secs);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        be_u16(&data),
+        data[2],
+        data[3],
+        data[4],
+        data[5],
+        data[6]
+    )
+}
+
+/// SHA-256 of `png` as a file, leaving out any seal.
+fn digest(png: &Png) -> String {
+    let mut unsealed = png.clone();
+    unsealed.remove_chunks(SEAL_CHUNK);
+    Sha256::digest(unsealed.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Add (or renew) the seal of `png`, returning the hash it holds.
+fn seal(png: &mut Png) -> Result<String> {
+    png.remove_chunks(SEAL_CHUNK);
+    let hash = digest(png);
+    png.insert_ordered(Chunk::new(
+        ChunkType::from_str(SEAL_CHUNK)?,
+        format!("sha256 {}", hash).into_bytes(),
+    ));
+    Ok(hash)
+}
+
+/// Whether the seal of `png` still matches its contents, `None` if it was never sealed.
+pub fn check_seal(png: &Png) -> Option<bool> {
+    let chunk = png.chunk_by_type(SEAL_CHUNK)?;
+    let stored = chunk.data().strip_prefix(b"sha256 ")?;
+    Some(stored == digest(png).as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raster::{self, Image};
+
+    fn image() -> Png {
+        let mut image = Image::new(16, 16);
+        image.set_pixel(3, 4, [255, 0, 0, 255]);
+        raster::encode(&image).unwrap()
+    }
+
+    fn chunk(code: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(code).unwrap(), data.to_vec())
+    }
+
+    fn types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_web_prep() {
+        let mut png = image();
+        let idat = png.nth_chunk_index("IDAT", 0).unwrap();
+        png.insert_chunk(idat, chunk("tEXt", b"Comment\0hi"));
+        png.insert_chunk(idat, chunk("prIv", b"x"));
+        // Misplaced after the image data
+        png.insert_chunk(idat + 3, chunk("gAMA", &[0, 0, 177, 143]));
+
+        let pixels = raster::decode(&png).unwrap().pixel(3, 4);
+        let report = WebPrep {
+            keep: vec![],
+            optimize: true,
+            reorder: true,
+        }
+        .apply(&mut png)
+        .unwrap();
+
+        assert_eq!(types(&png), ["IHDR", "gAMA", "IDAT", "IEND"]);
+        assert!(report.iter().any(|line| line.starts_with("stripped 2")));
+        assert!(ordering::check(&png).is_empty());
+        assert_eq!(raster::decode(&png).unwrap().pixel(3, 4), pixels);
+    }
+
+    #[test]
+    fn test_archive_prep_and_seal() {
+        let mut png = image();
+        ArchivePrep {
+            author: Some("Ada".into()),
+            comment: None,
+            timestamp: true,
+            seal: true,
+        }
+        .apply(&mut png, "photo.png")
+        .unwrap();
+
+        assert_eq!(types(&png).last().unwrap(), "IEND");
+        assert!(png.chunk_by_type("tIME").is_some());
+        assert_eq!(check_seal(&png), Some(true));
+
+        set_text(&mut png, "Author", "Someone else").unwrap();
+        assert_eq!(check_seal(&png), Some(false));
+        assert_eq!(check_seal(&image()), None);
+    }
+
+    #[test]
+    fn test_time_data() {
+        // 2024-02-29 12:34:56 UTC
+        assert_eq!(
+            time_data(1_709_210_096),
+            [0x07, 0xe8, 2, 29, 12, 34, 56].to_vec()
+        );
+        assert_eq!(time_data(0), [0x07, 0xb2, 1, 1, 0, 0, 0].to_vec());
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_time(1_700_000_000), "2023-11-14 22:13:20 UTC");
+    }
+}
@@ -0,0 +1,102 @@
+//! Progress bars on stderr for long reads and large batches, shown only to someone watching.
+
+use std::{
+    io::{self, IsTerminal, Read},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// Files at least this large show a bar while they are read.
+pub const LARGE_FILE: u64 = 64 * 1024 * 1024;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// File bars being shown. Files read during a batch don't get a bar of their own.
+static BATCHES: AtomicUsize = AtomicUsize::new(0);
+
+/// Turn bars on for the rest of the process if someone is watching: both stdout and stderr are
+/// terminals, so no output is being piped or captured, and `quiet` isn't set.
+pub fn install(quiet: bool) {
+    let enabled = !quiet && io::stdout().is_terminal() && io::stderr().is_terminal();
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// A progress bar, or nothing when bars are off.
+pub struct Progress {
+    bar: Option<ProgressBar>,
+    batch: bool,
+}
+
+impl Progress {
+    fn new(enabled: bool, total: u64, template: &str) -> Self {
+        let enabled = enabled && ENABLED.load(Ordering::Relaxed);
+        let bar = enabled.then(|| {
+            let style = ProgressStyle::with_template(template)
+                .unwrap_or_else(|_| ProgressStyle::default_bar())
+                .progress_chars("=> ");
+            ProgressBar::new(total).with_style(style)
+        });
+        Progress { bar, batch: false }
+    }
+
+    /// A bar over `total` files, shown for batches of more than one.
+    pub fn files(total: usize) -> Self {
+        let mut progress = Self::new(
+            total > 1,
+            total as u64,
+            "[{bar:30}] {pos}/{len} files, {per_sec}, ETA {eta}",
+        );
+        if progress.bar.is_some() {
+            progress.batch = true;
+            BATCHES.fetch_add(1, Ordering::Relaxed);
+        }
+        progress
+    }
+
+    /// A bar over the `total` bytes of `name`, shown for a [`LARGE_FILE`] outside of a batch.
+    pub fn bytes(total: u64, name: &str) -> Self {
+        let progress = Self::new(
+            total >= LARGE_FILE && BATCHES.load(Ordering::Relaxed) == 0,
+            total,
+            "{msg} [{bar:30}] {binary_bytes}/{binary_total_bytes}, {binary_bytes_per_sec}, ETA {eta}",
+        );
+        if let Some(bar) = &progress.bar {
+            bar.set_message(name.to_string());
+        }
+        progress
+    }
+
+    pub fn inc(&self, delta: u64) {
+        if let Some(bar) = &self.bar {
+            bar.inc(delta);
+        }
+    }
+
+    /// Run `f` with the bar hidden, so what it prints doesn't get mixed up with it.
+    pub fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        match &self.bar {
+            Some(bar) => bar.suspend(f),
+            None => f(),
+        }
+    }
+
+    /// `reader`, moving the bar along as it is read.
+    pub fn wrap_read<'a, R: Read + 'a>(&self, reader: R) -> Box<dyn Read + 'a> {
+        match &self.bar {
+            Some(bar) => Box::new(bar.wrap_read(reader)),
+            None => Box::new(reader),
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+        if self.batch {
+            BATCHES.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,473 @@
+use std::io::Write;
+use std::str::FromStr;
+
+use flate2::{write::ZlibEncoder, Compression};
+
+use crate::{
+    bytes::{be_u16, put_be_u32},
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    ihdr_chunk::IhdrChunk,
+    limits,
+    png::Png,
+    Error, Result,
+};
+
+/// Starting offsets and strides (x, y, dx, dy) of the seven Adam7 passes.
+const ADAM7_PASSES: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+#[derive(Debug)]
+pub enum RasterError {
+    MissingIhdr,
+    MissingPalette,
+    UnsupportedFormat(u8, u8),
+    InvalidFilter(u8),
+    TruncatedData,
+}
+
+impl std::error::Error for RasterError {}
+
+impl std::fmt::Display for RasterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RasterError::MissingIhdr => write!(f, "PNG has no IHDR chunk"),
+            RasterError::MissingPalette => write!(f, "Indexed PNG has no PLTE chunk"),
+            RasterError::UnsupportedFormat(color_type, bit_depth) => write!(
+                f,
+                "Unsupported color type {} with bit depth {}",
+                color_type, bit_depth
+            ),
+            RasterError::InvalidFilter(filter) => write!(f, "Invalid scanline filter {}", filter),
+            RasterError::TruncatedData => write!(f, "Image data is shorter than expected"),
+        }
+    }
+}
+
+/// A decoded image, always stored as 8-bit RGBA regardless of the source format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    fn offset(&self, x: u32, y: u32) -> usize {
+        (y as usize * self.width as usize + x as usize) * 4
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let idx = self.offset(x, y);
+        [
+            self.pixels[idx],
+            self.pixels[idx + 1],
+            self.pixels[idx + 2],
+            self.pixels[idx + 3],
+        ]
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, rgba: [u8; 4]) {
+        let idx = self.offset(x, y);
+        self.pixels[idx..idx + 4].copy_from_slice(&rgba);
+    }
+}
+
+/// Everything needed to turn a zlib stream of filtered scanlines into pixels.
+///
+/// The same decoder is reused for every frame of an animated PNG, since all frames share the
+/// IHDR format, palette and transparency information of the default image.
+pub struct Decoder {
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+    palette: Vec<u8>,
+    transparency: Option<Vec<u8>>,
+}
+
+impl Decoder {
+    pub fn new(png: &Png) -> Result<Self> {
+        let ihdr = png.chunk_by_type("IHDR").ok_or(RasterError::MissingIhdr)?;
+        let ihdr = IhdrChunk::try_from(ihdr.clone())?;
+
+        let supported = match ihdr.color_type() {
+            0 => matches!(ihdr.bit_depth(), 1 | 2 | 4 | 8 | 16),
+            3 => matches!(ihdr.bit_depth(), 1 | 2 | 4 | 8),
+            2 | 4 | 6 => matches!(ihdr.bit_depth(), 8 | 16),
+            _ => false,
+        };
+        if !supported {
+            return Err(RasterError::UnsupportedFormat(ihdr.color_type(), ihdr.bit_depth()).into());
+        }
+
+        let palette = png
+            .chunk_by_type("PLTE")
+            .map(|chunk| chunk.data().to_vec())
+            .unwrap_or_default();
+        if ihdr.color_type() == 3 && palette.is_empty() {
+            return Err(RasterError::MissingPalette.into());
+        }
+
+        Ok(Self {
+            bit_depth: ihdr.bit_depth(),
+            color_type: ihdr.color_type(),
+            interlaced: ihdr.interlace_method() == 1,
+            palette,
+            transparency: png.chunk_by_type("tRNS").map(|chunk| chunk.data().to_vec()),
+        })
+    }
+
+    fn channels(&self) -> usize {
+        match self.color_type {
+            0 | 3 => 1,
+            4 => 2,
+            2 => 3,
+            _ => 4,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.bit_depth as usize
+    }
+
+    fn row_bytes(&self, width: usize) -> usize {
+        (width * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    /// Size of the filtered scanlines of an image, summed over all passes when interlaced.
+    pub fn data_len(&self, width: u32, height: u32) -> u64 {
+        let (width, height) = (width as u64, height as u64);
+        let row_bytes = |w: u64| (w * self.bits_per_pixel() as u64).div_ceil(8);
+
+        if !self.interlaced {
+            return (row_bytes(width) + 1) * height;
+        }
+
+        ADAM7_PASSES
+            .iter()
+            .map(|&(x0, y0, dx, dy)| {
+                let (x0, y0, dx, dy) = (x0 as u64, y0 as u64, dx as u64, dy as u64);
+                let pass_width = (width + dx - 1 - x0.min(width)) / dx;
+                let pass_height = (height + dy - 1 - y0.min(height)) / dy;
+                if pass_width == 0 || pass_height == 0 {
+                    0
+                } else {
+                    (row_bytes(pass_width) + 1) * pass_height
+                }
+            })
+            .sum()
+    }
+
+    /// Decode a zlib-compressed stream of filtered scanlines of the given dimensions.
+    pub fn decode(&self, width: u32, height: u32, compressed: &[u8]) -> Result<Image> {
+        limits::active().check_dimensions(width, height)?;
+
+        // Only as much data as the scanlines need is inflated, the rest can't be a valid image
+        let raw = limits::inflate_prefix(compressed, self.data_len(width, height))?;
+
+        let mut image = Image::new(width, height);
+        let (width, height) = (width as usize, height as usize);
+
+        if !self.interlaced {
+            let rows = self.unfilter(&raw, width, height)?;
+            self.write_rows(&mut image, &rows, width, height, (0, 0, 1, 1))?;
+            return Ok(image);
+        }
+
+        let mut offset = 0;
+        for pass in ADAM7_PASSES {
+            let (x0, y0, dx, dy) = pass;
+            let pass_width = (width + dx - 1 - x0.min(width)) / dx;
+            let pass_height = (height + dy - 1 - y0.min(height)) / dy;
+            if pass_width == 0 || pass_height == 0 {
+                continue;
+            }
+
+            let len = (self.row_bytes(pass_width) + 1) * pass_height;
+            let data = raw
+                .get(offset..offset + len)
+                .ok_or(RasterError::TruncatedData)?;
+            offset += len;
+
+            let rows = self.unfilter(data, pass_width, pass_height)?;
+            self.write_rows(&mut image, &rows, pass_width, pass_height, pass)?;
+        }
+
+        Ok(image)
+    }
+
+    /// Reverse the per-scanline filters, returning the unfiltered rows back to back.
+    fn unfilter(&self, data: &[u8], width: usize, height: usize) -> Result<Vec<u8>> {
+        let stride = self.row_bytes(width);
+        let bpp = self.bits_per_pixel().div_ceil(8);
+
+        if data.len() < (stride + 1) * height {
+            return Err(RasterError::TruncatedData.into());
+        }
+
+        let mut rows = vec![0u8; stride * height];
+        for y in 0..height {
+            let filter = data[y * (stride + 1)];
+            let line = &data[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+            let (previous, current) = rows.split_at_mut(y * stride);
+            let previous = if y == 0 {
+                None
+            } else {
+                Some(&previous[(y - 1) * stride..])
+            };
+            let current = &mut current[..stride];
+
+            for x in 0..stride {
+                let a = if x >= bpp { current[x - bpp] } else { 0 };
+                let b = previous.map_or(0, |row| row[x]);
+                let c = if x >= bpp {
+                    previous.map_or(0, |row| row[x - bpp])
+                } else {
+                    0
+                };
+
+                current[x] = match filter {
+                    0 => line[x],
+                    1 => line[x].wrapping_add(a),
+                    2 => line[x].wrapping_add(b),
+                    3 => line[x].wrapping_add(((a as u16 + b as u16) / 2) as u8),
+                    4 => line[x].wrapping_add(paeth(a, b, c)),
+                    other => return Err(RasterError::InvalidFilter(other).into()),
+                };
+            }
+        }
+
+        Ok(rows)
+    }
+
+    fn write_rows(
+        &self,
+        image: &mut Image,
+        rows: &[u8],
+        width: usize,
+        height: usize,
+        (x0, y0, dx, dy): (usize, usize, usize, usize),
+    ) -> Result<()> {
+        let stride = self.row_bytes(width);
+        for y in 0..height {
+            let row = &rows[y * stride..(y + 1) * stride];
+            for x in 0..width {
+                let rgba = self.pixel_at(row, x)?;
+                image.set_pixel((x0 + x * dx) as u32, (y0 + y * dy) as u32, rgba);
+            }
+        }
+        Ok(())
+    }
+
+    /// Read sample `index` of a row, returning both the raw value and the value scaled to 8 bits.
+    fn sample(&self, row: &[u8], index: usize) -> (u16, u8) {
+        match self.bit_depth {
+            16 => {
+                let raw = be_u16(&row[index * 2..]);
+                (raw, (raw >> 8) as u8)
+            }
+            8 => (row[index] as u16, row[index]),
+            depth => {
+                let depth = depth as usize;
+                let bit = index * depth;
+                let shift = 8 - depth - bit % 8;
+                let max = (1u16 << depth) - 1;
+                let raw = (row[bit / 8] as u16 >> shift) & max;
+                (raw, (raw * 255 / max) as u8)
+            }
+        }
+    }
+
+    fn pixel_at(&self, row: &[u8], x: usize) -> Result<[u8; 4]> {
+        let trns = self.transparency.as_deref();
+        let trns_value = |i: usize| trns.map(|t| be_u16(&t[i * 2..]));
+
+        let rgba = match self.color_type {
+            0 => {
+                let (raw, gray) = self.sample(row, x);
+                let alpha = if trns.is_some_and(|t| t.len() >= 2) && trns_value(0) == Some(raw) {
+                    0
+                } else {
+                    255
+                };
+                [gray, gray, gray, alpha]
+            }
+            2 => {
+                let (r_raw, r) = self.sample(row, x * 3);
+                let (g_raw, g) = self.sample(row, x * 3 + 1);
+                let (b_raw, b) = self.sample(row, x * 3 + 2);
+                let transparent = trns.is_some_and(|t| t.len() >= 6)
+                    && trns_value(0) == Some(r_raw)
+                    && trns_value(1) == Some(g_raw)
+                    && trns_value(2) == Some(b_raw);
+                [r, g, b, if transparent { 0 } else { 255 }]
+            }
+            3 => {
+                let (index, _) = self.sample(row, x);
+                let index = index as usize;
+                let color = self
+                    .palette
+                    .get(index * 3..index * 3 + 3)
+                    .ok_or(RasterError::MissingPalette)?;
+                let alpha = trns.and_then(|t| t.get(index).copied()).unwrap_or(255);
+                [color[0], color[1], color[2], alpha]
+            }
+            4 => {
+                let (_, gray) = self.sample(row, x * 2);
+                let (_, alpha) = self.sample(row, x * 2 + 1);
+                [gray, gray, gray, alpha]
+            }
+            _ => {
+                let (_, r) = self.sample(row, x * 4);
+                let (_, g) = self.sample(row, x * 4 + 1);
+                let (_, b) = self.sample(row, x * 4 + 2);
+                let (_, a) = self.sample(row, x * 4 + 3);
+                [r, g, b, a]
+            }
+        };
+
+        Ok(rgba)
+    }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// The canvas width and height declared in the IHDR chunk.
+pub fn dimensions(png: &Png) -> Result<(u32, u32)> {
+    let ihdr = png.chunk_by_type("IHDR").ok_or(RasterError::MissingIhdr)?;
+    let ihdr = IhdrChunk::try_from(ihdr.clone())?;
+    Ok((ihdr.width(), ihdr.height()))
+}
+
+/// Decode the default image of a PNG into RGBA pixels.
+pub fn decode(png: &Png) -> Result<Image> {
+    let decoder = Decoder::new(png)?;
+    let (width, height) = dimensions(png)?;
+
+    let compressed: Vec<u8> = png
+        .chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type().to_string() == "IDAT")
+        .flat_map(|chunk| chunk.data().iter().copied())
+        .collect();
+
+    decoder.decode(width, height, &compressed)
+}
+
+/// Compress RGBA pixels into a zlib stream of unfiltered scanlines.
+pub fn compress(image: &Image) -> Result<Vec<u8>> {
+    let stride = image.width as usize * 4;
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    for row in image.pixels.chunks(stride.max(1)) {
+        encoder.write_all(&[0])?;
+        encoder.write_all(row)?;
+    }
+    Ok(encoder.finish()?)
+}
+
+/// Build the 13 byte IHDR payload for an 8-bit RGBA image.
+pub fn ihdr_data(width: u32, height: u32) -> Vec<u8> {
+    let mut data = Vec::with_capacity(13);
+    put_be_u32(&mut data, width);
+    put_be_u32(&mut data, height);
+    data.extend_from_slice(&[8, 6, 0, 0, 0]);
+    data
+}
+
+/// Encode RGBA pixels as a standalone 8-bit RGBA PNG.
+pub fn encode(image: &Image) -> Result<Png> {
+    let chunk = |code: &str, data: Vec<u8>| -> Result<Chunk> {
+        Ok(Chunk::new(ChunkType::from_str(code)?, data))
+    };
+
+    Ok(Png::from_chunks(vec![
+        chunk("IHDR", ihdr_data(image.width, image.height))?,
+        chunk("IDAT", compress(image)?)?,
+        chunk("IEND", vec![])?,
+    ]))
+}
+
+impl TryFrom<&Png> for Image {
+    type Error = Error;
+
+    fn try_from(png: &Png) -> Result<Self> {
+        decode(png)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkerboard() -> Image {
+        let mut image = Image::new(5, 3);
+        for y in 0..3 {
+            for x in 0..5 {
+                let value = if (x + y) % 2 == 0 { 255 } else { 0 };
+                image.set_pixel(x, y, [value, 128, 255 - value, 200]);
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn test_encode_decode_round_trip() {
+        let image = checkerboard();
+        let png = encode(&image).unwrap();
+        let decoded = decode(&png).unwrap();
+        assert_eq!(decoded, image);
+    }
+
+    #[test]
+    fn test_round_trip_through_bytes() {
+        let image = checkerboard();
+        let bytes = encode(&image).unwrap().as_bytes();
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+        assert!(png.verify());
+        assert_eq!(Image::try_from(&png).unwrap(), image);
+    }
+
+    #[test]
+    fn test_decode_example_file() {
+        let bytes = std::fs::read("examples/1pixel_red.png").unwrap();
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+        let image = decode(&png).unwrap();
+        assert_eq!((image.width, image.height), (1, 1));
+        assert_eq!(&image.pixel(0, 0)[..3], &[255, 0, 0]);
+    }
+
+    #[test]
+    fn test_paeth() {
+        assert_eq!(paeth(10, 20, 10), 20);
+        assert_eq!(paeth(20, 10, 10), 20);
+        assert_eq!(paeth(0, 0, 0), 0);
+    }
+}
@@ -0,0 +1,189 @@
+use std::{
+    fs,
+    io::{Read, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use age::{ssh, x25519};
+use zeroize::Zeroizing;
+
+use crate::Result;
+
+#[derive(Debug)]
+pub enum RecipientError {
+    InvalidRecipient(String),
+    IdentityRequired,
+    InvalidIdentity(String),
+    EncryptedSshKey(String),
+    Encryption(String),
+    Decryption(String),
+}
+
+impl std::error::Error for RecipientError {}
+
+impl std::fmt::Display for RecipientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RecipientError::InvalidRecipient(key) => write!(
+                f,
+                "'{}' is not an age (age1...) or SSH (ssh-ed25519, ssh-rsa) public key",
+                key
+            ),
+            RecipientError::IdentityRequired => write!(
+                f,
+                "This message is encrypted to public keys, an identity file is required"
+            ),
+            RecipientError::InvalidIdentity(path) => {
+                write!(f, "{} holds no age or SSH private key", path)
+            }
+            RecipientError::EncryptedSshKey(path) => write!(
+                f,
+                "{} is a passphrase protected SSH key, which isn't supported",
+                path
+            ),
+            RecipientError::Encryption(reason) => write!(f, "Could not encrypt: {}", reason),
+            RecipientError::Decryption(reason) => write!(f, "Could not decrypt: {}", reason),
+        }
+    }
+}
+
+/// A public key a payload can be encrypted to.
+#[derive(Clone)]
+pub enum Recipient {
+    Age(x25519::Recipient),
+    /// Boxed, an RSA key is much larger than the rest.
+    Ssh(Box<ssh::Recipient>),
+}
+
+impl Recipient {
+    fn as_age(&self) -> &dyn age::Recipient {
+        match self {
+            Recipient::Age(recipient) => recipient,
+            Recipient::Ssh(recipient) => recipient.as_ref(),
+        }
+    }
+}
+
+impl FromStr for Recipient {
+    type Err = RecipientError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, RecipientError> {
+        let key = s.trim();
+        if let Ok(recipient) = key.parse::<x25519::Recipient>() {
+            return Ok(Recipient::Age(recipient));
+        }
+        key.parse::<ssh::Recipient>()
+            .map(|recipient| Recipient::Ssh(Box::new(recipient)))
+            .map_err(|_| RecipientError::InvalidRecipient(s.to_string()))
+    }
+}
+
+impl std::fmt::Display for Recipient {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Recipient::Age(recipient) => write!(f, "{}", recipient),
+            Recipient::Ssh(recipient) => write!(f, "{}", recipient),
+        }
+    }
+}
+
+impl std::fmt::Debug for Recipient {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Recipient({})", self)
+    }
+}
+
+/// Encrypt `message` into an age file that any of `recipients` can open.
+pub fn encrypt(message: &[u8], recipients: &[Recipient]) -> Result<Vec<u8>> {
+    let encryptor = age::Encryptor::with_recipients(recipients.iter().map(Recipient::as_age))
+        .map_err(|e| RecipientError::Encryption(e.to_string()))?;
+
+    let mut encrypted = vec![];
+    let mut writer = encryptor.wrap_output(&mut encrypted)?;
+    writer.write_all(message)?;
+    writer.finish()?;
+
+    Ok(encrypted)
+}
+
+/// Private keys read from an age identity file or an unencrypted OpenSSH private key.
+pub struct Identities(Vec<Box<dyn age::Identity + Send + Sync>>);
+
+impl Identities {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let name = path.display().to_string();
+
+        if contents.contains("-----BEGIN") {
+            return match ssh::Identity::from_buffer(contents.as_bytes(), Some(name.clone()))
+                .map_err(|_| RecipientError::InvalidIdentity(name.clone()))?
+            {
+                ssh::Identity::Unencrypted(key) => {
+                    Ok(Self(vec![Box::new(ssh::Identity::Unencrypted(key))]))
+                }
+                ssh::Identity::Encrypted(_) => Err(RecipientError::EncryptedSshKey(name).into()),
+                ssh::Identity::Unsupported(_) => Err(RecipientError::InvalidIdentity(name).into()),
+            };
+        }
+
+        let identities = age::IdentityFile::from_buffer(contents.as_bytes())
+            .map_err(|_| RecipientError::InvalidIdentity(name.clone()))?
+            .into_identities()
+            .map_err(|e| RecipientError::Decryption(e.to_string()))?;
+        if identities.is_empty() {
+            return Err(RecipientError::InvalidIdentity(name).into());
+        }
+        Ok(Self(identities))
+    }
+}
+
+impl From<x25519::Identity> for Identities {
+    fn from(identity: x25519::Identity) -> Self {
+        Self(vec![Box::new(identity)])
+    }
+}
+
+/// Reverse of [`encrypt`] with any private key matching one of the recipients.
+pub fn decrypt(encrypted: &[u8], identities: &Identities) -> Result<Zeroizing<Vec<u8>>> {
+    let decryptor = age::Decryptor::new_buffered(encrypted)
+        .map_err(|e| RecipientError::Decryption(e.to_string()))?;
+    let mut reader = decryptor
+        .decrypt(identities.0.iter().map(|identity| identity.as_ref() as _))
+        .map_err(|e| RecipientError::Decryption(e.to_string()))?;
+
+    // Read in one go into a buffer of the final size, a growing one would leave copies behind
+    let mut message = Zeroizing::new(Vec::with_capacity(encrypted.len()));
+    reader.read_to_end(&mut message)?;
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let identity = x25519::Identity::generate();
+        let recipient = Recipient::Age(identity.to_public());
+        let identities = Identities::from(identity);
+
+        let encrypted = encrypt(b"drop box", &[recipient]).unwrap();
+        assert_eq!(*decrypt(&encrypted, &identities).unwrap(), b"drop box");
+
+        let stranger = Identities::from(x25519::Identity::generate());
+        assert!(decrypt(&encrypted, &stranger).is_err());
+    }
+
+    #[test]
+    fn test_parse_recipient() {
+        let public = x25519::Identity::generate().to_public().to_string();
+        assert!(matches!(public.parse(), Ok(Recipient::Age(_))));
+        assert!(
+            "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIHsKLqeplhpW+uObz5dvMgjz1OxfM/XXUB+VHtZ6isGN"
+                .parse::<Recipient>()
+                .is_ok()
+        );
+        assert!("hunter2".parse::<Recipient>().is_err());
+    }
+}
@@ -0,0 +1,311 @@
+//! What every registered chunk type is for and how its data is laid out.
+
+use std::{fmt::Write, str::FromStr};
+
+use crate::{
+    chunk_type::{self, ChunkType},
+    ordering::{self, Constraint},
+    Result,
+};
+
+/// A registered chunk type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub code: &'static str,
+    pub name: &'static str,
+    pub purpose: &'static str,
+    pub layout: &'static str,
+}
+
+const fn entry(
+    code: &'static str,
+    name: &'static str,
+    purpose: &'static str,
+    layout: &'static str,
+) -> Entry {
+    Entry {
+        code,
+        name,
+        purpose,
+        layout,
+    }
+}
+
+pub const ENTRIES: [Entry; 30] = [
+    entry(
+        "IHDR",
+        "Image header",
+        "Dimensions and pixel format of the image.",
+        "width u32, height u32, bit depth u8, color type u8, compression u8, filter u8, interlace u8",
+    ),
+    entry(
+        "PLTE",
+        "Palette",
+        "Colors referenced by indexed images, suggested colors for truecolor ones.",
+        "1 to 256 RGB triples of u8",
+    ),
+    entry(
+        "IDAT",
+        "Image data",
+        "The filtered, zlib-compressed pixels, split across one or more chunks.",
+        "a slice of a single zlib stream",
+    ),
+    entry("IEND", "Image trailer", "Marks the end of the file.", "empty"),
+    entry(
+        "tRNS",
+        "Transparency",
+        "Alpha for palette entries, or a single transparent color for images without alpha.",
+        "indexed: u8 alpha per palette entry; grayscale: u16 gray; truecolor: u16 red, green, blue",
+    ),
+    entry(
+        "cHRM",
+        "Primary chromaticities",
+        "CIE chromaticities of the white point and the red, green and blue primaries.",
+        "8 u32 values, x and y times 100000 for white, red, green and blue",
+    ),
+    entry(
+        "gAMA",
+        "Image gamma",
+        "The gamma the image was encoded with.",
+        "gamma times 100000 as u32",
+    ),
+    entry(
+        "iCCP",
+        "Embedded ICC profile",
+        "A full ICC color profile for the image's color space.",
+        "profile name (1-79 Latin-1 bytes), NUL, compression method u8 (0), zlib-compressed profile",
+    ),
+    entry(
+        "sBIT",
+        "Significant bits",
+        "How many bits of each sample were significant in the original data.",
+        "1 to 4 u8 values, one per channel",
+    ),
+    entry(
+        "sRGB",
+        "Standard RGB color space",
+        "The image is in the sRGB color space.",
+        "rendering intent u8: 0 perceptual, 1 relative colorimetric, 2 saturation, 3 absolute",
+    ),
+    entry(
+        "cICP",
+        "Coding-independent code points",
+        "Color primaries, transfer function and matrix coefficients, e.g. for HDR content.",
+        "color primaries u8, transfer function u8, matrix coefficients u8, full range flag u8",
+    ),
+    entry(
+        "mDCV",
+        "Mastering display color volume",
+        "The display the image was mastered on.",
+        "6 u16 primary chromaticities, 2 u16 white point, max and min luminance u32",
+    ),
+    entry(
+        "cLLI",
+        "Content light level information",
+        "Maximum content and frame-average light levels of HDR images.",
+        "max content light level u32, max frame-average light level u32",
+    ),
+    entry(
+        "tEXt",
+        "Textual data",
+        "A keyword and an uncompressed Latin-1 text.",
+        "keyword (1-79 Latin-1 bytes), NUL, text",
+    ),
+    entry(
+        "zTXt",
+        "Compressed textual data",
+        "A keyword and a zlib-compressed Latin-1 text.",
+        "keyword, NUL, compression method u8 (0), zlib-compressed text",
+    ),
+    entry(
+        "iTXt",
+        "International textual data",
+        "A keyword and a UTF-8 text, optionally compressed, with a language tag.",
+        "keyword, NUL, compression flag u8, compression method u8, language tag, NUL, translated keyword, NUL, text",
+    ),
+    entry(
+        "bKGD",
+        "Background color",
+        "A default color to show the image against.",
+        "indexed: palette index u8; grayscale: u16 gray; truecolor: u16 red, green, blue",
+    ),
+    entry(
+        "hIST",
+        "Image histogram",
+        "Approximate usage frequency of each palette entry.",
+        "u16 per palette entry",
+    ),
+    entry(
+        "pHYs",
+        "Physical pixel dimensions",
+        "Pixel density or aspect ratio.",
+        "pixels per unit x u32, pixels per unit y u32, unit u8: 0 unknown, 1 meter",
+    ),
+    entry(
+        "sPLT",
+        "Suggested palette",
+        "A reduced palette for displays that can show few colors.",
+        "palette name, NUL, sample depth u8, entries of RGBA samples and a u16 frequency",
+    ),
+    entry(
+        "eXIf",
+        "Exchangeable image file profile",
+        "Exif metadata such as camera settings, timestamps and GPS coordinates.",
+        "a TIFF structure starting with II or MM",
+    ),
+    entry(
+        "tIME",
+        "Last modification time",
+        "When the image was last changed, in UTC.",
+        "year u16, month u8, day u8, hour u8, minute u8, second u8",
+    ),
+    entry(
+        "acTL",
+        "Animation control",
+        "Marks the file as an APNG: how many frames and how many plays.",
+        "number of frames u32, number of plays u32 (0 loops forever)",
+    ),
+    entry(
+        "fcTL",
+        "Frame control",
+        "Size, offset, delay and compositing of the next animation frame.",
+        "sequence u32, width u32, height u32, x offset u32, y offset u32, delay num u16, delay den u16, dispose op u8, blend op u8",
+    ),
+    entry(
+        "fdAT",
+        "Frame data",
+        "The image data of an animation frame after the first.",
+        "sequence u32, then a slice of the frame's zlib stream",
+    ),
+    entry(
+        "oFFs",
+        "Image offset",
+        "Where the image sits on a page or larger canvas.",
+        "x position i32, y position i32, unit u8: 0 pixel, 1 micrometer",
+    ),
+    entry(
+        "pCAL",
+        "Pixel calibration",
+        "Maps sample values to physical quantities.",
+        "calibration name, NUL, x0 i32, x1 i32, equation type u8, parameter count u8, unit name, NUL-separated ASCII parameters",
+    ),
+    entry(
+        "sCAL",
+        "Physical scale",
+        "The physical size of a pixel.",
+        "unit u8: 1 meter, 2 radian, width as ASCII float, NUL, height as ASCII float",
+    ),
+    entry(
+        "gIFg",
+        "GIF graphic control extension",
+        "Timing and disposal carried over from a converted GIF.",
+        "disposal method u8, user input flag u8, delay time u16 in 1/100 s",
+    ),
+    entry(
+        "sTER",
+        "Stereo image indicator",
+        "The image holds a left and right view side by side.",
+        "mode u8: 0 cross-fuse, 1 diverging-fuse",
+    ),
+];
+
+/// The registry entry for `code`, if it is a registered type.
+pub fn lookup(code: &str) -> Option<&'static Entry> {
+    ENTRIES.iter().find(|entry| entry.code == code)
+}
+
+/// Describe the placement rules of `constraint` in words.
+fn describe_order(constraint: &Constraint) -> String {
+    let rules: Vec<&str> = [
+        (constraint.first, "must be the first chunk"),
+        (constraint.last, "must be the last chunk"),
+        (constraint.unique, "at most once"),
+        (constraint.before_plte, "before PLTE"),
+        (constraint.after_plte, "after PLTE"),
+        (constraint.before_idat, "before IDAT"),
+        (constraint.after_idat, "after IDAT"),
+        (constraint.consecutive, "all chunks of this type in a row"),
+    ]
+    .into_iter()
+    .filter_map(|(applies, rule)| applies.then_some(rule))
+    .collect();
+
+    if rules.is_empty() {
+        "anywhere between IHDR and IEND, any number of times".to_string()
+    } else {
+        rules.join(", ")
+    }
+}
+
+/// A human readable explanation of the chunk type `code`, registered or not.
+pub fn explain(code: &str) -> Result<String> {
+    let chunk_type = ChunkType::from_str(code)?;
+    let mut out = String::new();
+
+    match lookup(code) {
+        Some(entry) => {
+            let _ = writeln!(out, "{}: {}", entry.code, entry.name);
+            let _ = writeln!(out, "  Purpose: {}", entry.purpose);
+            let _ = writeln!(out, "  Layout:  {}", entry.layout);
+        }
+        None => {
+            let _ = writeln!(out, "{}: not a registered chunk type", code);
+            if let Some(suggestion) = chunk_type::suggest(code) {
+                let _ = writeln!(out, "  Did you mean {}?", suggestion);
+            }
+        }
+    }
+
+    let bit = |set: bool, yes: &'static str, no: &'static str| if set { yes } else { no };
+    let _ = writeln!(
+        out,
+        "  Flags:   {} ({}), {} ({}), reserved bit {} ({}), {} ({})",
+        bit(chunk_type.is_critical(), "critical", "ancillary"),
+        &code[0..1],
+        bit(chunk_type.is_public(), "public", "private"),
+        &code[1..2],
+        bit(chunk_type.is_reserved_bit_valid(), "valid", "invalid"),
+        &code[2..3],
+        bit(
+            chunk_type.is_safe_to_copy(),
+            "safe to copy",
+            "unsafe to copy"
+        ),
+        &code[3..4],
+    );
+    let _ = writeln!(
+        out,
+        "  Order:   {}",
+        describe_order(&ordering::constraint_for(&chunk_type))
+    );
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_covers_known_types() {
+        for code in chunk_type::KNOWN_TYPES {
+            assert!(lookup(code).is_some(), "{} has no registry entry", code);
+        }
+    }
+
+    #[test]
+    fn test_explain() {
+        let text = explain("tEXt").unwrap();
+        assert!(text.starts_with("tEXt: Textual data"));
+        assert!(
+            text.contains("ancillary (t), public (E), reserved bit valid (X), safe to copy (t)")
+        );
+        assert!(text.contains("any number of times"));
+
+        assert!(explain("gAMA")
+            .unwrap()
+            .contains("at most once, before PLTE, before IDAT"));
+        assert!(explain("tEXT").unwrap().contains("Did you mean tEXt?"));
+        assert!(explain("t3Xt").is_err());
+    }
+}
@@ -0,0 +1,132 @@
+//! PNGs read straight from HTTP(S) URLs, so remote images can be inspected without saving them
+//! first.
+
+use std::{io::Read, path::Path, time::Duration};
+
+use crate::{limits, progress::Progress, Result};
+
+/// Largest download accepted, unless `--hardened` sets a lower file size limit.
+pub const MAX_SIZE: u64 = 256 * 1024 * 1024;
+
+/// How long connecting, or waiting for the next bytes, may take before giving up.
+pub const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub enum RemoteError {
+    Status { url: String, status: u16 },
+    TooLarge { url: String, max: u64 },
+    Request { url: String, reason: String },
+}
+
+impl std::error::Error for RemoteError {}
+
+impl std::fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RemoteError::Status { url, status } => {
+                write!(f, "Fetching {} failed with HTTP status {}", url, status)
+            }
+            RemoteError::TooLarge { url, max } => {
+                write!(f, "{} is larger than the limit of {} bytes", url, max)
+            }
+            RemoteError::Request { url, reason } => {
+                write!(f, "Fetching {} failed: {}", url, reason)
+            }
+        }
+    }
+}
+
+/// Whether `path` is an `http://` or `https://` URL rather than a file.
+pub fn is_url(path: &Path) -> bool {
+    path.to_str().is_some_and(|path| {
+        let lower = path.to_ascii_lowercase();
+        lower.starts_with("http://") || lower.starts_with("https://")
+    })
+}
+
+/// Download `url` into memory, within [`MAX_SIZE`] and [`TIMEOUT`].
+pub fn fetch(url: &str) -> Result<Vec<u8>> {
+    let max = MAX_SIZE.min(limits::active().max_file_size);
+    let too_large = || RemoteError::TooLarge {
+        url: url.to_string(),
+        max,
+    };
+    tracing::info!("fetching {}", url);
+
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(TIMEOUT)
+        .timeout_read(TIMEOUT)
+        .build();
+    let response = agent.get(url).call().map_err(|error| match error {
+        ureq::Error::Status(status, _) => RemoteError::Status {
+            url: url.to_string(),
+            status,
+        },
+        ureq::Error::Transport(transport) => RemoteError::Request {
+            url: url.to_string(),
+            reason: transport.to_string(),
+        },
+    })?;
+
+    let length = response
+        .header("Content-Length")
+        .and_then(|length| length.parse::<u64>().ok());
+    if length.is_some_and(|length| length > max) {
+        return Err(too_large().into());
+    }
+
+    let progress = Progress::bytes(length.unwrap_or(0), url);
+    let mut body = Vec::with_capacity(length.unwrap_or(0).min(max) as usize);
+    // One byte past the limit tells a body that is exactly at it from one that goes over
+    progress
+        .wrap_read(response.into_reader().take(max + 1))
+        .read_to_end(&mut body)?;
+    if body.len() as u64 > max {
+        return Err(too_large().into());
+    }
+    tracing::debug!("fetched {} bytes from {}", body.len(), url);
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::Write, net::TcpListener, thread};
+
+    /// Serve a single HTTP response on a local port, returning its URL.
+    fn serve(status: &str, body: &'static [u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/image.png", listener.local_addr().unwrap());
+        let head = format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            body.len()
+        );
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request);
+            stream.write_all(head.as_bytes()).unwrap();
+            stream.write_all(body).unwrap();
+        });
+        url
+    }
+
+    #[test]
+    fn test_is_url() {
+        assert!(is_url(Path::new("https://example.com/a.png")));
+        assert!(is_url(Path::new("HTTP://example.com/a.png")));
+        assert!(!is_url(Path::new("http.png")));
+        assert!(!is_url(Path::new("-")));
+    }
+
+    #[test]
+    fn test_fetch() {
+        let url = serve("200 OK", b"\x89PNG\r\n\x1a\n");
+        assert_eq!(fetch(&url).unwrap(), b"\x89PNG\r\n\x1a\n");
+
+        let url = serve("404 Not Found", b"missing");
+        let error = fetch(&url).unwrap_err();
+        assert!(error.to_string().contains("status 404"));
+    }
+}
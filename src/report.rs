@@ -0,0 +1,340 @@
+//! Readable reports of a sweep, `--report`, in HTML or Markdown to attach to a ticket.
+
+use std::path::Path;
+
+use crate::{png::human_size, scan::Finding, stats::Stats};
+
+#[derive(Debug)]
+pub enum ReportError {
+    UnknownExtension(String),
+}
+
+impl std::error::Error for ReportError {}
+
+impl std::fmt::Display for ReportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ReportError::UnknownExtension(path) => {
+                write!(
+                    f,
+                    "Can't tell the report format of {}, use .html or .md",
+                    path
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Html,
+    Markdown,
+}
+
+impl ReportFormat {
+    /// The format for a report written to `path`, from its extension.
+    pub fn from_path(path: &Path) -> Result<Self, ReportError> {
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        match extension.to_ascii_lowercase().as_str() {
+            "html" | "htm" => Ok(ReportFormat::Html),
+            "md" | "markdown" => Ok(ReportFormat::Markdown),
+            _ => Err(ReportError::UnknownExtension(path.display().to_string())),
+        }
+    }
+}
+
+/// A file that failed verification or drew warnings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub file: String,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// A table of the report under its own heading.
+struct Section {
+    heading: &'static str,
+    /// Column headings, all empty for a table without them.
+    columns: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+/// Everything a sweep found, collected file by file.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub title: String,
+    pub problems: Vec<Problem>,
+    pub findings: Vec<(String, Finding)>,
+    pub stats: Stats,
+}
+
+impl Report {
+    pub fn new(title: &str) -> Self {
+        Report {
+            title: title.to_string(),
+            problems: vec![],
+            findings: vec![],
+            stats: Stats::new(10),
+        }
+    }
+
+    /// Note the `errors` and `warnings` of `path`, if it has any.
+    pub fn add_problems(&mut self, path: &Path, errors: Vec<String>, warnings: Vec<String>) {
+        if !errors.is_empty() || !warnings.is_empty() {
+            self.problems.push(Problem {
+                file: path.display().to_string(),
+                errors,
+                warnings,
+            });
+        }
+    }
+
+    pub fn add_findings(&mut self, path: &Path, findings: &[Finding]) {
+        let file = path.display().to_string();
+        self.findings.extend(
+            findings
+                .iter()
+                .map(|finding| (file.clone(), finding.clone())),
+        );
+    }
+
+    fn sections(&self) -> Vec<Section> {
+        let stats = &self.stats;
+        let failed = self
+            .problems
+            .iter()
+            .filter(|p| !p.errors.is_empty())
+            .count();
+        let flagged = {
+            let mut files: Vec<_> = self.findings.iter().map(|(file, _)| file).collect();
+            files.dedup();
+            files.len()
+        };
+        let ratio = stats
+            .average_idat_ratio
+            .map_or("unknown".to_string(), |ratio| format!("{:.2}x", ratio));
+        let summary = vec![
+            vec!["Files".to_string(), stats.files.to_string()],
+            vec!["Total size".to_string(), human_size(stats.bytes as usize)],
+            vec!["Failed verification".to_string(), failed.to_string()],
+            vec!["Flagged by scan".to_string(), flagged.to_string()],
+            vec!["Average IDAT ratio".to_string(), ratio],
+        ];
+
+        let mut problems = vec![];
+        for problem in &self.problems {
+            for error in &problem.errors {
+                problems.push(vec![
+                    problem.file.clone(),
+                    "error".to_string(),
+                    error.clone(),
+                ]);
+            }
+            for warning in &problem.warnings {
+                let row = vec![problem.file.clone(), "warning".to_string(), warning.clone()];
+                problems.push(row);
+            }
+        }
+
+        let findings = self
+            .findings
+            .iter()
+            .map(|(file, finding)| {
+                vec![
+                    file.clone(),
+                    finding.offset.to_string(),
+                    finding.kind.to_string(),
+                    finding.chunk_type.clone().unwrap_or_default(),
+                    finding.detail.clone(),
+                ]
+            })
+            .collect();
+
+        let mut chunk_types: Vec<_> = stats.chunk_types.iter().collect();
+        chunk_types.sort_by(|a, b| b.1.count.cmp(&a.1.count).then(a.0.cmp(b.0)));
+        let chunk_types = chunk_types
+            .into_iter()
+            .map(|(chunk_type, totals)| {
+                vec![
+                    chunk_type.clone(),
+                    totals.count.to_string(),
+                    human_size(totals.bytes as usize),
+                ]
+            })
+            .collect();
+
+        let color_types = stats
+            .color_types
+            .iter()
+            .map(|(color_type, count)| vec![color_type.clone(), count.to_string()])
+            .collect();
+
+        let largest = stats
+            .largest
+            .iter()
+            .map(|file| {
+                vec![
+                    file.path.display().to_string(),
+                    human_size(file.size as usize),
+                ]
+            })
+            .collect();
+
+        let section = |heading, columns, rows| Section {
+            heading,
+            columns,
+            rows,
+        };
+        vec![
+            section("Summary", vec!["", ""], summary),
+            section(
+                "Verification",
+                vec!["File", "Severity", "Problem"],
+                problems,
+            ),
+            section(
+                "Scan findings",
+                vec!["File", "Offset", "Kind", "Chunk", "Detail"],
+                findings,
+            ),
+            section("Chunk types", vec!["Type", "Chunks", "Bytes"], chunk_types),
+            section("Color types", vec!["Color type", "Files"], color_types),
+            section("Largest files", vec!["File", "Size"], largest),
+        ]
+    }
+
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Html => self.html(),
+            ReportFormat::Markdown => self.markdown(),
+        }
+    }
+
+    fn markdown(&self) -> String {
+        let cell = |text: &str| text.replace('|', "\\|").replace('\n', " ");
+        let mut out = format!("# {}\n", cell(&self.title));
+        for Section {
+            heading,
+            columns,
+            rows,
+        } in self.sections()
+        {
+            out += &format!("\n## {}\n\n", heading);
+            if rows.is_empty() {
+                out += "Nothing to report.\n";
+                continue;
+            }
+            out += &format!("| {} |\n", columns.join(" | "));
+            out += &format!("|{}\n", "---|".repeat(columns.len()));
+            for row in rows {
+                let row: Vec<_> = row.iter().map(|text| cell(text)).collect();
+                out += &format!("| {} |\n", row.join(" | "));
+            }
+        }
+        out
+    }
+
+    fn html(&self) -> String {
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+             <style>\nbody {{ font-family: sans-serif; margin: 2em; }}\n\
+             table {{ border-collapse: collapse; margin-bottom: 1em; }}\n\
+             th, td {{ border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; }}\n\
+             th {{ background: #f0f0f0; }}\n</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+            escape(&self.title),
+            escape(&self.title)
+        );
+        for Section {
+            heading,
+            columns,
+            rows,
+        } in self.sections()
+        {
+            out += &format!("<h2>{}</h2>\n", heading);
+            if rows.is_empty() {
+                out += "<p>Nothing to report.</p>\n";
+                continue;
+            }
+            out += "<table>\n";
+            if columns.iter().any(|column| !column.is_empty()) {
+                let header: String = columns
+                    .iter()
+                    .map(|column| format!("<th>{}</th>", column))
+                    .collect();
+                out += &format!("<tr>{}</tr>\n", header);
+            }
+            for row in rows {
+                let row: String = row
+                    .iter()
+                    .map(|text| format!("<td>{}</td>", escape(text)))
+                    .collect();
+                out += &format!("<tr>{}</tr>\n", row);
+            }
+            out += "</table>\n";
+        }
+        out += "</body>\n</html>\n";
+        out
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        raster::{self, Image},
+        scan::Kind,
+    };
+
+    fn report() -> Report {
+        let mut report = Report::new("Audit of assets/");
+        let png = raster::encode(&Image::new(2, 2)).unwrap();
+        report.stats.add(Path::new("a.png"), 80, &png);
+        report.add_problems(
+            Path::new("b<1>.png"),
+            vec!["CRC mismatch in chunk 2".to_string()],
+            vec![],
+        );
+        report.add_problems(Path::new("a.png"), vec![], vec![]);
+        report.add_findings(
+            Path::new("a.png"),
+            &[Finding {
+                kind: Kind::PrivateChunk,
+                offset: 33,
+                chunk_type: Some("ruSt".to_string()),
+                detail: "private ruSt chunk, 1 | 2 bytes".to_string(),
+            }],
+        );
+        report
+    }
+
+    #[test]
+    fn test_render() {
+        let report = report();
+        assert_eq!(report.problems.len(), 1);
+
+        let markdown = report.render(ReportFormat::Markdown);
+        assert!(markdown.starts_with("# Audit of assets/\n"));
+        assert!(markdown.contains("| b<1>.png | error | CRC mismatch in chunk 2 |"));
+        assert!(markdown.contains("1 \\| 2 bytes"));
+        assert!(markdown.contains("| Failed verification | 1 |"));
+
+        let html = report.render(ReportFormat::Html);
+        assert!(html.contains("<td>b&lt;1&gt;.png</td>"));
+        assert!(html.contains("<td>truecolor+alpha</td>"));
+    }
+
+    #[test]
+    fn test_format() {
+        let format = |path: &str| ReportFormat::from_path(Path::new(path));
+        assert_eq!(format("out.HTML").unwrap(), ReportFormat::Html);
+        assert_eq!(format("audit.md").unwrap(), ReportFormat::Markdown);
+        assert!(format("audit.pdf").is_err());
+    }
+}
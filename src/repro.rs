@@ -0,0 +1,230 @@
+//! Minimal reproducers for files that fail verification: the raw bytes of each offending chunk
+//! plus a JSON note saying where it came from and what is wrong with it.
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::{
+    ordering,
+    png::Png,
+    stream::{self, ChunkData, ChunkHeader, Flow, StreamError, Visitor},
+    Result,
+};
+
+/// A chunk that breaks the file, located by its position in the raw bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Failure {
+    pub index: usize,
+    pub offset: u64,
+    pub chunk_type: Option<String>,
+    pub length: Option<u32>,
+    pub reason: String,
+    pub stored_crc: Option<u32>,
+    pub computed_crc: Option<u32>,
+}
+
+#[derive(Default)]
+struct Collector {
+    headers: Vec<ChunkHeader>,
+    failures: Vec<Failure>,
+}
+
+impl Visitor for Collector {
+    fn on_chunk(&mut self, header: &ChunkHeader, _: &mut ChunkData) -> Result<Flow> {
+        self.headers.push(header.clone());
+        Ok(Flow::Continue)
+    }
+
+    fn on_crc_mismatch(&mut self, header: &ChunkHeader, stored: u32, computed: u32) -> Result<()> {
+        self.failures.push(Failure {
+            index: self.headers.len() - 1,
+            offset: header.offset,
+            chunk_type: Some(header.chunk_type.to_string()),
+            length: Some(header.length),
+            reason: format!(
+                "[{}] {} stored CRC {:#010x} doesn't match {:#010x}",
+                self.headers.len() - 1,
+                header.chunk_type,
+                stored,
+                computed
+            ),
+            stored_crc: Some(stored),
+            computed_crc: Some(computed),
+        });
+        Ok(())
+    }
+}
+
+/// Every chunk of `bytes` with a bad CRC or a broken placement, plus the chunk the file ends in
+/// the middle of, if any. Empty for a valid file.
+pub fn find(bytes: &[u8]) -> Vec<Failure> {
+    let mut collector = Collector::default();
+    let parsed = stream::parse(bytes, &mut collector);
+    let mut failures = collector.failures;
+
+    match parsed {
+        Ok(_) => {}
+        Err(e) => {
+            let offset = match e.downcast_ref::<StreamError>() {
+                Some(StreamError::Truncated { offset }) => *offset,
+                _ => collector
+                    .headers
+                    .last()
+                    .map_or(0, |header| header.offset + 12 + header.length as u64),
+            };
+            let header = collector.headers.last().filter(|h| h.offset == offset);
+            let index = collector.headers.len() - usize::from(header.is_some());
+            failures.push(Failure {
+                index,
+                offset,
+                chunk_type: header.map(|h| h.chunk_type.to_string()),
+                length: header.map(|h| h.length),
+                reason: format!("[{}] {}", index, e),
+                stored_crc: None,
+                computed_crc: None,
+            });
+            return failures;
+        }
+    }
+
+    if let Ok(png) = Png::try_from(bytes) {
+        failures.extend(ordering::check(&png).into_iter().map(|violation| {
+            let header = &collector.headers[violation.index];
+            Failure {
+                index: violation.index,
+                offset: header.offset,
+                chunk_type: Some(violation.chunk_type.clone()),
+                length: Some(header.length),
+                reason: violation.to_string(),
+                stored_crc: None,
+                computed_crc: None,
+            }
+        }));
+    }
+
+    failures.sort_by_key(|failure| failure.index);
+    failures
+}
+
+#[derive(Serialize)]
+struct Context<'a> {
+    source: String,
+    file_size: usize,
+    pngme_version: &'static str,
+    #[serde(flatten)]
+    failure: &'a Failure,
+}
+
+/// The files [`dump`] makes for one failure, left for the caller to write so that a dry run
+/// can skip them.
+pub struct Dump {
+    pub chunk_path: PathBuf,
+    /// Length, type, data and CRC, cut short if the file is.
+    pub chunk: Vec<u8>,
+    pub context_path: PathBuf,
+    pub context: Vec<u8>,
+}
+
+/// The bytes of `failure`'s chunk from `bytes` and a JSON context file, named for a place in
+/// `dir`.
+pub fn dump(dir: &Path, source: &Path, bytes: &[u8], failure: &Failure) -> Result<Dump> {
+    let start = (failure.offset as usize).min(bytes.len());
+    let end = match failure.length {
+        Some(length) => start.saturating_add(12 + length as usize),
+        None => bytes.len(),
+    }
+    .min(bytes.len());
+
+    let stem = source
+        .file_stem()
+        .map_or("stdin".into(), |stem| stem.to_string_lossy());
+    // A corrupt chunk type can hold any byte, keep the file name portable
+    let chunk_type: String = failure
+        .chunk_type
+        .as_deref()
+        .unwrap_or("unknown")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let name = format!("{}-{}-{}", stem, failure.index, chunk_type);
+
+    let context = Context {
+        source: source.display().to_string(),
+        file_size: bytes.len(),
+        pngme_version: env!("CARGO_PKG_VERSION"),
+        failure,
+    };
+
+    Ok(Dump {
+        chunk_path: dir.join(format!("{}.bin", name)),
+        chunk: bytes[start..end].to_vec(),
+        context_path: dir.join(format!("{}.json", name)),
+        context: serde_json::to_string_pretty(&context)?.into_bytes(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk::Chunk, chunk_type::ChunkType};
+    use std::str::FromStr;
+
+    fn file(types: &[&str]) -> Vec<u8> {
+        Png::from_chunks(
+            types
+                .iter()
+                .map(|code| Chunk::new(ChunkType::from_str(code).unwrap(), b"data".to_vec()))
+                .collect(),
+        )
+        .as_bytes()
+    }
+
+    #[test]
+    fn test_valid_file() {
+        assert!(find(&file(&["IHDR", "tEXt", "IEND"])).is_empty());
+    }
+
+    #[test]
+    fn test_crc_mismatch() {
+        let mut bytes = file(&["IHDR", "tEXt", "IEND"]);
+        // Flip a data byte of the tEXt chunk
+        bytes[8 + 16 + 8] ^= 1;
+
+        let failures = find(&bytes);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].index, 1);
+        assert_eq!(failures[0].offset, 24);
+        assert!(failures[0].stored_crc != failures[0].computed_crc);
+    }
+
+    #[test]
+    fn test_truncated_and_misplaced() {
+        let bytes = file(&["IHDR", "tEXt", "IEND"]);
+        let failures = find(&bytes[..bytes.len() - 6]);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].chunk_type.as_deref(), Some("IEND"));
+
+        let failures = find(&file(&["tEXt", "IHDR", "IEND"]));
+        assert_eq!(failures[0].index, 1);
+        assert_eq!(failures[0].chunk_type.as_deref(), Some("IHDR"));
+    }
+
+    #[test]
+    fn test_dump() {
+        let mut bytes = file(&["IHDR", "tEXt", "IEND"]);
+        bytes[8 + 16 + 8] ^= 1;
+        let failure = &find(&bytes)[0];
+
+        let dir = std::env::temp_dir().join(format!("pngme-repro-{}", std::process::id()));
+        let dump = dump(&dir, Path::new("broken.png"), &bytes, failure).unwrap();
+        assert_eq!(dump.chunk_path, dir.join("broken-1-tEXt.bin"));
+        assert_eq!(dump.chunk, &bytes[24..40]);
+        assert_eq!(dump.context_path, dir.join("broken-1-tEXt.json"));
+        assert!(String::from_utf8(dump.context)
+            .unwrap()
+            .contains("broken.png"));
+        // Nothing is written until the caller decides to, which a dry run doesn't
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,275 @@
+//! Triage for hidden data: chunks no decoder knows, bytes after the image ends, oversized
+//! ancillary chunks and text that looks encoded or encrypted rather than written.
+
+use serde::Serialize;
+
+use crate::{
+    bytes,
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    png::{Png, PngError},
+    registry,
+    text::{self, TextualChunk},
+    Result,
+};
+
+/// Ancillary chunks at least this large are flagged whatever the image size.
+const LARGE_CHUNK: usize = 256 * 1024;
+
+/// Shortest text whose entropy says anything. Below this even random text can't reach
+/// [`HIGH_ENTROPY`].
+const MIN_ENTROPY_LEN: usize = 64;
+
+/// Bits per byte above which text is more likely base64, compressed or encrypted data than
+/// prose, which stays around 4 to 4.5.
+const HIGH_ENTROPY: f64 = 4.8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Kind {
+    /// A public chunk type that isn't registered.
+    UnknownChunk,
+    PrivateChunk,
+    /// Bytes after IEND, or that don't form a chunk.
+    TrailingData,
+    LargeChunk,
+    HighEntropyText,
+}
+
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Kind::UnknownChunk => "unknown-chunk",
+            Kind::PrivateChunk => "private-chunk",
+            Kind::TrailingData => "trailing-data",
+            Kind::LargeChunk => "large-chunk",
+            Kind::HighEntropyText => "high-entropy-text",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Something in a file worth a closer look.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Finding {
+    pub kind: Kind,
+    /// Where the chunk or the trailing data starts in the file.
+    pub offset: usize,
+    pub chunk_type: Option<String>,
+    pub detail: String,
+}
+
+/// Shannon entropy of `data` in bits per byte.
+fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for &byte in data {
+        counts[byte as usize] += 1;
+    }
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Everything suspicious in the PNG file `file`. Chunks are walked by hand rather than parsed
+/// into a [`Png`], so the bytes that don't form one are found too.
+pub fn scan(file: &[u8]) -> Result<Vec<Finding>> {
+    if !file.starts_with(&Png::STANDARD_HEADER) {
+        return Err(PngError::InvalidPngHeader.into());
+    }
+
+    let mut chunks = vec![];
+    let mut findings = vec![];
+    let mut offset = Png::STANDARD_HEADER.len();
+    while offset < file.len() {
+        let chunk = bytes::read_be_u32(file, offset).and_then(|length| {
+            let end = offset.checked_add(12)?.checked_add(length as usize)?;
+            let code: [u8; 4] = file.get(offset + 4..offset + 8)?.try_into().ok()?;
+            let chunk_type = ChunkType::try_from(code).ok()?;
+            (end <= file.len()).then_some((chunk_type, end))
+        });
+        let Some((chunk_type, end)) = chunk else {
+            findings.push(Finding {
+                kind: Kind::TrailingData,
+                offset,
+                chunk_type: None,
+                detail: format!("{} bytes that don't form a chunk", file.len() - offset),
+            });
+            break;
+        };
+
+        let data = file[offset + 8..end - 4].to_vec();
+        chunks.push((offset, Chunk::new(chunk_type, data)));
+        offset = end;
+
+        if chunks
+            .last()
+            .is_some_and(|(_, chunk)| chunk.chunk_type().to_string() == "IEND")
+        {
+            if offset < file.len() {
+                findings.push(Finding {
+                    kind: Kind::TrailingData,
+                    offset,
+                    chunk_type: None,
+                    detail: format!("{} bytes after IEND", file.len() - offset),
+                });
+            }
+            break;
+        }
+    }
+
+    let image_data: usize = chunks
+        .iter()
+        .filter(|(_, chunk)| chunk.chunk_type().to_string() == "IDAT")
+        .map(|(_, chunk)| chunk.data().len())
+        .sum();
+
+    for (offset, chunk) in &chunks {
+        findings.extend(check_chunk(*offset, chunk, image_data));
+    }
+    findings.sort_by_key(|finding| finding.offset);
+    Ok(findings)
+}
+
+fn check_chunk(offset: usize, chunk: &Chunk, image_data: usize) -> Vec<Finding> {
+    let mut findings = vec![];
+    let chunk_type = chunk.chunk_type();
+    let code = chunk_type.to_string();
+    let len = chunk.data().len();
+    let mut finding = |kind, detail| {
+        findings.push(Finding {
+            kind,
+            offset,
+            chunk_type: Some(code.clone()),
+            detail,
+        })
+    };
+
+    // APNG's chunks are registered despite their private-looking names
+    match (registry::lookup(&code), chunk_type.is_public()) {
+        (Some(_), _) => {}
+        (None, false) => finding(
+            Kind::PrivateChunk,
+            format!("private {} chunk, {} bytes", code, len),
+        ),
+        (None, true) => finding(
+            Kind::UnknownChunk,
+            format!("unregistered {} chunk, {} bytes", code, len),
+        ),
+    }
+
+    if !chunk_type.is_critical() && (len >= LARGE_CHUNK || (len > 1024 && len > image_data)) {
+        finding(
+            Kind::LargeChunk,
+            format!(
+                "{} bytes of {}, against {} of image data",
+                len, code, image_data
+            ),
+        );
+    }
+
+    if text::is_text_chunk(&code) {
+        if let Ok(parsed) = TextualChunk::parse(chunk, Default::default()) {
+            let value = parsed.text.as_bytes();
+            let bits = entropy(value);
+            if value.len() >= MIN_ENTROPY_LEN && bits > HIGH_ENTROPY {
+                finding(
+                    Kind::HighEntropyText,
+                    format!(
+                        "{} text \"{}\" has {:.1} bits/byte over {} bytes",
+                        code,
+                        parsed.keyword,
+                        bits,
+                        value.len()
+                    ),
+                );
+            }
+        }
+    }
+
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raster::{self, Image};
+    use std::str::FromStr;
+
+    fn chunk(code: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(code).unwrap(), data.to_vec())
+    }
+
+    fn image(extra: Vec<Chunk>) -> Vec<u8> {
+        let mut png = raster::encode(&Image::new(8, 8)).unwrap();
+        let idat = png.nth_chunk_index("IDAT", 0).unwrap();
+        for chunk in extra.into_iter().rev() {
+            png.insert_chunk(idat, chunk);
+        }
+        png.as_bytes()
+    }
+
+    fn kinds(file: &[u8]) -> Vec<Kind> {
+        scan(file)
+            .unwrap()
+            .iter()
+            .map(|finding| finding.kind)
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_image() {
+        let prose = b"Comment\0A photo of the harbour at dusk, taken from the north pier.";
+        assert!(kinds(&image(vec![chunk("tEXt", prose)])).is_empty());
+    }
+
+    #[test]
+    fn test_suspicious_chunks() {
+        let mut secret = b"Comment\0".to_vec();
+        secret.extend(b"U2FsdGVkX1+vupppZksvRf5pq5g5XjFRIipRkwB0K1Y96Qsv2Lm+31cmzaAILwyt");
+        let file = image(vec![
+            chunk("ruSt", b"x"),
+            chunk("zZZz", b"y"),
+            chunk("tEXt", &secret),
+            chunk("eXIf", &[0; 2048]),
+        ]);
+
+        assert_eq!(
+            kinds(&file),
+            [
+                Kind::PrivateChunk,
+                Kind::UnknownChunk,
+                Kind::HighEntropyText,
+                Kind::LargeChunk
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let mut file = image(vec![]);
+        let end = file.len();
+        file.extend(b"hidden");
+
+        let findings = scan(&file).unwrap();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].kind, Kind::TrailingData);
+        assert_eq!(findings[0].offset, end);
+
+        // Bytes that can't be a chunk are caught before IEND too
+        let mut cut = image(vec![]);
+        cut.truncate(cut.len() - 6);
+        assert_eq!(kinds(&cut), [Kind::TrailingData]);
+    }
+
+    #[test]
+    fn test_entropy() {
+        assert_eq!(entropy(b"aaaa"), 0.0);
+        assert_eq!(entropy(&(0..=255).collect::<Vec<u8>>()), 8.0);
+    }
+}
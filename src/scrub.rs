@@ -0,0 +1,295 @@
+use crate::{
+    bytes::{be_u16, be_u32, le_u16, le_u32},
+    chunk::Chunk,
+    png::Png,
+    text::{self, Charset, TextualChunk},
+    Result,
+};
+
+/// Text keywords that hint at who made an image, when or where, matched case-insensitively
+/// anywhere in the keyword. XMP packets are dropped wholesale since they embed all of these.
+const PRIVATE_KEYWORDS: [&str; 10] = [
+    "author", "artist", "creator", "owner", "time", "date", "serial", "gps", "location", "xmp",
+];
+
+/// Sensitive tags of the first IFD.
+const IFD0_TAGS: [(u16, &str); 4] = [
+    (0x0132, "DateTime"),
+    (0x013b, "Artist"),
+    (0x8298, "Copyright"),
+    (0x9c9d, "XPAuthor"),
+];
+
+/// Sensitive tags of the Exif IFD.
+const EXIF_TAGS: [(u16, &str); 11] = [
+    (0x9003, "DateTimeOriginal"),
+    (0x9004, "DateTimeDigitized"),
+    (0x9010, "OffsetTime"),
+    (0x9011, "OffsetTimeOriginal"),
+    (0x9012, "OffsetTimeDigitized"),
+    (0x9291, "SubSecTimeOriginal"),
+    (0x9292, "SubSecTimeDigitized"),
+    (0xa420, "ImageUniqueID"),
+    (0xa430, "CameraOwnerName"),
+    (0xa431, "BodySerialNumber"),
+    (0xa435, "LensSerialNumber"),
+];
+
+const EXIF_IFD_POINTER: u16 = 0x8769;
+const GPS_IFD_POINTER: u16 = 0x8825;
+const GPS_VERSION_ID: u16 = 0x0000;
+
+/// A TIFF structure (the body of an eXIf chunk) whose values can be blanked in place.
+struct Tiff<'a> {
+    data: &'a mut [u8],
+    little_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a mut [u8]) -> Option<Self> {
+        let little_endian = match data.get(..4)? {
+            b"II*\0" => true,
+            b"MM\0*" => false,
+            _ => return None,
+        };
+        Some(Self {
+            data,
+            little_endian,
+        })
+    }
+
+    fn u16_at(&self, offset: usize) -> Option<u16> {
+        let bytes = self.data.get(offset..offset.checked_add(2)?)?;
+        Some(if self.little_endian {
+            le_u16(bytes)
+        } else {
+            be_u16(bytes)
+        })
+    }
+
+    fn u32_at(&self, offset: usize) -> Option<u32> {
+        let bytes = self.data.get(offset..offset.checked_add(4)?)?;
+        Some(if self.little_endian {
+            le_u32(bytes)
+        } else {
+            be_u32(bytes)
+        })
+    }
+
+    /// Tags and entry offsets of the IFD at `offset`.
+    fn entries(&self, offset: usize) -> Vec<(u16, usize)> {
+        let count = self.u16_at(offset).unwrap_or(0) as usize;
+        (0..count)
+            .map(|idx| offset + 2 + idx * 12)
+            .map_while(|entry| Some((self.u16_at(entry)?, entry)))
+            .collect()
+    }
+
+    fn pointer(&self, entry: usize) -> Option<usize> {
+        self.u32_at(entry + 8).map(|offset| offset as usize)
+    }
+
+    /// Zero the value of an entry, wherever it is stored. Returns whether anything was blanked.
+    fn blank(&mut self, entry: usize) -> bool {
+        let (Some(field_type), Some(count)) = (self.u16_at(entry + 2), self.u32_at(entry + 4))
+        else {
+            return false;
+        };
+        let unit = match field_type {
+            1 | 2 | 6 | 7 => 1,
+            3 | 8 => 2,
+            4 | 9 | 11 => 4,
+            5 | 10 | 12 => 8,
+            _ => return false,
+        };
+        let Some(size) = (count as usize).checked_mul(unit) else {
+            return false;
+        };
+
+        let start = if size <= 4 {
+            entry + 8
+        } else {
+            match self.pointer(entry) {
+                Some(offset) => offset,
+                None => return false,
+            }
+        };
+        match start
+            .checked_add(size)
+            .and_then(|end| self.data.get_mut(start..end))
+        {
+            Some(value) => {
+                value.fill(0);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn blank_tags(&mut self, ifd: usize, tags: &[(u16, &str)], report: &mut Vec<String>) {
+        for (tag, entry) in self.entries(ifd) {
+            if let Some((_, name)) = tags.iter().find(|(sensitive, _)| *sensitive == tag) {
+                if self.blank(entry) {
+                    report.push(format!("blanked eXIf {}", name));
+                }
+            }
+        }
+    }
+}
+
+/// Blank the sensitive values of an eXIf payload in place.
+fn scrub_exif(data: &mut [u8], report: &mut Vec<String>) {
+    // Some writers keep the JPEG APP1 prefix, offsets are relative to the TIFF header after it
+    let data = match data.strip_prefix(b"Exif\0\0") {
+        Some(_) => &mut data[6..],
+        None => data,
+    };
+    let Some(mut tiff) = Tiff::new(data) else {
+        return;
+    };
+    let Some(ifd0) = tiff.u32_at(4).map(|offset| offset as usize) else {
+        return;
+    };
+
+    tiff.blank_tags(ifd0, &IFD0_TAGS, report);
+
+    for (tag, entry) in tiff.entries(ifd0) {
+        let Some(ifd) = tiff.pointer(entry) else {
+            continue;
+        };
+        match tag {
+            EXIF_IFD_POINTER => tiff.blank_tags(ifd, &EXIF_TAGS, report),
+            GPS_IFD_POINTER => {
+                let blanked = tiff
+                    .entries(ifd)
+                    .into_iter()
+                    .filter(|(gps_tag, _)| *gps_tag != GPS_VERSION_ID)
+                    .filter(|(_, gps_entry)| tiff.blank(*gps_entry))
+                    .count();
+                if blanked > 0 {
+                    report.push(format!("blanked eXIf GPS coordinates ({} fields)", blanked));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn is_private_keyword(keyword: &str) -> bool {
+    let keyword = keyword.to_ascii_lowercase();
+    PRIVATE_KEYWORDS.iter().any(|word| keyword.contains(word))
+}
+
+/// Remove or blank identifying metadata: timestamps, GPS coordinates, serial numbers and
+/// author fields. Returns a line describing each thing removed.
+pub fn scrub(png: &mut Png) -> Result<Vec<String>> {
+    let mut report = vec![];
+    let mut kept = Vec::with_capacity(png.chunks.len());
+
+    for chunk in png.chunks.drain(..) {
+        let chunk_type = chunk.chunk_type().to_string();
+
+        if chunk_type == "tIME" {
+            report.push("removed tIME".to_string());
+            continue;
+        }
+
+        if text::is_text_chunk(&chunk_type) {
+            if let Ok(text) = TextualChunk::parse(&chunk, Charset::Auto) {
+                if is_private_keyword(&text.keyword) {
+                    report.push(format!("removed {} {:?}", chunk_type, text.keyword));
+                    continue;
+                }
+            }
+        }
+
+        if chunk_type == "eXIf" {
+            let mut data = chunk.data().to_vec();
+            scrub_exif(&mut data, &mut report);
+            kept.push(Chunk::new(chunk.chunk_type().clone(), data));
+            continue;
+        }
+
+        kept.push(chunk);
+    }
+
+    png.chunks = kept;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bytes::put_be_u16, bytes::put_be_u32, chunk_type::ChunkType};
+    use std::str::FromStr;
+
+    fn chunk(code: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(code).unwrap(), data.to_vec())
+    }
+
+    fn entry(out: &mut Vec<u8>, tag: u16, field_type: u16, count: u32, value: u32) {
+        put_be_u16(out, tag);
+        put_be_u16(out, field_type);
+        put_be_u32(out, count);
+        put_be_u32(out, value);
+    }
+
+    /// Big-endian TIFF: IFD0 at 8 with Artist (inline), DateTime (at 50) and a GPS pointer
+    /// (to 70), GPS IFD with GPSVersionID and GPSLatitude (at 100).
+    fn exif() -> Vec<u8> {
+        let mut data = b"MM\0*".to_vec();
+        put_be_u32(&mut data, 8);
+
+        put_be_u16(&mut data, 3);
+        entry(&mut data, 0x013b, 2, 3, be_u32(b"Me\0\0"));
+        entry(&mut data, 0x0132, 2, 20, 50);
+        entry(&mut data, GPS_IFD_POINTER, 4, 1, 70);
+        data.resize(50, 0);
+        data.extend_from_slice(b"2024:01:01 12:00:00\0");
+
+        put_be_u16(&mut data, 2);
+        entry(&mut data, GPS_VERSION_ID, 1, 4, 0x0202_0000);
+        entry(&mut data, 0x0002, 5, 3, 100);
+        data.resize(100, 0);
+        data.extend_from_slice(&[7; 24]);
+        data
+    }
+
+    #[test]
+    fn test_scrub_exif() {
+        let mut data = exif();
+        let mut report = vec![];
+        scrub_exif(&mut data, &mut report);
+
+        assert!(data[50..70].iter().all(|&b| b == 0));
+        assert!(data[100..124].iter().all(|&b| b == 0));
+        // Artist is stored inline in its entry
+        assert_eq!(&data[10 + 8..10 + 12], &[0, 0, 0, 0]);
+        // The GPS version is kept, only coordinates are personal
+        assert_eq!(&data[72 + 8..72 + 12], &[2, 2, 0, 0]);
+        assert_eq!(report.len(), 3);
+    }
+
+    #[test]
+    fn test_scrub_chunks() {
+        let mut png = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("tIME", &[0; 7]),
+            chunk("tEXt", b"Author\0Jane"),
+            chunk("tEXt", b"Creation Time\0yesterday"),
+            chunk("tEXt", b"Software\0pngme"),
+            chunk("eXIf", &exif()),
+            chunk("IEND", &[]),
+        ]);
+
+        let report = scrub(&mut png).unwrap();
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+
+        assert_eq!(types, ["IHDR", "tEXt", "eXIf", "IEND"]);
+        assert_eq!(report.len(), 6);
+    }
+}
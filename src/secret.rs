@@ -0,0 +1,131 @@
+//! Passwords and keys from files, the environment or a terminal prompt, so they stay out of
+//! argv and shell history.
+
+use std::{env, fs, path::PathBuf};
+
+use zeroize::Zeroizing;
+
+use crate::Result;
+
+/// Environment variable read for a password when neither `--password` nor `--password-file`
+/// is given.
+pub const PASSWORD_ENV: &str = "PNGME_PASSWORD";
+
+#[derive(Debug)]
+pub enum SecretError {
+    NoTerminal(String),
+    Mismatch,
+    Empty,
+}
+
+impl std::error::Error for SecretError {}
+
+impl std::fmt::Display for SecretError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SecretError::NoTerminal(what) => write!(
+                f,
+                "A {} is required and there is no terminal to ask for it",
+                what
+            ),
+            SecretError::Mismatch => write!(f, "The entries don't match"),
+            SecretError::Empty => write!(f, "An empty secret isn't allowed"),
+        }
+    }
+}
+
+/// A secret supplied without a prompt: `value` from the command line, else the contents of
+/// `file` (without its trailing newline), else the environment variable `env_var`.
+///
+/// Like every secret here it is wiped when dropped. Copies this process can't reach, such as
+/// the original argv, are out of its hands.
+pub fn given(
+    value: &Option<String>,
+    file: &Option<PathBuf>,
+    env_var: Option<&str>,
+) -> Result<Option<Zeroizing<String>>> {
+    if let Some(value) = value {
+        return Ok(Some(Zeroizing::new(value.clone())));
+    }
+    if let Some(file) = file {
+        let contents = Zeroizing::new(fs::read_to_string(file)?);
+        return Ok(Some(Zeroizing::new(strip_newline(&contents).to_string())));
+    }
+    Ok(env_var.and_then(|name| env::var(name).ok().map(Zeroizing::new)))
+}
+
+fn strip_newline(contents: &str) -> &str {
+    let contents = contents.strip_suffix('\n').unwrap_or(contents);
+    contents.strip_suffix('\r').unwrap_or(contents)
+}
+
+/// Ask for an existing secret, such as the password of a message being decoded. Input isn't
+/// echoed, and is read from the controlling terminal even when stdin is a pipe.
+pub fn prompt(what: &str) -> Result<Zeroizing<String>> {
+    rpassword::prompt_password(format!("{}: ", capitalize(what)))
+        .map(Zeroizing::new)
+        .map_err(|_| SecretError::NoTerminal(what.to_string()).into())
+}
+
+/// Ask for a new secret twice, so a typo doesn't lock the message away for good.
+pub fn prompt_new(what: &str) -> Result<Zeroizing<String>> {
+    let secret = prompt(what)?;
+    if secret.is_empty() {
+        return Err(SecretError::Empty.into());
+    }
+    let again = prompt(&format!("{} again", what))?;
+    confirm(secret, &again)
+}
+
+/// `given` if there is one, otherwise a new secret asked for with [`prompt_new`].
+pub fn or_prompt_new(given: Option<Zeroizing<String>>, what: &str) -> Result<Zeroizing<String>> {
+    match given {
+        Some(secret) => Ok(secret),
+        None => prompt_new(what),
+    }
+}
+
+fn confirm(secret: Zeroizing<String>, again: &str) -> Result<Zeroizing<String>> {
+    match secret.as_str() == again {
+        true => Ok(secret),
+        false => Err(SecretError::Mismatch.into()),
+    }
+}
+
+fn capitalize(what: &str) -> String {
+    let mut chars = what.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_confirm() {
+        let secret = || Zeroizing::new("hunter2".to_string());
+        assert_eq!(*confirm(secret(), "hunter2").unwrap(), "hunter2");
+        assert!(confirm(secret(), "hunter3").is_err());
+        assert_eq!(capitalize("decoy password"), "Decoy password");
+    }
+
+    #[test]
+    fn test_given() {
+        let dir = std::env::temp_dir().join(format!("pngme-secret-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("password");
+        fs::write(&file, "from file\r\n").unwrap();
+
+        let given_value = given(&Some("argv".into()), &Some(file.clone()), None).unwrap();
+        assert_eq!(given_value.as_deref().map(String::as_str), Some("argv"));
+        let from_file = given(&None, &Some(file), None).unwrap();
+        assert_eq!(from_file.as_deref().map(String::as_str), Some("from file"));
+        assert!(given(&None, &None, None).unwrap().is_none());
+        assert_eq!(strip_newline("a\n\n"), "a\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,164 @@
+use std::{fs, str::FromStr};
+
+use crate::{
+    apng::{self, Delay},
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    crypto::{Cipher, KdfParams},
+    envelope::Envelope,
+    payload::{self, Decoy, Keys},
+    png::Png,
+    raster::{self, Image},
+    span, Result,
+};
+
+type Check = fn() -> Result<()>;
+
+/// A small gradient, enough to exercise filtering and compression.
+fn test_image(shift: u8) -> Image {
+    let mut image = Image::new(16, 8);
+    for y in 0..8 {
+        for x in 0..16 {
+            image.set_pixel(x, y, [x as u8 * 16, y as u8 * 32, shift, 255 - x as u8 * 8]);
+        }
+    }
+    image
+}
+
+fn check(ok: bool, what: &str) -> Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        Err(what.to_string().into())
+    }
+}
+
+fn file_round_trip() -> Result<()> {
+    let png = raster::encode(&test_image(0))?;
+    let path = std::env::temp_dir().join(format!("pngme-selftest-{}.png", std::process::id()));
+
+    fs::write(&path, png.as_bytes())?;
+    let read = fs::read(&path);
+    fs::remove_file(&path)?;
+
+    check(read? == png.as_bytes(), "file contents changed on disk")
+}
+
+fn encode_decode() -> Result<()> {
+    let mut png = raster::encode(&test_image(0))?;
+    png.append_chunk(Chunk::new(ChunkType::from_str("ruSt")?, b"hello".to_vec()));
+
+    let parsed = Png::try_from(png.as_bytes().as_slice())?;
+    let chunk = parsed
+        .chunk_by_type("ruSt")
+        .ok_or("encoded chunk is missing")?;
+    check(chunk.data() == b"hello", "decoded message differs")
+}
+
+fn remove() -> Result<()> {
+    let original = raster::encode(&test_image(0))?;
+    let mut png = original.clone();
+    png.append_chunk(Chunk::new(ChunkType::from_str("ruSt")?, b"hello".to_vec()));
+    png.remove_first_chunk("ruSt")?;
+
+    check(
+        png.as_bytes() == original.as_bytes(),
+        "file differs after removing the chunk",
+    )
+}
+
+fn verify() -> Result<()> {
+    check(
+        raster::encode(&test_image(0))?.verify(),
+        "generated file does not verify",
+    )
+}
+
+fn pixels() -> Result<()> {
+    let image = test_image(0);
+    let decoded = raster::decode(&raster::encode(&image)?)?;
+    check(
+        decoded == image,
+        "pixels changed after encoding and decoding",
+    )
+}
+
+fn spanned() -> Result<()> {
+    let message = b"spread over three images";
+    let parts = span::split(message, &[1, 2, 3])?;
+    let parts = parts
+        .iter()
+        .map(|part| Envelope::try_from(part.as_bytes().as_slice()))
+        .collect::<Result<Vec<_>>>()?;
+
+    check(span::join(parts)? == message, "joined message differs")
+}
+
+fn encrypted() -> Result<()> {
+    let decoy = Decoy {
+        message: b"decoy",
+        password: "decoy password",
+    };
+    let sealed = payload::seal_with_decoy(
+        b"real",
+        "real password",
+        decoy,
+        KdfParams::default(),
+        Cipher::default(),
+    )?
+    .as_bytes();
+
+    let keys = |password| Keys {
+        password: Some(password),
+        ..Default::default()
+    };
+    let real = payload::open(
+        Envelope::try_from(sealed.as_slice())?,
+        keys("real password"),
+    )?;
+    check(*real == b"real", "wrong plaintext for the real password")?;
+    check(
+        payload::open(Envelope::try_from(sealed.as_slice())?, keys("wrong")).is_err(),
+        "a wrong password was accepted",
+    )
+}
+
+fn animation() -> Result<()> {
+    let frames = [test_image(0), test_image(128)];
+    let png = apng::build(&frames, Delay::from_str("50ms")?, 0)?;
+    check(
+        apng::render_frames(&png)? == frames,
+        "rendered frames differ",
+    )
+}
+
+/// Every check as its name and outcome, in the order they ran.
+pub fn run() -> Vec<(&'static str, Result<()>)> {
+    let checks: [(&'static str, Check); 8] = [
+        ("file round trip", file_round_trip),
+        ("encode/decode", encode_decode),
+        ("remove", remove),
+        ("verify", verify),
+        ("pixel round trip", pixels),
+        ("spanned payload", spanned),
+        ("encrypted payload", encrypted),
+        ("animation", animation),
+    ];
+
+    checks
+        .into_iter()
+        .map(|(name, check)| (name, check()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selftest_passes() {
+        for (name, result) in run() {
+            assert!(result.is_ok(), "{} failed: {:?}", name, result);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! Detached Ed25519 signatures over the critical chunks of an image.
+
+use std::{fs, path::Path, str::FromStr};
+
+use ed25519_dalek::{
+    pkcs8::{DecodePrivateKey, DecodePublicKey},
+    Signature, Signer, SigningKey, VerifyingKey,
+};
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, codec, png::Png, Result};
+
+/// Private chunk holding `ed25519 <public key> <signature>` in hex. Unsafe to copy, since an
+/// editor that changes the image data invalidates it.
+pub const SIGNATURE_CHUNK: &str = "siGN";
+
+#[derive(Debug)]
+pub enum SignatureError {
+    InvalidKey(String),
+    Unsigned,
+    InvalidChunk,
+    WrongKey,
+    Mismatch,
+}
+
+impl std::error::Error for SignatureError {}
+
+impl std::fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SignatureError::InvalidKey(path) => {
+                write!(f, "{} is not a PEM encoded Ed25519 key", path)
+            }
+            SignatureError::Unsigned => write!(f, "The image is not signed"),
+            SignatureError::InvalidChunk => {
+                write!(f, "The {} chunk is malformed", SIGNATURE_CHUNK)
+            }
+            SignatureError::WrongKey => write!(f, "The image was signed with a different key"),
+            SignatureError::Mismatch => write!(
+                f,
+                "The signature doesn't match, the image data was changed after signing"
+            ),
+        }
+    }
+}
+
+/// Read a PKCS#8 private key, such as one made by `openssl genpkey -algorithm ed25519`.
+pub fn load_signing_key(path: &Path) -> Result<SigningKey> {
+    let pem = fs::read_to_string(path)?;
+    Ok(SigningKey::from_pkcs8_pem(&pem)
+        .map_err(|_| SignatureError::InvalidKey(path.display().to_string()))?)
+}
+
+/// Read a public key, such as one made by `openssl pkey -pubout`.
+pub fn load_verifying_key(path: &Path) -> Result<VerifyingKey> {
+    let pem = fs::read_to_string(path)?;
+    Ok(VerifyingKey::from_public_key_pem(&pem)
+        .map_err(|_| SignatureError::InvalidKey(path.display().to_string()))?)
+}
+
+/// The complete critical chunks (length, type, data and CRC) in file order.
+fn signed_data(png: &Png) -> Vec<u8> {
+    png.chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type().is_critical())
+        .flat_map(Chunk::as_bytes)
+        .collect()
+}
+
+/// Add (or renew) the signature of `png`.
+pub fn sign(png: &mut Png, key: &SigningKey) -> Result<()> {
+    png.remove_chunks(SIGNATURE_CHUNK);
+    let signature = key.sign(&signed_data(png));
+    png.insert_ordered(Chunk::new(
+        ChunkType::from_str(SIGNATURE_CHUNK)?,
+        format!(
+            "ed25519 {} {}",
+            codec::encode_hex(key.verifying_key().as_bytes()),
+            codec::encode_hex(&signature.to_bytes())
+        )
+        .into_bytes(),
+    ));
+    Ok(())
+}
+
+/// Check the signature of `png` against `key`.
+pub fn verify(png: &Png, key: &VerifyingKey) -> Result<()> {
+    let chunk = png
+        .chunk_by_type(SIGNATURE_CHUNK)
+        .ok_or(SignatureError::Unsigned)?;
+    let text = std::str::from_utf8(chunk.data()).map_err(|_| SignatureError::InvalidChunk)?;
+    let (public, signature) = match text.split(' ').collect::<Vec<_>>()[..] {
+        ["ed25519", public, signature] => (
+            codec::decode_hex(public).map_err(|_| SignatureError::InvalidChunk)?,
+            codec::decode_hex(signature).map_err(|_| SignatureError::InvalidChunk)?,
+        ),
+        _ => return Err(SignatureError::InvalidChunk.into()),
+    };
+
+    if public != key.as_bytes() {
+        return Err(SignatureError::WrongKey.into());
+    }
+    let signature = Signature::from_slice(&signature).map_err(|_| SignatureError::InvalidChunk)?;
+    key.verify_strict(&signed_data(png), &signature)
+        .map_err(|_| SignatureError::Mismatch)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raster::{self, Image};
+
+    #[test]
+    fn test_sign_and_verify() {
+        let mut image = Image::new(4, 4);
+        image.set_pixel(1, 2, [255, 0, 0, 255]);
+        let mut png = raster::encode(&image).unwrap();
+
+        let key = SigningKey::from_bytes(&[7; 32]);
+        assert!(verify(&png, &key.verifying_key()).is_err());
+
+        sign(&mut png, &key).unwrap();
+        sign(&mut png, &key).unwrap();
+        let signatures = png
+            .chunks()
+            .iter()
+            .filter(|chunk| chunk.chunk_type().to_string() == SIGNATURE_CHUNK);
+        assert_eq!(signatures.count(), 1);
+        assert!(verify(&png, &key.verifying_key()).is_ok());
+
+        // Ancillary chunks aren't covered
+        png.insert_ordered(Chunk::new(
+            ChunkType::from_str("tEXt").unwrap(),
+            b"Comment\0hi".to_vec(),
+        ));
+        assert!(verify(&png, &key.verifying_key()).is_ok());
+
+        let stranger = SigningKey::from_bytes(&[8; 32]);
+        assert!(verify(&png, &stranger.verifying_key()).is_err());
+
+        let mut other = Image::new(4, 4);
+        other.set_pixel(1, 2, [0, 255, 0, 255]);
+        let mut tampered = raster::encode(&other).unwrap();
+        tampered.insert_ordered(png.chunk_by_type(SIGNATURE_CHUNK).unwrap().clone());
+        assert!(verify(&tampered, &key.verifying_key()).is_err());
+    }
+}
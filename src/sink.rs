@@ -0,0 +1,137 @@
+//! Places a decoded payload can be sent instead of being printed.
+
+use std::{
+    fs,
+    io::Write,
+    net::{Shutdown, TcpStream},
+    path::PathBuf,
+    process::{Command, ExitStatus, Stdio},
+};
+
+use crate::Result;
+
+#[derive(Debug)]
+pub enum SinkError {
+    CommandFailed { command: String, status: ExitStatus },
+}
+
+impl std::error::Error for SinkError {}
+
+impl std::fmt::Display for SinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SinkError::CommandFailed { command, status } => {
+                write!(f, "'{}' failed ({})", command, status)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sink {
+    /// A shell command reading the payload on its stdin.
+    Command(String),
+    /// A TCP server at `host:port`.
+    Tcp(String),
+    /// A file or named pipe, opened for writing without creating anything else first.
+    Path(PathBuf),
+}
+
+impl std::fmt::Display for Sink {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Sink::Command(command) => write!(f, "'{}'", command),
+            Sink::Tcp(address) => write!(f, "tcp://{}", address),
+            Sink::Path(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").arg(command);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command);
+        shell
+    }
+}
+
+impl Sink {
+    /// Deliver `data` in full, waiting for a command to finish and failing if it does.
+    pub fn send(&self, data: &[u8]) -> Result<()> {
+        match self {
+            Sink::Command(command) => {
+                let mut child = shell(command).stdin(Stdio::piped()).spawn()?;
+                let written = child.stdin.take().map(|mut stdin| stdin.write_all(data));
+                let status = child.wait()?;
+
+                if !status.success() {
+                    return Err(SinkError::CommandFailed {
+                        command: command.clone(),
+                        status,
+                    }
+                    .into());
+                }
+                // A command that exits early on purpose (e.g. `head`) closes the pipe
+                if let Some(Err(e)) = written {
+                    if e.kind() != std::io::ErrorKind::BrokenPipe {
+                        return Err(e.into());
+                    }
+                }
+            }
+            Sink::Tcp(address) => {
+                let mut stream = TcpStream::connect(address)?;
+                stream.write_all(data)?;
+                stream.shutdown(Shutdown::Write)?;
+            }
+            Sink::Path(path) => {
+                // Truncating is harmless for a FIFO and needed for a regular file
+                let mut file = fs::OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(path)?;
+                file.write_all(data)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::Read, net::TcpListener, thread};
+
+    #[test]
+    fn test_tcp_sink() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let mut received = vec![];
+            listener
+                .accept()
+                .unwrap()
+                .0
+                .read_to_end(&mut received)
+                .unwrap();
+            received
+        });
+
+        Sink::Tcp(address).send(b"payload").unwrap();
+        assert_eq!(server.join().unwrap(), b"payload");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_sink() {
+        assert!(Sink::Command("cat > /dev/null".into())
+            .send(b"payload")
+            .is_ok());
+        assert!(Sink::Command("exit 3".into()).send(b"payload").is_err());
+    }
+}
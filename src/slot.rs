@@ -0,0 +1,186 @@
+//! Several independent messages under one chunk type, told apart by their order in the image.
+
+use crate::{chunk::Chunk, envelope::Envelope, png::Png, preset, signature, Result};
+
+#[derive(Debug)]
+pub enum SlotError {
+    OutOfRange { slot: usize, count: usize },
+    Occupied { chunk_type: String, count: usize },
+}
+
+impl std::error::Error for SlotError {}
+
+impl std::fmt::Display for SlotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SlotError::OutOfRange { slot, count } => write!(
+                f,
+                "There is no slot {}, the image holds {} message(s) of this type and the next one goes in slot {}",
+                slot, count, count
+            ),
+            SlotError::Occupied { chunk_type, count } => write!(
+                f,
+                "The image already holds {} {} message(s), pass --overwrite to replace them, --append to add another or --slot to replace one",
+                count, chunk_type
+            ),
+        }
+    }
+}
+
+/// The indices into `chunks` of every message of type `chunk_type`, one entry per slot in file
+/// order. The chunks of a message that was split over several are one message, in the slot of
+/// its first chunk.
+pub fn messages(chunks: &[Chunk], chunk_type: &str) -> Vec<Vec<usize>> {
+    let mut messages: Vec<Vec<usize>> = vec![];
+    let mut ids = vec![];
+
+    let matching = chunks
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| chunk.chunk_type().to_string() == chunk_type);
+    for (index, chunk) in matching {
+        let id = part_id(chunk.data());
+        match id.and_then(|id| ids.iter().position(|&other| other == Some(id))) {
+            Some(slot) => messages[slot].push(index),
+            None => {
+                messages.push(vec![index]);
+                ids.push(id);
+            }
+        }
+    }
+
+    messages
+}
+
+/// The chunk types in `chunks` that look like they hold messages: any chunk carrying a pngme
+/// envelope, and private ancillary chunks other than the ones pngme keeps its signature and seal
+/// in. In file order, each type once.
+pub fn message_types(chunks: &[Chunk]) -> Vec<String> {
+    let mut types: Vec<String> = vec![];
+    for chunk in chunks {
+        let chunk_type = chunk.chunk_type();
+        let name = chunk_type.to_string();
+        let private = !chunk_type.is_critical()
+            && !chunk_type.is_public()
+            && name != signature::SIGNATURE_CHUNK
+            && name != preset::SEAL_CHUNK;
+        if (private || Envelope::is_envelope(chunk.data())) && !types.contains(&name) {
+            types.push(name);
+        }
+    }
+    types
+}
+
+/// The payload id shared by the chunks of a split message.
+fn part_id(data: &[u8]) -> Option<u32> {
+    if !Envelope::is_envelope(data) {
+        return None;
+    }
+    Envelope::try_from(data).ok()?.part.map(|part| part.id)
+}
+
+/// Check that `slot` holds one of `count` messages or is the next free one.
+pub fn check(slot: usize, count: usize) -> Result<()> {
+    match slot <= count {
+        true => Ok(()),
+        false => Err(SlotError::OutOfRange { slot, count }.into()),
+    }
+}
+
+/// What encode does when the image already holds messages of the chunk type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Existing {
+    /// Stop, the user didn't say whether the old messages should stay.
+    Refuse,
+    Overwrite,
+    /// Keep them, the new message gets the next slot.
+    Append,
+}
+
+/// Prepare `png` for a new message of `chunk_type` the way `existing` says. Returns where the
+/// first overwritten chunk was, for the new message to take its place.
+pub fn make_room(png: &mut Png, chunk_type: &str, existing: Existing) -> Result<Option<usize>> {
+    let messages = messages(png.chunks(), chunk_type);
+    let Some(first) = messages.first() else {
+        return Ok(None);
+    };
+
+    match existing {
+        Existing::Refuse => Err(SlotError::Occupied {
+            chunk_type: chunk_type.to_string(),
+            count: messages.len(),
+        }
+        .into()),
+        Existing::Overwrite => {
+            let bytes: usize = messages
+                .iter()
+                .flatten()
+                .map(|&index| png.chunks()[index].data().len())
+                .sum();
+            tracing::warn!(
+                "Overwriting {} {} message(s), {} bytes in all",
+                messages.len(),
+                chunk_type,
+                bytes
+            );
+            let index = first[0];
+            png.remove_chunks(chunk_type);
+            Ok(Some(index))
+        }
+        Existing::Append => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk_type::ChunkType, span};
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    #[test]
+    fn test_messages() {
+        let parts = span::split_chunks(&[7; 300], 100).unwrap();
+        let mut chunks = vec![
+            chunk("teSt", b"first".to_vec()),
+            chunk("ruSt", b"other type".to_vec()),
+        ];
+        chunks.extend(parts.iter().map(|part| chunk("teSt", part.as_bytes())));
+        chunks.push(chunk("teSt", b"last".to_vec()));
+
+        let messages = messages(&chunks, "teSt");
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0], [0]);
+        assert_eq!(messages[1].len(), parts.len());
+        assert_eq!(messages[2], [chunks.len() - 1]);
+        assert!(super::messages(&chunks, "neNe").is_empty());
+
+        chunks.push(chunk("siGN", b"ed25519".to_vec()));
+        chunks.push(chunk("tEXt", b"Comment\0hi".to_vec()));
+        assert_eq!(message_types(&chunks), ["teSt", "ruSt"]);
+
+        assert!(check(3, 3).is_ok());
+        assert!(check(4, 3).is_err());
+    }
+
+    #[test]
+    fn test_make_room() {
+        let mut png = Png::from_chunks(vec![
+            chunk("IHDR", vec![0; 13]),
+            chunk("teSt", b"old".to_vec()),
+            chunk("IEND", vec![]),
+        ]);
+        assert!(make_room(&mut png, "teSt", Existing::Refuse).is_err());
+        assert_eq!(make_room(&mut png, "ruSt", Existing::Refuse).unwrap(), None);
+        assert_eq!(make_room(&mut png, "teSt", Existing::Append).unwrap(), None);
+        assert_eq!(png.chunks().len(), 3);
+        assert_eq!(
+            make_room(&mut png, "teSt", Existing::Overwrite).unwrap(),
+            Some(1)
+        );
+        assert!(png.chunk_by_type("teSt").is_none());
+    }
+}
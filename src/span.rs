@@ -0,0 +1,189 @@
+use crate::{
+    envelope::{Envelope, Part},
+    Result,
+};
+
+#[derive(Debug)]
+pub enum SpanError {
+    TooManyParts,
+    NotAPart,
+    MismatchedParts,
+    MissingPart(u16),
+    ChecksumMismatch,
+}
+
+impl std::error::Error for SpanError {}
+
+impl std::fmt::Display for SpanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SpanError::TooManyParts => write!(f, "A payload can span at most {} images", u16::MAX),
+            SpanError::NotAPart => write!(f, "Chunk does not hold part of a spanned payload"),
+            SpanError::MismatchedParts => {
+                write!(f, "Parts belong to different spanned payloads")
+            }
+            SpanError::MissingPart(index) => {
+                write!(f, "Part {} of the payload is missing", index + 1)
+            }
+            SpanError::ChecksumMismatch => {
+                write!(f, "Reassembled payload does not match its checksum")
+            }
+        }
+    }
+}
+
+fn checksum(payload: &[u8]) -> u32 {
+    crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC).checksum(payload)
+}
+
+/// Split `payload` into one envelope per cover image.
+///
+/// Each cover receives a share proportional to its weight (usually its file size), so a large
+/// image hides a larger piece than a small one.
+pub fn split(payload: &[u8], weights: &[u64]) -> Result<Vec<Envelope>> {
+    let total = u16::try_from(weights.len()).map_err(|_| SpanError::TooManyParts)?;
+    let id = checksum(payload);
+    let weight_sum: u128 = weights.iter().map(|&w| w.max(1) as u128).sum();
+
+    let mut envelopes = Vec::with_capacity(weights.len());
+    let mut start = 0;
+    let mut accumulated = 0u128;
+
+    for (index, &weight) in weights.iter().enumerate() {
+        accumulated += weight.max(1) as u128;
+        let end = (payload.len() as u128 * accumulated / weight_sum) as usize;
+
+        envelopes.push(Envelope {
+            part: Some(Part {
+                id,
+                index: index as u16,
+                total,
+            }),
+            body: payload[start..end].to_vec(),
+            ..Default::default()
+        });
+        start = end;
+    }
+
+    Ok(envelopes)
+}
+
+/// Reassemble a payload from its parts, which may be given in any order.
+pub fn join(mut envelopes: Vec<Envelope>) -> Result<Vec<u8>> {
+    let first = envelopes
+        .first()
+        .and_then(|envelope| envelope.part)
+        .ok_or(SpanError::NotAPart)?;
+
+    for envelope in &envelopes {
+        let part = envelope.part.ok_or(SpanError::NotAPart)?;
+        if part.id != first.id || part.total != first.total {
+            return Err(SpanError::MismatchedParts.into());
+        }
+    }
+
+    envelopes.sort_by_key(|envelope| envelope.part.map(|part| part.index));
+
+    let mut payload = vec![];
+    for (expected, envelope) in (0..first.total).zip(envelopes.iter()) {
+        if envelope.part.map(|part| part.index) != Some(expected) {
+            return Err(SpanError::MissingPart(expected).into());
+        }
+        payload.extend_from_slice(&envelope.body);
+    }
+
+    if envelopes.len() < first.total as usize {
+        return Err(SpanError::MissingPart(envelopes.len() as u16).into());
+    }
+
+    if checksum(&payload) != first.id {
+        return Err(SpanError::ChecksumMismatch.into());
+    }
+
+    Ok(payload)
+}
+
+/// Largest chunk written for a message before it is split over several, well below the 16 MiB
+/// per chunk that decoding accepts with --hardened.
+pub const CHUNK_SIZE: u32 = 8 << 20;
+
+/// Split `payload` into envelopes of at most `chunk_size` bytes each, stored as consecutive chunks
+/// of one image rather than one huge chunk. [`join_chunks`] reassembles them.
+pub fn split_chunks(payload: &[u8], chunk_size: usize) -> Result<Vec<Envelope>> {
+    let overhead = split(&[], &[1])?[0].as_bytes().len();
+    let count = payload
+        .len()
+        .div_ceil(chunk_size.saturating_sub(overhead).max(1))
+        .max(1);
+    split(payload, &vec![1; count])
+}
+
+/// Reassemble a payload from the chunks `split_chunks` made, given the data of every chunk of
+/// that type. `None` when the first chunk holds no part, or when parts are missing because the
+/// payload was spread over several images.
+pub fn join_chunks<'a>(chunks: impl IntoIterator<Item = &'a [u8]>) -> Result<Option<Vec<u8>>> {
+    let mut parts = vec![];
+    for data in chunks
+        .into_iter()
+        .filter(|data| Envelope::is_envelope(data))
+    {
+        parts.push(Envelope::try_from(data)?);
+    }
+    let Some(first) = parts.first().and_then(|envelope| envelope.part) else {
+        return Ok(None);
+    };
+
+    parts.retain(|envelope| envelope.part.is_some_and(|part| part.id == first.id));
+    match parts.len() < first.total as usize {
+        true => Ok(None),
+        false => join(parts).map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_and_join() {
+        let payload = b"a payload that is far too large for a single image".to_vec();
+        let mut parts = split(&payload, &[10, 30, 20]).unwrap();
+
+        assert_eq!(parts.len(), 3);
+        assert!(parts[1].body.len() > parts[0].body.len());
+
+        parts.reverse();
+        assert_eq!(join(parts).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_join_missing_part() {
+        let mut parts = split(b"some payload", &[1, 1, 1]).unwrap();
+        parts.remove(1);
+        assert!(join(parts).is_err());
+    }
+
+    #[test]
+    fn test_join_mismatched_parts() {
+        let mut parts = split(b"some payload", &[1, 1]).unwrap();
+        parts[1] = split(b"another payload", &[1, 1]).unwrap().remove(1);
+        assert!(join(parts).is_err());
+    }
+
+    #[test]
+    fn test_split_and_join_chunks() {
+        let payload: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+        let parts = split_chunks(&payload, 100).unwrap();
+        assert!(parts.len() > 10);
+        assert!(parts.iter().all(|part| part.as_bytes().len() <= 100));
+
+        let data: Vec<Vec<u8>> = parts.iter().map(Envelope::as_bytes).collect();
+        let joined = join_chunks(data.iter().map(Vec::as_slice)).unwrap();
+        assert_eq!(joined.unwrap(), payload);
+        assert!(join_chunks(data[1..].iter().map(Vec::as_slice))
+            .unwrap()
+            .is_none());
+        assert!(join_chunks([b"plain".as_slice()]).unwrap().is_none());
+        assert_eq!(split_chunks(b"small", 100).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,122 @@
+//! Metadata templates for `stamp`: the same author, copyright, density and timestamp applied
+//! to every image of a set.
+//!
+//! ```toml
+//! dpi = 300
+//! time = "modified"
+//!
+//! [text]
+//! Author = "Jane Doe"
+//! Copyright = "© 2024 Example Ltd"
+//! Software = "pngme"
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    edit::{Dpi, Edit, TextEntry},
+    Result,
+};
+
+/// What happens to tIME.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TimeMode {
+    /// Leave it as it is.
+    #[default]
+    Keep,
+    /// Set it to the time of stamping.
+    Now,
+    /// Set it to when the file was last modified, before stamping.
+    Modified,
+    Remove,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Template {
+    /// Remove all text chunks before adding those of the template.
+    #[serde(default)]
+    pub replace_text: bool,
+    /// Keywords and their text, stored as tEXt, or iTXt when the text isn't Latin-1.
+    #[serde(default)]
+    pub text: BTreeMap<String, String>,
+    pub dpi: Option<f64>,
+    #[serde(default)]
+    pub time: TimeMode,
+}
+
+impl Template {
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(toml::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// The edits that stamp a file last modified at `modified`.
+    pub fn edits(&self, modified: Option<SystemTime>) -> Result<Vec<Edit>> {
+        let mut edits = vec![];
+        if self.replace_text {
+            edits.push(Edit::StripText);
+        }
+        for (keyword, text) in &self.text {
+            edits.push(Edit::SetText(TextEntry::new(keyword, text)?));
+        }
+        if let Some(dpi) = self.dpi {
+            edits.push(Edit::SetDpi(Dpi::new(dpi)?));
+        }
+
+        let secs =
+            |time: SystemTime| -> Result<u64> { Ok(time.duration_since(UNIX_EPOCH)?.as_secs()) };
+        match self.time {
+            TimeMode::Keep => {}
+            TimeMode::Now => edits.push(Edit::SetTime(secs(SystemTime::now())?)),
+            // Stdin has no modification time, it was made just now
+            TimeMode::Modified => {
+                let modified = modified.unwrap_or_else(SystemTime::now);
+                edits.push(Edit::SetTime(secs(modified)?));
+            }
+            TimeMode::Remove => edits.push(Edit::Remove("tIME".to_string())),
+        }
+        Ok(edits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_edits() {
+        let template: Template = toml::from_str(
+            r#"
+            dpi = 72
+            time = "modified"
+            [text]
+            Author = "Jane Doe"
+            Software = "pngme"
+            "#,
+        )
+        .unwrap();
+
+        let modified = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let edits = template.edits(Some(modified)).unwrap();
+        assert_eq!(edits.len(), 4);
+        assert!(matches!(&edits[0], Edit::SetText(entry) if entry.keyword == "Author"));
+        assert!(matches!(edits[3], Edit::SetTime(1_000_000)));
+    }
+
+    #[test]
+    fn test_invalid_template() {
+        assert!(toml::from_str::<Template>("colour = 1").is_err());
+        assert!(toml::from_str::<Template>("time = \"later\"").is_err());
+
+        let template: Template = toml::from_str("dpi = -3").unwrap();
+        assert!(template.edits(None).is_err());
+    }
+}
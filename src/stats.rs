@@ -0,0 +1,189 @@
+//! Totals over a tree of PNGs: which chunks they hold, how well their image data compresses,
+//! their pixel formats and which files take up the most space.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{
+    ihdr_chunk::IhdrChunk,
+    png::{human_size, Png},
+    raster::{self, Decoder},
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ChunkTotals {
+    pub count: usize,
+    /// Data bytes, without the length, type and CRC fields.
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LargeFile {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Stats {
+    pub files: usize,
+    pub bytes: u64,
+    pub chunk_types: BTreeMap<String, ChunkTotals>,
+    pub color_types: BTreeMap<String, usize>,
+    /// Mean of raw scanline size over compressed IDAT size, across the files it is known for.
+    pub average_idat_ratio: Option<f64>,
+    /// The biggest files with their size, largest first.
+    pub largest: Vec<LargeFile>,
+    #[serde(skip)]
+    ratios: Vec<f64>,
+    #[serde(skip)]
+    top: usize,
+}
+
+/// How many times smaller the image data of `png` is than its filtered scanlines.
+fn idat_ratio(png: &Png) -> Option<f64> {
+    let compressed: usize = png
+        .chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type().to_string() == "IDAT")
+        .map(|chunk| chunk.data().len())
+        .sum();
+    let (width, height) = raster::dimensions(png).ok()?;
+    let raw = Decoder::new(png).ok()?.data_len(width, height);
+    (compressed > 0).then(|| raw as f64 / compressed as f64)
+}
+
+impl Stats {
+    /// Empty totals that keep track of the `top` largest files.
+    pub fn new(top: usize) -> Self {
+        Stats {
+            files: 0,
+            bytes: 0,
+            chunk_types: BTreeMap::new(),
+            color_types: BTreeMap::new(),
+            average_idat_ratio: None,
+            largest: vec![],
+            ratios: vec![],
+            top,
+        }
+    }
+
+    /// Count `png`, read from the `size` byte file at `path`.
+    pub fn add(&mut self, path: &Path, size: u64, png: &Png) {
+        self.files += 1;
+        self.bytes += size;
+
+        for chunk in png.chunks() {
+            let totals = self
+                .chunk_types
+                .entry(chunk.chunk_type().to_string())
+                .or_default();
+            totals.count += 1;
+            totals.bytes += chunk.length() as u64;
+        }
+
+        let color_type = png
+            .chunk_by_type("IHDR")
+            .and_then(|chunk| IhdrChunk::try_from(chunk.clone()).ok())
+            .map_or("unknown", |ihdr| ihdr.color_type_name());
+        *self.color_types.entry(color_type.to_string()).or_default() += 1;
+
+        if let Some(ratio) = idat_ratio(png) {
+            self.ratios.push(ratio);
+            self.average_idat_ratio =
+                Some(self.ratios.iter().sum::<f64>() / self.ratios.len() as f64);
+        }
+
+        let at = self.largest.partition_point(|file| file.size >= size);
+        if at < self.top {
+            self.largest.insert(
+                at,
+                LargeFile {
+                    path: path.to_path_buf(),
+                    size,
+                },
+            );
+            self.largest.truncate(self.top);
+        }
+    }
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Files:       {}, {}",
+            self.files,
+            human_size(self.bytes as usize)
+        )?;
+        match self.average_idat_ratio {
+            Some(ratio) => writeln!(
+                f,
+                "IDAT ratio:  {:.2}x on average over {} file(s)",
+                ratio,
+                self.ratios.len()
+            )?,
+            None => writeln!(f, "IDAT ratio:  unknown")?,
+        }
+
+        let mut chunk_types: Vec<_> = self.chunk_types.iter().collect();
+        chunk_types.sort_by(|a, b| b.1.count.cmp(&a.1.count).then(a.0.cmp(b.0)));
+        writeln!(f, "\nChunk types:")?;
+        for (chunk_type, totals) in chunk_types {
+            writeln!(
+                f,
+                "  {:<4} {:>8} chunk(s) {:>11}",
+                chunk_type,
+                totals.count,
+                human_size(totals.bytes as usize)
+            )?;
+        }
+
+        writeln!(f, "\nColor types:")?;
+        for (color_type, count) in &self.color_types {
+            writeln!(f, "  {:<16} {:>8}", color_type, count)?;
+        }
+
+        writeln!(f, "\nLargest files:")?;
+        for file in &self.largest {
+            writeln!(
+                f,
+                "  {:>11}  {}",
+                human_size(file.size as usize),
+                file.path.display()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raster::Image;
+
+    #[test]
+    fn test_stats() {
+        let small = raster::encode(&Image::new(4, 4)).unwrap();
+        let large = raster::encode(&Image::new(64, 64)).unwrap();
+
+        let mut stats = Stats::new(2);
+        stats.add(Path::new("a.png"), 100, &small);
+        stats.add(Path::new("b.png"), 300, &large);
+        stats.add(Path::new("c.png"), 200, &small);
+
+        assert_eq!(stats.files, 3);
+        assert_eq!(stats.chunk_types["IHDR"].count, 3);
+        assert_eq!(stats.chunk_types["IHDR"].bytes, 39);
+        assert_eq!(stats.color_types["truecolor+alpha"], 3);
+        assert!(stats.average_idat_ratio.unwrap() > 1.0);
+        let largest: Vec<_> = stats.largest.iter().map(|file| file.size).collect();
+        assert_eq!(largest, [300, 200]);
+        assert_eq!(stats.largest[0].path, Path::new("b.png"));
+        assert!(stats.to_string().contains("IDAT ratio:"));
+    }
+}
@@ -0,0 +1,270 @@
+//! Single-pass chunk parsing from any reader, for analytics that don't need a [`Png`] in memory.
+//!
+//! [`parse`] reads the signature and then hands every chunk to a [`Visitor`] as it arrives, with
+//! a reader over just that chunk's data. Whatever the visitor doesn't read is skipped, and the
+//! CRC is checked either way. Closures work as visitors:
+//!
+//! ```
+//! use pngme::{png::Png, stream::{self, Flow}};
+//! use std::io::Read;
+//!
+//! # let file = Png::from_chunks(vec![]).as_bytes();
+//! let mut data_bytes = 0;
+//! let chunks = stream::parse(file.as_slice(), &mut |header: &stream::ChunkHeader, data: &mut stream::ChunkData| {
+//!     if header.chunk_type.to_string() == "IDAT" {
+//!         data_bytes += std::io::copy(data, &mut std::io::sink())?;
+//!     }
+//!     Ok(Flow::Continue)
+//! })?;
+//! # assert_eq!((chunks, data_bytes), (0, 0));
+//! # Ok::<(), pngme::Error>(())
+//! ```
+//!
+//! [`Png`]: crate::png::Png
+
+use std::io::{self, Read, Take};
+
+use crc::{Crc, Digest, CRC_32_ISO_HDLC};
+
+use crate::{bytes::be_u32, chunk_type::ChunkType, png::Png, Result};
+
+static CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+#[derive(Debug)]
+pub enum StreamError {
+    InvalidSignature,
+    Truncated { offset: u64 },
+}
+
+impl std::error::Error for StreamError {}
+
+impl std::fmt::Display for StreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StreamError::InvalidSignature => write!(f, "Invalid PNG header"),
+            StreamError::Truncated { offset } => {
+                write!(
+                    f,
+                    "File ends in the middle of the chunk at offset {}",
+                    offset
+                )
+            }
+        }
+    }
+}
+
+/// Everything known about a chunk before its data is read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkHeader {
+    /// Offset of the chunk's length field from the start of the file.
+    pub offset: u64,
+    pub length: u32,
+    pub chunk_type: ChunkType,
+}
+
+/// Whether to keep parsing after a chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Stop,
+}
+
+/// The data of the current chunk. Reading from it also feeds the CRC check.
+pub struct ChunkData<'a> {
+    inner: Take<&'a mut dyn Read>,
+    digest: Digest<'static, u32>,
+}
+
+impl Read for ChunkData<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.digest.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+impl ChunkData<'_> {
+    /// Bytes of data not read yet.
+    pub fn remaining(&self) -> u64 {
+        self.inner.limit()
+    }
+}
+
+pub trait Visitor {
+    /// Called for every chunk in file order. `data` can be read partly, fully or not at all.
+    fn on_chunk(&mut self, header: &ChunkHeader, data: &mut ChunkData) -> Result<Flow>;
+
+    /// Called when a chunk's stored CRC doesn't match its contents. Like [`Png`], parsing
+    /// tolerates this by default; return an error to reject the file instead.
+    ///
+    /// [`Png`]: crate::png::Png
+    fn on_crc_mismatch(&mut self, header: &ChunkHeader, stored: u32, computed: u32) -> Result<()> {
+        let _ = (header, stored, computed);
+        Ok(())
+    }
+}
+
+impl<F> Visitor for F
+where
+    F: FnMut(&ChunkHeader, &mut ChunkData) -> Result<Flow>,
+{
+    fn on_chunk(&mut self, header: &ChunkHeader, data: &mut ChunkData) -> Result<Flow> {
+        self(header, data)
+    }
+}
+
+/// Fill `buf` completely, or return `false` if the reader is already at its end.
+fn read_or_eof(reader: &mut impl Read, buf: &mut [u8], offset: u64) -> Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(StreamError::Truncated { offset }.into()),
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(true)
+}
+
+/// Parse the PNG in `reader`, calling `visitor` for each chunk. Returns how many chunks were
+/// visited.
+pub fn parse<R: Read>(mut reader: R, visitor: &mut impl Visitor) -> Result<usize> {
+    let mut signature = [0; 8];
+    if !read_or_eof(&mut reader, &mut signature, 0)? || signature != Png::STANDARD_HEADER {
+        return Err(StreamError::InvalidSignature.into());
+    }
+
+    let mut offset = signature.len() as u64;
+    let mut visited = 0;
+    let mut prefix = [0; 8];
+    while read_or_eof(&mut reader, &mut prefix, offset)? {
+        let header = ChunkHeader {
+            offset,
+            length: be_u32(&prefix[0..4]),
+            chunk_type: ChunkType::try_from([prefix[4], prefix[5], prefix[6], prefix[7]])?,
+        };
+
+        tracing::trace!(
+            "{} at offset {}, {} bytes",
+            header.chunk_type,
+            offset,
+            header.length
+        );
+
+        let mut digest = CRC32.digest();
+        digest.update(&prefix[4..8]);
+        let mut data = ChunkData {
+            inner: (&mut reader as &mut dyn Read).take(header.length as u64),
+            digest,
+        };
+
+        let flow = visitor.on_chunk(&header, &mut data)?;
+        visited += 1;
+        if flow == Flow::Stop {
+            break;
+        }
+
+        io::copy(&mut data, &mut io::sink())?;
+        if data.remaining() > 0 {
+            return Err(StreamError::Truncated { offset }.into());
+        }
+        let computed = data.digest.finalize();
+
+        let mut crc = [0; 4];
+        if !read_or_eof(&mut reader, &mut crc, offset)? {
+            return Err(StreamError::Truncated { offset }.into());
+        }
+        let stored = be_u32(&crc);
+        if stored != computed {
+            tracing::debug!(
+                "{} at offset {}: stored CRC {:#010x} doesn't match {:#010x}",
+                header.chunk_type,
+                offset,
+                stored,
+                computed
+            );
+            visitor.on_crc_mismatch(&header, stored, computed)?;
+        }
+
+        offset += 12 + header.length as u64;
+    }
+
+    Ok(visited)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use std::str::FromStr;
+
+    fn file() -> Vec<u8> {
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"a\0b".to_vec()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ])
+        .as_bytes()
+    }
+
+    #[test]
+    fn test_visits_every_chunk() {
+        let mut seen = vec![];
+        let visited = parse(
+            file().as_slice(),
+            &mut |header: &ChunkHeader, data: &mut ChunkData| {
+                let mut first = [0; 1];
+                let read = data.read(&mut first)?;
+                seen.push((header.chunk_type.to_string(), header.offset, read));
+                Ok(Flow::Continue)
+            },
+        )
+        .unwrap();
+
+        assert_eq!(visited, 3);
+        assert_eq!(
+            seen,
+            [
+                ("IHDR".to_string(), 8, 1),
+                ("tEXt".to_string(), 33, 1),
+                ("IEND".to_string(), 48, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stop_early() {
+        let visited = parse(
+            file().as_slice(),
+            &mut |_: &ChunkHeader, _: &mut ChunkData| Ok(Flow::Stop),
+        )
+        .unwrap();
+        assert_eq!(visited, 1);
+    }
+
+    struct Strict;
+
+    impl Visitor for Strict {
+        fn on_chunk(&mut self, _: &ChunkHeader, _: &mut ChunkData) -> Result<Flow> {
+            Ok(Flow::Continue)
+        }
+
+        fn on_crc_mismatch(&mut self, header: &ChunkHeader, _: u32, _: u32) -> Result<()> {
+            Err(format!("bad CRC in {}", header.chunk_type).into())
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut corrupt = file();
+        corrupt[38] ^= 0xff;
+        assert!(parse(file().as_slice(), &mut Strict).is_ok());
+        assert!(parse(corrupt.as_slice(), &mut Strict).is_err());
+
+        let truncated = &file()[..40];
+        assert!(parse(truncated, &mut Strict).is_err());
+        assert!(parse(&b"GIF89a.."[..], &mut Strict).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+//! Colors for text output on a terminal.
+
+use std::{
+    fmt::Display,
+    io::{self, IsTerminal},
+    str::FromStr,
+};
+
+#[derive(Debug)]
+pub enum StyleError {
+    Unknown(String),
+}
+
+impl std::error::Error for StyleError {}
+
+impl std::fmt::Display for StyleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StyleError::Unknown(when) => {
+                write!(
+                    f,
+                    "Unknown color mode '{}', expected auto, always or never",
+                    when
+                )
+            }
+        }
+    }
+}
+
+/// When to color output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChoice {
+    /// When stdout is a terminal and `NO_COLOR` isn't set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = StyleError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, StyleError> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(StyleError::Unknown(s.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for ColorChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            ColorChoice::Auto => "auto",
+            ColorChoice::Always => "always",
+            ColorChoice::Never => "never",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// Critical chunks and failures.
+    Red,
+    /// Suspicious entries worth a second look.
+    Yellow,
+    Green,
+    /// Ancillary chunks and other detail.
+    Dim,
+}
+
+impl Color {
+    fn code(self) -> &'static str {
+        match self {
+            Color::Red => "31",
+            Color::Yellow => "1;33",
+            Color::Green => "32",
+            Color::Dim => "2",
+        }
+    }
+}
+
+/// Paints text for stdout, or leaves it alone when colors are off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Style {
+    pub enabled: bool,
+}
+
+impl Style {
+    pub fn new(choice: ColorChoice) -> Self {
+        let enabled = match choice {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                io::stdout().is_terminal()
+                    && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                    && std::env::var_os("TERM").is_none_or(|term| term != "dumb")
+            }
+        };
+        Style { enabled }
+    }
+
+    /// `text` in `color`. Pad before painting, escape codes would count towards the width.
+    pub fn paint(self, text: impl Display, color: Color) -> String {
+        match self.enabled {
+            true => format!("\x1b[{}m{}\x1b[0m", color.code(), text),
+            false => text.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paint() {
+        let on = Style { enabled: true };
+        assert_eq!(on.paint("IHDR", Color::Red), "\x1b[31mIHDR\x1b[0m");
+        assert_eq!(
+            Style::new(ColorChoice::Never).paint("IHDR", Color::Red),
+            "IHDR"
+        );
+        assert!(Style::new(ColorChoice::Always).enabled);
+        assert_eq!(ColorChoice::from_str("NEVER").unwrap(), ColorChoice::Never);
+        assert!(ColorChoice::from_str("sometimes").is_err());
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    cell::{Cell, RefCell},
+    fs,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use serde::Serialize;
+
+use crate::Result;
+
+/// What a command did with one file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    Written,
+    /// Would have been written, but this is a dry run.
+    DryRun,
+    Read,
+    Skipped,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: f64,
+    pub bytes_before: Option<usize>,
+    pub bytes_after: Option<usize>,
+    pub bytes_changed: Option<i64>,
+}
+
+/// The machine readable account of a run written by `--summary-file`.
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: f64,
+    pub files: Vec<FileReport>,
+}
+
+fn millis_since(instant: Instant) -> f64 {
+    instant.elapsed().as_secs_f64() * 1000.0
+}
+
+/// Collects a [`FileReport`] for every file a command touches.
+///
+/// A file's duration is the time since the previous file was recorded, which for the
+/// sequential batches pngme runs is the time spent on that file.
+#[derive(Debug)]
+pub struct Recorder {
+    started: Instant,
+    last: Cell<Instant>,
+    files: RefCell<Vec<FileReport>>,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        let now = Instant::now();
+        Self {
+            started: now,
+            last: Cell::new(now),
+            files: RefCell::new(vec![]),
+        }
+    }
+}
+
+impl Recorder {
+    pub fn record(
+        &self,
+        path: &Path,
+        status: Status,
+        bytes_before: Option<usize>,
+        bytes_after: Option<usize>,
+    ) {
+        self.push(path, status, None, bytes_before, bytes_after);
+    }
+
+    pub fn skip(&self, path: &Path, error: &str) {
+        self.push(path, Status::Skipped, Some(error.to_string()), None, None);
+    }
+
+    fn push(
+        &self,
+        path: &Path,
+        status: Status,
+        error: Option<String>,
+        bytes_before: Option<usize>,
+        bytes_after: Option<usize>,
+    ) {
+        let duration_ms = millis_since(self.last.replace(Instant::now()));
+        let bytes_changed = match (bytes_before, bytes_after) {
+            (Some(before), Some(after)) => Some(after as i64 - before as i64),
+            _ => None,
+        };
+
+        self.files.borrow_mut().push(FileReport {
+            path: path.to_path_buf(),
+            status,
+            error,
+            duration_ms,
+            bytes_before,
+            bytes_after,
+            bytes_changed,
+        });
+    }
+
+    /// Wrap up the run, `result` being its outcome.
+    pub fn finish(self, result: &Result<()>) -> Summary {
+        Summary {
+            ok: result.is_ok(),
+            error: result.as_ref().err().map(ToString::to_string),
+            duration_ms: millis_since(self.started),
+            files: self.files.into_inner(),
+        }
+    }
+}
+
+impl Summary {
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_json() {
+        let recorder = Recorder::default();
+        recorder.record(Path::new("a.png"), Status::Written, Some(100), Some(120));
+        recorder.skip(Path::new("b.png"), "Invalid PNG header");
+
+        let summary = recorder.finish(&Err("boom".into()));
+        let json: serde_json::Value = serde_json::to_value(&summary).unwrap();
+
+        assert_eq!(json["ok"], false);
+        assert_eq!(json["error"], "boom");
+        assert_eq!(json["files"][0]["status"], "written");
+        assert_eq!(json["files"][0]["bytes_changed"], 20);
+        assert_eq!(json["files"][1]["status"], "skipped");
+        assert_eq!(json["files"][1]["error"], "Invalid PNG header");
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use sha2::{Digest, Sha256};
+
+const PLACEHOLDERS: [&str; 6] = ["stem", "ext", "name", "index", "hash", "hash8"];
+
+#[derive(Debug)]
+pub enum TemplateError {
+    UnknownPlaceholder(String),
+    Unterminated,
+}
+
+impl std::error::Error for TemplateError {}
+
+impl std::fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TemplateError::UnknownPlaceholder(name) => write!(
+                f,
+                "Unknown placeholder {{{}}}, expected one of {}",
+                name,
+                PLACEHOLDERS.map(|p| format!("{{{}}}", p)).join(", ")
+            ),
+            TemplateError::Unterminated => write!(f, "Unterminated {{ in output template"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Literal(String),
+    Placeholder(String),
+}
+
+/// A file name pattern such as `{stem}-{hash8}.png` used to name written files.
+///
+/// * `{stem}`, `{ext}`, `{name}`: parts of the input file name
+/// * `{index}`: position of the file within a batch
+/// * `{hash}`, `{hash8}`: SHA-256 of the written content, in full or its first 8 hex digits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputTemplate {
+    pieces: Vec<Piece>,
+}
+
+impl FromStr for OutputTemplate {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> Result<Self, TemplateError> {
+        let mut pieces = vec![];
+        let mut rest = s;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                pieces.push(Piece::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..].find('}').ok_or(TemplateError::Unterminated)? + start;
+            let name = &rest[start + 1..end];
+            if !PLACEHOLDERS.contains(&name) {
+                return Err(TemplateError::UnknownPlaceholder(name.to_string()));
+            }
+            pieces.push(Piece::Placeholder(name.to_string()));
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            pieces.push(Piece::Literal(rest.to_string()));
+        }
+
+        Ok(Self { pieces })
+    }
+}
+
+impl OutputTemplate {
+    /// The path to write `content` to, relative to the directory of `input`.
+    pub fn render(&self, input: &Path, content: &[u8], index: usize) -> PathBuf {
+        let hash: String = Sha256::digest(content)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        let lossy = |part: Option<&std::ffi::OsStr>| {
+            part.map(|part| part.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+
+        let name: String = self
+            .pieces
+            .iter()
+            .map(|piece| match piece {
+                Piece::Literal(text) => text.clone(),
+                Piece::Placeholder(name) => match name.as_str() {
+                    "stem" => lossy(input.file_stem()),
+                    "ext" => lossy(input.extension()),
+                    "name" => lossy(input.file_name()),
+                    "index" => index.to_string(),
+                    "hash8" => hash[..8].to_string(),
+                    _ => hash.clone(),
+                },
+            })
+            .collect();
+
+        input.parent().unwrap_or(Path::new("")).join(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let template = OutputTemplate::from_str("{stem}-{hash8}.{ext}").unwrap();
+        let path = template.render(Path::new("assets/logo.png"), b"abc", 0);
+        // SHA-256 of "abc" starts with ba7816bf
+        assert_eq!(path, PathBuf::from("assets/logo-ba7816bf.png"));
+    }
+
+    #[test]
+    fn test_index_and_literals() {
+        let template = OutputTemplate::from_str("out/{index}_{name}").unwrap();
+        let path = template.render(Path::new("a.png"), b"", 7);
+        assert_eq!(path, PathBuf::from("out/7_a.png"));
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        assert!(OutputTemplate::from_str("{stem").is_err());
+        assert!(OutputTemplate::from_str("{nope}.png").is_err());
+    }
+}
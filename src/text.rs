@@ -0,0 +1,281 @@
+use std::{io::Write, str::FromStr};
+
+use flate2::{write::ZlibEncoder, Compression};
+
+use crate::{chunk::Chunk, limits, Result};
+
+#[derive(Debug)]
+pub enum TextError {
+    UnknownCharset(String),
+    InvalidUtf8,
+    MissingSeparator,
+    UnsupportedCompression(u8),
+    NotATextChunk(String),
+    NotLatin1,
+}
+
+impl std::error::Error for TextError {}
+
+impl std::fmt::Display for TextError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TextError::UnknownCharset(charset) => write!(
+                f,
+                "Unknown charset '{}', expected auto, utf8 or latin1",
+                charset
+            ),
+            TextError::InvalidUtf8 => write!(f, "Text is not valid UTF-8"),
+            TextError::MissingSeparator => write!(f, "Text chunk has no keyword separator"),
+            TextError::UnsupportedCompression(method) => {
+                write!(f, "Unsupported text compression method {}", method)
+            }
+            TextError::NotATextChunk(typ) => write!(f, "{} is not a text chunk", typ),
+            TextError::NotLatin1 => write!(
+                f,
+                "tEXt and zTXt only hold Latin-1 text, use an iTXt chunk instead"
+            ),
+        }
+    }
+}
+
+/// How to turn stored bytes into a displayable string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Charset {
+    /// Latin-1 for tEXt/zTXt (as the PNG spec mandates), UTF-8 for everything else and
+    /// Latin-1 again if the bytes turn out not to be UTF-8.
+    #[default]
+    Auto,
+    Utf8,
+    Latin1,
+}
+
+impl FromStr for Charset {
+    type Err = TextError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, TextError> {
+        match s.to_ascii_lowercase().replace(['-', '_'], "").as_str() {
+            "auto" => Ok(Charset::Auto),
+            "utf8" => Ok(Charset::Utf8),
+            "latin1" | "iso88591" => Ok(Charset::Latin1),
+            _ => Err(TextError::UnknownCharset(s.to_string())),
+        }
+    }
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+/// Transcode `bytes` to a UTF-8 string. `legacy` marks data the spec defines as Latin-1.
+pub fn decode_bytes(bytes: &[u8], charset: Charset, legacy: bool) -> Result<String> {
+    match charset {
+        Charset::Latin1 => Ok(latin1(bytes)),
+        Charset::Utf8 => Ok(String::from_utf8(bytes.to_vec()).map_err(|_| TextError::InvalidUtf8)?),
+        Charset::Auto if legacy => Ok(latin1(bytes)),
+        Charset::Auto => Ok(String::from_utf8(bytes.to_vec()).unwrap_or_else(|_| latin1(bytes))),
+    }
+}
+
+/// The decoded contents of a tEXt, zTXt or iTXt chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextualChunk {
+    pub keyword: String,
+    /// Only iTXt chunks carry a language tag and a translated keyword.
+    pub language: Option<String>,
+    pub translated_keyword: Option<String>,
+    pub text: String,
+}
+
+pub fn is_text_chunk(chunk_type: &str) -> bool {
+    matches!(chunk_type, "tEXt" | "zTXt" | "iTXt")
+}
+
+fn to_latin1(text: &str) -> Result<Vec<u8>> {
+    text.chars()
+        .map(|c| u8::try_from(c as u32).map_err(|_| TextError::NotLatin1.into()))
+        .collect()
+}
+
+/// Compress `data` into a zlib stream, as used by zTXt, iTXt and iCCP.
+pub fn deflate(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+fn split_nul(data: &[u8]) -> Result<(&[u8], &[u8])> {
+    let idx = data
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or(TextError::MissingSeparator)?;
+    Ok((&data[..idx], &data[idx + 1..]))
+}
+
+fn inflate(method: u8, data: &[u8]) -> Result<Vec<u8>> {
+    if method != 0 {
+        return Err(TextError::UnsupportedCompression(method).into());
+    }
+    limits::inflate(data, u64::MAX)
+}
+
+impl TextualChunk {
+    pub fn parse(chunk: &Chunk, charset: Charset) -> Result<Self> {
+        let chunk_type = chunk.chunk_type().to_string();
+        let (keyword, rest) = split_nul(chunk.data())?;
+        // Keywords are always Latin-1
+        let keyword = latin1(keyword);
+
+        let (language, translated_keyword, text) = match chunk_type.as_str() {
+            "tEXt" => (None, None, decode_bytes(rest, charset, true)?),
+            "zTXt" => {
+                let (&method, compressed) =
+                    rest.split_first().ok_or(TextError::MissingSeparator)?;
+                let text = inflate(method, compressed)?;
+                (None, None, decode_bytes(&text, charset, true)?)
+            }
+            "iTXt" => {
+                let (&compressed, rest) = rest.split_first().ok_or(TextError::MissingSeparator)?;
+                let (&method, rest) = rest.split_first().ok_or(TextError::MissingSeparator)?;
+                let (language, rest) = split_nul(rest)?;
+                let (translated, text) = split_nul(rest)?;
+
+                let text = if compressed == 1 {
+                    inflate(method, text)?
+                } else {
+                    text.to_vec()
+                };
+
+                (
+                    Some(latin1(language)),
+                    Some(decode_bytes(translated, charset, false)?),
+                    decode_bytes(&text, charset, false)?,
+                )
+            }
+            other => return Err(TextError::NotATextChunk(other.to_string()).into()),
+        };
+
+        Ok(Self {
+            keyword,
+            language,
+            translated_keyword,
+            text,
+        })
+    }
+
+    /// Serialize as the data of a `chunk_type` chunk, the reverse of [`TextualChunk::parse`].
+    ///
+    /// zTXt text is compressed, iTXt text is stored uncompressed.
+    pub fn to_data(&self, chunk_type: &str) -> Result<Vec<u8>> {
+        let mut data = to_latin1(&self.keyword)?;
+        data.push(0);
+
+        match chunk_type {
+            "tEXt" => data.extend(to_latin1(&self.text)?),
+            "zTXt" => {
+                data.push(0);
+                data.extend(deflate(&to_latin1(&self.text)?)?);
+            }
+            "iTXt" => {
+                data.extend_from_slice(&[0, 0]);
+                data.extend(to_latin1(self.language.as_deref().unwrap_or_default())?);
+                data.push(0);
+                data.extend_from_slice(
+                    self.translated_keyword
+                        .as_deref()
+                        .unwrap_or_default()
+                        .as_bytes(),
+                );
+                data.push(0);
+                data.extend_from_slice(self.text.as_bytes());
+            }
+            other => return Err(TextError::NotATextChunk(other.to_string()).into()),
+        }
+
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::io::Write;
+
+    fn chunk(code: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(code).unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_latin1_text_is_transcoded() {
+        // "Café" in Latin-1
+        let text = chunk("tEXt", b"Comment\0Caf\xe9");
+        let parsed = TextualChunk::parse(&text, Charset::Auto).unwrap();
+        assert_eq!(parsed.keyword, "Comment");
+        assert_eq!(parsed.text, "Café");
+    }
+
+    #[test]
+    fn test_charset_override() {
+        let text = chunk("tEXt", "Comment\0Café".as_bytes());
+        let parsed = TextualChunk::parse(&text, Charset::Utf8).unwrap();
+        assert_eq!(parsed.text, "Café");
+
+        let invalid = chunk("tEXt", b"Comment\0Caf\xe9");
+        assert!(TextualChunk::parse(&invalid, Charset::Utf8).is_err());
+    }
+
+    #[test]
+    fn test_compressed_text() {
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"squeezed").unwrap();
+        let mut data = b"Comment\0\0".to_vec();
+        data.extend(encoder.finish().unwrap());
+
+        let parsed = TextualChunk::parse(&chunk("zTXt", &data), Charset::Auto).unwrap();
+        assert_eq!(parsed.text, "squeezed");
+    }
+
+    #[test]
+    fn test_international_text() {
+        let parsed = TextualChunk::parse(
+            &chunk("iTXt", "Title\0\0\0fr\0Titre\0Été".as_bytes()),
+            Charset::Auto,
+        )
+        .unwrap();
+        assert_eq!(parsed.language.as_deref(), Some("fr"));
+        assert_eq!(parsed.translated_keyword.as_deref(), Some("Titre"));
+        assert_eq!(parsed.text, "Été");
+    }
+
+    #[test]
+    fn test_to_data_round_trips() {
+        for (code, data) in [
+            ("tEXt", b"Comment\0Caf\xe9".to_vec()),
+            ("iTXt", "Title\0\0\0fr\0Titre\0Été".as_bytes().to_vec()),
+        ] {
+            let parsed = TextualChunk::parse(&chunk(code, &data), Charset::Auto).unwrap();
+            assert_eq!(parsed.to_data(code).unwrap(), data);
+        }
+
+        let text = TextualChunk::parse(&chunk("tEXt", b"Comment\0hi"), Charset::Auto).unwrap();
+        let compressed = chunk("zTXt", &text.to_data("zTXt").unwrap());
+        assert_eq!(
+            TextualChunk::parse(&compressed, Charset::Auto).unwrap(),
+            text
+        );
+
+        let emoji = TextualChunk {
+            text: "🦀".to_string(),
+            ..text
+        };
+        assert!(emoji.to_data("tEXt").is_err());
+    }
+
+    #[test]
+    fn test_auto_falls_back_to_latin1() {
+        assert_eq!(decode_bytes(b"\xff", Charset::Auto, false).unwrap(), "ÿ");
+        assert_eq!(Charset::from_str("ISO-8859-1").unwrap(), Charset::Latin1);
+        assert!(Charset::from_str("ebcdic").is_err());
+    }
+}
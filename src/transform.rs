@@ -0,0 +1,130 @@
+use std::{io::Write, str::FromStr};
+
+use flate2::{write::GzEncoder, Compression};
+
+use crate::{codec, limits, Result};
+
+#[derive(Debug)]
+pub enum TransformError {
+    Unknown(String),
+    NotText(Transform),
+}
+
+impl std::error::Error for TransformError {}
+
+impl std::fmt::Display for TransformError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TransformError::Unknown(name) => write!(
+                f,
+                "Unknown transform '{}', expected gzip, base64, hex or reverse",
+                name
+            ),
+            TransformError::NotText(transform) => {
+                write!(f, "Data to undo {} on is not ASCII text", transform)
+            }
+        }
+    }
+}
+
+/// A reversible encoding applied to chunk data before it is embedded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transform {
+    Gzip,
+    Base64,
+    Hex,
+    Reverse,
+}
+
+impl FromStr for Transform {
+    type Err = TransformError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, TransformError> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "gzip" => Ok(Transform::Gzip),
+            "base64" => Ok(Transform::Base64),
+            "hex" => Ok(Transform::Hex),
+            "reverse" => Ok(Transform::Reverse),
+            _ => Err(TransformError::Unknown(s.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for Transform {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Transform::Gzip => "gzip",
+            Transform::Base64 => "base64",
+            Transform::Hex => "hex",
+            Transform::Reverse => "reverse",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Transform {
+    pub fn apply(self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(match self {
+            Transform::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()?
+            }
+            Transform::Base64 => codec::encode_base64(data).into_bytes(),
+            Transform::Hex => codec::encode_hex(data).into_bytes(),
+            Transform::Reverse => data.iter().rev().copied().collect(),
+        })
+    }
+
+    pub fn undo(self, data: &[u8]) -> Result<Vec<u8>> {
+        let text = || std::str::from_utf8(data).map_err(|_| TransformError::NotText(self));
+        match self {
+            Transform::Gzip => limits::gunzip(data),
+            Transform::Base64 => codec::decode_base64(text()?),
+            Transform::Hex => codec::decode_hex(text()?),
+            Transform::Reverse => Ok(data.iter().rev().copied().collect()),
+        }
+    }
+}
+
+/// Run `data` through every transform in order.
+pub fn apply(data: Vec<u8>, pipeline: &[Transform]) -> Result<Vec<u8>> {
+    pipeline
+        .iter()
+        .try_fold(data, |data, transform| transform.apply(&data))
+}
+
+/// Undo [`apply`]: run `data` back through the transforms in reverse order.
+pub fn undo(data: Vec<u8>, pipeline: &[Transform]) -> Result<Vec<u8>> {
+    pipeline
+        .iter()
+        .rev()
+        .try_fold(data, |data, transform| transform.undo(&data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pipeline_round_trip() {
+        let pipeline = [
+            Transform::Gzip,
+            Transform::Reverse,
+            Transform::Base64,
+            Transform::Hex,
+        ];
+        let data = b"hidden in plain sight".to_vec();
+
+        let encoded = apply(data.clone(), &pipeline).unwrap();
+        assert!(encoded.iter().all(u8::is_ascii_hexdigit));
+        assert_eq!(undo(encoded, &pipeline).unwrap(), data);
+    }
+
+    #[test]
+    fn test_parse_transform() {
+        assert_eq!("GZIP".parse::<Transform>().unwrap(), Transform::Gzip);
+        assert!("rot13".parse::<Transform>().is_err());
+        assert!(Transform::Hex.undo(&[0xff]).is_err());
+    }
+}
@@ -0,0 +1,223 @@
+//! Commands run end to end against files in a scratch directory.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use clap::Parser;
+use pngme::{args::Cli, commands::run, png::Png, Result};
+
+/// A fresh directory holding a copy of `examples/1pixel_red.png` as `image.png`.
+fn scratch() -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "pngme-cli-{}-{}",
+        process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    fs::copy("examples/1pixel_red.png", dir.join("image.png")).unwrap();
+    dir
+}
+
+fn pngme(dir: &Path, args: &[&str]) -> Result<()> {
+    let args = args.iter().map(|arg| match arg.strip_prefix('@') {
+        Some(name) => dir.join(name).display().to_string(),
+        None => arg.to_string(),
+    });
+    run(&Cli::try_parse_from(
+        std::iter::once("pngme".to_string()).chain(args),
+    )?)
+}
+
+/// Type and data of every chunk of the PNG at `path`, in order.
+fn chunks(path: &Path) -> Vec<(String, Vec<u8>)> {
+    let png = Png::try_from(fs::read(path).unwrap().as_slice()).unwrap();
+    png.chunks()
+        .iter()
+        .map(|chunk| (chunk.chunk_type().to_string(), chunk.data().to_vec()))
+        .collect()
+}
+
+#[test]
+fn test_decoy_with_transform() {
+    let dir = scratch();
+    #[rustfmt::skip]
+    pngme(&dir, &[
+        "encode", "@image.png", "ruSt", "meet at dawn", "--transform", "gzip,base64",
+        "--decoy", "grocery list", "--password", "hunter2", "--decoy-password", "1234",
+        "--kdf-memory", "1024", "--kdf-iterations", "1", "--overwrite",
+    ])
+    .unwrap();
+
+    for (password, message) in [("hunter2", "meet at dawn"), ("1234", "grocery list")] {
+        #[rustfmt::skip]
+        pngme(&dir, &[
+            "decode", "@image.png", "ruSt", "--transform", "gzip,base64",
+            "--password", password, "--out", "@message.txt",
+        ])
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("message.txt")).unwrap(),
+            message
+        );
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_decoy_password_must_differ() {
+    let dir = scratch();
+    let before = fs::read(dir.join("image.png")).unwrap();
+    #[rustfmt::skip]
+    let result = pngme(&dir, &[
+        "encode", "@image.png", "ruSt", "meet at dawn", "--decoy", "grocery list",
+        "--password", "hunter2", "--decoy-password", "hunter2", "--overwrite",
+    ]);
+    assert!(result.unwrap_err().to_string().contains("decoy password"));
+    assert_eq!(fs::read(dir.join("image.png")).unwrap(), before);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_remove_index() {
+    let dir = scratch();
+    pngme(&dir, &["encode", "@image.png", "ruSt", "one"]).unwrap();
+    pngme(&dir, &["encode", "@image.png", "ruSt", "two", "--append"]).unwrap();
+    pngme(&dir, &["encode", "@image.png", "teSt", "three"]).unwrap();
+
+    // Among the chunks of a type
+    pngme(&dir, &["remove", "@image.png", "ruSt", "--index", "1"]).unwrap();
+    let messages: Vec<_> = chunks(&dir.join("image.png"))
+        .into_iter()
+        .filter(|(chunk_type, _)| chunk_type == "ruSt")
+        .map(|(_, data)| data)
+        .collect();
+    assert_eq!(messages, [b"one"]);
+
+    // Among all chunks
+    let chunks_before = chunks(&dir.join("image.png"));
+    let index = chunks_before
+        .iter()
+        .position(|(chunk_type, _)| chunk_type == "teSt")
+        .unwrap();
+    pngme(
+        &dir,
+        &["remove", "@image.png", "--index", &index.to_string()],
+    )
+    .unwrap();
+    let mut expected = chunks_before;
+    expected.remove(index);
+    assert_eq!(chunks(&dir.join("image.png")), expected);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_output_leaves_input_untouched() {
+    let dir = scratch();
+    let before = fs::read(dir.join("image.png")).unwrap();
+    pngme(
+        &dir,
+        &["encode", "@image.png", "ruSt", "hello", "-o", "@out.png"],
+    )
+    .unwrap();
+
+    assert_eq!(fs::read(dir.join("image.png")).unwrap(), before);
+    assert!(chunks(&dir.join("out.png")).contains(&("ruSt".to_string(), b"hello".to_vec())));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_backup() {
+    let dir = scratch();
+    let before = fs::read(dir.join("image.png")).unwrap();
+    pngme(&dir, &["encode", "@image.png", "ruSt", "hello", "--backup"]).unwrap();
+    assert_eq!(fs::read(dir.join("image.png.bak")).unwrap(), before);
+    assert_ne!(fs::read(dir.join("image.png")).unwrap(), before);
+
+    // Nothing is overwritten with -o, so there is nothing to back up
+    #[rustfmt::skip]
+    pngme(&dir, &[
+        "encode", "@image.png", "teSt", "hello", "-o", "@out.png", "--backup={stem}.orig",
+    ])
+    .unwrap();
+    assert!(!dir.join("image.orig").exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_encode_file() {
+    let dir = scratch();
+    let contents = [0, 1, 0xff, b'\n', 0x89, b'P', b'N', b'G'];
+    fs::write(dir.join("secret.bin"), contents).unwrap();
+    pngme(
+        &dir,
+        &["encode", "@image.png", "ruSt", "--file", "@secret.bin"],
+    )
+    .unwrap();
+
+    pngme(
+        &dir,
+        &["decode", "@image.png", "ruSt", "--out-dir", "@restored"],
+    )
+    .unwrap();
+    assert_eq!(
+        fs::read(dir.join("restored").join("secret.bin")).unwrap(),
+        contents
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_decode_out() {
+    let dir = scratch();
+    pngme(&dir, &["encode", "@image.png", "ruSt", "two\nlines"]).unwrap();
+    pngme(
+        &dir,
+        &["decode", "@image.png", "ruSt", "--out", "@message.txt"],
+    )
+    .unwrap();
+
+    // The message exactly, with no newline added
+    assert_eq!(fs::read(dir.join("message.txt")).unwrap(), b"two\nlines");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_recursive() {
+    let dir = scratch();
+    fs::create_dir_all(dir.join("tree").join("sub")).unwrap();
+    fs::rename(dir.join("image.png"), dir.join("tree").join("a.png")).unwrap();
+    fs::copy(
+        dir.join("tree").join("a.png"),
+        dir.join("tree").join("sub").join("b"),
+    )
+    .unwrap();
+    fs::write(dir.join("tree").join("notes.txt"), "not an image").unwrap();
+
+    pngme(&dir, &["encode", "-r", "@tree", "ruSt", "hello"]).unwrap();
+
+    // Every PNG beneath the directory, whatever its name, and nothing else
+    for path in [
+        dir.join("tree").join("a.png"),
+        dir.join("tree").join("sub").join("b"),
+    ] {
+        assert!(chunks(&path).contains(&("ruSt".to_string(), b"hello".to_vec())));
+    }
+    assert_eq!(
+        fs::read_to_string(dir.join("tree").join("notes.txt")).unwrap(),
+        "not an image"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}